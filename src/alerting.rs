@@ -0,0 +1,361 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::{
+    config::{
+        AlertRuleConfig, AlertingConfig, MastodonNotifierConfig, WebhookConfig, WebhookFormat,
+    },
+    derived::{Expression, Labels},
+    mastodon::MastodonClient,
+    metrics::REGISTRY,
+};
+
+/// The number of seconds after which sending a notification is given up.
+const TIMEOUT_SECONDS: u64 = 30;
+
+lazy_static! {
+    /// The HTTP client used to send notifications.
+    static ref HTTP_CLIENT: reqwest::Client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(TIMEOUT_SECONDS))
+        .build()
+        .unwrap();
+
+    /// The state of every alert rule and label set, keyed by rule name and labels.
+    static ref STATES: Mutex<HashMap<(String, Labels), AlertState>> = Mutex::new(HashMap::new());
+}
+
+/// A condition on the value of an alert expression.
+#[derive(Clone, Copy)]
+pub enum Condition {
+    /// Compares the value to a threshold with an operator like `==` or `>`.
+    Compare(&'static str, f64),
+
+    /// Holds when the value differs from the previous collection cycle.
+    Changed,
+}
+
+impl Condition {
+    /// Parses a condition like `== 0`, `> 10` or `changed`.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let input = input.trim();
+        if input == "changed" {
+            return Ok(Condition::Changed);
+        }
+
+        // Two character operators first, so `>=` isn't parsed as `>`
+        for operator in ["==", "!=", ">=", "<=", ">", "<"] {
+            if let Some(threshold) = input.strip_prefix(operator) {
+                let threshold = threshold
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("invalid threshold in condition {}", input))?;
+                return Ok(Condition::Compare(operator, threshold));
+            }
+        }
+
+        Err(format!("invalid condition {}", input))
+    }
+
+    fn holds(&self, value: f64, previous: Option<f64>) -> bool {
+        match *self {
+            Condition::Compare(operator, threshold) => match operator {
+                "==" => value == threshold,
+                "!=" => value != threshold,
+                ">=" => value >= threshold,
+                "<=" => value <= threshold,
+                ">" => value > threshold,
+                _ => value < threshold,
+            },
+            Condition::Changed => previous.is_some_and(|previous| previous != value),
+        }
+    }
+}
+
+/// The state of an alert rule for a single label set.
+#[derive(Default)]
+struct AlertState {
+    /// The value of the previous collection cycle.
+    previous: Option<f64>,
+
+    /// The number of consecutive collection cycles the condition held.
+    pending_cycles: u32,
+
+    /// Whether the alert has been notified as firing.
+    firing: bool,
+}
+
+/// A change of the state of an alert which is notified.
+pub struct Notification {
+    pub name: String,
+    pub firing: bool,
+    pub labels: Labels,
+    pub value: f64,
+}
+
+impl Notification {
+    /// Returns a human-readable message of the notification.
+    pub fn message(&self) -> String {
+        let labels: Vec<String> = self
+            .labels
+            .iter()
+            .map(|(name, value)| format!("{}=\"{}\"", name, value))
+            .collect();
+
+        format!(
+            "[{}] {} {{{}}}: {}",
+            if self.firing { "FIRING" } else { "RESOLVED" },
+            self.name,
+            labels.join(", "),
+            self.value
+        )
+    }
+}
+
+/// Updates the states of the rule and returns the notifications of changed states.
+///
+/// Alerts on `changed` conditions are only notified when firing, as they resolve with the next
+/// collection cycle. The states of label sets without a sample are dropped, and firing alerts of
+/// them are resolved with their last value, e.g. when an account was removed.
+fn evaluate_rule(rule: &AlertRuleConfig, samples: Vec<(Labels, f64)>) -> Vec<Notification> {
+    // The rule was validated when loading the configuration
+    let Ok(condition) = Condition::parse(&rule.condition) else {
+        return Vec::new();
+    };
+
+    let mut states = STATES.lock().unwrap();
+    let mut notifications = Vec::new();
+
+    let current: HashSet<Labels> = samples.iter().map(|(labels, _)| labels.clone()).collect();
+    states.retain(|(name, labels), state| {
+        if *name != rule.name || current.contains(labels) {
+            return true;
+        }
+        if state.firing && matches!(condition, Condition::Compare(..)) {
+            notifications.push(Notification {
+                name: rule.name.clone(),
+                firing: false,
+                labels: labels.clone(),
+                value: state.previous.unwrap_or_default(),
+            });
+        }
+        false
+    });
+
+    for (labels, value) in samples {
+        let state = states
+            .entry((rule.name.clone(), labels.clone()))
+            .or_default();
+
+        if condition.holds(value, state.previous) {
+            state.pending_cycles += 1;
+        } else {
+            state.pending_cycles = 0;
+        }
+        state.previous = Some(value);
+
+        let firing = state.pending_cycles >= rule.for_cycles;
+        let notify = match condition {
+            Condition::Changed => firing,
+            Condition::Compare(..) => firing != state.firing,
+        };
+        state.firing = firing;
+
+        if notify {
+            notifications.push(Notification {
+                name: rule.name.clone(),
+                firing,
+                labels,
+                value,
+            });
+        }
+    }
+
+    notifications
+}
+
+async fn send_direct_status(notifier: &MastodonNotifierConfig, notification: &Notification) {
+    let client = MastodonClient::new(&notifier.instance)
+        .with_access_token(notifier.access_token.get().unwrap_or_default());
+    let text = format!(
+        "@{} {}",
+        notifier.mention.trim_start_matches('@'),
+        notification.message()
+    );
+
+    let timeout = Duration::from_secs(TIMEOUT_SECONDS);
+    match tokio::time::timeout(timeout, client.post_status(&text, "direct")).await {
+        Ok(Ok(_)) => {}
+        Ok(Err(err)) => log!("Error: Sending alert {} failed: {}", notification.name, err),
+        Err(_) => log!(
+            "Error: Sending alert {} timed out after {:?}",
+            notification.name,
+            timeout
+        ),
+    }
+}
+
+async fn send_webhook(webhook: &WebhookConfig, notification: &Notification) {
+    let payload = match webhook.format {
+        WebhookFormat::Json => json!({
+            "alert": notification.name,
+            "state": if notification.firing { "firing" } else { "resolved" },
+            "labels": notification.labels,
+            "value": notification.value,
+        }),
+        WebhookFormat::Slack => json!({ "text": notification.message() }),
+        WebhookFormat::Matrix => json!({
+            "msgtype": "m.text",
+            "body": notification.message(),
+        }),
+    };
+
+    let result = HTTP_CLIENT
+        .post(&webhook.url)
+        .json(&payload)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    if let Err(err) = result {
+        log!("Error: Sending alert {} failed: {}", notification.name, err);
+    }
+}
+
+/// Evaluates the alert rules over the collected metrics and notifies about changed states.
+///
+/// The notifications are sent in the background, so a hanging webhook doesn't delay the
+/// collection cycle.
+pub fn evaluate(config: &AlertingConfig) {
+    let families = REGISTRY.gather();
+
+    let mut notifications = Vec::new();
+    for rule in &config.rules {
+        // The expression was validated when loading the configuration
+        let Ok(expression) = Expression::parse(&rule.expression) else {
+            continue;
+        };
+
+        notifications.extend(evaluate_rule(rule, expression.evaluate_all(&families)));
+    }
+    if notifications.is_empty() {
+        return;
+    }
+
+    let webhooks = config.webhooks.clone();
+    let notifiers = config.mastodon.clone();
+    tokio::spawn(async move {
+        for notification in notifications {
+            log!("Alert: {}", notification.message());
+
+            for webhook in &webhooks {
+                send_webhook(webhook, &notification).await;
+            }
+            for notifier in &notifiers {
+                send_direct_status(notifier, &notification).await;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(name: &str, condition: &str, for_cycles: u32) -> AlertRuleConfig {
+        AlertRuleConfig {
+            name: name.to_string(),
+            expression: "instance_up".to_string(),
+            condition: condition.to_string(),
+            for_cycles,
+        }
+    }
+
+    fn sample(instance: &str, value: f64) -> (Labels, f64) {
+        let labels = Labels::from([("instance".to_string(), instance.to_string())]);
+        (labels, value)
+    }
+
+    fn states(notifications: Vec<Notification>) -> Vec<(String, bool, f64)> {
+        notifications
+            .into_iter()
+            .map(|notification| {
+                (
+                    notification.labels["instance"].clone(),
+                    notification.firing,
+                    notification.value,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_conditions() {
+        assert!(matches!(
+            Condition::parse(">= 10"),
+            Ok(Condition::Compare(">=", threshold)) if threshold == 10.0
+        ));
+        assert!(matches!(
+            Condition::parse(" == 0 "),
+            Ok(Condition::Compare("==", threshold)) if threshold == 0.0
+        ));
+        assert!(matches!(
+            Condition::parse("changed"),
+            Ok(Condition::Changed)
+        ));
+        assert!(Condition::parse("> ten").is_err());
+        assert!(Condition::parse("= 1").is_err());
+    }
+
+    #[test]
+    fn fires_after_the_pending_cycles_and_resolves() {
+        let rule = rule("fires_and_resolves", "== 0", 2);
+
+        assert!(evaluate_rule(&rule, vec![sample("a", 0.0)]).is_empty());
+        assert_eq!(
+            states(evaluate_rule(&rule, vec![sample("a", 0.0)])),
+            [("a".to_string(), true, 0.0)]
+        );
+        assert!(evaluate_rule(&rule, vec![sample("a", 0.0)]).is_empty());
+        assert_eq!(
+            states(evaluate_rule(&rule, vec![sample("a", 1.0)])),
+            [("a".to_string(), false, 1.0)]
+        );
+    }
+
+    #[test]
+    fn resolves_label_sets_without_samples() {
+        let rule = rule("resolves_vanished", "> 5", 1);
+
+        assert_eq!(
+            states(evaluate_rule(
+                &rule,
+                vec![sample("a", 10.0), sample("b", 1.0)]
+            )),
+            [("a".to_string(), true, 10.0)]
+        );
+        assert_eq!(
+            states(evaluate_rule(&rule, vec![])),
+            [("a".to_string(), false, 10.0)]
+        );
+        assert!(!STATES
+            .lock()
+            .unwrap()
+            .keys()
+            .any(|(name, _)| name == "resolves_vanished"));
+    }
+
+    #[test]
+    fn notifies_changes_only_when_firing() {
+        let rule = rule("notifies_changes", "changed", 1);
+
+        assert!(evaluate_rule(&rule, vec![sample("a", 1.0)]).is_empty());
+        assert_eq!(
+            states(evaluate_rule(&rule, vec![sample("a", 2.0)])),
+            [("a".to_string(), true, 2.0)]
+        );
+        assert!(evaluate_rule(&rule, vec![sample("a", 2.0)]).is_empty());
+        assert!(evaluate_rule(&rule, vec![]).is_empty());
+    }
+}
@@ -0,0 +1,69 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    config::CanaryConfig,
+    mastodon::{self, MastodonClient},
+    metrics::{
+        WithLimitedLabelValues, MASTODON_CANARY_POST_DURATION_SECONDS, MASTODON_CANARY_POST_SUCCESS,
+    },
+};
+
+async fn post_canary(config: &CanaryConfig) -> Result<(), mastodon::Error> {
+    let instance = config.instance.as_str();
+    let text = format!(
+        "mastodon_exporter canary {}",
+        chrono::Utc::now().to_rfc3339()
+    );
+
+    log!("Posting canary status {}", instance);
+
+    let client = MastodonClient::new(instance)
+        .with_access_token(config.access_token.get().unwrap_or_default());
+
+    let start = Instant::now();
+    let status = client.post_status(&text, &config.visibility).await?;
+    let duration = start.elapsed().as_secs_f64();
+
+    log!("{}: Canary post duration: {}", instance, duration);
+    if let Some(gauge) =
+        MASTODON_CANARY_POST_DURATION_SECONDS.with_limited_label_values(&[instance])
+    {
+        gauge.set(duration);
+    }
+
+    // The post succeeded, so failing to clean up doesn't fail the canary
+    if let Err(err) = client.delete_status(&status.id).await {
+        log!(
+            "Error: {} unable to delete canary {}: {}",
+            instance,
+            status.id,
+            err
+        );
+    }
+
+    Ok(())
+}
+
+/// Periodically posts and deletes a canary status on an instance.
+///
+/// This runs until the process exits or a reload changes it, independent of `/metrics` scrapes.
+pub async fn run(config: CanaryConfig) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval_seconds));
+
+    loop {
+        interval.tick().await;
+
+        let success = match post_canary(&config).await {
+            Ok(()) => true,
+            Err(err) => {
+                log!("Error: {} canary {}", config.instance, err);
+                false
+            }
+        };
+        if let Some(gauge) =
+            MASTODON_CANARY_POST_SUCCESS.with_limited_label_values(&[&config.instance])
+        {
+            gauge.set(i64::from(success));
+        }
+    }
+}
@@ -1,133 +1,957 @@
-use chrono::NaiveDate;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use prometheus::{core::Collector as _, IntGaugeVec};
+use tracing::Instrument;
 
 use crate::{
-    mastodon, MASTODON_ACCOUNT_FOLLOWERS_COUNT, MASTODON_ACCOUNT_FOLLOWING_COUNT,
-    MASTODON_ACCOUNT_LAST_STATUS_AT, MASTODON_ACCOUNT_STATUSES_COUNT, MASTODON_RATELIMIT_REMAINING,
-    MASTODON_RATELIMIT_RESET,
+    config::{AccountConfig, CollectorName, Config},
+    mastodon::{
+        self, CredentialAccountResponse, FeaturedTagResponse, MastodonClient, PageCount,
+        StatusResponse,
+    },
+    metrics::{
+        self, WithLimitedLabelValues, MASTODON_ACCOUNT_BLOCKS_COUNT,
+        MASTODON_ACCOUNT_BOOKMARKS_COUNT, MASTODON_ACCOUNT_COUNT_TRUNCATED,
+        MASTODON_ACCOUNT_DEFAULT_SENSITIVE, MASTODON_ACCOUNT_DEFAULT_VISIBILITY,
+        MASTODON_ACCOUNT_FAVOURITES_COUNT, MASTODON_ACCOUNT_FEATURED_TAGS_COUNT,
+        MASTODON_ACCOUNT_FEATURED_TAG_STATUSES_COUNT, MASTODON_ACCOUNT_FOLLOWED_TAGS,
+        MASTODON_ACCOUNT_FOLLOWERS_COUNT, MASTODON_ACCOUNT_FOLLOWING_COUNT,
+        MASTODON_ACCOUNT_FOLLOWS, MASTODON_ACCOUNT_FOLLOW_REQUESTS_COUNT, MASTODON_ACCOUNT_FOUND,
+        MASTODON_ACCOUNT_INFO, MASTODON_ACCOUNT_LAST_STATUS_AT, MASTODON_ACCOUNT_MUTES_COUNT,
+        MASTODON_ACCOUNT_POSTING_RATE_PER_DAY, MASTODON_ACCOUNT_RECENT_AVG_FAVOURITES,
+        MASTODON_ACCOUNT_RECENT_AVG_REBLOGS, MASTODON_ACCOUNT_RECENT_AVG_REPLIES,
+        MASTODON_ACCOUNT_STATE, MASTODON_ACCOUNT_STATUSES_COUNT,
+        MASTODON_ACCOUNT_UNREAD_CONVERSATIONS, MASTODON_ACCOUNT_VERIFIED_FIELDS,
+        MASTODON_SCRAPE_PARSE_ERRORS_TOTAL,
+    },
+    targets,
 };
 
-pub async fn collect_account(instance: &str, account_id: &str) -> Result<(), reqwest::Error> {
-    let url = format!("https://{}/api/v1/accounts/{}", instance, account_id);
+/// The visibilities of statuses, of which one is the default of an account.
+const VISIBILITIES: [&str; 4] = ["public", "unlisted", "private", "direct"];
 
-    println!("Collecting account {}@{}", account_id, instance);
+/// The counts of paginated lists of accounts, which may stop at the page limit.
+const PAGE_COUNTS: [&str; 4] = ["mutes", "blocks", "bookmarks", "favourites"];
 
-    let response = reqwest::get(url).await?;
+lazy_static! {
+    /// The ids of accounts configured by acct, keyed by instance and acct.
+    static ref RESOLVED_IDS: Mutex<HashMap<(String, String), String>> = Mutex::new(HashMap::new());
 
-    // Collect x-ratelimit-remaining from header
-    let ratelimit_remaining: i64 = response
-        .headers()
-        .get("x-ratelimit-remaining")
-        .unwrap()
-        .to_str()
+    /// The last collection of accounts with an interval, keyed by instance and id or acct.
+    static ref LAST_COLLECTED: Mutex<HashMap<(String, String), Instant>> =
+        Mutex::new(HashMap::new());
+
+    /// The username label of the series of accounts, keyed by instance and id.
+    static ref USERNAME_LABELS: Mutex<HashMap<(String, String), String>> =
+        Mutex::new(HashMap::new());
+
+    /// The display name and avatar labels of the info series of accounts, keyed by instance and id.
+    static ref PROFILE_LABELS: Mutex<HashMap<(String, String), (String, String)>> =
+        Mutex::new(HashMap::new());
+
+    /// The featured hashtags of accounts, keyed by instance and id.
+    static ref FEATURED_TAGS: Mutex<HashMap<(String, String), Vec<String>>> =
+        Mutex::new(HashMap::new());
+
+    /// When accounts were first not found, keyed by instance and id.
+    static ref NOT_FOUND_SINCE: Mutex<HashMap<(String, String), Instant>> =
+        Mutex::new(HashMap::new());
+}
+
+/// The options of the configuration which apply to all accounts.
+#[derive(Clone, Copy)]
+pub struct AccountOptions {
+    /// Whether or not to label the series with the username.
+    pub username_label: bool,
+
+    /// Whether or not to export the display name and avatar of the account.
+    pub profile_labels: bool,
+
+    /// Whether or not to export the hashtags featured on the profile of the account.
+    pub featured_tags: bool,
+
+    /// Whether or not to count the statuses bookmarked and favourited by the account.
+    pub bookmarks_favourites: bool,
+
+    /// The number of recent statuses to export engagement averages of.
+    pub recent_statuses: Option<usize>,
+
+    /// The time after which the series of accounts which are not found are removed.
+    pub not_found_grace: Duration,
+}
+
+impl From<&Config> for AccountOptions {
+    fn from(config: &Config) -> Self {
+        Self {
+            username_label: config.account_username_label,
+            profile_labels: config.account_profile_labels,
+            featured_tags: config.account_featured_tags,
+            bookmarks_favourites: config.account_bookmarks_favourites,
+            recent_statuses: config.account_recent_statuses,
+            not_found_grace: Duration::from_secs(config.account_not_found_grace_seconds),
+        }
+    }
+}
+
+/// Removes the series of the account with a previous username label, e.g. after a rename.
+fn remove_superseded_series(instance: &str, account_id: &str, username: &str) {
+    let key = (instance.to_string(), account_id.to_string());
+    let previous = USERNAME_LABELS
+        .lock()
         .unwrap()
-        .parse()
-        .unwrap();
-    println!("{}: Ratelimit remaining: {}", instance, ratelimit_remaining);
-    MASTODON_RATELIMIT_REMAINING
-        .with_label_values(&[instance])
-        .set(ratelimit_remaining);
-
-    // Collect x-ratelimit-reset from header
-    let ratelimit_reset: i64 = response
-        .headers()
-        .get("x-ratelimit-reset")
+        .insert(key, username.to_string());
+    let Some(previous) = previous.filter(|previous| previous != username) else {
+        return;
+    };
+
+    log!(
+        "{}: Account {} changed username label from {} to {}",
+        instance,
+        account_id,
+        previous,
+        username
+    );
+    remove_count_series(instance, account_id, &previous);
+}
+
+/// Removes the series of the account with the given username label.
+fn remove_count_series(instance: &str, account_id: &str, username: &str) {
+    let labels = [instance, account_id, username];
+    for metric in [
+        &*MASTODON_ACCOUNT_FOLLOWERS_COUNT,
+        &*MASTODON_ACCOUNT_FOLLOWING_COUNT,
+        &*MASTODON_ACCOUNT_STATUSES_COUNT,
+        &*MASTODON_ACCOUNT_LAST_STATUS_AT,
+        &*MASTODON_ACCOUNT_VERIFIED_FIELDS,
+        &*MASTODON_ACCOUNT_FOLLOWED_TAGS,
+        &*MASTODON_ACCOUNT_MUTES_COUNT,
+        &*MASTODON_ACCOUNT_BLOCKS_COUNT,
+        &*MASTODON_ACCOUNT_FEATURED_TAGS_COUNT,
+        &*MASTODON_ACCOUNT_DEFAULT_SENSITIVE,
+        &*MASTODON_ACCOUNT_FOLLOW_REQUESTS_COUNT,
+        &*MASTODON_ACCOUNT_UNREAD_CONVERSATIONS,
+        &*MASTODON_ACCOUNT_BOOKMARKS_COUNT,
+        &*MASTODON_ACCOUNT_FAVOURITES_COUNT,
+    ] {
+        metric.remove_label_values(&labels).ok();
+    }
+    for visibility in VISIBILITIES {
+        MASTODON_ACCOUNT_DEFAULT_VISIBILITY
+            .remove_label_values(&[instance, account_id, username, visibility])
+            .ok();
+    }
+    for count in PAGE_COUNTS {
+        MASTODON_ACCOUNT_COUNT_TRUNCATED
+            .remove_label_values(&[instance, account_id, username, count])
+            .ok();
+    }
+    for metric in [
+        &*MASTODON_ACCOUNT_RECENT_AVG_FAVOURITES,
+        &*MASTODON_ACCOUNT_RECENT_AVG_REBLOGS,
+        &*MASTODON_ACCOUNT_RECENT_AVG_REPLIES,
+        &*MASTODON_ACCOUNT_POSTING_RATE_PER_DAY,
+    ] {
+        metric.remove_label_values(&labels).ok();
+    }
+}
+
+/// Sets the account info gauge with the profile, replacing the series of a previous profile.
+fn collect_account_info(instance: &str, account_id: &str, display_name: &str, avatar: &str) {
+    let key = (instance.to_string(), account_id.to_string());
+    let profile = (display_name.to_string(), avatar.to_string());
+    let previous = PROFILE_LABELS.lock().unwrap().insert(key, profile.clone());
+    if let Some((previous_display_name, previous_avatar)) =
+        previous.filter(|previous| *previous != profile)
+    {
+        log!(
+            "{}: Account {} changed profile from {:?} to {:?}",
+            instance,
+            account_id,
+            previous_display_name,
+            display_name
+        );
+        MASTODON_ACCOUNT_INFO
+            .remove_label_values(&[
+                instance,
+                account_id,
+                &previous_display_name,
+                &previous_avatar,
+            ])
+            .ok();
+    }
+
+    if let Some(gauge) = MASTODON_ACCOUNT_INFO.with_limited_label_values(&[
+        instance,
+        account_id,
+        display_name,
+        avatar,
+    ]) {
+        gauge.set(1);
+    }
+}
+
+/// Sets the statuses count of every featured hashtag, removing the series of unfeatured ones.
+fn collect_featured_tags(instance: &str, account_id: &str, tags: &[FeaturedTagResponse]) {
+    let key = (instance.to_string(), account_id.to_string());
+    let names: Vec<String> = tags.iter().map(|tag| tag.name.to_lowercase()).collect();
+    let previous = FEATURED_TAGS
+        .lock()
         .unwrap()
-        .to_str()
+        .insert(key, names.clone())
+        .unwrap_or_default();
+    for name in previous.iter().filter(|name| !names.contains(name)) {
+        MASTODON_ACCOUNT_FEATURED_TAG_STATUSES_COUNT
+            .remove_label_values(&[instance, account_id, name])
+            .ok();
+    }
+
+    for (tag, name) in tags.iter().zip(&names) {
+        if let Some(gauge) = MASTODON_ACCOUNT_FEATURED_TAG_STATUSES_COUNT
+            .with_limited_label_values(&[instance, account_id, name])
+        {
+            gauge.set(tag.statuses_count);
+        }
+    }
+}
+
+/// Sets the default settings for posting and the pending follow requests of the account of the
+/// access token.
+fn collect_credentials(
+    credentials: &CredentialAccountResponse,
+    username: &str,
+    info_labels: [&str; 3],
+) {
+    let [instance, account_id, username_label] = info_labels;
+
+    let source = &credentials.source;
+    log!(
+        "@{}@{}: Default visibility: {}, sensitive: {}, follow requests: {}",
+        username,
+        instance,
+        source.privacy,
+        source.sensitive,
+        source.follow_requests_count
+    );
+    for visibility in VISIBILITIES {
+        let labels = [instance, account_id, username_label, visibility];
+        if let Some(gauge) = MASTODON_ACCOUNT_DEFAULT_VISIBILITY.with_limited_label_values(&labels)
+        {
+            gauge.set(i64::from(source.privacy == visibility));
+        }
+    }
+    if let Some(gauge) = MASTODON_ACCOUNT_DEFAULT_SENSITIVE.with_limited_label_values(&info_labels)
+    {
+        gauge.set(i64::from(source.sensitive));
+    }
+    if let Some(gauge) =
+        MASTODON_ACCOUNT_FOLLOW_REQUESTS_COUNT.with_limited_label_values(&info_labels)
+    {
+        gauge.set(source.follow_requests_count);
+    }
+}
+
+/// Sets the metrics only the account of the access token can fetch about itself, like its
+/// followed hashtags, mutes and blocks.
+async fn collect_token_account(
+    client: &MastodonClient,
+    username: &str,
+    info_labels: [&str; 3],
+    options: AccountOptions,
+) {
+    let [instance, account_id, _] = info_labels;
+
+    match client.followed_tags().await {
+        Ok(tags) => {
+            log!("@{}@{}: Followed tags: {}", username, instance, tags.len());
+            if let Some(gauge) =
+                MASTODON_ACCOUNT_FOLLOWED_TAGS.with_limited_label_values(&info_labels)
+            {
+                gauge.set(tags.len() as i64);
+            }
+        }
+        Err(err) => log!("Error: {} {} followed tags {}", instance, account_id, err),
+    }
+
+    // Collect mutes and blocks count
+    collect_page_count(
+        &MASTODON_ACCOUNT_MUTES_COUNT,
+        "mutes",
+        username,
+        info_labels,
+        client.mutes_count().await,
+    );
+    collect_page_count(
+        &MASTODON_ACCOUNT_BLOCKS_COUNT,
+        "blocks",
+        username,
+        info_labels,
+        client.blocks_count().await,
+    );
+
+    // Collect bookmarks and favourites count if enabled, which takes a request per 40 statuses
+    if options.bookmarks_favourites {
+        collect_page_count(
+            &MASTODON_ACCOUNT_BOOKMARKS_COUNT,
+            "bookmarks",
+            username,
+            info_labels,
+            client.bookmarks_count().await,
+        );
+        collect_page_count(
+            &MASTODON_ACCOUNT_FAVOURITES_COUNT,
+            "favourites",
+            username,
+            info_labels,
+            client.favourites_count().await,
+        );
+    }
+
+    // Collect unread conversations, e.g. of support accounts answering direct statuses
+    match client.conversations().await {
+        Ok(conversations) => {
+            let unread = conversations
+                .iter()
+                .filter(|conversation| conversation.unread)
+                .count();
+            log!(
+                "@{}@{}: Unread conversations: {}",
+                username,
+                instance,
+                unread
+            );
+            if let Some(gauge) =
+                MASTODON_ACCOUNT_UNREAD_CONVERSATIONS.with_limited_label_values(&info_labels)
+            {
+                gauge.set(unread as i64);
+            }
+        }
+        Err(err) => log!("Error: {} {} conversations {}", instance, account_id, err),
+    }
+}
+
+/// Sets the account state gauges, marking every given state as active.
+///
+/// The `active` state is set if none of the other states apply.
+fn collect_account_state(instance: &str, account_id: &str, states: &[&str]) {
+    log!("{}: Account {} state: {:?}", instance, account_id, states);
+
+    for state in ["moved", "suspended", "limited"] {
+        if let Some(gauge) =
+            MASTODON_ACCOUNT_STATE.with_limited_label_values(&[instance, account_id, state])
+        {
+            gauge.set(i64::from(states.contains(&state)));
+        }
+    }
+    if let Some(gauge) =
+        MASTODON_ACCOUNT_STATE.with_limited_label_values(&[instance, account_id, "active"])
+    {
+        gauge.set(i64::from(states.is_empty()));
+    }
+}
+
+/// Sets the account labels metric with the alias and labels from the configuration.
+fn collect_account_labels(account: &AccountConfig, account_id: &str) {
+    let Some(metric) = metrics::account_labels() else {
+        return;
+    };
+
+    let mut labels = HashMap::from([
+        ("instance", account.instance.as_str()),
+        ("account_id", account_id),
+        ("alias", account.alias.as_deref().unwrap_or_default()),
+    ]);
+    labels.extend(
+        account
+            .labels
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str())),
+    );
+
+    // Accounts without some of the label names of other accounts leave them empty
+    let values: Vec<&str> = metric.desc()[0]
+        .variable_labels
+        .iter()
+        .map(|name| labels.get(name.as_str()).copied().unwrap_or_default())
+        .collect();
+    if let Some(gauge) = metric.with_limited_label_values(&values) {
+        gauge.set(1);
+    }
+}
+
+/// Marks the account as not found and removes its series once the grace period passed.
+///
+/// The account is only logged when it's first not found.
+fn collect_account_not_found(instance: &str, account_id: &str, options: AccountOptions) {
+    if let Some(gauge) = MASTODON_ACCOUNT_FOUND.with_limited_label_values(&[instance, account_id]) {
+        gauge.set(0);
+    }
+
+    let key = (instance.to_string(), account_id.to_string());
+    let since = *NOT_FOUND_SINCE
+        .lock()
         .unwrap()
-        .parse::<chrono::DateTime<chrono::Utc>>()
+        .entry(key.clone())
+        .or_insert_with(|| {
+            log!("{}: Account {} not found", instance, account_id);
+            Instant::now()
+        });
+
+    if since.elapsed() >= options.not_found_grace {
+        if let Some(username) = USERNAME_LABELS.lock().unwrap().remove(&key) {
+            log!(
+                "{}: Removing series of account {} not found for {:?}",
+                instance,
+                account_id,
+                since.elapsed()
+            );
+            remove_count_series(instance, account_id, &username);
+        }
+        for name in FEATURED_TAGS
+            .lock()
+            .unwrap()
+            .remove(&key)
+            .unwrap_or_default()
+        {
+            MASTODON_ACCOUNT_FEATURED_TAG_STATUSES_COUNT
+                .remove_label_values(&[instance, account_id, &name])
+                .ok();
+        }
+        if let Some((display_name, avatar)) = PROFILE_LABELS.lock().unwrap().remove(&key) {
+            MASTODON_ACCOUNT_INFO
+                .remove_label_values(&[instance, account_id, &display_name, &avatar])
+                .ok();
+        }
+    }
+}
+
+/// Marks the account as found again.
+fn collect_account_found(instance: &str, account_id: &str) {
+    if let Some(gauge) = MASTODON_ACCOUNT_FOUND.with_limited_label_values(&[instance, account_id]) {
+        gauge.set(1);
+    }
+
+    let key = (instance.to_string(), account_id.to_string());
+    if NOT_FOUND_SINCE.lock().unwrap().remove(&key).is_some() {
+        log!("{}: Account {} found again", instance, account_id);
+    }
+}
+
+/// Parses the date of the last status of an account into seconds since 1970.
+///
+/// Mastodon returns a date like `2023-01-31`, some forks a full datetime like
+/// `2023-01-31T12:34:56.000Z`.
+fn parse_last_status_at(last_status_at: &str) -> Option<i64> {
+    if let Ok(date) = NaiveDate::parse_from_str(last_status_at, "%Y-%m-%d") {
+        return Some(date.and_hms_opt(0, 0, 0)?.and_utc().timestamp());
+    }
+
+    DateTime::parse_from_rfc3339(last_status_at)
+        .ok()
+        .map(|datetime| datetime.timestamp())
+}
+
+/// Returns the number of statuses per day over the last week.
+///
+/// If all statuses were posted within the week, more may have been posted before the oldest
+/// one, so the rate is calculated over the time since the oldest one instead.
+fn posting_rate_per_day(statuses: &[StatusResponse], fetched_all: bool) -> f64 {
+    let now = Utc::now();
+    let week_ago = now - chrono::Duration::days(7);
+    let created: Vec<DateTime<Utc>> = statuses
+        .iter()
+        .filter_map(|status| DateTime::parse_from_rfc3339(&status.created_at).ok())
+        .map(|created_at| created_at.with_timezone(&Utc))
+        .collect();
+    let within_week = created
+        .iter()
+        .filter(|created| **created >= week_ago)
+        .count();
+
+    let since = match created.iter().min() {
+        Some(oldest) if !fetched_all && within_week == created.len() => *oldest,
+        _ => week_ago,
+    };
+    let days = (now - since).num_seconds().max(1) as f64 / (24 * 60 * 60) as f64;
+
+    within_week as f64 / days
+}
+
+/// Sets the posting rate and engagement averages of the recent statuses of an account.
+fn collect_recent_statuses(info_labels: &[&str], statuses: &[StatusResponse], requested: usize) {
+    let (instance, username) = (info_labels[0], info_labels[2]);
+
+    // Fewer statuses than requested are all statuses of the account
+    let posting_rate = posting_rate_per_day(statuses, statuses.len() < requested);
+    log!(
+        "@{}@{}: Posting rate per day: {}",
+        username,
+        instance,
+        posting_rate
+    );
+    if let Some(gauge) =
+        MASTODON_ACCOUNT_POSTING_RATE_PER_DAY.with_limited_label_values(info_labels)
+    {
+        gauge.set(posting_rate);
+    }
+
+    // Averages of no statuses are undefined
+    if statuses.is_empty() {
+        return;
+    }
+
+    let average = |count: fn(&StatusResponse) -> i64| {
+        statuses.iter().map(count).sum::<i64>() as f64 / statuses.len() as f64
+    };
+    for (metric, name, value) in [
+        (
+            &*MASTODON_ACCOUNT_RECENT_AVG_FAVOURITES,
+            "favourites",
+            average(|status| status.favourites_count),
+        ),
+        (
+            &*MASTODON_ACCOUNT_RECENT_AVG_REBLOGS,
+            "reblogs",
+            average(|status| status.reblogs_count),
+        ),
+        (
+            &*MASTODON_ACCOUNT_RECENT_AVG_REPLIES,
+            "replies",
+            average(|status| status.replies_count),
+        ),
+    ] {
+        log!(
+            "@{}@{}: Recent average {}: {}",
+            username,
+            instance,
+            name,
+            value
+        );
+        if let Some(gauge) = metric.with_limited_label_values(info_labels) {
+            gauge.set(value);
+        }
+    }
+}
+
+/// Returns whether the account was collected within its interval.
+fn collected_recently(account: &AccountConfig) -> bool {
+    let Some(interval_seconds) = account.interval_seconds else {
+        return false;
+    };
+
+    let key = (account.instance.clone(), account.key().to_string());
+    let mut last_collected = LAST_COLLECTED.lock().unwrap();
+    match last_collected.get(&key) {
+        Some(last) if last.elapsed() < Duration::from_secs(interval_seconds) => true,
+        _ => {
+            last_collected.insert(key, Instant::now());
+            false
+        }
+    }
+}
+
+/// Removes the series of an account which is no longer collected, e.g. after it was removed from
+/// the configuration, and forgets what was collected about it.
+pub(crate) fn remove_account(account: &AccountConfig) {
+    let instance = account.instance.as_str();
+    LAST_COLLECTED
+        .lock()
         .unwrap()
-        .timestamp();
-    println!("{}: Ratelimit reset: {}", instance, ratelimit_reset);
-    MASTODON_RATELIMIT_RESET
-        .with_label_values(&[instance])
-        .set(ratelimit_reset);
-
-    if let Err(err) = &response.error_for_status_ref() {
-        if err.status() == Some(reqwest::StatusCode::NOT_FOUND) {
-            println!("{}: Account {} not found", instance, account_id);
-            return Ok(());
+        .remove(&(instance.to_string(), account.key().to_string()));
+    let Some(account_id) = known_id(account) else {
+        return;
+    };
+    RESOLVED_IDS.lock().unwrap().remove(&(
+        instance.to_string(),
+        account.acct.clone().unwrap_or_default(),
+    ));
+
+    let key = (instance.to_string(), account_id.clone());
+    USERNAME_LABELS.lock().unwrap().remove(&key);
+    PROFILE_LABELS.lock().unwrap().remove(&key);
+    FEATURED_TAGS.lock().unwrap().remove(&key);
+    NOT_FOUND_SINCE.lock().unwrap().remove(&key);
+
+    metrics::remove_series(|labels| {
+        metrics::has_label(labels, "instance", instance)
+            && metrics::has_label(labels, "account_id", &account_id)
+    });
+}
+
+/// Sets the account labels metric of the accounts whose id is known, replacing the labels of
+/// removed accounts and previous labels from the configuration.
+pub(crate) fn set_account_labels(accounts: &[AccountConfig]) {
+    let Some(metric) = metrics::account_labels() else {
+        return;
+    };
+
+    metric.reset();
+    for account in accounts {
+        if let Some(account_id) = known_id(account) {
+            collect_account_labels(account, &account_id);
         }
+    }
+}
+
+/// Returns the id of the account, resolving its acct on the instance if necessary.
+async fn resolve_id(
+    client: &MastodonClient,
+    account: &AccountConfig,
+) -> Result<String, mastodon::Error> {
+    if let Some(id) = &account.id {
+        return Ok(id.clone());
+    }
+
+    let acct = account.acct.clone().unwrap_or_default();
+    let key = (account.instance.clone(), acct.clone());
+    if let Some(id) = RESOLVED_IDS.lock().unwrap().get(&key) {
+        return Ok(id.clone());
+    }
+
+    let id = client.lookup_account(&acct).await?.id;
+    log!("{}: Resolved account {} to {}", account.instance, acct, id);
+    RESOLVED_IDS.lock().unwrap().insert(key, id.clone());
+
+    Ok(id)
+}
+
+#[tracing::instrument(skip_all, fields(instance = %account.instance, account = %account.key()))]
+pub async fn collect_account(
+    account: &AccountConfig,
+    options: AccountOptions,
+) -> Result<(), mastodon::Error> {
+    let instance = account.instance.as_str();
 
-        println!("Error: {} {} {}", instance, account_id, err);
+    if collected_recently(account) {
+        log!(
+            "Skipping account {}@{} within its interval",
+            account.key(),
+            instance
+        );
         return Ok(());
     }
 
+    log!("Collecting account {}@{}", account.key(), instance);
+
+    let mut client = MastodonClient::new(instance);
+    if let Some(access_token) = account.access_token.get() {
+        client = client.with_access_token(access_token);
+    }
+
+    let start = Instant::now();
+    let target = Some(account.key());
+    let account_id = match resolve_id(&client, account).await {
+        Ok(account_id) => account_id,
+        Err(err) => {
+            log!("Error: {} {} {}", instance, account.key(), err);
+            targets::record(instance, target, start, Err(err.to_string()));
+            return Ok(());
+        }
+    };
+    let account_id = account_id.as_str();
+
+    collect_account_labels(account, account_id);
+
     // Collect response body data
-    let body = response.json::<mastodon::AccountResponse>().await.unwrap();
+    let body = match client.account(account_id).await {
+        Ok(body) => body,
+        Err(err) if err.status() == Some(reqwest::StatusCode::NOT_FOUND) => {
+            targets::record(instance, target, start, Err(err.to_string()));
+            collect_account_not_found(instance, account_id, options);
+            return Ok(());
+        }
+        // Suspended accounts are reported as gone
+        Err(err) if err.status() == Some(reqwest::StatusCode::GONE) => {
+            targets::record(instance, target, start, Err(err.to_string()));
+            collect_account_state(instance, account_id, &["suspended"]);
+            collect_account_not_found(instance, account_id, options);
+            return Ok(());
+        }
+        Err(err) => {
+            log!("Error: {} {} {}", instance, account_id, err);
+            targets::record(instance, target, start, Err(err.to_string()));
+            return Ok(());
+        }
+    };
+    targets::record(instance, target, start, Ok(()));
 
-    // TODO @Shinigami92 2022-11-21: Handle case when account is not found
     let username = &body.username;
+    collect_account_found(instance, account_id);
 
     // Collect account info
-    let info_labels = [instance, account_id, username];
-    println!("Account info: {:?}", info_labels);
+    let username_label = if options.username_label {
+        username.as_str()
+    } else {
+        ""
+    };
+    remove_superseded_series(instance, account_id, username_label);
+    let info_labels = [instance, account_id, username_label];
+    log!("Account info: {:?}", info_labels);
+    if options.profile_labels {
+        collect_account_info(instance, account_id, &body.display_name, &body.avatar);
+    }
+
+    // Collect account state
+    let states: Vec<&str> = [
+        ("moved", body.moved.is_some()),
+        ("suspended", body.suspended),
+        ("limited", body.limited),
+    ]
+    .into_iter()
+    .filter(|(_, flag)| *flag)
+    .map(|(state, _)| state)
+    .collect();
+    collect_account_state(instance, account_id, &states);
 
     // Collect account followers count
     let followers_count = body.followers_count;
-    println!(
+    log!(
         "@{}@{}: Followers count: {}",
-        username, instance, followers_count
+        username,
+        instance,
+        followers_count
     );
-    MASTODON_ACCOUNT_FOLLOWERS_COUNT
-        .with_label_values(&info_labels)
-        .set(followers_count);
+    if let Some(gauge) = MASTODON_ACCOUNT_FOLLOWERS_COUNT.with_limited_label_values(&info_labels) {
+        gauge.set(followers_count);
+    }
 
     // Collect account following count
     let following_count = body.following_count;
-    println!(
+    log!(
         "@{}@{}: Following count: {}",
-        username, instance, following_count
+        username,
+        instance,
+        following_count
     );
-    MASTODON_ACCOUNT_FOLLOWING_COUNT
-        .with_label_values(&info_labels)
-        .set(following_count);
+    if let Some(gauge) = MASTODON_ACCOUNT_FOLLOWING_COUNT.with_limited_label_values(&info_labels) {
+        gauge.set(following_count);
+    }
 
     // Collect account statuses count
     let statuses_count = body.statuses_count;
-    println!(
+    log!(
         "@{}@{}: Statuses count: {}",
-        username, instance, statuses_count
+        username,
+        instance,
+        statuses_count
     );
-    MASTODON_ACCOUNT_STATUSES_COUNT
-        .with_label_values(&info_labels)
-        .set(statuses_count);
+    if let Some(gauge) = MASTODON_ACCOUNT_STATUSES_COUNT.with_limited_label_values(&info_labels) {
+        gauge.set(statuses_count);
+    }
 
     // Collect account last status at
     if let Some(last_status_at) = body.last_status_at {
-        let last_status_at: i64 = NaiveDate::parse_from_str(&last_status_at, "%Y-%m-%d")
-            .unwrap()
-            .and_hms_opt(0, 0, 0)
-            .unwrap()
-            .timestamp();
+        match parse_last_status_at(&last_status_at) {
+            Some(last_status_at) => {
+                log!(
+                    "@{}@{}: Last status at: {}",
+                    username,
+                    instance,
+                    last_status_at
+                );
+                if let Some(gauge) =
+                    MASTODON_ACCOUNT_LAST_STATUS_AT.with_limited_label_values(&info_labels)
+                {
+                    gauge.set(last_status_at);
+                }
+            }
+            None => {
+                log!(
+                    "@{}@{}: Unable to parse last status at {}",
+                    username,
+                    instance,
+                    last_status_at
+                );
+                if let Some(counter) = MASTODON_SCRAPE_PARSE_ERRORS_TOTAL
+                    .with_limited_label_values(&[instance, "last_status_at"])
+                {
+                    counter.inc();
+                }
+            }
+        }
+    }
 
-        println!(
-            "@{}@{}: Last status at: {}",
-            username, instance, last_status_at
-        );
-        MASTODON_ACCOUNT_LAST_STATUS_AT
-            .with_label_values(&info_labels)
-            .set(last_status_at);
+    // Collect account verified fields
+    let verified_fields = body
+        .fields
+        .iter()
+        .filter(|field| field.verified_at.is_some())
+        .count() as i64;
+    log!(
+        "@{}@{}: Verified fields: {}",
+        username,
+        instance,
+        verified_fields
+    );
+    if let Some(gauge) = MASTODON_ACCOUNT_VERIFIED_FIELDS.with_limited_label_values(&info_labels) {
+        gauge.set(verified_fields);
+    }
+
+    // Collect engagement and posting rate of recent statuses
+    if let Some(recent_statuses) = options.recent_statuses {
+        match client.account_statuses(account_id, recent_statuses).await {
+            Ok(statuses) => collect_recent_statuses(&info_labels, &statuses, recent_statuses),
+            Err(err) => log!("Error: {} {} statuses {}", instance, account_id, err),
+        }
+    }
+
+    // Collect featured hashtags
+    if options.featured_tags {
+        match client.featured_tags(account_id).await {
+            Ok(tags) => {
+                log!("@{}@{}: Featured tags: {}", username, instance, tags.len());
+                if let Some(gauge) =
+                    MASTODON_ACCOUNT_FEATURED_TAGS_COUNT.with_limited_label_values(&info_labels)
+                {
+                    gauge.set(tags.len() as i64);
+                }
+                collect_featured_tags(instance, account_id, &tags);
+            }
+            Err(err) => log!("Error: {} {} featured tags {}", instance, account_id, err),
+        }
+    }
+
+    // Collect the metrics of the account of the access token, which may be shared by accounts
+    if account.access_token.get().is_some() {
+        match client.verify_credentials().await {
+            Ok(credentials) if credentials.id == account_id => {
+                collect_credentials(&credentials, username, info_labels);
+                collect_token_account(&client, username, info_labels, options).await;
+            }
+            Ok(credentials) => log!(
+                "Warning: @{}@{}: The access token belongs to account {}, skipping its metrics",
+                username,
+                instance,
+                credentials.id
+            ),
+            Err(err) => log!("Error: {} {} credentials {}", instance, account_id, err),
+        }
     }
 
     Ok(())
 }
 
+/// Sets the gauge with the count of a paginated list of the account, and whether the count
+/// stopped at the page limit.
+fn collect_page_count(
+    metric: &IntGaugeVec,
+    count: &str,
+    username: &str,
+    info_labels: [&str; 3],
+    result: Result<PageCount, mastodon::Error>,
+) {
+    let [instance, account_id, username_label] = info_labels;
+    let page_count = match result {
+        Ok(page_count) => page_count,
+        Err(err) => {
+            log!("Error: {} {} {} {}", instance, account_id, count, err);
+            return;
+        }
+    };
+
+    log!(
+        "@{}@{}: {} count: {}{}",
+        username,
+        instance,
+        count,
+        page_count.count,
+        if page_count.truncated {
+            ", truncated at the page limit"
+        } else {
+            ""
+        }
+    );
+    if let Some(gauge) = metric.with_limited_label_values(&info_labels) {
+        gauge.set(page_count.count as i64);
+    }
+    if let Some(gauge) = MASTODON_ACCOUNT_COUNT_TRUNCATED.with_limited_label_values(&[
+        instance,
+        account_id,
+        username_label,
+        count,
+    ]) {
+        gauge.set(i64::from(page_count.truncated));
+    }
+}
+
+/// Returns the id of the account if it's configured or has been resolved.
+fn known_id(account: &AccountConfig) -> Option<String> {
+    if let Some(id) = &account.id {
+        return Some(id.clone());
+    }
+
+    let key = (account.instance.clone(), account.acct.clone()?);
+    RESOLVED_IDS.lock().unwrap().get(&key).cloned()
+}
+
+/// Sets whether every account with an access token follows the other accounts on its instance.
+async fn collect_relationships(accounts: &[AccountConfig]) {
+    for source in accounts {
+        let Some(access_token) = source.access_token.get() else {
+            continue;
+        };
+        let Some(source_id) = known_id(source) else {
+            continue;
+        };
+        let target_ids: Vec<String> = accounts
+            .iter()
+            .filter(|target| target.instance == source.instance)
+            .filter_map(known_id)
+            .filter(|target_id| *target_id != source_id)
+            .collect();
+        if target_ids.is_empty() {
+            continue;
+        }
+
+        let instance = source.instance.as_str();
+        let client = MastodonClient::new(instance).with_access_token(access_token);
+        let target_ids: Vec<&str> = target_ids.iter().map(String::as_str).collect();
+        let relationships = match client.relationships(&target_ids).await {
+            Ok(relationships) => relationships,
+            Err(err) => {
+                log!("Error: {} {} relationships {}", instance, source_id, err);
+                continue;
+            }
+        };
+
+        for relationship in relationships {
+            log!(
+                "{}: Account {} follows {}: {}",
+                instance,
+                source_id,
+                relationship.id,
+                relationship.following
+            );
+            if let Some(gauge) = MASTODON_ACCOUNT_FOLLOWS.with_limited_label_values(&[
+                instance,
+                &source_id,
+                &relationship.id,
+            ]) {
+                gauge.set(i64::from(relationship.following));
+            }
+        }
+    }
+}
+
 pub async fn collect_accounts(
-    accounts: Vec<(String, String)>,
+    accounts: Vec<AccountConfig>,
+    options: AccountOptions,
 ) -> Result<(), tokio::task::JoinError> {
     let mut handles = Vec::new();
 
-    for (instance, account_id) in accounts {
+    for account in accounts.clone() {
+        let target = account.instance.clone();
         let handle =
-            tokio::spawn(
-                async move { collect_account(instance.as_str(), account_id.as_str()).await },
-            );
-        handles.push(handle);
+            tokio::spawn(async move { collect_account(&account, options).await }.in_current_span());
+        handles.push((target, handle));
     }
 
-    for handle in handles {
-        handle.await.ok();
-    }
+    super::join(CollectorName::Accounts, handles).await;
+
+    // Relationships are collected last, so the ids of accounts configured by acct are resolved
+    collect_relationships(&accounts).await;
 
     Ok(())
 }
@@ -0,0 +1,59 @@
+use std::time::Instant;
+
+use tracing::Instrument;
+
+use crate::{
+    config::CollectorName,
+    mastodon::{self, MastodonClient},
+    metrics::{
+        WithLimitedLabelValues, MASTODON_INSTANCE_HEALTH_DURATION_SECONDS,
+        MASTODON_INSTANCE_HEALTH_UP,
+    },
+};
+
+/// Checks the health endpoint of an instance, which answers without touching the API.
+///
+/// Requests go through the client like those of the API, so they are replayed in record/replay
+/// mode and failures count as scrape errors.
+#[tracing::instrument]
+pub async fn collect_health(instance: &str) -> Result<(), mastodon::Error> {
+    log!("Checking health {}", instance);
+
+    let start = Instant::now();
+    let client = MastodonClient::new(instance);
+    let up = match client.send(client.get("/health")).await {
+        Ok(_) => true,
+        Err(err) => {
+            log!("Error: {} health {}", instance, err);
+            false
+        }
+    };
+    let duration = start.elapsed().as_secs_f64();
+
+    log!("{}: Health up: {} in {}", instance, up, duration);
+    if let Some(gauge) = MASTODON_INSTANCE_HEALTH_UP.with_limited_label_values(&[instance]) {
+        gauge.set(i64::from(up));
+    }
+    if let Some(gauge) =
+        MASTODON_INSTANCE_HEALTH_DURATION_SECONDS.with_limited_label_values(&[instance])
+    {
+        gauge.set(duration);
+    }
+
+    Ok(())
+}
+
+pub async fn collect_healths(instances: Vec<String>) -> Result<(), tokio::task::JoinError> {
+    let mut handles = Vec::new();
+
+    for instance in instances {
+        let target = instance.clone();
+        let handle =
+            tokio::spawn(async move { collect_health(instance.as_str()).await }.in_current_span());
+        handles.push((target, handle));
+    }
+
+    super::join(CollectorName::HealthChecks, handles).await;
+
+    Ok(())
+}
@@ -1,91 +1,430 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use tracing::Instrument;
+
 use crate::{
-    mastodon, MASTODON_INFO, MASTODON_RATELIMIT_REMAINING, MASTODON_RATELIMIT_RESET,
-    MASTODON_REGISTRATIONS_APPROVAL_REQUIRED, MASTODON_REGISTRATIONS_ENABLED,
+    config::{CollectorName, InfoLabel},
+    mastodon::{self, InstanceResponse, MastodonClient},
+    metrics::{
+        self, WithLimitedLabelValues, MASTODON_INSTANCE_BRANDING_CHANGES_TOTAL,
+        MASTODON_INSTANCE_SECONDS_SINCE_VERSION_CHANGE, MASTODON_INSTANCE_SOFTWARE_INFO,
+        MASTODON_INSTANCE_STREAMING_AVAILABLE, MASTODON_INSTANCE_TRANSLATION_ENABLED,
+        MASTODON_INSTANCE_VERSION_BEHIND_PATCHES, MASTODON_INSTANCE_VERSION_CHANGES_TOTAL,
+        MASTODON_INSTANCE_VERSION_CHANGE_INFO, MASTODON_INSTANCE_VERSION_INFO,
+        MASTODON_INSTANCE_VERSION_MAJOR, MASTODON_INSTANCE_VERSION_MINOR,
+        MASTODON_INSTANCE_VERSION_OUTDATED, MASTODON_REGISTRATIONS_APPROVAL_REQUIRED,
+        MASTODON_REGISTRATIONS_ENABLED, MASTODON_REGISTRATIONS_MESSAGE_SET,
+    },
+    targets,
+    version::{Software, Version},
 };
 
-pub async fn collect_instance(instance: &str) -> Result<(), reqwest::Error> {
-    let url = format!("https://{}/api/v2/instance", instance);
+lazy_static! {
+    /// The last seen version of instances and since when it is seen, keyed by instance.
+    static ref VERSIONS: Mutex<HashMap<String, SeenVersion>> = Mutex::new(HashMap::new());
 
-    println!("Collecting instance {}", instance);
+    /// The detected software of instances, keyed by instance.
+    static ref SOFTWARE: Mutex<HashMap<String, DetectedSoftware>> = Mutex::new(HashMap::new());
 
-    let response = reqwest::get(url).await?;
+    /// The hashes of the last seen branding fields of instances, keyed by instance.
+    static ref BRANDINGS: Mutex<HashMap<String, [u64; 3]>> = Mutex::new(HashMap::new());
+}
 
-    // Collect x-ratelimit-remaining from header
-    let ratelimit_remaining: i64 = response
-        .headers()
-        .get("x-ratelimit-remaining")
-        .unwrap()
-        .to_str()
-        .unwrap()
-        .parse()
-        .unwrap();
-    println!("{}: Ratelimit remaining: {}", instance, ratelimit_remaining);
-    MASTODON_RATELIMIT_REMAINING
-        .with_label_values(&[instance])
-        .set(ratelimit_remaining);
-
-    // Collect x-ratelimit-reset from header
-    let ratelimit_reset: i64 = response
-        .headers()
-        .get("x-ratelimit-reset")
-        .unwrap()
-        .to_str()
+/// The public-facing branding fields of instances, in the order of their hashes.
+const BRANDING_FIELDS: [&str; 3] = ["title", "description", "thumbnail"];
+
+/// The software of an instance detected for a version.
+struct DetectedSoftware {
+    version: String,
+    software: Software,
+    /// Whether or not the detection is final, otherwise it's retried on the next collection.
+    complete: bool,
+}
+
+/// A version of an instance seen by the exporter.
+struct SeenVersion {
+    version: String,
+    since: Instant,
+    /// The version before the last change, if the version changed since the exporter started.
+    previous: Option<String>,
+}
+
+/// Detects the server software of an instance, which is only repeated when its version changes.
+async fn collect_software(client: &MastodonClient, instance: &str, version: &str) -> Software {
+    let detected = SOFTWARE
+        .lock()
         .unwrap()
-        .parse::<chrono::DateTime<chrono::Utc>>()
+        .get(instance)
+        .filter(|detected| detected.version == version && detected.complete)
+        .map(|detected| detected.software.clone());
+    if let Some(software) = detected {
+        return software;
+    }
+
+    let nodeinfo = match client.nodeinfo().await {
+        Ok(nodeinfo) => Ok(nodeinfo.map(|nodeinfo| nodeinfo.software.name)),
+        Err(err) => {
+            log!("Error: {} nodeinfo {}", instance, err);
+            Err(err)
+        }
+    };
+    let software = Software::detect(
+        version,
+        nodeinfo.as_ref().ok().and_then(|name| name.as_deref()),
+    );
+    log!(
+        "{}: Software: {}, flavour: {}",
+        instance,
+        software.name,
+        software.flavour
+    );
+
+    let previous = SOFTWARE.lock().unwrap().insert(
+        instance.to_string(),
+        DetectedSoftware {
+            version: version.to_string(),
+            software: software.clone(),
+            // Instances without nodeinfo respond with a client error, which is not retried
+            complete: match &nodeinfo {
+                Ok(_) => true,
+                Err(err) => err.status().is_some_and(|status| status.is_client_error()),
+            },
+        },
+    );
+    if let Some(previous) = previous.filter(|previous| previous.software != software) {
+        MASTODON_INSTANCE_SOFTWARE_INFO
+            .remove_label_values(&[
+                instance,
+                &previous.software.name,
+                &previous.software.flavour,
+            ])
+            .ok();
+    }
+    if let Some(gauge) = MASTODON_INSTANCE_SOFTWARE_INFO.with_limited_label_values(&[
+        instance,
+        &software.name,
+        &software.flavour,
+    ]) {
+        gauge.set(1);
+    }
+
+    software
+}
+
+/// Tracks changes of the version of an instance, e.g. upgrades and rollbacks.
+fn collect_version_change(instance: &str, version: &str) {
+    let mut versions = VERSIONS.lock().unwrap();
+    let seen = versions
+        .entry(instance.to_string())
+        .or_insert_with(|| SeenVersion {
+            version: version.to_string(),
+            since: Instant::now(),
+            previous: None,
+        });
+
+    if seen.version != version {
+        log!(
+            "{}: Version changed from {:?} to {:?}",
+            instance,
+            seen.version,
+            version
+        );
+        if let Some(previous) = &seen.previous {
+            MASTODON_INSTANCE_VERSION_CHANGE_INFO
+                .remove_label_values(&[instance, previous, &seen.version])
+                .ok();
+        }
+        if let Some(gauge) = MASTODON_INSTANCE_VERSION_CHANGE_INFO.with_limited_label_values(&[
+            instance,
+            &seen.version,
+            version,
+        ]) {
+            gauge.set(1);
+        }
+        if let Some(counter) =
+            MASTODON_INSTANCE_VERSION_CHANGES_TOTAL.with_limited_label_values(&[instance])
+        {
+            counter.inc();
+        }
+
+        // Drop the structured version info of the old version
+        if let Some(old) = Version::parse(&seen.version) {
+            MASTODON_INSTANCE_VERSION_INFO
+                .remove_label_values(&[
+                    instance,
+                    &seen.version,
+                    &old.major.to_string(),
+                    &old.minor.to_string(),
+                    &old.patch.to_string(),
+                    &old.flavour,
+                ])
+                .ok();
+        }
+
+        *seen = SeenVersion {
+            version: version.to_string(),
+            since: Instant::now(),
+            previous: Some(seen.version.clone()),
+        };
+    } else {
+        if let Some(counter) =
+            MASTODON_INSTANCE_VERSION_CHANGES_TOTAL.with_limited_label_values(&[instance])
+        {
+            counter.inc_by(0);
+        }
+    }
+
+    if let Some(gauge) =
+        MASTODON_INSTANCE_SECONDS_SINCE_VERSION_CHANGE.with_limited_label_values(&[instance])
+    {
+        gauge.set(seen.since.elapsed().as_secs() as i64);
+    }
+}
+
+/// Returns the value of a label of the instance info metric.
+fn info_label_value(body: &InstanceResponse, label: InfoLabel) -> &str {
+    match label {
+        InfoLabel::Domain => &body.domain,
+        InfoLabel::Title => &body.title,
+        InfoLabel::Version => &body.version,
+        InfoLabel::SourceUrl => &body.source_url,
+        InfoLabel::Description => &body.description,
+    }
+}
+
+fn hash(value: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Tracks changes of the title, description and thumbnail of an instance, which may hint at a
+/// compromise or a misdeploy.
+///
+/// Mastodon stores every uploaded thumbnail under a new URL, so only the URL of the thumbnail is
+/// compared instead of downloading it.
+fn collect_branding_change(instance: &str, body: &InstanceResponse) {
+    let thumbnail = body
+        .thumbnail
+        .as_ref()
+        .map_or("", |thumbnail| thumbnail.url.as_str());
+    let hashes = [hash(&body.title), hash(&body.description), hash(thumbnail)];
+
+    let previous = BRANDINGS
+        .lock()
         .unwrap()
-        .timestamp();
-    println!("{}: Ratelimit reset: {}", instance, ratelimit_reset);
-    MASTODON_RATELIMIT_RESET
-        .with_label_values(&[instance])
-        .set(ratelimit_reset);
-
-    if let Err(err) = &response.error_for_status_ref() {
-        println!("Error: {} {}", instance, err);
-        return Ok(());
+        .insert(instance.to_string(), hashes);
+    let changed: Vec<&str> = previous
+        .iter()
+        .flat_map(|previous| {
+            BRANDING_FIELDS
+                .iter()
+                .zip(previous.iter().zip(&hashes))
+                .filter(|(_, (previous, current))| previous != current)
+                .map(|(field, _)| *field)
+        })
+        .collect();
+
+    if changed.is_empty() {
+        if let Some(counter) =
+            MASTODON_INSTANCE_BRANDING_CHANGES_TOTAL.with_limited_label_values(&[instance])
+        {
+            counter.inc_by(0);
+        }
+    } else {
+        log!(
+            "Warning: {}: Branding changed: {}",
+            instance,
+            changed.join(", ")
+        );
+        if let Some(counter) =
+            MASTODON_INSTANCE_BRANDING_CHANGES_TOTAL.with_limited_label_values(&[instance])
+        {
+            counter.inc();
+        }
     }
+}
+
+#[tracing::instrument(skip(releases, info_labels))]
+pub async fn collect_instance(
+    instance: &str,
+    releases: Option<Arc<Vec<Version>>>,
+    info_labels: &[InfoLabel],
+) -> Result<(), mastodon::Error> {
+    log!("Collecting instance {}", instance);
 
     // Collect response body data
-    let body = response.json::<mastodon::InstanceResponse>().await.unwrap();
+    let start = Instant::now();
+    let client = MastodonClient::new(instance);
+    let body = match client.instance().await {
+        Ok(body) => body,
+        Err(err) => {
+            log!("Error: {} {}", instance, err);
+            targets::record(instance, None, start, Err(err.to_string()));
+            return Ok(());
+        }
+    };
+    targets::record(instance, None, start, Ok(()));
 
     // Collect instance info
-    let info_labels = [instance, &body.domain, &body.title, &body.version];
-    println!("Instance info: {:?}", info_labels);
-    MASTODON_INFO.with_label_values(&info_labels).set(1);
+    let info_labels: Vec<&str> = [instance]
+        .into_iter()
+        .chain(
+            info_labels
+                .iter()
+                .map(|&label| info_label_value(&body, label)),
+        )
+        .collect();
+    log!("Instance info: {:?}", info_labels);
+    if let Some(info) = metrics::info() {
+        if let Some(gauge) = info.with_limited_label_values(&info_labels) {
+            gauge.set(1);
+        }
+    }
+    collect_version_change(instance, &body.version);
+    collect_branding_change(instance, &body);
+    let software = collect_software(&client, instance, &body.version).await;
+
+    // Collect structured version info
+    match Version::parse(&body.version) {
+        Some(version) => {
+            let version_labels = [
+                instance,
+                &body.version,
+                &version.major.to_string(),
+                &version.minor.to_string(),
+                &version.patch.to_string(),
+                &version.flavour,
+            ];
+            log!("Instance version info: {:?}", version_labels);
+            if let Some(gauge) =
+                MASTODON_INSTANCE_VERSION_INFO.with_limited_label_values(&version_labels)
+            {
+                gauge.set(1);
+            }
+            if let Some(gauge) =
+                MASTODON_INSTANCE_VERSION_MAJOR.with_limited_label_values(&[instance])
+            {
+                gauge.set(version.major as i64);
+            }
+            if let Some(gauge) =
+                MASTODON_INSTANCE_VERSION_MINOR.with_limited_label_values(&[instance])
+            {
+                gauge.set(version.minor as i64);
+            }
+
+            // Compare against upstream releases if available, which only apply to Mastodon and its
+            // forks, while compatible servers report the version of the API they implement
+            if let Some(releases) = releases.filter(|_| software.is_mastodon()) {
+                let outdated = i64::from(version.is_outdated(&releases));
+                let behind_patches = version.behind_patches(&releases) as i64;
+                log!(
+                    "{}: Version outdated: {}, behind patches: {}",
+                    instance,
+                    outdated,
+                    behind_patches
+                );
+                if let Some(gauge) =
+                    MASTODON_INSTANCE_VERSION_OUTDATED.with_limited_label_values(&[instance])
+                {
+                    gauge.set(outdated);
+                }
+                if let Some(gauge) =
+                    MASTODON_INSTANCE_VERSION_BEHIND_PATCHES.with_limited_label_values(&[instance])
+                {
+                    gauge.set(behind_patches);
+                }
+            }
+        }
+        None => log!("{}: Unable to parse version {:?}", instance, body.version),
+    }
 
     // Collect registrations_enabled value
     let registrations_enabled = i64::from(body.registrations.enabled);
-    println!(
+    log!(
         "{}: Registrations enabled: {:?}",
-        instance, registrations_enabled
+        instance,
+        registrations_enabled
     );
-    MASTODON_REGISTRATIONS_ENABLED
-        .with_label_values(&[instance])
-        .set(registrations_enabled);
+    if let Some(gauge) = MASTODON_REGISTRATIONS_ENABLED.with_limited_label_values(&[instance]) {
+        gauge.set(registrations_enabled);
+    }
 
     // Collect registrations_approval_required value
     let registrations_approval_required = i64::from(body.registrations.approval_required);
-    println!(
+    log!(
         "{}: Registrations approval required: {:?}",
-        instance, registrations_approval_required
+        instance,
+        registrations_approval_required
     );
-    MASTODON_REGISTRATIONS_APPROVAL_REQUIRED
-        .with_label_values(&[instance])
-        .set(registrations_approval_required);
+    if let Some(gauge) =
+        MASTODON_REGISTRATIONS_APPROVAL_REQUIRED.with_limited_label_values(&[instance])
+    {
+        gauge.set(registrations_approval_required);
+    }
+
+    // Collect the capabilities of the instance, which are unset on older and compatible servers
+    let registrations_message_set = i64::from(
+        body.registrations
+            .message
+            .as_deref()
+            .is_some_and(|message| !message.trim().is_empty()),
+    );
+    let translation_enabled = i64::from(body.configuration.translation.enabled);
+    let streaming_available = i64::from(
+        body.configuration
+            .urls
+            .streaming
+            .as_deref()
+            .is_some_and(|url| !url.is_empty()),
+    );
+    log!(
+        "{}: Registrations message set: {:?}, translation enabled: {:?}, streaming available: {:?}",
+        instance,
+        registrations_message_set,
+        translation_enabled,
+        streaming_available
+    );
+    if let Some(gauge) = MASTODON_REGISTRATIONS_MESSAGE_SET.with_limited_label_values(&[instance]) {
+        gauge.set(registrations_message_set);
+    }
+    if let Some(gauge) =
+        MASTODON_INSTANCE_TRANSLATION_ENABLED.with_limited_label_values(&[instance])
+    {
+        gauge.set(translation_enabled);
+    }
+    if let Some(gauge) =
+        MASTODON_INSTANCE_STREAMING_AVAILABLE.with_limited_label_values(&[instance])
+    {
+        gauge.set(streaming_available);
+    }
 
     Ok(())
 }
 
-pub async fn collect_instances(instances: Vec<String>) -> Result<(), tokio::task::JoinError> {
+pub async fn collect_instances(
+    instances: Vec<String>,
+    releases: Option<Arc<Vec<Version>>>,
+    info_labels: Vec<InfoLabel>,
+) -> Result<(), tokio::task::JoinError> {
     let mut handles = Vec::new();
 
     for instance in instances {
-        let handle = tokio::spawn(async move { collect_instance(instance.as_str()).await });
-        handles.push(handle);
+        let target = instance.clone();
+        let releases = releases.clone();
+        let info_labels = info_labels.clone();
+        let handle = tokio::spawn(
+            async move { collect_instance(instance.as_str(), releases, &info_labels).await }
+                .in_current_span(),
+        );
+        handles.push((target, handle));
     }
 
-    for handle in handles {
-        handle.await.ok();
-    }
+    super::join(CollectorName::InstanceInfo, handles).await;
 
     Ok(())
 }
@@ -0,0 +1,102 @@
+use std::time::Instant;
+
+use tracing::Instrument;
+
+use crate::{
+    config::{CollectorName, MediaProbeConfig},
+    mastodon::{self, MastodonClient},
+    metrics::{
+        WithLimitedLabelValues, MASTODON_MEDIA_FETCH_DURATION_SECONDS, MASTODON_MEDIA_FETCH_SUCCESS,
+    },
+};
+
+/// Looks up the URL of the thumbnail of the instance.
+async fn thumbnail_url(instance: &str) -> Result<Option<String>, mastodon::Error> {
+    let body = MastodonClient::new(instance).instance().await?;
+
+    Ok(body.thumbnail.map(|thumbnail| thumbnail.url))
+}
+
+/// Fetches the media file, failing on error statuses.
+async fn fetch_media(instance: &str, url: &str) -> Result<(), mastodon::Error> {
+    let start = Instant::now();
+
+    let client = MastodonClient::new(instance);
+    let response = client.send(client.get_url(url)).await?;
+
+    // Read the whole body, as storage failures may only show up while transferring
+    let bytes = client.read_body(response).await?;
+
+    let duration = start.elapsed().as_secs_f64();
+    log!(
+        "{}: Media fetch duration: {} ({} bytes)",
+        instance,
+        duration,
+        bytes.len()
+    );
+    if let Some(gauge) =
+        MASTODON_MEDIA_FETCH_DURATION_SECONDS.with_limited_label_values(&[instance])
+    {
+        gauge.set(duration);
+    }
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all, fields(instance = %probe.instance))]
+pub async fn collect_media(probe: &MediaProbeConfig) -> Result<(), mastodon::Error> {
+    let instance = probe.instance.as_str();
+
+    log!("Probing media {}", instance);
+
+    let url = match &probe.url {
+        Some(url) => Some(url.clone()),
+        None => match thumbnail_url(instance).await {
+            Ok(url) => url,
+            Err(err) => {
+                // Without a thumbnail no media can be fetched, which is a failure of the probe
+                log!("Error: {} media thumbnail {}", instance, err);
+                if let Some(gauge) =
+                    MASTODON_MEDIA_FETCH_SUCCESS.with_limited_label_values(&[instance])
+                {
+                    gauge.set(0);
+                }
+                return Ok(());
+            }
+        },
+    };
+
+    let Some(url) = url else {
+        log!("{}: Instance has no thumbnail to probe", instance);
+        return Ok(());
+    };
+
+    let success = match fetch_media(instance, &url).await {
+        Ok(()) => true,
+        Err(err) => {
+            log!("Error: {} media {}", instance, err);
+            false
+        }
+    };
+    if let Some(gauge) = MASTODON_MEDIA_FETCH_SUCCESS.with_limited_label_values(&[instance]) {
+        gauge.set(i64::from(success));
+    }
+
+    Ok(())
+}
+
+pub async fn collect_media_probes(
+    probes: Vec<MediaProbeConfig>,
+) -> Result<(), tokio::task::JoinError> {
+    let mut handles = Vec::new();
+
+    for probe in probes {
+        let target = probe.instance.clone();
+        let handle = tokio::spawn(async move { collect_media(&probe).await }.in_current_span());
+        handles.push((target, handle));
+    }
+
+    super::join(CollectorName::MediaProbes, handles).await;
+
+    Ok(())
+}
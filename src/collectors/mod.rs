@@ -1,2 +1,587 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use futures::future::{BoxFuture, FutureExt};
+use serde::Serialize;
+use tokio::{sync::broadcast, task::JoinHandle};
+
+use crate::{
+    alerting, canary,
+    config::{AccountConfig, CollectorName, Config, HistoryConfig, MetricGroupConfig},
+    derived::{self, Labels},
+    federation,
+    history::History,
+    mastodon,
+    metrics::{
+        self, WithLimitedLabelValues, MASTODON_COLLECTOR_PANICS_TOTAL, MASTODON_TARGET_DISABLED,
+    },
+    releases,
+    schedule::Schedule,
+    streaming, targets, tokens, tracker,
+};
+
 pub mod account;
+pub mod health;
 pub mod instance;
+pub mod media;
+pub mod peers;
+pub mod poll;
+pub mod resolve;
+pub mod search;
+pub mod tag_measures;
+pub mod timeline;
+pub mod well_known;
+
+/// The number of collection events kept for subscribers which are behind.
+const EVENTS_CAPACITY: usize = 16;
+
+/// The value of a metric with the given labels.
+#[derive(Serialize)]
+pub struct Sample {
+    pub metric: String,
+    pub labels: Labels,
+    pub value: f64,
+}
+
+/// The values of all gauges and counters after a collection cycle completed.
+#[derive(Serialize)]
+pub struct CollectionEvent {
+    /// The number of seconds since 1970 the collection cycle completed at.
+    pub timestamp: i64,
+    pub samples: Vec<Sample>,
+}
+
+/// Waits for the collection tasks of a collector, each given with the instance it collects.
+///
+/// A panicking task, e.g. on an unexpected response, is counted and only loses the metrics of its
+/// target, while the other tasks complete.
+async fn join<T>(collector: CollectorName, handles: Vec<(String, JoinHandle<T>)>) {
+    for (instance, handle) in handles {
+        let Err(err) = handle.await else {
+            continue;
+        };
+        if !err.is_panic() {
+            continue;
+        }
+
+        let panic = err.into_panic();
+        let message = panic
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown panic");
+        log!(
+            "Error: {} {} collector panicked: {}",
+            instance,
+            collector.name(),
+            message
+        );
+        if let Some(counter) = MASTODON_COLLECTOR_PANICS_TOTAL
+            .with_limited_label_values(&[collector.name(), &instance])
+        {
+            counter.inc();
+        }
+    }
+}
+
+/// Sets up the metrics which depend on the configuration, at startup and after each reload.
+///
+/// The instance info and account labels metrics are registered with the label names of the
+/// configuration, the targets are listed before their first scrape and the disabled targets are
+/// marked.
+fn configure_metrics(config: &Config) {
+    let info_label_names: Vec<&str> = config
+        .instance_info_labels
+        .iter()
+        .map(|label| label.name())
+        .collect();
+    metrics::register_info(&info_label_names);
+    metrics::set_series_limit(config.max_series_per_metric);
+
+    // The account labels metric is only exported if any account has additional labels
+    let mut label_names: Vec<&str> = config
+        .accounts
+        .iter()
+        .flat_map(|account| account.labels.keys().map(String::as_str))
+        .collect();
+    if config
+        .accounts
+        .iter()
+        .any(|account| account.alias.is_some())
+    {
+        label_names.push("alias");
+    }
+    label_names.sort_unstable();
+    label_names.dedup();
+    metrics::register_account_labels(&label_names);
+
+    // List the targets before their first scrape
+    for instance in config.enabled_instances() {
+        targets::add(&instance, None);
+    }
+    for account in config.enabled_accounts() {
+        targets::add(&account.instance, Some(account.key()));
+    }
+
+    // Mark parked targets, which are never collected
+    MASTODON_TARGET_DISABLED.reset();
+    for instance in config
+        .instance_info
+        .iter()
+        .filter(|instance| !instance.enabled)
+    {
+        if let Some(gauge) =
+            MASTODON_TARGET_DISABLED.with_limited_label_values(&[&instance.instance, ""])
+        {
+            gauge.set(1);
+        }
+    }
+    for account in config.accounts.iter().filter(|account| !account.enabled) {
+        if let Some(gauge) =
+            MASTODON_TARGET_DISABLED.with_limited_label_values(&[&account.instance, account.key()])
+        {
+            gauge.set(1);
+        }
+    }
+}
+
+/// A collector in the collection cycles of a metric group, or of all targets if not set.
+type RunKey = (CollectorName, Option<String>);
+
+/// A running background task with its key.
+type BackgroundTask = (String, JoinHandle<()>);
+
+/// Returns the key of a background task, which changes with the configuration it runs with,
+/// including its resolved access token.
+fn task_key(task: &str, config: &impl Serialize) -> String {
+    format!(
+        "{} {}",
+        task,
+        serde_yaml::to_string(config).unwrap_or_default()
+    )
+}
+
+/// Returns the configured tasks which run independently of collection cycles, by their key.
+fn background_tasks(config: &Config) -> Vec<(String, BoxFuture<'static, ()>)> {
+    let mut tasks = Vec::new();
+
+    // Status engagement tracker if configured
+    if let Some(status_tracker) = config.status_tracker.clone() {
+        let key = task_key("tracker", &status_tracker);
+        tasks.push((key, tracker::run(status_tracker).boxed()));
+    }
+
+    // Canary posts if configured
+    for canary in config.canaries.clone() {
+        let key = task_key("canary", &canary);
+        tasks.push((key, canary::run(canary).boxed()));
+    }
+
+    // Federation delivery probes if configured
+    for federation_probe in config.federation_probes.clone() {
+        let key = task_key("federation", &federation_probe);
+        tasks.push((key, federation::run(federation_probe).boxed()));
+    }
+
+    // Checks of the access tokens if any are configured
+    let token_checks = tokens::checks(config);
+    if !token_checks.is_empty() {
+        let key = task_key("tokens", &token_checks);
+        tasks.push((key, tokens::run(token_checks).boxed()));
+    }
+
+    // Streaming API connections if configured
+    for streaming in config.streaming.clone() {
+        let key = task_key("streaming", &streaming);
+        tasks.push((key, streaming::run(streaming).boxed()));
+    }
+
+    tasks
+}
+
+/// Collects the metrics of all targets of a configuration.
+pub struct Collector {
+    config: RwLock<Arc<Config>>,
+    history: Option<History>,
+    events: broadcast::Sender<Arc<CollectionEvent>>,
+    /// The last run of scheduled collectors, by collector and metric group.
+    last_runs: Mutex<HashMap<RunKey, DateTime<Utc>>>,
+    /// Whether or not a collection cycle over all targets completed.
+    collected: AtomicBool,
+    /// The interval of collection cycles run in the background, if any.
+    cycle_interval: Mutex<Option<Duration>>,
+    /// When the last collection cycle over all targets completed, or the background cycles
+    /// started.
+    last_cycle: Mutex<Instant>,
+    /// The running background tasks by their key, once they were started.
+    background_tasks: Mutex<Option<Vec<BackgroundTask>>>,
+}
+
+impl Collector {
+    /// Creates a collector for the given configuration and registers all metrics.
+    pub fn new(config: Config) -> Self {
+        metrics::register();
+        configure_metrics(&config);
+
+        // The dashboard needs a history, which is kept in memory if not configured
+        let history_config = config.history.clone().or_else(|| {
+            config.server.ui.then(|| HistoryConfig {
+                path: ":memory:".to_string(),
+                retention_days: 1,
+            })
+        });
+        let history = history_config
+            .as_ref()
+            .and_then(|history| match History::open(history) {
+                Ok(history) => Some(history),
+                Err(err) => {
+                    log!("Error: Unable to open history {}: {}", history.path, err);
+                    None
+                }
+            });
+
+        Self {
+            config: RwLock::new(Arc::new(config)),
+            history,
+            events: broadcast::channel(EVENTS_CAPACITY).0,
+            last_runs: Mutex::new(HashMap::new()),
+            collected: AtomicBool::new(false),
+            cycle_interval: Mutex::new(None),
+            last_cycle: Mutex::new(Instant::now()),
+            background_tasks: Mutex::new(None),
+        }
+    }
+
+    /// Returns the configuration of the collector.
+    pub fn config(&self) -> Arc<Config> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Replaces the configuration with a reloaded one, e.g. after the mounted target files
+    /// changed.
+    ///
+    /// The targets and their access tokens are picked up by the next collection cycle, while the
+    /// targets which are no longer collected are removed along with their series. Background
+    /// tasks which were added, removed or changed, e.g. by a rotated access token, are restarted.
+    /// Other settings, like the ports of the server or the connections to instances, need a
+    /// restart.
+    pub fn reload(&self, config: Config) {
+        let previous = self.config();
+
+        let accounts = config.enabled_accounts();
+        let is_kept = |removed: &AccountConfig| {
+            accounts.iter().any(|account| {
+                account.instance == removed.instance && account.key() == removed.key()
+            })
+        };
+        for account in previous.enabled_accounts() {
+            if !is_kept(&account) {
+                log!(
+                    "{}: No longer collecting account {}",
+                    account.instance,
+                    account.key()
+                );
+                targets::remove(&account.instance, Some(account.key()));
+                account::remove_account(&account);
+            }
+        }
+
+        let polls = poll::polls(&config);
+        for removed in poll::polls(&previous) {
+            if !polls
+                .iter()
+                .any(|poll| poll.instance == removed.instance && poll.id == removed.id)
+            {
+                log!(
+                    "{}: No longer collecting poll {}",
+                    removed.instance,
+                    removed.id
+                );
+                poll::remove_poll(&removed);
+            }
+        }
+
+        // The series of accounts on a removed instance are kept if the accounts are
+        let instances = config.enabled_instances();
+        for instance in previous.enabled_instances() {
+            if !instances.contains(&instance) {
+                log!("{}: No longer collecting the instance", instance);
+                targets::remove(&instance, None);
+                metrics::remove_series(|labels| {
+                    metrics::has_label(labels, "instance", &instance)
+                        && !labels.iter().any(|label| label.get_name() == "account_id")
+                });
+            }
+        }
+
+        mastodon::set_timeouts(config.instance_timeouts());
+        configure_metrics(&config);
+        account::set_account_labels(&accounts);
+        self.restart_background_tasks(&config);
+
+        *self.config.write().unwrap() = Arc::new(config);
+    }
+
+    /// Starts the background tasks of the configuration which aren't running yet, and stops the
+    /// running ones which it no longer has, if the background tasks were started.
+    fn restart_background_tasks(&self, config: &Config) {
+        let mut running = self.background_tasks.lock().unwrap();
+        let Some(running) = running.as_mut() else {
+            return;
+        };
+
+        let mut started = 0;
+        let mut tasks = Vec::new();
+        for (key, task) in background_tasks(config) {
+            let handle = match running.iter().position(|(running, _)| *running == key) {
+                Some(index) => running.swap_remove(index).1,
+                None => {
+                    started += 1;
+                    tokio::spawn(task)
+                }
+            };
+            tasks.push((key, handle));
+        }
+
+        // The remaining tasks were removed or changed
+        for (_, handle) in running.iter() {
+            handle.abort();
+        }
+        if started > 0 || !running.is_empty() {
+            log!(
+                "Started {} and stopped {} background tasks",
+                started,
+                running.len()
+            );
+        }
+        *running = tasks;
+    }
+
+    /// Returns the history of collected values if enabled.
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// Returns whether a collection cycle over all targets completed.
+    pub fn has_collected(&self) -> bool {
+        self.collected.load(Ordering::Relaxed)
+    }
+
+    /// Marks that collection cycles run in the background every interval, e.g. for the
+    /// textfile output, so missed cycles make the exporter stale.
+    pub fn schedule_cycles(&self, interval: Duration) {
+        *self.cycle_interval.lock().unwrap() = Some(interval);
+        *self.last_cycle.lock().unwrap() = Instant::now();
+    }
+
+    /// Returns whether no background collection cycle completed within the given number of
+    /// intervals, e.g. because a cycle hangs. Cycles collected by scrapes are never stale.
+    pub fn is_stale(&self, stale_after_cycles: u32) -> bool {
+        let Some(interval) = *self.cycle_interval.lock().unwrap() else {
+            return false;
+        };
+
+        self.last_cycle.lock().unwrap().elapsed() > interval * stale_after_cycles
+    }
+
+    /// Subscribes to the values of every completed collection cycle.
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<CollectionEvent>> {
+        self.events.subscribe()
+    }
+
+    /// Starts the configured tasks which run independently of collection cycles.
+    ///
+    /// This must be called from within a Tokio runtime.
+    pub fn spawn_background_tasks(&self) {
+        let tasks = background_tasks(&self.config())
+            .into_iter()
+            .map(|(key, task)| (key, tokio::spawn(task)))
+            .collect();
+        *self.background_tasks.lock().unwrap() = Some(tasks);
+    }
+
+    /// Returns whether the collector runs in the collection cycle of the metric group, or of all
+    /// targets if not set.
+    ///
+    /// Scheduled collectors run in the first cycle, and then in the first cycle after each time
+    /// matching their schedule. Collectors of a metric class with a refresh interval run in the
+    /// first cycle, and then in the first cycle after the interval elapsed. Other collectors run
+    /// in every cycle.
+    fn is_due(&self, collector: CollectorName, group: Option<&MetricGroupConfig>) -> bool {
+        let config = self.config();
+        let schedule = config.schedules.get(&collector);
+        let interval_seconds = config
+            .refresh_intervals
+            .get(&collector.class())
+            .copied()
+            .filter(|&interval_seconds| interval_seconds > 0);
+        if schedule.is_none() && interval_seconds.is_none() {
+            return true;
+        }
+
+        let now = Utc::now();
+        let key = (collector, group.map(|group| group.name.clone()));
+        let mut last_runs = self.last_runs.lock().unwrap();
+        let due = match (last_runs.get(&key), schedule) {
+            (None, _) => true,
+            // The schedule was validated when loading the configuration
+            (Some(&last_run), Some(expression)) => {
+                Schedule::parse(expression).map_or(true, |schedule| schedule.is_due(last_run, now))
+            }
+            (Some(&last_run), None) => {
+                (now - last_run).num_seconds() >= interval_seconds.unwrap_or_default() as i64
+            }
+        };
+        if due {
+            last_runs.insert(key, now);
+        } else {
+            log!("Skipping {:?} until it's due", collector);
+        }
+
+        due
+    }
+
+    /// Collects the targets of the instances of the metric group, or of all instances if not set.
+    async fn collect_targets(&self, group: Option<&MetricGroupConfig>) {
+        let config = &self.config();
+        let instances = group.map(|group| &group.instances);
+        let included = |instance: &str| {
+            instances.is_none_or(|instances| instances.iter().any(|i| i == instance))
+        };
+        let due = |collector| self.is_due(collector, group);
+
+        let collect_instances = due(CollectorName::InstanceInfo);
+        let releases = match &config.upstream_releases {
+            Some(upstream_releases) if collect_instances => {
+                releases::upstream_releases(upstream_releases).await
+            }
+            _ => None,
+        };
+
+        let mut enabled_instances = config.enabled_instances();
+        enabled_instances.retain(|instance| included(instance));
+        let mut enabled_accounts = config.enabled_accounts();
+        enabled_accounts.retain(|account| included(&account.instance));
+        let mut polls = config.polls.clone();
+        polls.retain(|poll| included(&poll.instance));
+        let mut tracked_statuses: Vec<(String, String)> = config
+            .status_tracker
+            .iter()
+            .filter(|status_tracker| status_tracker.polls)
+            .flat_map(|status_tracker| status_tracker.statuses.clone())
+            .collect();
+        tracked_statuses.retain(|(instance, _)| included(instance));
+        let mut posting_rate = config.posting_rate.clone();
+        posting_rate.retain(|instance| included(instance));
+        let mut peers = config.peers.clone();
+        peers.retain(|instance| included(instance));
+        let mut search_probes = config.search_probes.clone();
+        search_probes.retain(|probe| included(&probe.instance));
+        let mut resolve_probes = config.resolve_probes.clone();
+        resolve_probes.retain(|probe| included(&probe.instance));
+        let mut tag_measures = config.tag_measures.clone();
+        tag_measures.retain(|measures| included(&measures.instance));
+        let mut media_probes = config.media_probes.clone();
+        media_probes.retain(|probe| included(&probe.instance));
+
+        if collect_instances {
+            instance::collect_instances(
+                enabled_instances.clone(),
+                releases,
+                config.instance_info_labels.clone(),
+            )
+            .await
+            .ok();
+        }
+        if due(CollectorName::Accounts) {
+            account::collect_accounts(enabled_accounts, account::AccountOptions::from(&**config))
+                .await
+                .ok();
+        }
+        if due(CollectorName::Polls) {
+            poll::collect_polls(polls, tracked_statuses).await.ok();
+        }
+        if due(CollectorName::PostingRate) {
+            timeline::collect_timelines(posting_rate).await.ok();
+        }
+        if due(CollectorName::Peers) {
+            peers::collect_peers(peers).await.ok();
+        }
+        if due(CollectorName::SearchProbes) {
+            search::collect_searches(search_probes).await.ok();
+        }
+        if due(CollectorName::ResolveProbes) {
+            resolve::collect_resolves(resolve_probes).await.ok();
+        }
+        if due(CollectorName::TagMeasures) {
+            tag_measures::collect_tag_measures(tag_measures).await.ok();
+        }
+        if due(CollectorName::MediaProbes) {
+            media::collect_media_probes(media_probes).await.ok();
+        }
+        if config.health_checks && due(CollectorName::HealthChecks) {
+            health::collect_healths(enabled_instances.clone())
+                .await
+                .ok();
+        }
+        if config.well_known_checks && due(CollectorName::WellKnownChecks) {
+            well_known::collect_well_knowns(enabled_instances)
+                .await
+                .ok();
+        }
+    }
+
+    /// Collects the targets of the instances of a metric group.
+    ///
+    /// Derived metrics, alerting and the history only follow the collection cycles of all targets.
+    #[tracing::instrument(name = "collect_group", skip_all, fields(group = %group.name))]
+    pub async fn collect_group(&self, group: &MetricGroupConfig) {
+        self.collect_targets(Some(group)).await;
+        metrics::enforce_series_limit();
+    }
+
+    /// Runs one collection cycle over all configured targets.
+    #[tracing::instrument(name = "collect", skip_all)]
+    pub async fn collect(&self) {
+        let config = &self.config();
+
+        self.collect_targets(None).await;
+
+        derived::evaluate(&config.derived_metrics);
+        metrics::enforce_series_limit();
+        if let Some(alerting) = &config.alerting {
+            alerting::evaluate(alerting);
+        }
+        if let Some(history) = &self.history {
+            if let Err(err) = history.record().await {
+                log!("Error: Unable to record history: {}", err);
+            }
+        }
+        self.collected.store(true, Ordering::Relaxed);
+        *self.last_cycle.lock().unwrap() = Instant::now();
+
+        if self.events.receiver_count() > 0 {
+            let mut samples = Vec::new();
+            for family in metrics::REGISTRY.gather() {
+                let mut family_samples: Vec<(Labels, f64)> =
+                    derived::samples(&family).into_iter().collect();
+                family_samples.sort_by(|a, b| a.0.cmp(&b.0));
+                samples.extend(family_samples.into_iter().map(|(labels, value)| Sample {
+                    metric: family.get_name().to_string(),
+                    labels,
+                    value,
+                }));
+            }
+
+            let event = CollectionEvent {
+                timestamp: chrono::Utc::now().timestamp(),
+                samples,
+            };
+            // Sending only fails if all subscribers disconnected in the meantime
+            self.events.send(Arc::new(event)).ok();
+        }
+    }
+}
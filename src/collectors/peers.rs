@@ -0,0 +1,92 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use tracing::Instrument;
+
+use crate::{
+    config::CollectorName,
+    discovery,
+    mastodon::{self, domain, MastodonClient},
+    metrics::{
+        WithLimitedLabelValues, MASTODON_FEDERATION_SHARED_PEERS, MASTODON_INSTANCE_PEERS_COUNT,
+    },
+};
+
+lazy_static! {
+    /// The hashes of the domains of the last collected peers of instances, keyed by instance.
+    ///
+    /// Only the hashes are kept, as the peers of large instances may be tens of thousands.
+    static ref PEERS: Mutex<HashMap<String, HashSet<u64>>> = Mutex::new(HashMap::new());
+}
+
+#[tracing::instrument]
+pub async fn collect_peer(instance: &str) -> Result<(), mastodon::Error> {
+    log!("Collecting peers of {}", instance);
+
+    let mut peers = HashSet::new();
+    if let Err(err) = MastodonClient::new(instance)
+        .for_each_peer(|peer| {
+            // Peers are reported in their ASCII form, but labeled in their Unicode form
+            peers.insert(discovery::hash(&domain::to_unicode(&peer)));
+        })
+        .await
+    {
+        log!("Error: {} peers {}", instance, err);
+        return Ok(());
+    }
+
+    log!("{}: Peers: {}", instance, peers.len());
+    if let Some(gauge) = MASTODON_INSTANCE_PEERS_COUNT.with_limited_label_values(&[instance]) {
+        gauge.set(peers.len() as i64);
+    }
+    PEERS.lock().unwrap().insert(instance.to_string(), peers);
+
+    Ok(())
+}
+
+/// Exports the number of peers shared by every pair of the instances, from their last collected
+/// peers.
+fn collect_shared_peers(instances: &[String]) {
+    let peers = PEERS.lock().unwrap();
+    let mut instances: Vec<&String> = instances
+        .iter()
+        .filter(|instance| peers.contains_key(*instance))
+        .collect();
+    instances.sort_unstable();
+    instances.dedup();
+
+    for (index, instance_a) in instances.iter().enumerate() {
+        for instance_b in &instances[index + 1..] {
+            let (peers_a, peers_b) = (&peers[*instance_a], &peers[*instance_b]);
+            let shared = peers_a.intersection(peers_b).count();
+            log!(
+                "{} and {}: Shared peers: {}",
+                instance_a,
+                instance_b,
+                shared
+            );
+            if let Some(gauge) = MASTODON_FEDERATION_SHARED_PEERS
+                .with_limited_label_values(&[instance_a, instance_b])
+            {
+                gauge.set(shared as i64);
+            }
+        }
+    }
+}
+
+pub async fn collect_peers(instances: Vec<String>) -> Result<(), tokio::task::JoinError> {
+    let mut handles = Vec::new();
+
+    for instance in instances.clone() {
+        let target = instance.clone();
+        let handle =
+            tokio::spawn(async move { collect_peer(instance.as_str()).await }.in_current_span());
+        handles.push((target, handle));
+    }
+
+    super::join(CollectorName::Peers, handles).await;
+
+    collect_shared_peers(&instances);
+
+    Ok(())
+}
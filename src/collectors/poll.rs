@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use tracing::Instrument;
+
+use crate::{
+    config::{CollectorName, Config, PollConfig},
+    mastodon::{self, MastodonClient},
+    metrics::{
+        self, WithLimitedLabelValues, MASTODON_POLL_EXPIRES_AT_SECONDS, MASTODON_POLL_VOTES_COUNT,
+        MASTODON_SCRAPE_PARSE_ERRORS_TOTAL,
+    },
+};
+
+lazy_static! {
+    /// The ids of the polls attached to tracked statuses, `None` for statuses without a poll,
+    /// keyed by instance and status id.
+    static ref STATUS_POLLS: Mutex<HashMap<(String, String), Option<String>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Returns the polls attached to the statuses, looking up each status only once, as a poll
+/// can't be attached to a status later.
+async fn discover_polls(statuses: &[(String, String)]) -> Vec<PollConfig> {
+    let mut polls = Vec::new();
+
+    for (instance, status_id) in statuses {
+        let key = (instance.clone(), status_id.clone());
+        let known = STATUS_POLLS.lock().unwrap().get(&key).cloned();
+        let poll_id = match known {
+            Some(poll_id) => poll_id,
+            None => match MastodonClient::new(instance).status(status_id).await {
+                Ok(status) => {
+                    let poll_id = status.poll.map(|poll| poll.id);
+                    if let Some(poll_id) = &poll_id {
+                        log!("{}: Status {} has poll {}", instance, status_id, poll_id);
+                    }
+                    STATUS_POLLS.lock().unwrap().insert(key, poll_id.clone());
+                    poll_id
+                }
+                Err(err) => {
+                    log!("Error: {} status {} {}", instance, status_id, err);
+                    continue;
+                }
+            },
+        };
+
+        if let Some(id) = poll_id {
+            polls.push(PollConfig {
+                instance: instance.clone(),
+                id,
+            });
+        }
+    }
+
+    polls
+}
+
+/// Returns the configured polls and the polls already discovered on the tracked statuses.
+pub(crate) fn polls(config: &Config) -> Vec<PollConfig> {
+    let status_polls = STATUS_POLLS.lock().unwrap();
+    let discovered = config
+        .status_tracker
+        .iter()
+        .filter(|status_tracker| status_tracker.polls)
+        .flat_map(|status_tracker| &status_tracker.statuses)
+        .filter_map(|(instance, status_id)| {
+            let poll_id = status_polls.get(&(instance.clone(), status_id.clone()))?;
+            Some(PollConfig {
+                instance: instance.clone(),
+                id: poll_id.clone()?,
+            })
+        });
+
+    config.polls.iter().cloned().chain(discovered).collect()
+}
+
+/// Removes the series of a poll which is no longer collected, e.g. after it was removed from
+/// the configuration.
+pub(crate) fn remove_poll(poll: &PollConfig) {
+    metrics::remove_series(|labels| {
+        metrics::has_label(labels, "instance", &poll.instance)
+            && metrics::has_label(labels, "poll_id", &poll.id)
+    });
+}
+
+#[tracing::instrument]
+pub async fn collect_poll(instance: &str, poll_id: &str) -> Result<(), mastodon::Error> {
+    log!("Collecting poll {}@{}", poll_id, instance);
+
+    // Collect response body data
+    let body = match MastodonClient::new(instance).poll(poll_id).await {
+        Ok(body) => body,
+        Err(err) if err.status() == Some(reqwest::StatusCode::NOT_FOUND) => {
+            log!("{}: Poll {} not found", instance, poll_id);
+            return Ok(());
+        }
+        Err(err) => {
+            log!("Error: {} {} {}", instance, poll_id, err);
+            return Ok(());
+        }
+    };
+
+    // Collect votes count per option
+    for option in &body.options {
+        // Results of hidden polls are not published until the poll has ended
+        if let Some(votes_count) = option.votes_count {
+            log!(
+                "{}: Poll {} option {:?}: Votes count: {}",
+                instance,
+                body.id,
+                option.title,
+                votes_count
+            );
+            if let Some(gauge) = MASTODON_POLL_VOTES_COUNT.with_limited_label_values(&[
+                instance,
+                poll_id,
+                &option.title,
+            ]) {
+                gauge.set(votes_count);
+            }
+        }
+    }
+
+    // Collect poll expires at
+    if let Some(expires_at) = body.expires_at {
+        let Ok(expires_at) = expires_at.parse::<chrono::DateTime<chrono::Utc>>() else {
+            log!(
+                "{}: Unable to parse poll expires at {:?}",
+                instance,
+                expires_at
+            );
+            if let Some(counter) = MASTODON_SCRAPE_PARSE_ERRORS_TOTAL
+                .with_limited_label_values(&[instance, "expires_at"])
+            {
+                counter.inc();
+            }
+            return Ok(());
+        };
+        let expires_at = expires_at.timestamp();
+
+        log!("{}: Poll {} expires at: {}", instance, body.id, expires_at);
+        if let Some(gauge) =
+            MASTODON_POLL_EXPIRES_AT_SECONDS.with_limited_label_values(&[instance, poll_id])
+        {
+            gauge.set(expires_at);
+        }
+    }
+
+    Ok(())
+}
+
+/// Collects the polls and the polls attached to the tracked statuses.
+pub async fn collect_polls(
+    mut polls: Vec<PollConfig>,
+    tracked_statuses: Vec<(String, String)>,
+) -> Result<(), tokio::task::JoinError> {
+    for poll in discover_polls(&tracked_statuses).await {
+        if !polls
+            .iter()
+            .any(|known| known.instance == poll.instance && known.id == poll.id)
+        {
+            polls.push(poll);
+        }
+    }
+
+    let mut handles = Vec::new();
+
+    for poll in polls {
+        let target = poll.instance.clone();
+        let handle = tokio::spawn(
+            async move { collect_poll(poll.instance.as_str(), poll.id.as_str()).await }
+                .in_current_span(),
+        );
+        handles.push((target, handle));
+    }
+
+    super::join(CollectorName::Polls, handles).await;
+
+    Ok(())
+}
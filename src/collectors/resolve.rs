@@ -0,0 +1,55 @@
+use tracing::Instrument;
+
+use crate::{
+    config::{CollectorName, ResolveProbeConfig},
+    mastodon::MastodonClient,
+    metrics::{WithLimitedLabelValues, MASTODON_FEDERATION_RESOLVABLE},
+};
+
+#[tracing::instrument(skip_all, fields(instance = %probe.instance))]
+pub async fn collect_resolve(probe: &ResolveProbeConfig) {
+    let instance = probe.instance.as_str();
+    let client = MastodonClient::new(instance)
+        .with_access_token(probe.access_token.get().unwrap_or_default());
+
+    for acct in &probe.accounts {
+        let acct = acct.trim_start_matches('@');
+        log!("Probing resolving {} from {}", acct, instance);
+
+        // Errors of the instance itself say nothing about federation, so they're only counted as
+        // scrape errors and the previous result is removed instead of exported as stale
+        let resolvable = match client.resolve_account(acct).await {
+            Ok(body) => !body.accounts.is_empty(),
+            Err(err) => {
+                log!("Error: {} resolve {} {}", instance, acct, err);
+                MASTODON_FEDERATION_RESOLVABLE
+                    .remove_label_values(&[instance, acct])
+                    .ok();
+                continue;
+            }
+        };
+
+        log!("{}: Resolvable {}: {}", instance, acct, resolvable);
+        if let Some(gauge) =
+            MASTODON_FEDERATION_RESOLVABLE.with_limited_label_values(&[instance, acct])
+        {
+            gauge.set(i64::from(resolvable));
+        }
+    }
+}
+
+pub async fn collect_resolves(
+    probes: Vec<ResolveProbeConfig>,
+) -> Result<(), tokio::task::JoinError> {
+    let mut handles = Vec::new();
+
+    for probe in probes {
+        let target = probe.instance.clone();
+        let handle = tokio::spawn(async move { collect_resolve(&probe).await }.in_current_span());
+        handles.push((target, handle));
+    }
+
+    super::join(CollectorName::ResolveProbes, handles).await;
+
+    Ok(())
+}
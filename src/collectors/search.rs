@@ -0,0 +1,78 @@
+use std::time::Instant;
+
+use tracing::Instrument;
+
+use crate::{
+    config::{CollectorName, SearchProbeConfig},
+    mastodon::{self, MastodonClient},
+    metrics::{
+        WithLimitedLabelValues, MASTODON_SEARCH_DURATION_SECONDS, MASTODON_SEARCH_RESULTS,
+        MASTODON_SEARCH_SUCCESS,
+    },
+};
+
+#[tracing::instrument(skip_all, fields(instance = %probe.instance))]
+pub async fn collect_search(probe: &SearchProbeConfig) -> Result<(), mastodon::Error> {
+    let instance = probe.instance.as_str();
+
+    log!("Probing search {} for {:?}", instance, probe.query);
+
+    let start = Instant::now();
+
+    // Collect response body data
+    let body = match MastodonClient::new(instance)
+        .with_access_token(probe.access_token.get().unwrap_or_default())
+        .search(&probe.query)
+        .await
+    {
+        Ok(body) => body,
+        Err(err) => {
+            log!("Error: {} search {}", instance, err);
+            if let Some(gauge) = MASTODON_SEARCH_SUCCESS.with_limited_label_values(&[instance]) {
+                gauge.set(0);
+            }
+            return Ok(());
+        }
+    };
+
+    let duration = start.elapsed().as_secs_f64();
+    log!("{}: Search duration: {}", instance, duration);
+    if let Some(gauge) = MASTODON_SEARCH_DURATION_SECONDS.with_limited_label_values(&[instance]) {
+        gauge.set(duration);
+    }
+    if let Some(gauge) = MASTODON_SEARCH_SUCCESS.with_limited_label_values(&[instance]) {
+        gauge.set(1);
+    }
+
+    // Collect number of results per type
+    for (result_type, count) in [
+        ("accounts", body.accounts.len()),
+        ("statuses", body.statuses.len()),
+        ("hashtags", body.hashtags.len()),
+    ] {
+        log!("{}: Search results {}: {}", instance, result_type, count);
+        if let Some(gauge) =
+            MASTODON_SEARCH_RESULTS.with_limited_label_values(&[instance, result_type])
+        {
+            gauge.set(count as i64);
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn collect_searches(
+    probes: Vec<SearchProbeConfig>,
+) -> Result<(), tokio::task::JoinError> {
+    let mut handles = Vec::new();
+
+    for probe in probes {
+        let target = probe.instance.clone();
+        let handle = tokio::spawn(async move { collect_search(&probe).await }.in_current_span());
+        handles.push((target, handle));
+    }
+
+    super::join(CollectorName::SearchProbes, handles).await;
+
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use chrono::{Duration, Utc};
+use prometheus::IntGaugeVec;
+use tracing::Instrument;
+
+use crate::{
+    config::{CollectorName, TagMeasuresConfig},
+    mastodon::MastodonClient,
+    metrics::{
+        WithLimitedLabelValues, MASTODON_ADMIN_TAG_ACCOUNTS, MASTODON_ADMIN_TAG_SERVERS,
+        MASTODON_ADMIN_TAG_USES,
+    },
+};
+
+/// The keys of the admin measures of hashtags.
+const MEASURE_KEYS: [&str; 3] = ["tag_accounts", "tag_uses", "tag_servers"];
+
+lazy_static! {
+    /// The ids of hashtags, keyed by instance and name.
+    static ref TAG_IDS: Mutex<HashMap<(String, String), String>> = Mutex::new(HashMap::new());
+}
+
+/// Returns the id of the hashtag, looking it up on the instance if necessary.
+async fn tag_id(client: &MastodonClient, tag: &str) -> Result<String, String> {
+    let key = (client.domain().to_string(), tag.to_lowercase());
+    if let Some(id) = TAG_IDS.lock().unwrap().get(&key) {
+        return Ok(id.clone());
+    }
+
+    let id = client.tag(tag).await.map_err(|err| err.to_string())?.id;
+    // Mastodon before 4.3 doesn't return the id of hashtags
+    if id.is_empty() {
+        return Err("the instance doesn't return the id of hashtags".to_string());
+    }
+    TAG_IDS.lock().unwrap().insert(key, id.clone());
+
+    Ok(id)
+}
+
+#[tracing::instrument(skip_all, fields(instance = %config.instance))]
+pub async fn collect_tag_measure(config: &TagMeasuresConfig) {
+    let instance = config.instance.as_str();
+    let client = MastodonClient::new(instance)
+        .with_access_token(config.access_token.get().unwrap_or_default());
+
+    let today = Utc::now().date_naive();
+    let start_at = today - Duration::days(i64::from(config.days.saturating_sub(1)));
+    let (start_at, end_at) = (start_at.to_string(), today.to_string());
+
+    for tag in &config.tags {
+        let tag = tag.trim_start_matches('#');
+        log!("Collecting admin measures of #{} on {}", tag, instance);
+
+        let id = match tag_id(&client, tag).await {
+            Ok(id) => id,
+            Err(err) => {
+                log!("Error: {} tag {} {}", instance, tag, err);
+                continue;
+            }
+        };
+        let measures = match client
+            .tag_measures(&MEASURE_KEYS, &id, &start_at, &end_at)
+            .await
+        {
+            Ok(measures) => measures,
+            Err(err) => {
+                log!("Error: {} measures of tag {} {}", instance, tag, err);
+                continue;
+            }
+        };
+
+        for measure in measures {
+            let metric: &IntGaugeVec = match measure.key.as_str() {
+                "tag_accounts" => &MASTODON_ADMIN_TAG_ACCOUNTS,
+                "tag_uses" => &MASTODON_ADMIN_TAG_USES,
+                "tag_servers" => &MASTODON_ADMIN_TAG_SERVERS,
+                _ => continue,
+            };
+            log!(
+                "{}: Admin measure {} of #{}: {}",
+                instance,
+                measure.key,
+                tag,
+                measure.total
+            );
+            if let Some(gauge) = metric.with_limited_label_values(&[instance, tag]) {
+                gauge.set(measure.total);
+            }
+        }
+    }
+}
+
+pub async fn collect_tag_measures(
+    configs: Vec<TagMeasuresConfig>,
+) -> Result<(), tokio::task::JoinError> {
+    let mut handles = Vec::new();
+
+    for config in configs {
+        let target = config.instance.clone();
+        let handle =
+            tokio::spawn(async move { collect_tag_measure(&config).await }.in_current_span());
+        handles.push((target, handle));
+    }
+
+    super::join(CollectorName::TagMeasures, handles).await;
+
+    Ok(())
+}
@@ -0,0 +1,94 @@
+use prometheus::GaugeVec;
+use tracing::Instrument;
+
+use crate::{
+    config::CollectorName,
+    mastodon::{self, MastodonClient},
+    metrics::{
+        WithLimitedLabelValues, MASTODON_INSTANCE_FEDERATED_POSTING_RATE,
+        MASTODON_INSTANCE_LOCAL_POSTING_RATE,
+    },
+};
+
+/// Computes the number of posts per minute from the creation dates of a page of statuses.
+///
+/// Returns `None` if there are too few statuses to span a time range.
+fn posting_rate(statuses: &[mastodon::StatusResponse]) -> Option<f64> {
+    let timestamps: Vec<i64> = statuses
+        .iter()
+        .filter_map(|status| {
+            status
+                .created_at
+                .parse::<chrono::DateTime<chrono::Utc>>()
+                .ok()
+        })
+        .map(|created_at| created_at.timestamp_millis())
+        .collect();
+
+    let newest = *timestamps.iter().max()?;
+    let oldest = *timestamps.iter().min()?;
+    let minutes = (newest - oldest) as f64 / 60_000.0;
+
+    if minutes <= 0.0 {
+        return None;
+    }
+
+    // The oldest status only marks the start of the time range
+    Some((timestamps.len() - 1) as f64 / minutes)
+}
+
+async fn collect_timeline(
+    instance: &str,
+    local: bool,
+    metric: &GaugeVec,
+) -> Result<(), mastodon::Error> {
+    // Collect response body data
+    let body = match MastodonClient::new(instance).public_timeline(local).await {
+        Ok(body) => body,
+        Err(err) => {
+            log!("Error: {} {}", instance, err);
+            return Ok(());
+        }
+    };
+
+    if let Some(rate) = posting_rate(&body) {
+        let timeline = if local { "local" } else { "federated" };
+        log!(
+            "{}: Posting rate of {} timeline: {}",
+            instance,
+            timeline,
+            rate
+        );
+        if let Some(gauge) = metric.with_limited_label_values(&[instance]) {
+            gauge.set(rate);
+        }
+    }
+
+    Ok(())
+}
+
+#[tracing::instrument]
+pub async fn collect_posting_rate(instance: &str) -> Result<(), mastodon::Error> {
+    log!("Collecting posting rate {}", instance);
+
+    collect_timeline(instance, true, &MASTODON_INSTANCE_LOCAL_POSTING_RATE).await?;
+    collect_timeline(instance, false, &MASTODON_INSTANCE_FEDERATED_POSTING_RATE).await?;
+
+    Ok(())
+}
+
+pub async fn collect_timelines(instances: Vec<String>) -> Result<(), tokio::task::JoinError> {
+    let mut handles = Vec::new();
+
+    for instance in instances {
+        let target = instance.clone();
+        let handle = tokio::spawn(
+            async move { collect_posting_rate(instance.as_str()).await }.in_current_span(),
+        );
+        handles.push((target, handle));
+    }
+
+    super::join(CollectorName::PostingRate, handles).await;
+
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use tracing::Instrument;
+
+use crate::{
+    config::CollectorName,
+    mastodon::{self, MastodonClient},
+    metrics::{WithLimitedLabelValues, MASTODON_WELL_KNOWN_SUCCESS},
+};
+
+/// Returns the well-known endpoints to check as pairs of name and path.
+///
+/// WebFinger is queried for the instance actor, which every Mastodon instance has.
+fn endpoints(instance: &str) -> [(&'static str, String); 3] {
+    [
+        (
+            "webfinger",
+            format!(
+                "/.well-known/webfinger?resource=acct:{}@{}",
+                instance, instance
+            ),
+        ),
+        ("host-meta", "/.well-known/host-meta".to_string()),
+        ("nodeinfo", "/.well-known/nodeinfo".to_string()),
+    ]
+}
+
+#[tracing::instrument]
+pub async fn collect_well_known(instance: &str) -> Result<(), mastodon::Error> {
+    log!("Checking well-known endpoints {}", instance);
+
+    let client = MastodonClient::new(instance);
+    for (endpoint, path) in endpoints(instance) {
+        let success = match client.send(client.get(&path)).await {
+            Ok(_) => true,
+            Err(err) => {
+                log!("Error: {} {} {}", instance, endpoint, err);
+                false
+            }
+        };
+
+        log!("{}: Well-known {}: {}", instance, endpoint, success);
+        if let Some(gauge) =
+            MASTODON_WELL_KNOWN_SUCCESS.with_limited_label_values(&[instance, endpoint])
+        {
+            gauge.set(i64::from(success));
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn collect_well_knowns(instances: Vec<String>) -> Result<(), tokio::task::JoinError> {
+    let mut handles = Vec::new();
+
+    for instance in instances {
+        let target = instance.clone();
+        let handle = tokio::spawn(
+            async move { collect_well_known(instance.as_str()).await }.in_current_span(),
+        );
+        handles.push((target, handle));
+    }
+
+    super::join(CollectorName::WellKnownChecks, handles).await;
+
+    Ok(())
+}
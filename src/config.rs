@@ -1,41 +1,2148 @@
-use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fmt,
+    fs::{self, File},
+    io::Read,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{
+    alerting::Condition,
+    derived::Expression,
+    mastodon::domain,
+    redact::{self, SECRET},
+    schedule::Schedule,
+};
+
+/// The version of the configuration format written by this version of the exporter.
+pub const CONFIG_VERSION: u32 = 2;
+
+/// An error which occurred while loading the configuration.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The configuration file could not be read.
+    Io(std::io::Error),
+
+    /// The configuration file is not valid.
+    Parse(serde_yaml::Error),
+
+    /// The configuration file is valid yaml, but contains invalid values.
+    Invalid(String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "unable to read config file: {}", err),
+            LoadError::Parse(err) => write!(f, "unable to parse config file: {}", err),
+            LoadError::Invalid(reason) => write!(f, "invalid config file: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// The configuration for limiting the request rate per client.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    /// The number of requests a client may send within the period.
+    pub requests: u32,
+
+    /// The length of the period in seconds.
+    #[serde(default = "RateLimitConfig::default_period_seconds")]
+    pub period_seconds: u64,
+}
+
+impl RateLimitConfig {
+    fn default_period_seconds() -> u64 {
+        60
+    }
+}
 
 /// The configuration for the server.
 #[derive(Serialize, Deserialize)]
 pub struct ServerConfig {
     /// The port to listen on.
     pub http_listen_port: u16,
+
+    /// A separate port to serve operational endpoints like `/healthz` on.
+    ///
+    /// They are served on `http_listen_port` if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin_listen_port: Option<u16>,
+
+    /// Whether or not to gzip compress responses for clients which accept it.
+    #[serde(default)]
+    pub compression: bool,
+
+    /// The configuration for limiting the request rate per client IP address.
+    ///
+    /// Requests are not limited if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimitConfig>,
+
+    /// Whether or not to log every request to the server.
+    #[serde(default)]
+    pub access_log: bool,
+
+    /// A file to append the access log to instead of printing it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_log_file: Option<String>,
+
+    /// Whether or not to serve statistics of the async runtime on `/debug/runtime`.
+    #[serde(default)]
+    pub runtime_diagnostics: bool,
+
+    /// Whether or not to serve a dashboard of the history on `/ui`.
+    ///
+    /// The history is kept in memory for a day if `history` is not configured.
+    #[serde(default)]
+    pub ui: bool,
+
+    /// How to complete the first collection cycle after the exporter starts.
+    #[serde(default)]
+    pub warm_up: WarmUp,
+
+    /// The number of intervals of the background collection cycles without a completed cycle
+    /// after which `/livez` and `/readyz` fail, e.g. because a cycle hangs.
+    #[serde(default = "ServerConfig::default_stale_after_cycles")]
+    pub stale_after_cycles: u32,
+}
+
+impl ServerConfig {
+    fn default_stale_after_cycles() -> u32 {
+        3
+    }
+}
+
+/// How the first collection cycle is completed after the exporter starts.
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarmUp {
+    /// The first cycle is collected by the first scrape.
+    #[default]
+    Off,
+
+    /// The first cycle is collected before the HTTP port is bound.
+    Collect,
+
+    /// The HTTP port is bound right away, but `/metrics` responds with `503 Service Unavailable`
+    /// until the first cycle collected in the background completes.
+    Unavailable,
 }
 
 impl Default for ServerConfig {
     fn default() -> Self {
         Self {
             http_listen_port: 9498,
+            admin_listen_port: None,
+            compression: false,
+            rate_limit: None,
+            access_log: false,
+            access_log_file: None,
+            runtime_diagnostics: false,
+            ui: false,
+            warm_up: WarmUp::Off,
+            stale_after_cycles: Self::default_stale_after_cycles(),
+        }
+    }
+}
+
+/// An access token, either given directly, read from a file or printed by a command.
+///
+/// References to environment variables like `${MASTODON_TOKEN}` are replaced by their value.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct AccessToken {
+    /// The access token.
+    #[serde(
+        default,
+        rename = "access_token",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub value: Option<String>,
+
+    /// A file to read the access token from, e.g. a mounted Kubernetes secret.
+    #[serde(
+        default,
+        rename = "access_token_file",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub file: Option<String>,
+
+    /// A shell command printing the access token, e.g. `pass show mastodon/exporter`.
+    ///
+    /// The output is trimmed and never logged.
+    #[serde(
+        default,
+        rename = "access_token_command",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub command: Option<String>,
+
+    /// Whether the access token is written in the configuration file itself, unlike tokens
+    /// referencing an environment variable or read from the secrets directory.
+    #[serde(skip)]
+    inline: bool,
+}
+
+impl AccessToken {
+    /// Returns the access token if one is configured.
+    ///
+    /// This only returns tokens from files after the configuration has been loaded.
+    pub fn get(&self) -> Option<&str> {
+        self.value.as_deref()
+    }
+
+    /// Replaces environment variable references and reads the token from its file if configured.
+    fn resolve(&mut self) -> Result<(), String> {
+        self.inline = self
+            .value
+            .as_deref()
+            .is_some_and(|value| !value.contains("${"));
+        resolve_secret(&mut self.value, self.file.as_deref(), "access token")?;
+
+        if let Some(command) = &self.command {
+            self.value = Some(run_command(command)?);
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns whether the file can be read by any user, which is always false on non-Unix systems.
+pub fn is_world_readable(path: impl AsRef<Path>) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        fs::metadata(path).is_ok_and(|metadata| metadata.permissions().mode() & 0o004 != 0)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+/// Replaces environment variable references in the secret and reads it from its file if
+/// configured, e.g. a mounted Kubernetes secret.
+fn resolve_secret(
+    value: &mut Option<String>,
+    file: Option<&str>,
+    name: &str,
+) -> Result<(), String> {
+    if let Some(value) = value {
+        *value = expand_env(value)?;
+    }
+
+    if let Some(file) = file {
+        let file = expand_env(file)?;
+        let secret = fs::read_to_string(&file)
+            .map_err(|err| format!("unable to read {} file {}: {}", name, file, err))?;
+        *value = Some(secret.trim().to_string());
+    }
+
+    Ok(())
+}
+
+/// The number of seconds an access token command may run before it's killed.
+const COMMAND_TIMEOUT_SECONDS: u64 = 10;
+
+/// Runs a shell command and returns its trimmed output, killing it if it doesn't exit in time.
+///
+/// The output is left out of errors, as it may contain a secret.
+fn run_command(command: &str) -> Result<String, String> {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut child = Command::new(shell)
+        .args([flag, command])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| format!("unable to run access token command: {}", err))?;
+
+    // The output is read in the background, so a command filling the pipe doesn't block
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        stdout.read_to_end(&mut output).map(|_| output)
+    });
+
+    let deadline = Instant::now() + Duration::from_secs(COMMAND_TIMEOUT_SECONDS);
+    let status = loop {
+        let status = child
+            .try_wait()
+            .map_err(|err| format!("unable to run access token command: {}", err))?;
+        if let Some(status) = status {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            child.kill().ok();
+            child.wait().ok();
+            return Err(format!(
+                "access token command timed out after {} seconds",
+                COMMAND_TIMEOUT_SECONDS
+            ));
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    if !status.success() {
+        return Err(format!("access token command failed with {}", status));
+    }
+
+    let output = reader
+        .join()
+        .unwrap()
+        .map_err(|err| format!("unable to read access token command output: {}", err))?;
+    String::from_utf8(output)
+        .map(|stdout| stdout.trim().to_string())
+        .map_err(|_| "access token command printed invalid UTF-8".to_string())
+}
+
+/// Replaces all `${VAR}` references in the value by the value of the environment variable.
+fn expand_env(value: &str) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = value;
+
+    while let Some(start) = rest.find("${") {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 2..start + end];
+        let var =
+            env::var(name).map_err(|_| format!("environment variable {} is not set", name))?;
+
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&var);
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
+/// Replaces the secrets in the serialized configuration with a placeholder.
+///
+/// Access tokens and the files and commands secrets are read from are replaced entirely. Webhook URLs often contain a token in their path, so
+/// only their origin is kept, and the passwords of all other URLs are replaced.
+fn redact(value: &mut serde_yaml::Value, key: Option<&str>) {
+    match value {
+        serde_yaml::Value::Mapping(mapping) => {
+            for (key, value) in mapping.iter_mut() {
+                redact(value, key.as_str());
+            }
+        }
+        serde_yaml::Value::Sequence(sequence) => {
+            for value in sequence {
+                redact(value, key);
+            }
+        }
+        serde_yaml::Value::String(string) => {
+            // Commands often contain the token itself, and the file shows where it's kept
+            if matches!(
+                key,
+                Some(
+                    "access_token"
+                        | "access_token_command"
+                        | "access_token_file"
+                        | "url_file"
+                        | "pushgateway_url_file"
+                        | "remote_write_url_file"
+                )
+            ) {
+                *string = SECRET.to_string();
+                return;
+            }
+
+            let Ok(url) = reqwest::Url::parse(string) else {
+                return;
+            };
+            if key == Some("url") && url.path() != "/" {
+                *string = format!("{}/{}", url.origin().ascii_serialization(), SECRET);
+            } else if let Some(password) = url.password() {
+                *string = string.replacen(&format!(":{}@", password), &format!(":{}@", SECRET), 1);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// A Mastodon instance to monitor.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct InstanceConfig {
+    /// The name of the instance.
+    pub instance: String,
+
+    /// Whether or not to collect the instance, so it can be parked without removing it.
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    /// The number of seconds after which requests to the instance time out, overriding
+    /// `client.timeout_seconds`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_seconds: Option<u64>,
+}
+
+impl From<String> for InstanceConfig {
+    fn from(instance: String) -> Self {
+        Self {
+            instance,
+            enabled: true,
+            timeout_seconds: None,
+        }
+    }
+}
+
+/// A label of the instance info metric, taken from the field of the same name of the instance.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InfoLabel {
+    Domain,
+    Title,
+    Version,
+    SourceUrl,
+    Description,
+}
+
+impl InfoLabel {
+    /// Returns the name of the label.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Domain => "domain",
+            Self::Title => "title",
+            Self::Version => "version",
+            Self::SourceUrl => "source_url",
+            Self::Description => "description",
+        }
+    }
+}
+
+/// A collector which can be scheduled with a cron expression, named like its configuration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CollectorName {
+    InstanceInfo,
+    Accounts,
+    Polls,
+    PostingRate,
+    Peers,
+    SearchProbes,
+    ResolveProbes,
+    TagMeasures,
+    MediaProbes,
+    HealthChecks,
+    WellKnownChecks,
+}
+
+impl CollectorName {
+    /// Returns the name of the collector as in the configuration, e.g. `instance_info`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::InstanceInfo => "instance_info",
+            Self::Accounts => "accounts",
+            Self::Polls => "polls",
+            Self::PostingRate => "posting_rate",
+            Self::Peers => "peers",
+            Self::SearchProbes => "search_probes",
+            Self::ResolveProbes => "resolve_probes",
+            Self::TagMeasures => "tag_measures",
+            Self::MediaProbes => "media_probes",
+            Self::HealthChecks => "health_checks",
+            Self::WellKnownChecks => "well_known_checks",
+        }
+    }
+
+    /// Returns the class of the metrics of the collector.
+    pub fn class(self) -> MetricClass {
+        match self {
+            Self::InstanceInfo | Self::Peers | Self::WellKnownChecks => MetricClass::Static,
+            Self::Accounts
+            | Self::Polls
+            | Self::PostingRate
+            | Self::SearchProbes
+            | Self::ResolveProbes
+            | Self::TagMeasures
+            | Self::MediaProbes
+            | Self::HealthChecks => MetricClass::Dynamic,
+        }
+    }
+}
+
+/// A class of metrics which are refreshed at the same interval.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricClass {
+    /// Data which rarely changes, like the instance info and the well-known endpoints.
+    Static,
+
+    /// Data which changes all the time, like the counts of accounts and the probes.
+    Dynamic,
+}
+
+/// An instance as written in the configuration file, either its name or the structured format.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum InstanceEntry {
+    Name(String),
+    Struct(InstanceConfig),
+}
+
+/// Deserializes the instances, converting names to the structured format.
+fn deserialize_instances<'de, D>(deserializer: D) -> Result<Vec<InstanceConfig>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let entries = Vec::<InstanceEntry>::deserialize(deserializer)?;
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| match entry {
+            InstanceEntry::Name(instance) => InstanceConfig::from(instance),
+            InstanceEntry::Struct(instance) => instance,
+        })
+        .collect())
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// A Mastodon account to monitor.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AccountConfig {
+    /// The name of the instance.
+    pub instance: String,
+
+    /// The id of the account.
+    ///
+    /// Either this or `acct` has to be set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    /// The username of the account, e.g. `Gargron`, which is resolved to its id on the instance.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acct: Option<String>,
+
+    /// A friendly name of the account, exported as the `alias` label of the account labels metric.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+
+    /// An access token of the instance, for instances which require authentication.
+    #[serde(flatten)]
+    pub access_token: AccessToken,
+
+    /// The minimum number of seconds between two collections of the account.
+    ///
+    /// The account is collected on every scrape if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval_seconds: Option<u64>,
+
+    /// Additional labels exported on the account labels metric.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+
+    /// Whether or not to collect the account, so it can be parked without removing it.
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+impl From<(String, String)> for AccountConfig {
+    fn from((instance, id): (String, String)) -> Self {
+        Self {
+            instance,
+            id: Some(id),
+            acct: None,
+            alias: None,
+            access_token: AccessToken::default(),
+            interval_seconds: None,
+            labels: BTreeMap::new(),
+            enabled: true,
         }
     }
 }
 
+impl AccountConfig {
+    /// Returns the account id, or the acct if the id has to be resolved.
+    pub fn key(&self) -> &str {
+        self.id
+            .as_deref()
+            .or(self.acct.as_deref())
+            .unwrap_or_default()
+    }
+}
+
+/// An account as written in the configuration file.
+///
+/// Before version 2, accounts were listed as `[instance, id]` tuples.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AccountEntry {
+    Tuple(String, String),
+    Struct(AccountConfig),
+}
+
+/// Returns whether the name is a valid Prometheus label or metric name.
+fn is_valid_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|char| char.is_ascii_alphanumeric() || char == '_')
+        && !name.starts_with("__")
+}
+
+/// Deserializes the accounts, migrating tuples to the structured format.
+fn deserialize_accounts<'de, D>(deserializer: D) -> Result<Vec<AccountConfig>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let entries = Vec::<AccountEntry>::deserialize(deserializer)?;
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| match entry {
+            AccountEntry::Tuple(instance, id) => AccountConfig::from((instance, id)),
+            AccountEntry::Struct(account) => account,
+        })
+        .collect())
+}
+
+/// A Mastodon poll to monitor.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PollConfig {
+    /// The name of the instance.
+    pub instance: String,
+
+    /// The id of the poll.
+    pub id: String,
+}
+
+/// A poll as written in the configuration file.
+///
+/// Before version 2, polls were listed as `[instance, id]` tuples.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PollEntry {
+    Tuple(String, String),
+    Struct(PollConfig),
+}
+
+/// Deserializes the polls, migrating tuples to the structured format.
+fn deserialize_polls<'de, D>(deserializer: D) -> Result<Vec<PollConfig>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let entries = Vec::<PollEntry>::deserialize(deserializer)?;
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| match entry {
+            PollEntry::Tuple(instance, id) => PollConfig { instance, id },
+            PollEntry::Struct(poll) => poll,
+        })
+        .collect())
+}
+
+/// A group of accounts sharing a set of labels, e.g. the accounts of a team.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AccountGroupConfig {
+    /// Labels added to the labels of every account of the group, unless an account sets the
+    /// same label itself.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+
+    /// The accounts of the group, which are added to `accounts`.
+    #[serde(deserialize_with = "deserialize_accounts")]
+    pub accounts: Vec<AccountConfig>,
+}
+
+/// A rule for discovering accounts to monitor from the profile directory of an instance.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DiscoverAccountsConfig {
+    /// The name of the instance.
+    pub instance: String,
+
+    /// Whether or not to only discover local accounts of the instance.
+    #[serde(default = "DiscoverAccountsConfig::default_local")]
+    pub local: bool,
+
+    /// The maximum number of accounts to discover, the most recently active first.
+    #[serde(default = "DiscoverAccountsConfig::default_limit")]
+    pub limit: usize,
+}
+
+impl DiscoverAccountsConfig {
+    fn default_local() -> bool {
+        true
+    }
+
+    fn default_limit() -> usize {
+        100
+    }
+}
+
+/// A rule for discovering instances to monitor from the peers of a seed instance.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DiscoverInstancesConfig {
+    /// The name of the seed instance.
+    pub seed: String,
+
+    /// The maximum number of peers to monitor, sampled deterministically from all allowed peers.
+    #[serde(default = "DiscoverInstancesConfig::default_sample")]
+    pub sample: usize,
+
+    /// Patterns of the peers to allow, where `*` matches any characters, e.g. `*.social`.
+    ///
+    /// All peers are allowed if empty.
+    #[serde(default)]
+    pub allow: Vec<String>,
+}
+
+impl DiscoverInstancesConfig {
+    fn default_sample() -> usize {
+        50
+    }
+}
+
+/// The configuration for the status engagement tracker.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StatusTrackerConfig {
+    /// The number of seconds between two samples of the monitored statuses.
+    #[serde(default = "StatusTrackerConfig::default_interval_seconds")]
+    pub interval_seconds: u64,
+
+    /// The number of seconds samples are kept to derive rates from.
+    #[serde(default = "StatusTrackerConfig::default_retention_seconds")]
+    pub retention_seconds: u64,
+
+    /// A list of Mastodon statuses to monitor.
+    ///
+    /// The first value is the name of the instance, the second is the status's id.
+    pub statuses: Vec<(String, String)>,
+
+    /// Whether or not to also collect the polls attached to the monitored statuses, like the
+    /// configured `polls`.
+    #[serde(default)]
+    pub polls: bool,
+}
+
+impl StatusTrackerConfig {
+    fn default_interval_seconds() -> u64 {
+        60
+    }
+
+    fn default_retention_seconds() -> u64 {
+        3600
+    }
+}
+
+/// The configuration for comparing instance versions against upstream releases.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct UpstreamReleasesConfig {
+    /// The number of seconds the fetched upstream releases are cached.
+    #[serde(default = "UpstreamReleasesConfig::default_cache_seconds")]
+    pub cache_seconds: u64,
+}
+
+impl UpstreamReleasesConfig {
+    fn default_cache_seconds() -> u64 {
+        3600
+    }
+}
+
+/// The configuration for a connection to the streaming API of an instance.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StreamingConfig {
+    /// The name of the instance.
+    pub instance: String,
+
+    /// The timeline to stream, e.g. `public` or `public:local`.
+    #[serde(default = "StreamingConfig::default_stream")]
+    pub stream: String,
+
+    /// An access token, required by instances which don't allow unauthenticated streaming.
+    #[serde(flatten)]
+    pub access_token: AccessToken,
+}
+
+impl StreamingConfig {
+    fn default_stream() -> String {
+        "public".to_string()
+    }
+}
+
+/// The configuration for a synthetic search probe against an instance.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SearchProbeConfig {
+    /// The name of the instance.
+    pub instance: String,
+
+    /// The query to search for.
+    pub query: String,
+
+    /// An access token of the instance, as search requires authentication.
+    #[serde(flatten)]
+    pub access_token: AccessToken,
+}
+
+/// The configuration for collecting the admin measures of hashtags on an instance.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TagMeasuresConfig {
+    /// The name of the instance.
+    pub instance: String,
+
+    /// The hashtags to collect the measures of, without the # sign.
+    pub tags: Vec<String>,
+
+    /// The number of days up to today to sum up the measures over.
+    #[serde(default = "TagMeasuresConfig::default_days")]
+    pub days: u32,
+
+    /// An access token of an admin of the instance with the `admin:read` scope.
+    #[serde(flatten)]
+    pub access_token: AccessToken,
+}
+
+impl TagMeasuresConfig {
+    fn default_days() -> u32 {
+        7
+    }
+}
+
+/// The configuration for probing whether remote accounts can be resolved from an instance.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ResolveProbeConfig {
+    /// The name of the instance.
+    pub instance: String,
+
+    /// The remote accounts to resolve, e.g. `friend@otherserver.tld`.
+    pub accounts: Vec<String>,
+
+    /// An access token of the instance, as resolving requires authentication.
+    #[serde(flatten)]
+    pub access_token: AccessToken,
+}
+
+/// The configuration for periodically posting and deleting a canary status.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CanaryConfig {
+    /// The name of the instance.
+    pub instance: String,
+
+    /// An access token of a dedicated canary account with the `write:statuses` scope.
+    #[serde(flatten)]
+    pub access_token: AccessToken,
+
+    /// The number of seconds between two canary posts.
+    #[serde(default = "CanaryConfig::default_interval_seconds")]
+    pub interval_seconds: u64,
+
+    /// The visibility of the canary status, e.g. `direct`, `private`, `unlisted` or `public`.
+    #[serde(default = "CanaryConfig::default_visibility")]
+    pub visibility: String,
+}
+
+impl CanaryConfig {
+    fn default_interval_seconds() -> u64 {
+        3600
+    }
+
+    fn default_visibility() -> String {
+        "direct".to_string()
+    }
+}
+
+/// An account on an instance used by the federation delivery probe.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FederationProbeAccountConfig {
+    /// The name of the instance.
+    pub instance: String,
+
+    /// An access token of the account on the instance.
+    #[serde(flatten)]
+    pub access_token: AccessToken,
+}
+
+/// The configuration for measuring the federation delivery latency between two instances.
+///
+/// The account on `to` has to follow the account on `from`, so the canary status is delivered to
+/// its home timeline.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FederationProbeConfig {
+    /// The account which posts the canary status, requires the `write:statuses` scope.
+    pub from: FederationProbeAccountConfig,
+
+    /// The account which waits for the canary status, requires the `read:statuses` scope.
+    pub to: FederationProbeAccountConfig,
+
+    /// The number of seconds between two probes.
+    #[serde(default = "FederationProbeConfig::default_interval_seconds")]
+    pub interval_seconds: u64,
+
+    /// The number of seconds to wait for the delivery before the probe fails.
+    #[serde(default = "FederationProbeConfig::default_timeout_seconds")]
+    pub timeout_seconds: u64,
+}
+
+impl FederationProbeConfig {
+    fn default_interval_seconds() -> u64 {
+        3600
+    }
+
+    fn default_timeout_seconds() -> u64 {
+        600
+    }
+}
+
+/// The configuration for probing the media storage of an instance.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MediaProbeConfig {
+    /// The name of the instance.
+    pub instance: String,
+
+    /// The URL of a media file to fetch.
+    ///
+    /// The thumbnail of the instance is fetched if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+/// A metric derived from collected metrics, exported as `mastodon_derived_<name>`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DerivedMetricConfig {
+    /// The name of the metric without its prefix, e.g. `followers_per_status`.
+    pub name: String,
+
+    /// An arithmetic expression of metrics without their `mastodon_` prefix and numbers, e.g.
+    /// `account_followers_count / account_statuses_count`.
+    pub expression: String,
+}
+
+/// A rule which notifies when its condition holds for the collected metrics.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AlertRuleConfig {
+    /// The name of the alert.
+    pub name: String,
+
+    /// An expression like in derived metrics, e.g. `instance_version_outdated`.
+    pub expression: String,
+
+    /// The condition on the value of the expression, e.g. `== 0`, `> 10` or `changed`.
+    pub condition: String,
+
+    /// The number of consecutive collection cycles the condition has to hold before notifying.
+    #[serde(default = "AlertRuleConfig::default_for_cycles")]
+    pub for_cycles: u32,
+}
+
+impl AlertRuleConfig {
+    fn default_for_cycles() -> u32 {
+        1
+    }
+}
+
+/// The payload format of a webhook.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    /// A JSON object with the alert name, state, labels and value.
+    Json,
+
+    /// A Slack incoming webhook message.
+    Slack,
+
+    /// A Matrix text message for webhook bridges.
+    Matrix,
+}
+
+/// A webhook to send alert notifications to.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    /// The URL to post notifications to.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub url: String,
+
+    /// A file to read the URL from instead, as webhook URLs often contain a token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url_file: Option<String>,
+
+    /// The payload format.
+    #[serde(default = "WebhookConfig::default_format")]
+    pub format: WebhookFormat,
+}
+
+impl WebhookConfig {
+    fn default_format() -> WebhookFormat {
+        WebhookFormat::Json
+    }
+
+    /// Replaces environment variable references and reads the URL from its file if configured.
+    fn resolve(&mut self) -> Result<(), String> {
+        let mut url = Some(std::mem::take(&mut self.url));
+        resolve_secret(&mut url, self.url_file.as_deref(), "webhook URL")?;
+        self.url = url.unwrap_or_default();
+
+        Ok(())
+    }
+}
+
+/// An account which notifies an admin about alerts with direct statuses.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MastodonNotifierConfig {
+    /// The name of the instance of the notifier account.
+    pub instance: String,
+
+    /// An access token of the notifier account with the `write:statuses` scope.
+    #[serde(flatten)]
+    pub access_token: AccessToken,
+
+    /// The account to mention, e.g. `admin` or `admin@mas.to`.
+    pub mention: String,
+}
+
+/// The configuration for alerting without Alertmanager.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AlertingConfig {
+    /// The rules evaluated after each collection cycle.
+    pub rules: Vec<AlertRuleConfig>,
+
+    /// The webhooks every notification is sent to.
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+
+    /// The accounts every notification is sent from as a direct status.
+    #[serde(default)]
+    pub mastodon: Vec<MastodonNotifierConfig>,
+}
+
+/// The configuration for storing the values of all collection cycles in a local database.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HistoryConfig {
+    /// The path of the SQLite database file.
+    #[serde(default = "HistoryConfig::default_path")]
+    pub path: String,
+
+    /// The number of days to keep values for.
+    #[serde(default = "HistoryConfig::default_retention_days")]
+    pub retention_days: u64,
+}
+
+impl HistoryConfig {
+    fn default_path() -> String {
+        "mastodon_exporter.db".to_string()
+    }
+
+    fn default_retention_days() -> u64 {
+        30
+    }
+}
+
+/// The configuration for exporting traces of collection cycles via OTLP.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TracingConfig {
+    /// The gRPC endpoint of the OTLP collector.
+    #[serde(default = "TracingConfig::default_otlp_endpoint")]
+    pub otlp_endpoint: String,
+
+    /// The service name reported with all traces.
+    #[serde(default = "TracingConfig::default_service_name")]
+    pub service_name: String,
+}
+
+impl TracingConfig {
+    fn default_otlp_endpoint() -> String {
+        "http://localhost:4317".to_string()
+    }
+
+    fn default_service_name() -> String {
+        "mastodon_exporter".to_string()
+    }
+}
+
+/// The configuration for tuning the connections of the HTTP client to instances.
+///
+/// The defaults of the HTTP client are used for options which are not set.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct ClientConfig {
+    /// Whether or not to use HTTP/2 without negotiating it, which only works if all instances
+    /// support it.
+    #[serde(default)]
+    pub http2_prior_knowledge: bool,
+
+    /// The number of seconds to keep idle connections open for reuse.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool_idle_timeout_seconds: Option<u64>,
+
+    /// The maximum number of idle connections kept open per instance.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool_max_idle_per_host: Option<usize>,
+
+    /// The interval in seconds of TCP keepalive probes on open connections.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tcp_keepalive_seconds: Option<u64>,
+
+    /// The number of seconds after which requests to instances time out.
+    ///
+    /// Requests don't time out if neither this nor the `timeout_seconds` of the instance is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_seconds: Option<u64>,
+
+    /// The number of seconds to skip requests to instances whose domain can't be resolved, or
+    /// which refused connections repeatedly.
+    ///
+    /// Requests are never skipped if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub negative_cache_seconds: Option<u64>,
+
+    /// The maximum size of response bodies in bytes, larger responses are discarded.
+    ///
+    /// Defaults to 16 MiB.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_response_bytes: Option<u64>,
+}
+
+/// The configuration for resolving the domains of instances.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DnsConfig {
+    /// Nameservers to query instead of the system resolver, e.g. `9.9.9.9` or `1.1.1.1:53`.
+    #[serde(default)]
+    pub nameservers: Vec<String>,
+
+    /// The URL of a DNS-over-HTTPS server to query instead, e.g. `https://1.1.1.1/dns-query`.
+    ///
+    /// The domain of the server itself is resolved with the system resolver.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub doh_url: Option<String>,
+
+    /// The number of seconds to cache resolved addresses for.
+    #[serde(default = "DnsConfig::default_cache_seconds")]
+    pub cache_seconds: u64,
+
+    /// The number of seconds to cache failed lookups for.
+    #[serde(default = "DnsConfig::default_negative_cache_seconds")]
+    pub negative_cache_seconds: u64,
+
+    /// The number of seconds to wait for a nameserver to respond.
+    #[serde(default = "DnsConfig::default_timeout_seconds")]
+    pub timeout_seconds: u64,
+}
+
+impl DnsConfig {
+    fn default_cache_seconds() -> u64 {
+        300
+    }
+
+    fn default_negative_cache_seconds() -> u64 {
+        30
+    }
+
+    fn default_timeout_seconds() -> u64 {
+        2
+    }
+}
+
+/// A group of instances whose metrics are served on `/metrics/<name>`, e.g. for another team.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MetricGroupConfig {
+    /// The name of the group, which may only contain letters, digits, `-` and `_`.
+    pub name: String,
+
+    /// The instances whose targets are collected and whose series are served.
+    pub instances: Vec<String>,
+
+    /// The token scrapes of the group must send as `Authorization: Bearer <token>`.
+    ///
+    /// Scrapes are not authenticated if not set.
+    #[serde(flatten)]
+    pub access_token: AccessToken,
+}
+
+/// The shard of the targets collected by this replica of the exporter.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ShardingConfig {
+    /// The number of replicas the targets are distributed across.
+    pub total: usize,
+
+    /// The index of this replica, from `0` to `total - 1`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<usize>,
+
+    /// An environment variable ending with the index of this replica if `index` is not set.
+    ///
+    /// For example `HOSTNAME`, which is `mastodon-exporter-2` in a Kubernetes StatefulSet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index_env: Option<String>,
+}
+
+impl ShardingConfig {
+    /// Reads the index from the environment variable if it's not set.
+    fn resolve(&mut self) -> Result<(), String> {
+        let Some(name) = self.index_env.as_ref().filter(|_| self.index.is_none()) else {
+            return Ok(());
+        };
+
+        let value =
+            env::var(name).map_err(|_| format!("environment variable {} is not set", name))?;
+        let digits = value.len() - value.chars().rev().take_while(char::is_ascii_digit).count();
+        let index = value[digits..].parse().map_err(|_| {
+            format!(
+                "environment variable {} doesn't end with a shard index: {}",
+                name, value
+            )
+        })?;
+        self.index = Some(index);
+
+        Ok(())
+    }
+}
+
+/// The sinks the metrics are pushed to with `--push-and-exit`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PushConfig {
+    /// The URL of a Pushgateway, e.g. `http://pushgateway:9091`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pushgateway_url: Option<String>,
+
+    /// The job the metrics are grouped by in the Pushgateway, and the `job` label of the series
+    /// written to the remote write endpoint.
+    #[serde(default = "PushConfig::default_job")]
+    pub job: String,
+
+    /// The URL of a Prometheus remote write endpoint, e.g. `http://prometheus:9090/api/v1/write`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_write_url: Option<String>,
+
+    /// A file to read the Pushgateway URL from instead, e.g. if it contains a password.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pushgateway_url_file: Option<String>,
+
+    /// A file to read the remote write URL from instead, e.g. if it contains a password.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_write_url_file: Option<String>,
+}
+
+impl PushConfig {
+    fn default_job() -> String {
+        "mastodon_exporter".to_string()
+    }
+
+    /// Replaces environment variable references and reads the URLs from their files if
+    /// configured.
+    fn resolve(&mut self) -> Result<(), String> {
+        resolve_secret(
+            &mut self.pushgateway_url,
+            self.pushgateway_url_file.as_deref(),
+            "Pushgateway URL",
+        )?;
+        resolve_secret(
+            &mut self.remote_write_url,
+            self.remote_write_url_file.as_deref(),
+            "remote write URL",
+        )
+    }
+}
+
+/// The maximum of `account_recent_statuses`, the number of statuses an account's statuses are
+/// returned with per request.
+const MAX_RECENT_STATUSES: usize = 40;
+
 /// Represents the configuration for the application.
 #[derive(Serialize, Deserialize)]
 pub struct Config {
+    /// The version of the configuration format.
+    ///
+    /// Configurations without a version are from before versioning was introduced.
+    #[serde(default = "Config::default_version")]
+    pub version: u32,
+
     /// The configuration for the server.
     pub server: ServerConfig,
 
     /// A list of Mastodon instances to monitor.
-    pub instance_info: Vec<String>,
+    #[serde(deserialize_with = "deserialize_instances")]
+    pub instance_info: Vec<InstanceConfig>,
+
+    /// The labels of `mastodon_info` besides `instance`.
+    #[serde(default = "Config::default_instance_info_labels")]
+    pub instance_info_labels: Vec<InfoLabel>,
+
+    /// A list of rules for discovering instances at startup, which are added to `instance_info`.
+    #[serde(default)]
+    pub discover_instances: Vec<DiscoverInstancesConfig>,
 
     /// A list of Mastodon accounts to monitor.
+    #[serde(deserialize_with = "deserialize_accounts")]
+    pub accounts: Vec<AccountConfig>,
+
+    /// Groups of accounts sharing labels by name, whose accounts are added to `accounts` when
+    /// loading the configuration.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub account_groups: BTreeMap<String, AccountGroupConfig>,
+
+    /// A list of rules for discovering accounts at startup, which are added to `accounts`.
+    #[serde(default)]
+    pub discover_accounts: Vec<DiscoverAccountsConfig>,
+
+    /// A directory of yaml files with one target each, e.g. a mounted ConfigMap.
+    ///
+    /// Files with an `id` or `acct` are added to `accounts`, all others to `instance_info`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub targets_dir: Option<String>,
+
+    /// A directory of access tokens in files named after their instance, e.g. a mounted Secret
+    /// with a key per instance.
+    ///
+    /// Targets without an access token use the one of their instance if there is one, and metric
+    /// groups the one named like the group.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secrets_dir: Option<String>,
+
+    /// Whether or not to label account metrics with the username of the account.
+    ///
+    /// Series of previous usernames are removed when an account changes its username. If
+    /// disabled, the `username` label is left empty, so renames never create new series.
+    #[serde(default = "default_enabled")]
+    pub account_username_label: bool,
+
+    /// Whether or not to export the display name and avatar of accounts with `mastodon_account_info`.
+    ///
+    /// The series is replaced whenever the profile changes.
+    #[serde(default)]
+    pub account_profile_labels: bool,
+
+    /// Whether or not to export the hashtags featured on the profiles of accounts.
+    #[serde(default)]
+    pub account_featured_tags: bool,
+
+    /// Whether or not to count the statuses bookmarked and favourited by accounts with an access
+    /// token.
+    ///
+    /// Counting takes a request per 40 statuses, and stops at 50 requests.
+    #[serde(default)]
+    pub account_bookmarks_favourites: bool,
+
+    /// The number of recent statuses of accounts to export engagement averages of, at most 40.
+    ///
+    /// Recent statuses are not fetched if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_recent_statuses: Option<usize>,
+
+    /// The number of seconds after which the series of accounts which are not found are removed.
+    #[serde(default = "Config::default_account_not_found_grace_seconds")]
+    pub account_not_found_grace_seconds: u64,
+
+    /// A list of Mastodon polls to monitor.
+    #[serde(default, deserialize_with = "deserialize_polls")]
+    pub polls: Vec<PollConfig>,
+
+    /// The configuration for the status engagement tracker.
+    ///
+    /// The tracker is disabled if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_tracker: Option<StatusTrackerConfig>,
+
+    /// The configuration for comparing instance versions against upstream releases from GitHub.
+    ///
+    /// The comparison is disabled if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream_releases: Option<UpstreamReleasesConfig>,
+
+    /// A list of streaming API connections to count events of.
+    #[serde(default)]
+    pub streaming: Vec<StreamingConfig>,
+
+    /// A list of Mastodon instances to measure the posting rate of the public timelines of.
+    #[serde(default)]
+    pub posting_rate: Vec<String>,
+
+    /// A list of Mastodon instances to collect the number of peers of.
+    ///
+    /// The number of peers shared by every pair of them is exported as well.
+    #[serde(default)]
+    pub peers: Vec<String>,
+
+    /// A list of synthetic search probes to run.
+    #[serde(default)]
+    pub search_probes: Vec<SearchProbeConfig>,
+
+    /// A list of probes resolving remote accounts to detect broken federation with their instances.
+    #[serde(default)]
+    pub resolve_probes: Vec<ResolveProbeConfig>,
+
+    /// A list of instances to collect the admin measures of hashtags on.
+    #[serde(default)]
+    pub tag_measures: Vec<TagMeasuresConfig>,
+
+    /// A list of instances to periodically post and delete a canary status on.
+    #[serde(default)]
+    pub canaries: Vec<CanaryConfig>,
+
+    /// A list of federation delivery probes between two instances.
+    #[serde(default)]
+    pub federation_probes: Vec<FederationProbeConfig>,
+
+    /// A list of media storage probes.
+    #[serde(default)]
+    pub media_probes: Vec<MediaProbeConfig>,
+
+    /// Whether or not to check the well-known endpoints of the monitored instances.
+    #[serde(default)]
+    pub well_known_checks: bool,
+
+    /// Whether or not to probe the health endpoint of the monitored instances.
+    #[serde(default)]
+    pub health_checks: bool,
+
+    /// Cron expressions in UTC of collectors which only run at specific times, by collector.
+    ///
+    /// Collectors without a schedule run in every collection cycle, unless their metric class has
+    /// a refresh interval.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub schedules: BTreeMap<CollectorName, String>,
+
+    /// The minimum number of seconds between refreshes of the metrics of a class, by class.
+    ///
+    /// Classes without an interval are refreshed in every collection cycle.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub refresh_intervals: BTreeMap<MetricClass, u64>,
+
+    /// A list of metrics derived from the collected metrics after each collection cycle.
+    #[serde(default)]
+    pub derived_metrics: Vec<DerivedMetricConfig>,
+
+    /// The configuration for alert notifications.
+    ///
+    /// Alerting is disabled if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alerting: Option<AlertingConfig>,
+
+    /// The configuration for the history of collected values, served on `/api/v1/history`.
+    ///
+    /// The history is disabled if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history: Option<HistoryConfig>,
+
+    /// The configuration for distributed tracing.
+    ///
+    /// Tracing is disabled if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tracing: Option<TracingConfig>,
+
+    /// A list of groups of instances whose metrics are served on their own path.
+    #[serde(default)]
+    pub metric_groups: Vec<MetricGroupConfig>,
+
+    /// The configuration for distributing the targets across replicas of the exporter.
+    ///
+    /// All targets are collected if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sharding: Option<ShardingConfig>,
+
+    /// The configuration for pushing the metrics of a single collection cycle.
+    ///
+    /// Only used with `--push-and-exit`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub push: Option<PushConfig>,
+
+    /// The maximum number of series per metric, new series beyond it are dropped.
+    ///
+    /// The number of series is not limited if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_series_per_metric: Option<usize>,
+
+    /// The configuration for tuning the connections to instances.
+    ///
+    /// The defaults of the HTTP client are used if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client: Option<ClientConfig>,
+
+    /// The configuration for resolving the domains of instances.
     ///
-    /// The first value is the name of the instance, the second is the account's id.
-    pub accounts: Vec<(String, String)>,
+    /// Domains are resolved with the system resolver without caching if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dns: Option<DnsConfig>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            version: CONFIG_VERSION,
             server: ServerConfig::default(),
-            instance_info: vec!["mas.to".to_string(), "mastodon.social".to_string()],
+            instance_info: vec![
+                InstanceConfig::from("mas.to".to_string()),
+                InstanceConfig::from("mastodon.social".to_string()),
+            ],
+            instance_info_labels: Self::default_instance_info_labels(),
+            discover_instances: Vec::new(),
             accounts: Vec::new(),
+            account_groups: BTreeMap::new(),
+            discover_accounts: Vec::new(),
+            targets_dir: None,
+            secrets_dir: None,
+            account_username_label: true,
+            account_profile_labels: false,
+            account_featured_tags: false,
+            account_bookmarks_favourites: false,
+            account_recent_statuses: None,
+            account_not_found_grace_seconds: Config::default_account_not_found_grace_seconds(),
+            polls: Vec::new(),
+            status_tracker: None,
+            upstream_releases: None,
+            streaming: Vec::new(),
+            posting_rate: Vec::new(),
+            peers: Vec::new(),
+            search_probes: Vec::new(),
+            resolve_probes: Vec::new(),
+            tag_measures: Vec::new(),
+            canaries: Vec::new(),
+            federation_probes: Vec::new(),
+            media_probes: Vec::new(),
+            well_known_checks: false,
+            health_checks: false,
+            schedules: BTreeMap::new(),
+            refresh_intervals: BTreeMap::new(),
+            derived_metrics: Vec::new(),
+            alerting: None,
+            history: None,
+            tracing: None,
+            metric_groups: Vec::new(),
+            sharding: None,
+            push: None,
+            max_series_per_metric: None,
+            client: None,
+            dns: None,
         }
     }
 }
+
+impl Config {
+    fn default_version() -> u32 {
+        1
+    }
+
+    fn default_instance_info_labels() -> Vec<InfoLabel> {
+        vec![InfoLabel::Domain, InfoLabel::Title, InfoLabel::Version]
+    }
+
+    fn default_account_not_found_grace_seconds() -> u64 {
+        3600
+    }
+
+    /// Loads the configuration from a yaml file.
+    ///
+    /// Configurations of older versions are migrated to the current version in memory.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        let yaml = fs::read_to_string(path).map_err(LoadError::Io)?;
+
+        Self::from_yaml(&yaml)
+    }
+
+    /// Loads the configuration from yaml like [`Self::load`], e.g. to validate an edited
+    /// configuration before writing it.
+    pub fn from_yaml(yaml: &str) -> Result<Self, LoadError> {
+        let mut config: Self = serde_yaml::from_str(yaml).map_err(LoadError::Parse)?;
+
+        config.expand_account_groups();
+        config.read_targets_dir()?;
+        for (_, access_token) in config.access_tokens_mut() {
+            access_token.resolve().map_err(LoadError::Invalid)?;
+        }
+        config.read_secrets_dir()?;
+        for (_, access_token) in config.access_tokens_mut() {
+            if let Some(value) = access_token.get() {
+                redact::register(value);
+            }
+        }
+        for instance in config.instances_mut() {
+            *instance = domain::to_unicode(instance);
+        }
+        // Webhook URLs often contain a token in their path
+        for webhook in config
+            .alerting
+            .iter_mut()
+            .flat_map(|alerting| &mut alerting.webhooks)
+        {
+            webhook.resolve().map_err(LoadError::Invalid)?;
+            if let Ok(url) = reqwest::Url::parse(&webhook.url) {
+                redact::register(url.path().trim_start_matches('/'));
+            }
+        }
+        if let Some(push) = &mut config.push {
+            push.resolve().map_err(LoadError::Invalid)?;
+        }
+        if let Some(sharding) = &mut config.sharding {
+            sharding.resolve().map_err(LoadError::Invalid)?;
+        }
+        config.validate()?;
+
+        if config.version < CONFIG_VERSION {
+            log!(
+                "Migrating config from version {} to version {}",
+                config.version,
+                CONFIG_VERSION
+            );
+            config.version = CONFIG_VERSION;
+        }
+
+        Ok(config)
+    }
+
+    /// Reads a configuration file of any version and returns it as yaml of the current version.
+    ///
+    /// Unlike loading, account groups are kept and access tokens are left unresolved, so they are
+    /// written back as configured. Comments are not kept.
+    pub fn migrate(path: impl AsRef<Path>) -> Result<String, LoadError> {
+        let config_file = File::open(path).map_err(LoadError::Io)?;
+        let mut config: Self = serde_yaml::from_reader(config_file).map_err(LoadError::Parse)?;
+        if config.version > CONFIG_VERSION {
+            return Err(LoadError::Invalid(format!(
+                "version {} is newer than the supported version {}",
+                config.version, CONFIG_VERSION
+            )));
+        }
+        config.version = CONFIG_VERSION;
+
+        serde_yaml::to_string(&config).map_err(LoadError::Parse)
+    }
+
+    /// Adds the targets of the files in the targets directory to the instances and accounts.
+    ///
+    /// Hidden files are skipped, like the `..data` link Kubernetes mounts ConfigMaps with.
+    fn read_targets_dir(&mut self) -> Result<(), LoadError> {
+        let Some(targets_dir) = &self.targets_dir else {
+            return Ok(());
+        };
+
+        let mut paths: Vec<PathBuf> = fs::read_dir(targets_dir)
+            .map_err(LoadError::Io)?
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                !name.starts_with('.') && (name.ends_with(".yml") || name.ends_with(".yaml"))
+            })
+            .collect();
+        // Sorted, so targets keep their order across reloads
+        paths.sort();
+
+        for path in paths {
+            let invalid = |err: serde_yaml::Error| {
+                LoadError::Invalid(format!("target file {}: {}", path.display(), err))
+            };
+            let target: serde_yaml::Value =
+                serde_yaml::from_str(&fs::read_to_string(&path).map_err(LoadError::Io)?)
+                    .map_err(invalid)?;
+            if target.get("id").is_some() || target.get("acct").is_some() {
+                self.accounts
+                    .push(serde_yaml::from_value(target).map_err(invalid)?);
+            } else {
+                self.instance_info
+                    .push(serde_yaml::from_value(target).map_err(invalid)?);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets the access tokens without one to the token of their instance in the secrets
+    /// directory, or for metric groups to the token named like the group.
+    fn read_secrets_dir(&mut self) -> Result<(), LoadError> {
+        let Some(secrets_dir) = self.secrets_dir.clone() else {
+            return Ok(());
+        };
+
+        for (instance, access_token) in self
+            .access_tokens_mut()
+            .into_iter()
+            .filter(|(_, access_token)| access_token.get().is_none())
+        {
+            // Instances with a scheme, like `http://localhost:3000`, are looked up without it
+            let name = domain::to_ascii(instance.rsplit("://").next().unwrap_or_default());
+            let path = Path::new(&secrets_dir).join(name);
+            match fs::read_to_string(&path) {
+                Ok(value) => access_token.value = Some(value.trim().to_string()),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(LoadError::Io(err)),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the paths which are watched for changes to reload the targets, besides the
+    /// configuration file.
+    pub fn watched_dirs(&self) -> Vec<&str> {
+        self.targets_dir
+            .iter()
+            .chain(&self.secrets_dir)
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Adds the accounts of all account groups to the accounts with the labels of their group.
+    fn expand_account_groups(&mut self) {
+        for group in std::mem::take(&mut self.account_groups).into_values() {
+            for mut account in group.accounts {
+                for (name, value) in &group.labels {
+                    account
+                        .labels
+                        .entry(name.clone())
+                        .or_insert_with(|| value.clone());
+                }
+                self.accounts.push(account);
+            }
+        }
+    }
+
+    /// Returns the names of the instances which are enabled.
+    pub fn enabled_instances(&self) -> Vec<String> {
+        self.instance_info
+            .iter()
+            .filter(|instance| instance.enabled)
+            .map(|instance| instance.instance.clone())
+            .collect()
+    }
+
+    /// Returns the request timeouts of the instances which override the timeout of the client.
+    pub fn instance_timeouts(&self) -> HashMap<String, u64> {
+        self.instance_info
+            .iter()
+            .filter_map(|instance| Some((instance.instance.clone(), instance.timeout_seconds?)))
+            .collect()
+    }
+
+    /// Returns the accounts which are enabled.
+    pub fn enabled_accounts(&self) -> Vec<AccountConfig> {
+        self.accounts
+            .iter()
+            .filter(|account| account.enabled)
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the configuration as YAML with all secrets replaced by `<secret>`.
+    pub fn to_redacted_yaml(&self) -> String {
+        let mut value = serde_yaml::to_value(self).unwrap();
+        redact(&mut value, None);
+        serde_yaml::to_string(&value).unwrap()
+    }
+
+    /// Returns the names of all instances of the configuration.
+    fn instances_mut(&mut self) -> Vec<&mut String> {
+        let instance_info = self
+            .instance_info
+            .iter_mut()
+            .map(|instance| &mut instance.instance);
+        let discover_instances = self
+            .discover_instances
+            .iter_mut()
+            .map(|rule| &mut rule.seed);
+        let accounts = self
+            .accounts
+            .iter_mut()
+            .map(|account| &mut account.instance);
+        let discover_accounts = self
+            .discover_accounts
+            .iter_mut()
+            .map(|rule| &mut rule.instance);
+        let polls = self.polls.iter_mut().map(|poll| &mut poll.instance);
+        let streaming = self
+            .streaming
+            .iter_mut()
+            .map(|streaming| &mut streaming.instance);
+        let search_probes = self
+            .search_probes
+            .iter_mut()
+            .map(|probe| &mut probe.instance);
+        let resolve_probes = self
+            .resolve_probes
+            .iter_mut()
+            .map(|probe| &mut probe.instance);
+        let tag_measures = self
+            .tag_measures
+            .iter_mut()
+            .map(|measures| &mut measures.instance);
+        let canaries = self.canaries.iter_mut().map(|canary| &mut canary.instance);
+        let federation_probes = self
+            .federation_probes
+            .iter_mut()
+            .flat_map(|probe| [&mut probe.from.instance, &mut probe.to.instance]);
+        let media_probes = self
+            .media_probes
+            .iter_mut()
+            .map(|probe| &mut probe.instance);
+        let notifiers = self
+            .alerting
+            .iter_mut()
+            .flat_map(|alerting| &mut alerting.mastodon)
+            .map(|notifier| &mut notifier.instance);
+        let metric_groups = self
+            .metric_groups
+            .iter_mut()
+            .flat_map(|group| &mut group.instances);
+
+        instance_info
+            .chain(discover_instances)
+            .chain(accounts)
+            .chain(discover_accounts)
+            .chain(polls)
+            .chain(streaming)
+            .chain(search_probes)
+            .chain(resolve_probes)
+            .chain(tag_measures)
+            .chain(canaries)
+            .chain(federation_probes)
+            .chain(&mut self.posting_rate)
+            .chain(&mut self.peers)
+            .chain(media_probes)
+            .chain(notifiers)
+            .chain(metric_groups)
+            .collect()
+    }
+
+    /// Returns the configured Mastodon access tokens with their instance and the scope their use
+    /// needs, e.g. `read` to collect accounts or `write` to post canaries.
+    pub fn access_tokens(&self) -> Vec<(&str, &AccessToken, &'static str)> {
+        let accounts = self
+            .accounts
+            .iter()
+            .map(|account| (account.instance.as_str(), &account.access_token, "read"));
+        let streaming = self
+            .streaming
+            .iter()
+            .map(|streaming| (streaming.instance.as_str(), &streaming.access_token, "read"));
+        let search_probes = self
+            .search_probes
+            .iter()
+            .map(|probe| (probe.instance.as_str(), &probe.access_token, "read"));
+        let resolve_probes = self
+            .resolve_probes
+            .iter()
+            .map(|probe| (probe.instance.as_str(), &probe.access_token, "read"));
+        let tag_measures = self.tag_measures.iter().map(|measures| {
+            (
+                measures.instance.as_str(),
+                &measures.access_token,
+                "admin:read",
+            )
+        });
+        let canaries = self
+            .canaries
+            .iter()
+            .map(|canary| (canary.instance.as_str(), &canary.access_token, "write"));
+        let federation_probes = self.federation_probes.iter().flat_map(|probe| {
+            [
+                (
+                    probe.from.instance.as_str(),
+                    &probe.from.access_token,
+                    "write",
+                ),
+                (probe.to.instance.as_str(), &probe.to.access_token, "read"),
+            ]
+        });
+        let notifiers = self
+            .alerting
+            .iter()
+            .flat_map(|alerting| &alerting.mastodon)
+            .map(|notifier| (notifier.instance.as_str(), &notifier.access_token, "write"));
+
+        accounts
+            .chain(streaming)
+            .chain(search_probes)
+            .chain(resolve_probes)
+            .chain(tag_measures)
+            .chain(canaries)
+            .chain(federation_probes)
+            .chain(notifiers)
+            .filter(|(_, access_token, _)| access_token.get().is_some())
+            .collect()
+    }
+
+    /// Returns whether any access token is written in the configuration file itself, instead of
+    /// referencing an environment variable or being read from a file, a command or the secrets
+    /// directory.
+    pub fn has_inline_access_tokens(&self) -> bool {
+        self.access_tokens()
+            .iter()
+            .any(|(_, access_token, _)| access_token.inline)
+    }
+
+    /// Returns all access tokens of the configuration with their instance, or the name of their
+    /// group for the tokens of metric groups, which aren't tied to one instance.
+    fn access_tokens_mut(&mut self) -> Vec<(&str, &mut AccessToken)> {
+        let accounts = self
+            .accounts
+            .iter_mut()
+            .map(|account| (account.instance.as_str(), &mut account.access_token));
+        let streaming = self
+            .streaming
+            .iter_mut()
+            .map(|streaming| (streaming.instance.as_str(), &mut streaming.access_token));
+        let search_probes = self
+            .search_probes
+            .iter_mut()
+            .map(|probe| (probe.instance.as_str(), &mut probe.access_token));
+        let resolve_probes = self
+            .resolve_probes
+            .iter_mut()
+            .map(|probe| (probe.instance.as_str(), &mut probe.access_token));
+        let tag_measures = self
+            .tag_measures
+            .iter_mut()
+            .map(|measures| (measures.instance.as_str(), &mut measures.access_token));
+        let canaries = self
+            .canaries
+            .iter_mut()
+            .map(|canary| (canary.instance.as_str(), &mut canary.access_token));
+        let federation_probes = self.federation_probes.iter_mut().flat_map(|probe| {
+            [
+                (probe.from.instance.as_str(), &mut probe.from.access_token),
+                (probe.to.instance.as_str(), &mut probe.to.access_token),
+            ]
+        });
+
+        let notifiers = self
+            .alerting
+            .iter_mut()
+            .flat_map(|alerting| &mut alerting.mastodon)
+            .map(|notifier| (notifier.instance.as_str(), &mut notifier.access_token));
+        let metric_groups = self
+            .metric_groups
+            .iter_mut()
+            .map(|group| (group.name.as_str(), &mut group.access_token));
+
+        accounts
+            .chain(streaming)
+            .chain(search_probes)
+            .chain(resolve_probes)
+            .chain(tag_measures)
+            .chain(canaries)
+            .chain(federation_probes)
+            .chain(notifiers)
+            .chain(metric_groups)
+            .collect()
+    }
+
+    fn validate(&self) -> Result<(), LoadError> {
+        if self.version > CONFIG_VERSION {
+            return Err(LoadError::Invalid(format!(
+                "version {} is newer than the supported version {}",
+                self.version, CONFIG_VERSION
+            )));
+        }
+
+        let required_tokens = self
+            .search_probes
+            .iter()
+            .map(|probe| (&probe.instance, &probe.access_token))
+            .chain(
+                self.resolve_probes
+                    .iter()
+                    .map(|probe| (&probe.instance, &probe.access_token)),
+            )
+            .chain(
+                self.tag_measures
+                    .iter()
+                    .map(|measures| (&measures.instance, &measures.access_token)),
+            )
+            .chain(
+                self.canaries
+                    .iter()
+                    .map(|canary| (&canary.instance, &canary.access_token)),
+            )
+            .chain(self.federation_probes.iter().flat_map(|probe| {
+                [
+                    (&probe.from.instance, &probe.from.access_token),
+                    (&probe.to.instance, &probe.to.access_token),
+                ]
+            }))
+            .chain(
+                self.alerting
+                    .iter()
+                    .flat_map(|alerting| &alerting.mastodon)
+                    .map(|notifier| (&notifier.instance, &notifier.access_token)),
+            );
+        for (instance, access_token) in required_tokens {
+            if access_token.get().is_none() {
+                return Err(LoadError::Invalid(format!(
+                    "probe on {} needs an access token",
+                    instance
+                )));
+            }
+        }
+
+        for derived in &self.derived_metrics {
+            if !is_valid_label_name(&derived.name) {
+                return Err(LoadError::Invalid(format!(
+                    "invalid derived metric name {}",
+                    derived.name
+                )));
+            }
+            Expression::parse(&derived.expression).map_err(LoadError::Invalid)?;
+        }
+
+        for schedule in self.schedules.values() {
+            Schedule::parse(schedule).map_err(LoadError::Invalid)?;
+        }
+
+        for rule in self.alerting.iter().flat_map(|alerting| &alerting.rules) {
+            Expression::parse(&rule.expression).map_err(LoadError::Invalid)?;
+            Condition::parse(&rule.condition).map_err(LoadError::Invalid)?;
+        }
+
+        for nameserver in self.dns.iter().flat_map(|dns| &dns.nameservers) {
+            if nameserver.parse::<IpAddr>().is_err() && nameserver.parse::<SocketAddr>().is_err() {
+                return Err(LoadError::Invalid(format!(
+                    "invalid nameserver {}",
+                    nameserver
+                )));
+            }
+        }
+
+        if let Some(recent_statuses) = self.account_recent_statuses {
+            if !(1..=MAX_RECENT_STATUSES).contains(&recent_statuses) {
+                return Err(LoadError::Invalid(format!(
+                    "account_recent_statuses must be between 1 and {}",
+                    MAX_RECENT_STATUSES
+                )));
+            }
+        }
+
+        if let Some(sharding) = &self.sharding {
+            match sharding.index {
+                Some(index) if index < sharding.total => {}
+                Some(index) => {
+                    return Err(LoadError::Invalid(format!(
+                        "shard index {} is not below the total {}",
+                        index, sharding.total
+                    )))
+                }
+                None => {
+                    return Err(LoadError::Invalid(
+                        "sharding needs an index or index_env".to_string(),
+                    ))
+                }
+            }
+        }
+
+        for (index, label) in self.instance_info_labels.iter().enumerate() {
+            if self.instance_info_labels[..index].contains(label) {
+                return Err(LoadError::Invalid(format!(
+                    "instance_info_labels contains {} more than once",
+                    label.name()
+                )));
+            }
+        }
+
+        for webhook in self.alerting.iter().flat_map(|alerting| &alerting.webhooks) {
+            if webhook.url.is_empty() {
+                return Err(LoadError::Invalid(
+                    "webhook needs a url or url_file".to_string(),
+                ));
+            }
+        }
+
+        if let Some(push) = &self.push {
+            if push.pushgateway_url.is_none() && push.remote_write_url.is_none() {
+                return Err(LoadError::Invalid(
+                    "push needs a pushgateway_url or remote_write_url".to_string(),
+                ));
+            }
+            if push.job.is_empty() || push.job.contains('/') {
+                return Err(LoadError::Invalid(format!(
+                    "push job {:?} must be non-empty and must not contain a slash",
+                    push.job
+                )));
+            }
+        }
+
+        for (index, group) in self.metric_groups.iter().enumerate() {
+            let valid = !group.name.is_empty()
+                && group
+                    .name
+                    .chars()
+                    .all(|char| char.is_ascii_alphanumeric() || char == '-' || char == '_');
+            if !valid {
+                return Err(LoadError::Invalid(format!(
+                    "invalid metric group name {}",
+                    group.name
+                )));
+            }
+            if self.metric_groups[..index]
+                .iter()
+                .any(|other| other.name == group.name)
+            {
+                return Err(LoadError::Invalid(format!(
+                    "duplicate metric group {}",
+                    group.name
+                )));
+            }
+        }
+
+        if let Some(status_tracker) = &self.status_tracker {
+            if status_tracker.interval_seconds == 0 {
+                return Err(LoadError::Invalid(
+                    "status_tracker interval_seconds must be greater than 0".to_string(),
+                ));
+            }
+            // Rates are derived from the oldest and newest sample, so at least two are kept
+            if status_tracker.retention_seconds <= status_tracker.interval_seconds {
+                return Err(LoadError::Invalid(
+                    "status_tracker retention_seconds must be greater than interval_seconds"
+                        .to_string(),
+                ));
+            }
+        }
+
+        for canary in &self.canaries {
+            if canary.interval_seconds == 0 {
+                return Err(LoadError::Invalid(format!(
+                    "canary on {} needs an interval_seconds greater than 0",
+                    canary.instance
+                )));
+            }
+        }
+
+        for probe in &self.federation_probes {
+            if probe.interval_seconds == 0 {
+                return Err(LoadError::Invalid(format!(
+                    "federation probe from {} to {} needs an interval_seconds greater than 0",
+                    probe.from.instance, probe.to.instance
+                )));
+            }
+        }
+
+        for account in &self.accounts {
+            if account.id.is_some() == account.acct.is_some() {
+                return Err(LoadError::Invalid(format!(
+                    "account on {} needs either an id or an acct",
+                    account.instance
+                )));
+            }
+
+            for name in account.labels.keys() {
+                if !is_valid_label_name(name)
+                    || ["instance", "account_id", "alias"].contains(&name.as_str())
+                {
+                    return Err(LoadError::Invalid(format!(
+                        "account on {} has invalid label name {}",
+                        account.instance, name
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,402 @@
+use serde_json::{json, Value};
+
+use crate::config::Config;
+
+/// The width of a panel, two panels fill a row of the 24 column grid.
+const PANEL_WIDTH: u32 = 12;
+
+/// The height of a panel in grid units.
+const PANEL_HEIGHT: u32 = 8;
+
+/// A time series panel of the dashboard.
+struct Panel {
+    title: String,
+    expression: String,
+    legend: &'static str,
+    unit: &'static str,
+}
+
+impl Panel {
+    fn new(title: &str, expression: &str, legend: &'static str, unit: &'static str) -> Self {
+        Self {
+            title: title.to_string(),
+            expression: expression.to_string(),
+            legend,
+            unit,
+        }
+    }
+}
+
+/// A collapsible row of panels for a section of the configuration.
+struct Row {
+    title: String,
+    panels: Vec<Panel>,
+}
+
+/// Returns the rows of panels for the metrics exported with the configuration.
+fn rows(config: &Config) -> Vec<Row> {
+    let mut rows = Vec::new();
+    let instance = "instance=~\"$instance\"";
+
+    if !config.instance_info.is_empty() || !config.discover_instances.is_empty() {
+        let mut panels = vec![
+            Panel::new(
+                "Version",
+                &format!("mastodon_instance_version_info{{{}}}", instance),
+                "{{instance}} {{version}}",
+                "none",
+            ),
+            Panel::new(
+                "Registrations enabled",
+                &format!("mastodon_registrations_enabled{{{}}}", instance),
+                "{{instance}}",
+                "bool",
+            ),
+            Panel::new(
+                "Remaining ratelimit",
+                &format!("mastodon_ratelimit_remaining{{{}}}", instance),
+                "{{instance}}",
+                "none",
+            ),
+            Panel::new(
+                "Scrape errors",
+                &format!("rate(mastodon_scrape_errors_total{{{}}}[5m])", instance),
+                "{{instance}} {{reason}}",
+                "reqps",
+            ),
+            Panel::new(
+                "Request duration (p95)",
+                &format!(
+                    "histogram_quantile(0.95, sum by (instance, le) (rate(mastodon_request_duration_seconds_bucket{{{}}}[5m])))",
+                    instance
+                ),
+                "{{instance}}",
+                "s",
+            ),
+        ];
+        if config.upstream_releases.is_some() {
+            panels.push(Panel::new(
+                "Patches behind upstream",
+                &format!("mastodon_instance_version_behind_patches{{{}}}", instance),
+                "{{instance}}",
+                "none",
+            ));
+        }
+        if config.health_checks {
+            panels.push(Panel::new(
+                "Health endpoint",
+                &format!("mastodon_instance_health_up{{{}}}", instance),
+                "{{instance}}",
+                "bool",
+            ));
+        }
+        if config.well_known_checks {
+            panels.push(Panel::new(
+                "Well-known endpoints",
+                &format!("mastodon_well_known_success{{{}}}", instance),
+                "{{instance}} {{endpoint}}",
+                "bool",
+            ));
+        }
+
+        rows.push(Row {
+            title: "Instances".to_string(),
+            panels,
+        });
+    }
+
+    if !config.accounts.is_empty() || !config.discover_accounts.is_empty() {
+        let account = "instance=~\"$instance\", account_id=~\"$account\"";
+        rows.push(Row {
+            title: "Accounts".to_string(),
+            panels: vec![
+                Panel::new(
+                    "Followers",
+                    &format!("mastodon_account_followers_count{{{}}}", account),
+                    "{{instance}} {{username}}",
+                    "none",
+                ),
+                Panel::new(
+                    "Following",
+                    &format!("mastodon_account_following_count{{{}}}", account),
+                    "{{instance}} {{username}}",
+                    "none",
+                ),
+                Panel::new(
+                    "Statuses",
+                    &format!("mastodon_account_statuses_count{{{}}}", account),
+                    "{{instance}} {{username}}",
+                    "none",
+                ),
+                Panel::new(
+                    "Time since last status",
+                    &format!("time() - mastodon_account_last_status_at{{{}}}", account),
+                    "{{instance}} {{username}}",
+                    "s",
+                ),
+            ],
+        });
+    }
+
+    if !config.posting_rate.is_empty() {
+        rows.push(Row {
+            title: "Posting rate".to_string(),
+            panels: vec![
+                Panel::new(
+                    "Local posting rate",
+                    &format!("mastodon_instance_local_posting_rate{{{}}}", instance),
+                    "{{instance}}",
+                    "none",
+                ),
+                Panel::new(
+                    "Federated posting rate",
+                    &format!("mastodon_instance_federated_posting_rate{{{}}}", instance),
+                    "{{instance}}",
+                    "none",
+                ),
+            ],
+        });
+    }
+
+    if !config.peers.is_empty() {
+        rows.push(Row {
+            title: "Federation".to_string(),
+            panels: vec![
+                Panel::new(
+                    "Peers",
+                    &format!("mastodon_instance_peers_count{{{}}}", instance),
+                    "{{instance}}",
+                    "none",
+                ),
+                Panel::new(
+                    "Shared peers",
+                    "mastodon_federation_shared_peers",
+                    "{{instance_a}} and {{instance_b}}",
+                    "none",
+                ),
+            ],
+        });
+    }
+
+    if !config.polls.is_empty() {
+        rows.push(Row {
+            title: "Polls".to_string(),
+            panels: vec![Panel::new(
+                "Poll votes",
+                &format!("mastodon_poll_votes_count{{{}}}", instance),
+                "{{poll_id}} {{option}}",
+                "none",
+            )],
+        });
+    }
+
+    if config.status_tracker.is_some() {
+        rows.push(Row {
+            title: "Status engagement".to_string(),
+            panels: vec![
+                Panel::new(
+                    "Reblogs per hour",
+                    &format!("mastodon_status_reblogs_per_hour{{{}}}", instance),
+                    "{{instance}} {{status_id}}",
+                    "none",
+                ),
+                Panel::new(
+                    "Favourites per hour",
+                    &format!("mastodon_status_favourites_per_hour{{{}}}", instance),
+                    "{{instance}} {{status_id}}",
+                    "none",
+                ),
+                Panel::new(
+                    "Replies per hour",
+                    &format!("mastodon_status_replies_per_hour{{{}}}", instance),
+                    "{{instance}} {{status_id}}",
+                    "none",
+                ),
+            ],
+        });
+    }
+
+    let mut probes = Vec::new();
+    if !config.search_probes.is_empty() {
+        probes.push(Panel::new(
+            "Search duration",
+            &format!("mastodon_search_duration_seconds{{{}}}", instance),
+            "{{instance}}",
+            "s",
+        ));
+    }
+    if !config.media_probes.is_empty() {
+        probes.push(Panel::new(
+            "Media fetch duration",
+            &format!("mastodon_media_fetch_duration_seconds{{{}}}", instance),
+            "{{instance}}",
+            "s",
+        ));
+    }
+    if !config.resolve_probes.is_empty() {
+        probes.push(Panel::new(
+            "Remote accounts resolvable",
+            &format!("mastodon_federation_resolvable{{{}}}", instance),
+            "{{instance}} {{acct}}",
+            "bool",
+        ));
+    }
+    if !config.tag_measures.is_empty() {
+        probes.push(Panel::new(
+            "Hashtag uses",
+            &format!("mastodon_admin_tag_uses{{{}}}", instance),
+            "{{instance}} #{{tag}}",
+            "none",
+        ));
+    }
+    if !config.canaries.is_empty() {
+        probes.push(Panel::new(
+            "Canary post duration",
+            &format!("mastodon_canary_post_duration_seconds{{{}}}", instance),
+            "{{instance}}",
+            "s",
+        ));
+    }
+    if !config.federation_probes.is_empty() {
+        probes.push(Panel::new(
+            "Federation delivery",
+            "mastodon_federation_delivery_seconds",
+            "{{from}} → {{to}}",
+            "s",
+        ));
+    }
+    if !config.streaming.is_empty() {
+        probes.push(Panel::new(
+            "Streaming events",
+            &format!("rate(mastodon_streaming_events_total{{{}}}[5m])", instance),
+            "{{instance}} {{event}}",
+            "ops",
+        ));
+    }
+    if !probes.is_empty() {
+        rows.push(Row {
+            title: "Probes".to_string(),
+            panels: probes,
+        });
+    }
+
+    if !config.derived_metrics.is_empty() {
+        rows.push(Row {
+            title: "Derived metrics".to_string(),
+            panels: config
+                .derived_metrics
+                .iter()
+                .map(|derived| {
+                    let metric = format!("mastodon_derived_{}", derived.name);
+                    Panel::new(&derived.name, &metric, "", "none")
+                })
+                .collect(),
+        });
+    }
+
+    rows
+}
+
+/// Returns a template variable offering the given values, or all values of the label if empty.
+fn variable(name: &str, label: &str, values: Vec<String>) -> Value {
+    if values.is_empty() {
+        return json!({
+            "name": name,
+            "type": "query",
+            "datasource": { "type": "prometheus", "uid": "${datasource}" },
+            "query": format!("label_values({})", label),
+            "includeAll": true,
+            "multi": true,
+            "current": { "text": "All", "value": "$__all" },
+        });
+    }
+
+    json!({
+        "name": name,
+        "type": "custom",
+        "query": values.join(","),
+        "includeAll": true,
+        "multi": true,
+        "current": { "text": "All", "value": "$__all" },
+    })
+}
+
+/// Generates a Grafana dashboard with panels for the metrics exported with the configuration.
+///
+/// The instance and account variables offer the configured targets, or all exported targets if
+/// targets are discovered.
+pub fn generate(config: &Config) -> Value {
+    let instances = if config.discover_instances.is_empty() {
+        config.enabled_instances()
+    } else {
+        Vec::new()
+    };
+    let accounts = if config.discover_accounts.is_empty() {
+        config
+            .enabled_accounts()
+            .iter()
+            .filter_map(|account| account.id.clone())
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let mut panels = Vec::new();
+    let mut y = 0;
+    let mut id = 0;
+    for row in rows(config) {
+        id += 1;
+        panels.push(json!({
+            "id": id,
+            "type": "row",
+            "title": row.title,
+            "collapsed": false,
+            "gridPos": { "h": 1, "w": 24, "x": 0, "y": y },
+        }));
+        y += 1;
+
+        for (index, panel) in row.panels.iter().enumerate() {
+            id += 1;
+            panels.push(json!({
+                "id": id,
+                "type": "timeseries",
+                "title": panel.title,
+                "datasource": { "type": "prometheus", "uid": "${datasource}" },
+                "fieldConfig": { "defaults": { "unit": panel.unit }, "overrides": [] },
+                "gridPos": {
+                    "h": PANEL_HEIGHT,
+                    "w": PANEL_WIDTH,
+                    "x": (index as u32 % 2) * PANEL_WIDTH,
+                    "y": y + (index as u32 / 2) * PANEL_HEIGHT,
+                },
+                "targets": [{
+                    "refId": "A",
+                    "expr": panel.expression,
+                    "legendFormat": panel.legend,
+                }],
+            }));
+        }
+        y += (row.panels.len() as u32).div_ceil(2) * PANEL_HEIGHT;
+    }
+
+    json!({
+        "title": "Mastodon",
+        "uid": "mastodon-exporter",
+        "tags": ["mastodon"],
+        "schemaVersion": 39,
+        "refresh": "1m",
+        "time": { "from": "now-24h", "to": "now" },
+        "templating": {
+            "list": [
+                {
+                    "name": "datasource",
+                    "type": "datasource",
+                    "query": "prometheus",
+                },
+                variable("instance", "mastodon_info, instance", instances),
+                variable("account", "mastodon_account_followers_count, account_id", accounts),
+            ],
+        },
+        "panels": panels,
+    })
+}
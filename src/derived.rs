@@ -0,0 +1,371 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use prometheus::{proto::MetricFamily, GaugeVec, Opts};
+
+use crate::{
+    config::DerivedMetricConfig,
+    metrics::{self, WithLimitedLabelValues, REGISTRY},
+};
+
+lazy_static! {
+    /// The gauges of the derived metrics, created on their first evaluation.
+    static ref DERIVED_METRICS: Mutex<HashMap<String, GaugeVec>> = Mutex::new(HashMap::new());
+}
+
+/// The labels of a sample, sorted by name.
+pub type Labels = BTreeMap<String, String>;
+
+/// An arithmetic expression over collected metrics.
+#[derive(Debug)]
+pub enum Expression {
+    Number(f64),
+
+    /// A metric without its `mastodon_` prefix, e.g. `account_followers_count`.
+    Metric(String),
+
+    Binary(Box<Expression>, char, Box<Expression>),
+}
+
+impl Expression {
+    /// Parses an expression of numbers, metric names, `+`, `-`, `*`, `/` and parentheses.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+        };
+
+        let expression = parser.sum()?;
+        match parser.tokens.get(parser.position) {
+            Some(token) => Err(format!("unexpected {:?} in expression {}", token, input)),
+            None => Ok(expression),
+        }
+    }
+
+    /// Returns the names of all metrics in the expression.
+    fn metrics<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            Expression::Number(_) => {}
+            Expression::Metric(name) => names.push(name),
+            Expression::Binary(left, _, right) => {
+                left.metrics(names);
+                right.metrics(names);
+            }
+        }
+    }
+
+    /// Evaluates the expression for every label set of the first metric in the expression.
+    ///
+    /// Samples are matched by identical labels, like one-to-one vector matching in PromQL. Label
+    /// sets missing from any metric of the expression and non-finite results like divisions by
+    /// zero are skipped.
+    pub fn evaluate_all(&self, families: &[MetricFamily]) -> Vec<(Labels, f64)> {
+        let mut names = Vec::new();
+        self.metrics(&mut names);
+        let Some(first) = names.first() else {
+            return Vec::new();
+        };
+
+        let samples: HashMap<&str, HashMap<Labels, f64>> = names
+            .iter()
+            .filter_map(|name| {
+                let family_name = format!("mastodon_{}", name);
+                let family = families
+                    .iter()
+                    .find(|family| family.get_name() == family_name)?;
+                Some((*name, samples(family)))
+            })
+            .collect();
+        let Some(label_sets) = samples.get(first) else {
+            return Vec::new();
+        };
+
+        label_sets
+            .keys()
+            .filter_map(|labels| {
+                let value = self.evaluate(&samples, labels)?;
+                value.is_finite().then(|| (labels.clone(), value))
+            })
+            .collect()
+    }
+
+    /// Evaluates the expression for the samples with the given labels.
+    ///
+    /// Returns `None` if a metric has no sample with these labels.
+    fn evaluate(
+        &self,
+        samples: &HashMap<&str, HashMap<Labels, f64>>,
+        labels: &Labels,
+    ) -> Option<f64> {
+        match self {
+            Expression::Number(value) => Some(*value),
+            Expression::Metric(name) => samples.get(name.as_str())?.get(labels).copied(),
+            Expression::Binary(left, operator, right) => {
+                let left = left.evaluate(samples, labels)?;
+                let right = right.evaluate(samples, labels)?;
+
+                Some(match operator {
+                    '+' => left + right,
+                    '-' => left - right,
+                    '*' => left * right,
+                    _ => left / right,
+                })
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Operator(char),
+    Open,
+    Close,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&char) = chars.peek() {
+        match char {
+            ' ' | '\t' => {
+                chars.next();
+            }
+            '+' | '-' | '*' | '/' => {
+                tokens.push(Token::Operator(char));
+                chars.next();
+            }
+            '(' => {
+                tokens.push(Token::Open);
+                chars.next();
+            }
+            ')' => {
+                tokens.push(Token::Close);
+                chars.next();
+            }
+            '0'..='9' | '.' => {
+                let mut number = String::new();
+                while let Some(&char) = chars.peek().filter(|c| c.is_ascii_digit() || **c == '.') {
+                    number.push(char);
+                    chars.next();
+                }
+                let number = number
+                    .parse()
+                    .map_err(|_| format!("invalid number {} in expression {}", number, input))?;
+                tokens.push(Token::Number(number));
+            }
+            'a'..='z' | 'A'..='Z' | '_' => {
+                let mut ident = String::new();
+                while let Some(&char) = chars
+                    .peek()
+                    .filter(|c| c.is_ascii_alphanumeric() || **c == '_')
+                {
+                    ident.push(char);
+                    chars.next();
+                }
+                tokens.push(Token::Ident(ident));
+            }
+            _ => return Err(format!("unexpected {} in expression {}", char, input)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// A recursive descent parser respecting operator precedence.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn binary(
+        &mut self,
+        operators: [char; 2],
+        operand: fn(&mut Self) -> Result<Expression, String>,
+    ) -> Result<Expression, String> {
+        let mut expression = operand(self)?;
+
+        while let Some(Token::Operator(operator)) = self.tokens.get(self.position) {
+            let operator = *operator;
+            if !operators.contains(&operator) {
+                break;
+            }
+            self.position += 1;
+            expression =
+                Expression::Binary(Box::new(expression), operator, Box::new(operand(self)?));
+        }
+
+        Ok(expression)
+    }
+
+    fn sum(&mut self) -> Result<Expression, String> {
+        self.binary(['+', '-'], Self::product)
+    }
+
+    fn product(&mut self) -> Result<Expression, String> {
+        self.binary(['*', '/'], Self::operand)
+    }
+
+    fn operand(&mut self) -> Result<Expression, String> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Expression::Number(*value)),
+            Some(Token::Ident(name)) => Ok(Expression::Metric(name.clone())),
+            Some(Token::Open) => {
+                let expression = self.sum()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expression),
+                    _ => Err("missing closing parenthesis in expression".to_string()),
+                }
+            }
+            Some(token) => Err(format!("unexpected {:?} in expression", token)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+/// Returns the values of all gauge and counter samples of the metric family by their labels.
+pub fn samples(family: &MetricFamily) -> HashMap<Labels, f64> {
+    family
+        .get_metric()
+        .iter()
+        .filter_map(|metric| {
+            let value = if metric.has_gauge() {
+                metric.get_gauge().get_value()
+            } else if metric.has_counter() {
+                metric.get_counter().get_value()
+            } else {
+                return None;
+            };
+            let labels = metric
+                .get_label()
+                .iter()
+                .map(|label| (label.get_name().to_string(), label.get_value().to_string()))
+                .collect();
+
+            Some((labels, value))
+        })
+        .collect()
+}
+
+/// Evaluates the derived metrics over the collected metrics and exports them as gauges.
+pub fn evaluate(derived_metrics: &[DerivedMetricConfig]) {
+    if derived_metrics.is_empty() {
+        return;
+    }
+
+    let families = REGISTRY.gather();
+
+    for derived in derived_metrics {
+        // The expression was validated when loading the configuration
+        let Ok(expression) = Expression::parse(&derived.expression) else {
+            continue;
+        };
+
+        for (labels, value) in expression.evaluate_all(&families) {
+            let mut gauges = DERIVED_METRICS.lock().unwrap();
+            let gauge = gauges.entry(derived.name.clone()).or_insert_with(|| {
+                let label_names: Vec<&str> = labels.keys().map(String::as_str).collect();
+                let gauge = GaugeVec::new(
+                    Opts::new(
+                        format!("mastodon_derived_{}", derived.name),
+                        &derived.expression,
+                    ),
+                    &label_names,
+                )
+                .unwrap();
+                metrics::register_limited(&gauge);
+                gauge
+            });
+
+            let values: Vec<&str> = labels.values().map(String::as_str).collect();
+            if let Some(gauge) = gauge.with_limited_label_values(&values) {
+                gauge.set(value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prometheus::{IntGaugeVec, Registry};
+
+    use super::*;
+
+    fn families() -> Vec<MetricFamily> {
+        let registry = Registry::new();
+        for (name, values) in [
+            ("mastodon_account_followers_count", [10, 30]),
+            ("mastodon_account_following_count", [5, 0]),
+        ] {
+            let gauge = IntGaugeVec::new(Opts::new(name, "help"), &["account_id"]).unwrap();
+            gauge.with_label_values(&["1"]).set(values[0]);
+            gauge.with_label_values(&["2"]).set(values[1]);
+            registry.register(Box::new(gauge)).unwrap();
+        }
+
+        registry.gather()
+    }
+
+    fn evaluate(expression: &str) -> Vec<(String, f64)> {
+        let mut results: Vec<(String, f64)> = Expression::parse(expression)
+            .unwrap()
+            .evaluate_all(&families())
+            .into_iter()
+            .map(|(labels, value)| (labels["account_id"].clone(), value))
+            .collect();
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+        results
+    }
+
+    #[test]
+    fn respects_precedence_and_parentheses() {
+        assert_eq!(
+            evaluate("account_followers_count + 2 * 3"),
+            [("1".to_string(), 16.0), ("2".to_string(), 36.0)]
+        );
+        assert_eq!(
+            evaluate("(account_followers_count + 2) * 3"),
+            [("1".to_string(), 36.0), ("2".to_string(), 96.0)]
+        );
+        assert_eq!(
+            evaluate("account_followers_count - 4 - 1"),
+            [("1".to_string(), 5.0), ("2".to_string(), 25.0)]
+        );
+    }
+
+    #[test]
+    fn skips_non_finite_results() {
+        assert_eq!(
+            evaluate("account_followers_count / account_following_count"),
+            [("1".to_string(), 2.0)]
+        );
+    }
+
+    #[test]
+    fn skips_unknown_metrics() {
+        assert!(evaluate("account_unknown * 2").is_empty());
+        assert!(evaluate("account_followers_count + account_unknown").is_empty());
+        assert!(evaluate("42").is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        assert!(Expression::parse("").is_err());
+        assert!(Expression::parse("account_followers_count +").is_err());
+        assert!(Expression::parse("(account_followers_count").is_err());
+        assert!(Expression::parse("account_followers_count)").is_err());
+        assert!(Expression::parse("account_followers_count % 2").is_err());
+        assert!(Expression::parse("1.2.3").is_err());
+        assert!(Expression::parse("2 3").is_err());
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::BinaryHeap;
+
+use crate::{
+    config::{
+        AccountConfig, Config, DiscoverAccountsConfig, DiscoverInstancesConfig, InstanceConfig,
+    },
+    mastodon::{self, domain, MastodonClient},
+};
+
+/// The maximum number of accounts the directory returns per page.
+const DIRECTORY_PAGE_SIZE: usize = 80;
+
+/// Returns whether the domain matches the pattern, where `*` matches any characters.
+fn matches(pattern: &str, domain: &str) -> bool {
+    match pattern.split_once('*') {
+        Some((prefix, rest)) => {
+            domain.starts_with(prefix)
+                && (prefix.len()..=domain.len())
+                    .filter(|start| domain.is_char_boundary(*start))
+                    .any(|start| matches(rest, &domain[start..]))
+        }
+        None => pattern == domain,
+    }
+}
+
+/// Returns whether the configured account is the discovered account with the `acct`, comparing
+/// their ids, or the `acct` if the configured account is resolved from it.
+fn is_same_account(configured: &AccountConfig, discovered: &AccountConfig, acct: &str) -> bool {
+    if configured.instance != discovered.instance {
+        return false;
+    }
+    if configured.id.is_some() && configured.id == discovered.id {
+        return true;
+    }
+
+    // Local accounts may be configured with the domain of their instance
+    let local = |acct: &str| {
+        let acct = acct.trim_start_matches('@');
+        acct.strip_suffix(&format!("@{}", configured.instance))
+            .unwrap_or(acct)
+            .to_lowercase()
+    };
+    configured
+        .acct
+        .as_deref()
+        .is_some_and(|configured| !acct.is_empty() && local(configured) == local(acct))
+}
+
+/// Returns the 64 bit FNV-1a hash of the value, which is stable across builds.
+pub(crate) fn hash(value: &str) -> u64 {
+    value.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Discovers instances from the peers of the seed instance.
+///
+/// The peers with the lowest hashes of their domain are sampled, so the same instances are
+/// discovered as long as they remain peers. Only the sample is held in memory while the peers
+/// are received, which may be tens of thousands.
+pub async fn discover_instances(
+    rule: &DiscoverInstancesConfig,
+) -> Result<Vec<String>, mastodon::Error> {
+    let mut sample = BinaryHeap::new();
+
+    MastodonClient::new(&rule.seed)
+        .for_each_peer(|peer| {
+            // Peers are reported in their ASCII form, but labeled in their Unicode form
+            let peer = domain::to_unicode(&peer);
+            if rule.allow.is_empty() || rule.allow.iter().any(|allow| matches(allow, &peer)) {
+                sample.push((hash(&peer), peer));
+                if sample.len() > rule.sample {
+                    sample.pop();
+                }
+            }
+        })
+        .await?;
+
+    let mut peers: Vec<String> = sample.into_iter().map(|(_, peer)| peer).collect();
+    peers.sort_unstable();
+    Ok(peers)
+}
+
+/// Discovers the most recently active accounts of the profile directory of an instance.
+///
+/// The accounts are returned with their `acct`, to recognize accounts configured by it.
+pub async fn discover_accounts(
+    rule: &DiscoverAccountsConfig,
+) -> Result<Vec<(AccountConfig, String)>, mastodon::Error> {
+    let client = MastodonClient::new(&rule.instance);
+    let mut accounts = Vec::new();
+
+    while accounts.len() < rule.limit {
+        let limit = DIRECTORY_PAGE_SIZE.min(rule.limit - accounts.len());
+        let page = client.directory(rule.local, accounts.len(), limit).await?;
+        let last_page = page.len() < limit;
+
+        accounts.extend(page.into_iter().map(|account| {
+            (
+                AccountConfig::from((rule.instance.clone(), account.id)),
+                account.acct,
+            )
+        }));
+
+        if last_page {
+            break;
+        }
+    }
+
+    Ok(accounts)
+}
+
+/// Adds the targets found by the discovery rules of the configuration to its targets.
+///
+/// Targets which are already configured are not added again.
+pub async fn discover(config: &mut Config) {
+    for rule in config.discover_instances.clone() {
+        let instances = match discover_instances(&rule).await {
+            Ok(instances) => instances,
+            Err(err) => {
+                log!("Error: {} instance discovery {}", rule.seed, err);
+                continue;
+            }
+        };
+        log!("{}: Discovered {} instances", rule.seed, instances.len());
+
+        for instance in instances {
+            let configured = config
+                .instance_info
+                .iter()
+                .any(|configured| configured.instance == instance);
+            if !configured {
+                config.instance_info.push(InstanceConfig::from(instance));
+            }
+        }
+    }
+
+    for rule in config.discover_accounts.clone() {
+        let accounts = match discover_accounts(&rule).await {
+            Ok(accounts) => accounts,
+            Err(err) => {
+                log!("Error: {} account discovery {}", rule.instance, err);
+                continue;
+            }
+        };
+        log!("{}: Discovered {} accounts", rule.instance, accounts.len());
+
+        for (account, acct) in accounts {
+            let configured = config
+                .accounts
+                .iter()
+                .any(|configured| is_same_account(configured, &account, &acct));
+            if !configured {
+                config.accounts.push(account);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_domains_exactly() {
+        assert!(matches("mastodon.social", "mastodon.social"));
+        assert!(!matches("mastodon.social", "mastodon.social.example"));
+        assert!(!matches("mastodon.social", "social"));
+    }
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(matches("*", "mastodon.social"));
+        assert!(matches("*.social", "mastodon.social"));
+        assert!(!matches("*.social", "mastodon.online"));
+        assert!(matches("mastodon.*", "mastodon.online"));
+        assert!(matches("*.mastodon.*", "eu.mastodon.example"));
+        assert!(!matches("*.mastodon.*", "mastodon.example"));
+        assert!(matches("m*n.*", "mastodon.social"));
+        assert!(matches("*.social", ".social"));
+        assert!(matches("*.bücher.*", "social.bücher.example"));
+    }
+
+    #[test]
+    fn recognizes_configured_accounts() {
+        let discovered = AccountConfig::from(("mastodon.social".to_string(), "1".to_string()));
+        let by_id = AccountConfig::from(("mastodon.social".to_string(), "1".to_string()));
+        let mut by_acct = AccountConfig::from(("mastodon.social".to_string(), "2".to_string()));
+        by_acct.id = None;
+        by_acct.acct = Some("@Gargron@mastodon.social".to_string());
+
+        assert!(is_same_account(&by_id, &discovered, "Gargron"));
+        assert!(is_same_account(&by_acct, &discovered, "Gargron"));
+        assert!(!is_same_account(
+            &by_acct,
+            &discovered,
+            "Gargron@mastodon.online"
+        ));
+        assert!(!is_same_account(&by_acct, &discovered, ""));
+
+        let other = AccountConfig::from(("mastodon.online".to_string(), "1".to_string()));
+        assert!(!is_same_account(&other, &discovered, "Gargron"));
+    }
+
+    #[test]
+    fn hashes_stably() {
+        assert_eq!(hash(""), 0xcbf29ce484222325);
+        assert_eq!(hash("a"), 0xaf63dc4c8601ec8c);
+    }
+}
@@ -0,0 +1,425 @@
+use std::fs;
+use std::path::Path;
+
+use serde_yaml::Value;
+
+use crate::{
+    config::Config,
+    init,
+    mastodon::{domain, MastodonClient},
+};
+
+/// The indentation of list items if a list has none yet.
+const DEFAULT_INDENT: &str = "  ";
+
+/// An item of a top-level list in a yaml file.
+struct Item {
+    /// The index of the first line of the item.
+    start: usize,
+    /// The index after the last line of the item, leaving out trailing comments.
+    end: usize,
+    value: Value,
+}
+
+/// A top-level list in a yaml file, edited line by line to keep comments and formatting.
+struct List<'a> {
+    lines: Vec<&'a str>,
+    /// The index of the line with the key of the list.
+    key: usize,
+    /// Whether the list is written as an empty flow sequence, `key: []`.
+    empty_flow: bool,
+    indent: String,
+    items: Vec<Item>,
+}
+
+/// Returns whether the line is blank or a comment.
+fn is_comment(line: &str) -> bool {
+    let line = line.trim_start();
+    line.is_empty() || line.starts_with('#')
+}
+
+impl<'a> List<'a> {
+    /// Finds the top-level list with the given key.
+    fn parse(yaml: &'a str, key: &str) -> Result<Self, String> {
+        let lines: Vec<&str> = yaml.lines().collect();
+        let prefix = format!("{}:", key);
+        let key_index = lines
+            .iter()
+            .position(|line| line.starts_with(&prefix))
+            .ok_or_else(|| format!("{} is missing", key))?;
+
+        let value = lines[key_index][prefix.len()..].trim();
+        let value = value.split(" #").next().unwrap_or_default().trim();
+        let empty_flow = value == "[]";
+        if !value.is_empty() && !empty_flow {
+            return Err(format!("{} is not a list of one item per line", key));
+        }
+
+        // The list ends at the next top-level key, items may be indented or not
+        let end = lines[key_index + 1..]
+            .iter()
+            .position(|line| !is_comment(line) && !line.starts_with(' ') && !line.starts_with('-'))
+            .map_or(lines.len(), |end| key_index + 1 + end);
+
+        let mut starts: Vec<usize> = (key_index + 1..end)
+            .filter(|&index| {
+                !is_comment(lines[index]) && lines[index].trim_start().starts_with('-')
+            })
+            .collect();
+        let indent = starts.first().map_or(DEFAULT_INDENT.to_string(), |&start| {
+            let line = lines[start];
+            line[..line.len() - line.trim_start().len()].to_string()
+        });
+        starts.retain(|&start| lines[start].starts_with(&format!("{}-", indent)));
+
+        let mut items = Vec::new();
+        for (index, &start) in starts.iter().enumerate() {
+            let mut item_end = starts.get(index + 1).copied().unwrap_or(end);
+            while item_end > start + 1 && is_comment(lines[item_end - 1]) {
+                item_end -= 1;
+            }
+            let text: Vec<&str> = lines[start..item_end]
+                .iter()
+                .map(|line| line.get(indent.len()..).unwrap_or_default())
+                .collect();
+            let value = serde_yaml::from_str::<Vec<Value>>(&text.join("\n"))
+                .map_err(|err| format!("unable to parse {} line {}: {}", key, start + 1, err))?
+                .pop()
+                .unwrap_or_default();
+            items.push(Item {
+                start,
+                end: item_end,
+                value,
+            });
+        }
+
+        Ok(Self {
+            lines,
+            key: key_index,
+            empty_flow,
+            indent,
+            items,
+        })
+    }
+
+    /// Returns the yaml with the item appended to the list.
+    ///
+    /// The first line of the item starts with `- `, following lines are indented by two spaces.
+    fn append(&self, item: &[String]) -> String {
+        let mut lines: Vec<String> = self.lines.iter().map(|line| line.to_string()).collect();
+        let item = item.iter().enumerate().map(|(index, line)| {
+            let marker = if index == 0 { "- " } else { "  " };
+            format!("{}{}{}", self.indent, marker, line)
+        });
+
+        let position = self.items.last().map_or(self.key + 1, |item| item.end);
+        lines.splice(position..position, item);
+        if self.empty_flow {
+            let key = self.lines[self.key];
+            lines[self.key] = format!("{}:", &key[..key.find(':').unwrap_or_default()]);
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    /// Returns the yaml without the items matching the predicate, and the number of removed
+    /// items.
+    fn remove(&self, predicate: impl Fn(&Value) -> bool) -> (String, usize) {
+        let removed: Vec<&Item> = self
+            .items
+            .iter()
+            .filter(|item| predicate(&item.value))
+            .collect();
+        let mut lines: Vec<String> = Vec::new();
+        for (index, line) in self.lines.iter().enumerate() {
+            if !removed
+                .iter()
+                .any(|item| (item.start..item.end).contains(&index))
+            {
+                lines.push(line.to_string());
+            }
+        }
+        if removed.len() == self.items.len() && !removed.is_empty() {
+            let key = self.lines[self.key];
+            lines[self.key] = format!("{}: []", &key[..key.find(':').unwrap_or_default()]);
+        }
+
+        (lines.join("\n") + "\n", removed.len())
+    }
+}
+
+/// Returns the value as a string, for ids written as numbers.
+fn string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(string) => Some(string.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    }
+}
+
+/// Returns the field of a mapping as a string.
+fn field(value: &Value, name: &str) -> Option<String> {
+    value.get(name).and_then(string)
+}
+
+/// Returns whether the item of `instance_info` is the instance.
+fn is_instance(value: &Value, instance: &str) -> bool {
+    string(value)
+        .or_else(|| field(value, "instance"))
+        .is_some_and(|configured| domain::to_unicode(&configured) == instance)
+}
+
+/// Returns whether the item of `accounts` is the account, given by its username and its id if
+/// known.
+fn is_account(value: &Value, instance: &str, username: &str, id: Option<&str>) -> bool {
+    let (configured, configured_id, acct) = match value {
+        // Accounts listed as `[instance, id]` tuples before version 2
+        Value::Sequence(tuple) => (
+            tuple.first().and_then(string),
+            tuple.get(1).and_then(string),
+            None,
+        ),
+        _ => (
+            field(value, "instance"),
+            field(value, "id"),
+            field(value, "acct"),
+        ),
+    };
+
+    configured.is_some_and(|configured| domain::to_unicode(&configured) == instance)
+        && (acct.is_some_and(|acct| acct.eq_ignore_ascii_case(username))
+            || configured_id.is_some_and(|configured_id| Some(configured_id.as_str()) == id))
+}
+
+/// Writes the edited yaml to the configuration file if it's still a valid configuration.
+fn write(path: &Path, yaml: &str) -> Result<(), String> {
+    Config::from_yaml(yaml)
+        .map_err(|err| format!("the edited configuration is invalid: {}", err))?;
+
+    fs::write(path, yaml).map_err(|err| format!("unable to write {}: {}", path.display(), err))
+}
+
+fn read(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|err| format!("unable to read {}: {}", path.display(), err))
+}
+
+/// Adds the instance to `instance_info` after checking that its API is reachable, unless forced.
+pub async fn add_instance(path: &Path, instance: &str, force: bool) -> Result<(), String> {
+    let instance = domain::to_unicode(instance);
+    let yaml = read(path)?;
+    let list = List::parse(&yaml, "instance_info")?;
+    if list
+        .items
+        .iter()
+        .any(|item| is_instance(&item.value, &instance))
+    {
+        log!("{} is already configured", instance);
+        return Ok(());
+    }
+
+    match MastodonClient::new(instance.as_str()).instance().await {
+        Ok(info) => log!("{}: {} running {}", instance, info.title, info.version),
+        Err(err) if force => log!("{}: Adding anyway, unable to connect: {}", instance, err),
+        Err(err) => return Err(format!("unable to connect to {}: {}", instance, err)),
+    }
+
+    write(path, &list.append(std::slice::from_ref(&instance)))?;
+    log!("Added instance {} to {}", instance, path.display());
+
+    Ok(())
+}
+
+/// Adds the account to `accounts` by its id after resolving its address, or by its username if
+/// forced and the address can't be resolved.
+pub async fn add_account(path: &Path, acct: &str, force: bool) -> Result<(), String> {
+    let account = match init::resolve_account(acct).await {
+        Ok(account) => account,
+        Err(err) if force => {
+            log!("Adding {} by its username, {}", acct, err);
+            let Some((username, instance)) = acct.trim_start_matches('@').split_once('@') else {
+                return Err(err);
+            };
+            init::ResolvedAccount {
+                instance: domain::to_unicode(instance),
+                username: username.to_string(),
+                id: String::new(),
+            }
+        }
+        Err(err) => return Err(err),
+    };
+    let id = Some(account.id.as_str()).filter(|id| !id.is_empty());
+
+    let yaml = read(path)?;
+    let list = List::parse(&yaml, "accounts")?;
+    if list
+        .items
+        .iter()
+        .any(|item| is_account(&item.value, &account.instance, &account.username, id))
+    {
+        log!("{} is already configured", acct);
+        return Ok(());
+    }
+
+    let mut item = vec![format!("instance: {}", account.instance)];
+    match id {
+        Some(id) => item.push(format!("id: \"{}\" # {}", id, account.username)),
+        None => item.push(format!("acct: {}", account.username)),
+    }
+    write(path, &list.append(&item))?;
+    log!(
+        "Added account {} on {} to {}",
+        account.username,
+        account.instance,
+        path.display()
+    );
+
+    Ok(())
+}
+
+/// Removes the instance from `instance_info`.
+pub fn remove_instance(path: &Path, instance: &str) -> Result<(), String> {
+    let instance = domain::to_unicode(instance);
+    let yaml = read(path)?;
+    let (yaml, removed) =
+        List::parse(&yaml, "instance_info")?.remove(|value| is_instance(value, &instance));
+    if removed == 0 {
+        return Err(format!("{} is not configured", instance));
+    }
+
+    write(path, &yaml)?;
+    log!("Removed instance {} from {}", instance, path.display());
+
+    Ok(())
+}
+
+/// Removes the account from `accounts`, whether it's configured by its username or its id.
+///
+/// The id is looked up on the instance, accounts configured by id are kept if that fails.
+pub async fn remove_account(path: &Path, acct: &str) -> Result<(), String> {
+    let Some((username, instance)) = acct.trim_start_matches('@').split_once('@') else {
+        return Err(format!("{} is not an address like username@instance", acct));
+    };
+    let (instance, id) = match init::resolve_account(acct).await {
+        Ok(account) => (account.instance, Some(account.id)),
+        Err(err) => {
+            log!("Removing {} by its username, {}", acct, err);
+            (domain::to_unicode(instance), None)
+        }
+    };
+
+    let yaml = read(path)?;
+    let (yaml, removed) = List::parse(&yaml, "accounts")?
+        .remove(|value| is_account(value, &instance, username, id.as_deref()));
+    if removed == 0 {
+        return Err(format!("{} is not configured", acct));
+    }
+
+    write(path, &yaml)?;
+    log!("Removed account {} from {}", acct, path.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn appends_to_an_empty_list() {
+        let yaml = "instance_info: [] # none yet\naccounts: []\n";
+        let list = List::parse(yaml, "instance_info").unwrap();
+
+        assert_eq!(
+            list.append(&["mas.to".to_string()]),
+            "instance_info:\n  - mas.to\naccounts: []\n"
+        );
+    }
+
+    #[test]
+    fn appends_with_the_indentation_of_the_list() {
+        let item = ["instance: mas.to".to_string(), "id: \"1\"".to_string()];
+
+        let yaml = "accounts:\n    - instance: mastodon.social\n      acct: Gargron\n# trailing\npolls: []\n";
+        assert_eq!(
+            List::parse(yaml, "accounts").unwrap().append(&item),
+            "accounts:\n    - instance: mastodon.social\n      acct: Gargron\n    - instance: mas.to\n      id: \"1\"\n# trailing\npolls: []\n"
+        );
+
+        let yaml = "accounts:\n- instance: mastodon.social\n  acct: Gargron\npolls: []\n";
+        assert_eq!(
+            List::parse(yaml, "accounts").unwrap().append(&item),
+            "accounts:\n- instance: mastodon.social\n  acct: Gargron\n- instance: mas.to\n  id: \"1\"\npolls: []\n"
+        );
+    }
+
+    #[test]
+    fn restores_an_empty_list_after_removing_the_last_item() {
+        let yaml = "instance_info:\n  - mas.to\naccounts: []\n";
+        let (yaml, removed) = List::parse(yaml, "instance_info")
+            .unwrap()
+            .remove(|value| is_instance(value, "mas.to"));
+
+        assert_eq!(removed, 1);
+        assert_eq!(yaml, "instance_info: []\naccounts: []\n");
+    }
+
+    #[test]
+    fn keeps_comments_of_other_items_when_removing() {
+        let yaml = [
+            "instance_info:",
+            "  # the flagship",
+            "  - mastodon.social # large",
+            "  - mas.to # removed",
+            "  # trailing comment",
+            "",
+            "accounts: []",
+        ]
+        .join("\n");
+        let (yaml, removed) = List::parse(&yaml, "instance_info")
+            .unwrap()
+            .remove(|value| is_instance(value, "mas.to"));
+
+        assert_eq!(removed, 1);
+        assert_eq!(
+            yaml,
+            "instance_info:\n  # the flagship\n  - mastodon.social # large\n  # trailing comment\n\naccounts: []\n"
+        );
+    }
+
+    #[test]
+    fn finds_accounts_listed_as_tuples() {
+        let yaml = "accounts:\n  - [mas.to, 109318825996481171]\n  - [mastodon.social, 1]\n";
+        let list = List::parse(yaml, "accounts").unwrap();
+        assert!(is_account(
+            &list.items[0].value,
+            "mas.to",
+            "exporter",
+            Some("109318825996481171")
+        ));
+
+        let (yaml, removed) =
+            list.remove(|value| is_account(value, "mastodon.social", "Gargron", Some("1")));
+        assert_eq!(removed, 1);
+        assert_eq!(yaml, "accounts:\n  - [mas.to, 109318825996481171]\n");
+    }
+
+    #[test]
+    fn only_writes_valid_configurations() {
+        let path =
+            std::env::temp_dir().join(format!("mastodon_exporter-edit-{}.yml", std::process::id()));
+        fs::write(&path, "unchanged").unwrap();
+
+        // Accounts need either an id or an acct, which only validation checks
+        let yaml = "server:\n  http_listen_port: 9498\ninstance_info: []\naccounts:\n  - instance: mas.to\n";
+        assert!(write(&path, yaml).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "unchanged");
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn rejects_lists_in_flow_style() {
+        assert!(List::parse("instance_info: [mas.to]\n", "instance_info").is_err());
+        assert!(List::parse("accounts: []\n", "instance_info").is_err());
+    }
+}
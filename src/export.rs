@@ -0,0 +1,118 @@
+use crate::{
+    derived::{self, Labels},
+    history,
+    metrics::scope::Scope,
+    Collector,
+};
+
+/// The header of the CSV export.
+const HEADER: &str = "timestamp,metric,labels,value";
+
+/// Quotes a CSV field if necessary.
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Escapes a label value like in the Prometheus text format, so the labels of a row can be told
+/// apart even if a value contains quotes or commas.
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn row(timestamp: i64, metric: &str, labels: &Labels, value: f64) -> String {
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape_label_value(value)))
+        .collect();
+
+    format!(
+        "{},{},{},{}\n",
+        timestamp,
+        escape(metric),
+        escape(&labels.join(",")),
+        value
+    )
+}
+
+/// Exports the values of all metrics, or of the given metric, in the scope as CSV.
+///
+/// The values within the range are read from the history if enabled, otherwise the values of the
+/// last collection cycle are exported.
+pub async fn csv(
+    collector: &Collector,
+    scope: &Scope,
+    metric: Option<&str>,
+    range: &str,
+) -> Result<String, String> {
+    let mut csv = format!("{}\n", HEADER);
+
+    if let Some(history) = collector.history() {
+        let range_seconds =
+            history::parse_range(range).ok_or_else(|| format!("invalid range {}", range))?;
+        let rows = history
+            .rows(metric, range_seconds)
+            .await
+            .map_err(|err| format!("unable to query history: {}", err))?;
+
+        for (timestamp, metric, labels, value) in rows {
+            if !scope.includes(
+                labels
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.as_str())),
+            ) {
+                continue;
+            }
+            csv.push_str(&row(timestamp, &metric, &labels, value));
+        }
+
+        return Ok(csv);
+    }
+
+    let timestamp = chrono::Utc::now().timestamp();
+    for family in scope.registry().gather() {
+        if metric.is_some_and(|metric| metric != family.get_name()) {
+            continue;
+        }
+
+        let mut samples: Vec<(Labels, f64)> = derived::samples(&family).into_iter().collect();
+        samples.sort_by(|a, b| a.0.cmp(&b.0));
+        for (labels, value) in samples {
+            csv.push_str(&row(timestamp, family.get_name(), &labels, value));
+        }
+    }
+
+    Ok(csv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_fields() {
+        assert_eq!(escape("mastodon_up"), "mastodon_up");
+        assert_eq!(escape("a,b"), "\"a,b\"");
+        assert_eq!(escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(escape("a\r\nb"), "\"a\r\nb\"");
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        let labels = Labels::from([
+            ("alias".to_string(), "a\",b=\"c".to_string()),
+            ("instance".to_string(), "mastodon.social".to_string()),
+        ]);
+
+        assert_eq!(
+            row(1700000000, "mastodon_up", &labels, 1.0),
+            "1700000000,mastodon_up,\"alias=\"\"a\\\"\",b=\\\"\"c\"\",instance=\"\"mastodon.social\"\"\",1\n"
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    config::FederationProbeConfig,
+    mastodon::{self, MastodonClient},
+    metrics::{
+        WithLimitedLabelValues, MASTODON_FEDERATION_DELIVERY_SECONDS,
+        MASTODON_FEDERATION_DELIVERY_SUCCESS,
+    },
+};
+
+/// The number of seconds between two lookups of the home timeline of the receiving account.
+const POLL_INTERVAL_SECONDS: u64 = 5;
+
+/// Returns whether a status with the given uri is in the home timeline of the account.
+async fn home_timeline_contains(
+    client: &MastodonClient,
+    uri: &str,
+) -> Result<bool, mastodon::Error> {
+    let statuses = client.home_timeline().await?;
+
+    Ok(statuses.iter().any(|status| status.uri == uri))
+}
+
+/// Posts a canary status and waits until it is delivered.
+///
+/// Returns the duration of the delivery, or `None` if it timed out.
+async fn probe(config: &FederationProbeConfig) -> Result<Option<Duration>, mastodon::Error> {
+    let (from, to) = (&config.from, &config.to);
+    let timeout = Duration::from_secs(config.timeout_seconds);
+    let text = format!(
+        "mastodon_exporter federation canary {}",
+        chrono::Utc::now().to_rfc3339()
+    );
+
+    log!(
+        "Probing federation delivery from {} to {}",
+        from.instance,
+        to.instance
+    );
+
+    let from_client = MastodonClient::new(&from.instance)
+        .with_access_token(from.access_token.get().unwrap_or_default());
+    let to_client = MastodonClient::new(&to.instance)
+        .with_access_token(to.access_token.get().unwrap_or_default());
+
+    let status = from_client.post_status(&text, "unlisted").await?;
+    let start = Instant::now();
+
+    let mut delivered = None;
+    while start.elapsed() < timeout {
+        tokio::time::sleep(Duration::from_secs(POLL_INTERVAL_SECONDS)).await;
+
+        match home_timeline_contains(&to_client, &status.uri).await {
+            Ok(true) => {
+                delivered = Some(start.elapsed());
+                break;
+            }
+            Ok(false) => {}
+            Err(err) => log!("Error: {} federation probe {}", to.instance, err),
+        }
+    }
+
+    // The delivery was measured, so failing to clean up doesn't fail the probe
+    if let Err(err) = from_client.delete_status(&status.id).await {
+        log!(
+            "Error: {} unable to delete federation canary {}: {}",
+            from.instance,
+            status.id,
+            err
+        );
+    }
+
+    Ok(delivered)
+}
+
+/// Periodically measures the federation delivery latency between two instances.
+///
+/// This runs until the process exits or a reload changes it, independent of `/metrics` scrapes.
+pub async fn run(config: FederationProbeConfig) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval_seconds));
+    let labels = [config.from.instance.as_str(), config.to.instance.as_str()];
+
+    loop {
+        interval.tick().await;
+
+        let delivered = match probe(&config).await {
+            Ok(delivered) => delivered,
+            Err(err) => {
+                log!("Error: {} federation probe {}", config.from.instance, err);
+                None
+            }
+        };
+
+        match delivered {
+            Some(duration) => {
+                log!(
+                    "{} -> {}: Federation delivery: {:?}",
+                    labels[0],
+                    labels[1],
+                    duration
+                );
+                if let Some(gauge) =
+                    MASTODON_FEDERATION_DELIVERY_SECONDS.with_limited_label_values(&labels)
+                {
+                    gauge.set(duration.as_secs_f64());
+                }
+            }
+            None => log!("{} -> {}: Federation delivery failed", labels[0], labels[1]),
+        }
+        if let Some(gauge) = MASTODON_FEDERATION_DELIVERY_SUCCESS.with_limited_label_values(&labels)
+        {
+            gauge.set(i64::from(delivered.is_some()));
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use rusqlite::{params, Connection};
+use serde::Serialize;
+
+use crate::{
+    config::HistoryConfig,
+    derived::{self, Labels},
+    metrics::REGISTRY,
+};
+
+/// The recorded values of a metric with the same labels.
+#[derive(Serialize)]
+pub struct Series {
+    pub labels: Labels,
+
+    /// Pairs of the number of seconds since 1970 and the value.
+    pub values: Vec<(i64, f64)>,
+}
+
+/// A local database of the values of all collection cycles.
+pub struct History {
+    connection: Arc<Mutex<Connection>>,
+    retention_seconds: i64,
+}
+
+impl History {
+    /// Opens the database, creating it if it doesn't exist.
+    pub fn open(config: &HistoryConfig) -> rusqlite::Result<Self> {
+        let connection = Connection::open(&config.path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS samples (
+                timestamp INTEGER NOT NULL,
+                metric TEXT NOT NULL,
+                labels TEXT NOT NULL,
+                value REAL NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS samples_metric_timestamp ON samples (metric, timestamp);",
+        )?;
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+            retention_seconds: config.retention_days as i64 * 24 * 60 * 60,
+        })
+    }
+
+    /// Runs the function with the connection on a thread for blocking tasks, as SQLite blocks
+    /// while reading and writing, which would stall the other tasks of a runtime thread.
+    async fn with_connection<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+    ) -> rusqlite::Result<T> {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || f(&mut connection.lock().unwrap()))
+            .await
+            .unwrap()
+    }
+
+    /// Appends the current values of all gauges and counters and removes expired values.
+    pub async fn record(&self) -> rusqlite::Result<()> {
+        let timestamp = chrono::Utc::now().timestamp();
+        let samples: Vec<(String, String, f64)> = REGISTRY
+            .gather()
+            .iter()
+            .flat_map(|family| {
+                derived::samples(family).into_iter().map(|(labels, value)| {
+                    let labels = serde_json::to_string(&labels).unwrap();
+                    (family.get_name().to_string(), labels, value)
+                })
+            })
+            .collect();
+        let expired_before = timestamp - self.retention_seconds;
+
+        self.with_connection(move |connection| {
+            let transaction = connection.transaction()?;
+            {
+                let mut insert = transaction.prepare_cached(
+                    "INSERT INTO samples (timestamp, metric, labels, value) VALUES (?1, ?2, ?3, ?4)",
+                )?;
+                for (metric, labels, value) in samples {
+                    insert.execute(params![timestamp, metric, labels, value])?;
+                }
+            }
+            transaction.execute(
+                "DELETE FROM samples WHERE timestamp < ?1",
+                params![expired_before],
+            )?;
+
+            transaction.commit()
+        })
+        .await
+    }
+
+    /// Returns the values of the metric within the last given number of seconds.
+    pub async fn query(&self, metric: &str, range_seconds: i64) -> rusqlite::Result<Vec<Series>> {
+        let since = chrono::Utc::now().timestamp().saturating_sub(range_seconds);
+        let metric = metric.to_string();
+
+        let series = self
+            .with_connection(move |connection| {
+                let mut select = connection.prepare_cached(
+                    "SELECT labels, timestamp, value FROM samples
+                    WHERE metric = ?1 AND timestamp >= ?2 ORDER BY timestamp",
+                )?;
+
+                let mut series: BTreeMap<String, Vec<(i64, f64)>> = BTreeMap::new();
+                let rows = select.query_map(params![metric, since], |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+                })?;
+                for row in rows {
+                    let (labels, timestamp, value): (String, i64, f64) = row?;
+                    series.entry(labels).or_default().push((timestamp, value));
+                }
+
+                Ok(series)
+            })
+            .await?;
+
+        Ok(series
+            .into_iter()
+            .map(|(labels, values)| Series {
+                labels: serde_json::from_str(&labels).unwrap_or_default(),
+                values,
+            })
+            .collect())
+    }
+
+    /// Returns the values of all metrics, or of the given metric, within the last given number of
+    /// seconds, ordered by time.
+    pub async fn rows(
+        &self,
+        metric: Option<&str>,
+        range_seconds: i64,
+    ) -> rusqlite::Result<Vec<(i64, String, Labels, f64)>> {
+        let since = chrono::Utc::now().timestamp().saturating_sub(range_seconds);
+        let metric = metric.map(str::to_string);
+
+        self.with_connection(move |connection| {
+            let mut select = connection.prepare_cached(
+                "SELECT timestamp, metric, labels, value FROM samples
+                WHERE (?1 IS NULL OR metric = ?1) AND timestamp >= ?2 ORDER BY timestamp, metric",
+            )?;
+
+            let rows = select.query_map(params![metric, since], |row| {
+                let labels: String = row.get(2)?;
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    serde_json::from_str(&labels).unwrap_or_default(),
+                    row.get(3)?,
+                ))
+            })?;
+
+            rows.collect()
+        })
+        .await
+    }
+}
+
+/// Parses a range like `30m`, `12h`, `7d` or a number of seconds into seconds.
+pub fn parse_range(range: &str) -> Option<i64> {
+    let (number, unit) = match range.find(|char: char| !char.is_ascii_digit()) {
+        Some(index) => range.split_at(index),
+        None => (range, "s"),
+    };
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return None,
+    };
+
+    number.parse::<i64>().ok()?.checked_mul(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(parse_range("90"), Some(90));
+        assert_eq!(parse_range("30s"), Some(30));
+        assert_eq!(parse_range("30m"), Some(30 * 60));
+        assert_eq!(parse_range("12h"), Some(12 * 60 * 60));
+        assert_eq!(parse_range("7d"), Some(7 * 24 * 60 * 60));
+    }
+
+    #[test]
+    fn rejects_invalid_ranges() {
+        assert_eq!(parse_range(""), None);
+        assert_eq!(parse_range("d"), None);
+        assert_eq!(parse_range("7w"), None);
+        assert_eq!(parse_range("-7d"), None);
+        assert_eq!(parse_range("9223372036854775807d"), None);
+    }
+}
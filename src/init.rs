@@ -0,0 +1,274 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use crate::{
+    config::Config,
+    mastodon::{domain, MastodonClient},
+};
+
+/// An account resolved to its id on the instance hosting it.
+pub struct ResolvedAccount {
+    /// The instance hosting the account, which may differ from the domain of its address.
+    pub instance: String,
+    pub username: String,
+    pub id: String,
+}
+
+/// Returns the instance reached at the URL, with its scheme unless it's HTTPS.
+fn instance_of(url: &reqwest::Url) -> Option<String> {
+    let host = url.host_str()?;
+
+    Some(match (url.scheme(), url.port()) {
+        ("https", None) => domain::to_unicode(host),
+        ("https", Some(port)) => format!("{}:{}", domain::to_unicode(host), port),
+        _ => url.origin().ascii_serialization(),
+    })
+}
+
+/// Resolves an address like `Gargron@mastodon.social` to the id of the account.
+///
+/// The instance hosting the account is found with WebFinger, as instances may serve their API on
+/// another domain than the one of their addresses. If WebFinger fails, the account is looked up on
+/// the domain of the address.
+pub async fn resolve_account(acct: &str) -> Result<ResolvedAccount, String> {
+    let acct = acct.trim_start_matches('@');
+    let Some((username, domain)) = acct.split_once('@') else {
+        return Err(format!("{} is not an address like username@instance", acct));
+    };
+
+    let mut instance = domain::to_unicode(domain);
+    let mut username = username.to_string();
+    match MastodonClient::new(instance.as_str()).webfinger(acct).await {
+        Ok(webfinger) => {
+            if let Some((canonical, _)) = webfinger
+                .subject
+                .trim_start_matches("acct:")
+                .split_once('@')
+            {
+                username = canonical.to_string();
+            }
+            let actor = webfinger
+                .links
+                .iter()
+                .find(|link| link.rel == "self")
+                .and_then(|link| link.href.as_deref())
+                .and_then(|href| reqwest::Url::parse(href).ok());
+            if let Some(canonical) = actor.as_ref().and_then(instance_of) {
+                instance = canonical;
+            }
+        }
+        Err(err) => log!(
+            "{}: WebFinger failed, looking up the account: {}",
+            domain,
+            err
+        ),
+    }
+
+    let account = MastodonClient::new(instance.as_str())
+        .lookup_account(&username)
+        .await
+        .map_err(|err| format!("unable to look up {} on {}: {}", username, instance, err))?;
+
+    Ok(ResolvedAccount {
+        instance,
+        username: account.username,
+        id: account.id,
+    })
+}
+
+/// An account entered in the setup, resolved to its id if requested.
+struct Account {
+    instance: String,
+    acct: String,
+    id: Option<String>,
+}
+
+/// Prints the prompt and returns the trimmed line entered on the terminal.
+fn read_line(prompt: &str) -> io::Result<String> {
+    print!("{}", prompt);
+    io::stdout().flush()?;
+
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "setup was aborted",
+        ));
+    }
+
+    Ok(line.trim().to_string())
+}
+
+/// Asks a question on the terminal and returns the answer, or the default if empty.
+fn ask(question: &str, default: &str) -> io::Result<String> {
+    let answer = read_line(&format!("{} [{}]: ", question, default))?;
+
+    Ok(if answer.is_empty() {
+        default.to_string()
+    } else {
+        answer
+    })
+}
+
+/// Asks a yes or no question on the terminal.
+fn confirm(question: &str, default: bool) -> io::Result<bool> {
+    let answer = ask(question, if default { "Y/n" } else { "y/N" })?;
+
+    Ok(match answer.to_lowercase().as_str() {
+        "y" | "yes" => true,
+        "n" | "no" => false,
+        _ => default,
+    })
+}
+
+/// Prints the question for a list of values, which are entered one per line.
+fn ask_list(question: &str) {
+    log!("{}, one per line, finish with an empty line:", question);
+}
+
+/// Tests the connection to the instance and prints its title and version.
+async fn check_instance(instance: &str) -> bool {
+    match MastodonClient::new(instance).instance().await {
+        Ok(info) => {
+            log!("  {}: {} running {}", instance, info.title, info.version);
+            true
+        }
+        Err(err) => {
+            log!("  {}: Unable to connect: {}", instance, err);
+            false
+        }
+    }
+}
+
+/// Renders the configuration file with comments explaining each section.
+fn render(port: u16, instances: &[String], accounts: &[Account]) -> String {
+    let mut yaml = String::new();
+    yaml.push_str("# Created by `mastodon_exporter init`.\n");
+    yaml.push_str("# See mastodon_exporter.yml in the repository for all options.\n");
+    yaml.push_str("version: 2\n\n");
+
+    yaml.push_str("server:\n");
+    yaml.push_str("  # The port the metrics are served on, at /metrics\n");
+    writeln!(yaml, "  http_listen_port: {}", port).unwrap();
+    yaml.push('\n');
+
+    yaml.push_str("# The instances to export the version, users, statuses and domains of\n");
+    if instances.is_empty() {
+        yaml.push_str("instance_info: []\n");
+    } else {
+        yaml.push_str("instance_info:\n");
+        for instance in instances {
+            writeln!(yaml, "  - {}", instance).unwrap();
+        }
+    }
+    yaml.push('\n');
+
+    yaml.push_str("# The accounts to export the followers, following and statuses of\n");
+    if accounts.is_empty() {
+        yaml.push_str("accounts: []\n");
+    } else {
+        yaml.push_str("accounts:\n");
+        for account in accounts {
+            writeln!(yaml, "  - instance: {}", account.instance).unwrap();
+            match &account.id {
+                Some(id) => writeln!(yaml, "    id: \"{}\" # {}", id, account.acct).unwrap(),
+                None => writeln!(yaml, "    acct: {}", account.acct).unwrap(),
+            }
+        }
+    }
+
+    yaml
+}
+
+/// Interactively asks for the instances and accounts to monitor, tests the connections and
+/// writes a commented configuration file.
+pub async fn run(path: &Path) -> io::Result<()> {
+    log!("This creates a configuration for the Mastodon exporter.");
+    if path.exists()
+        && !confirm(
+            &format!("{} already exists, overwrite it?", path.display()),
+            false,
+        )?
+    {
+        return Ok(());
+    }
+
+    let port = loop {
+        match ask("Port to serve the metrics on", "9498")?.parse::<u16>() {
+            Ok(port) => break port,
+            Err(err) => log!("  Invalid port: {}", err),
+        }
+    };
+
+    let mut instances = Vec::new();
+    ask_list("Instances to monitor, e.g. mastodon.social");
+    loop {
+        let instance = domain::to_unicode(&read_line("> ")?);
+        if instance.is_empty() {
+            break;
+        }
+        if check_instance(&instance).await || confirm("  Add it anyway?", false)? {
+            instances.push(instance);
+        }
+    }
+
+    let resolve = confirm(
+        "Resolve the usernames of accounts to their ids, which keep working after renames?",
+        true,
+    )?;
+    let mut accounts = Vec::new();
+    ask_list("Accounts to monitor, e.g. Gargron@mastodon.social");
+    loop {
+        let acct = read_line("> ")?;
+        if acct.is_empty() {
+            break;
+        }
+        let Some((username, instance)) = acct.trim_start_matches('@').split_once('@') else {
+            log!("  {}: Expected username@instance", acct);
+            continue;
+        };
+        let mut account = Account {
+            instance: domain::to_unicode(instance),
+            acct: username.to_string(),
+            id: None,
+        };
+        if resolve {
+            match resolve_account(&acct).await {
+                Ok(resolved) => {
+                    log!(
+                        "  {}: Resolved {} to {}",
+                        resolved.instance,
+                        resolved.username,
+                        resolved.id
+                    );
+                    account = Account {
+                        instance: resolved.instance,
+                        acct: resolved.username,
+                        id: Some(resolved.id),
+                    };
+                }
+                Err(err) => log!(
+                    "  Unable to resolve {}, keeping the username: {}",
+                    acct,
+                    err
+                ),
+            }
+        }
+        accounts.push(account);
+    }
+
+    fs::write(path, render(port, &instances, &accounts))?;
+    // The file is written by hand to keep its comments, so make sure it loads
+    if let Err(err) = Config::load(path) {
+        log!("Error: The written configuration doesn't load: {}", err);
+    }
+    log!(
+        "Wrote {}, start the exporter with `mastodon_exporter --config {}`",
+        path.display(),
+        path.display()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,62 @@
+//! A Mastodon exporter for Prometheus.
+//!
+//! The collection logic is available as a library, so it can be embedded in other programs.
+//! The [`Collector`] collects the metrics of all targets of a [`Config`] into the
+//! [`metrics::REGISTRY`], which can then be encoded with [`metrics::encode`].
+
+// The metrics are declared in a single `lazy_static!` block, which exceeds the default limit
+#![recursion_limit = "256"]
+
+#[macro_use]
+extern crate lazy_static;
+
+/// Prints a line to the log, like `println!`, with all secrets redacted.
+///
+/// All messages of the exporter are logged through here, so access tokens, `Authorization`
+/// headers and signed URLs never end up in the log, wherever they are formatted into a message.
+#[macro_export]
+macro_rules! log {
+    () => {
+        $crate::log!("")
+    };
+    ($($arg:tt)*) => {
+        $crate::redact::log(::std::format_args!($($arg)*))
+    };
+}
+
+pub mod alerting;
+pub mod canary;
+pub mod collectors;
+pub mod config;
+pub mod dashboard;
+pub mod derived;
+pub mod discovery;
+pub mod edit;
+pub mod export;
+pub mod federation;
+pub mod history;
+pub mod init;
+pub mod mastodon;
+pub mod metrics;
+pub mod push;
+pub mod redact;
+pub mod releases;
+pub mod reload;
+pub mod rules;
+pub mod schedule;
+pub mod server;
+#[cfg(windows)]
+pub mod service;
+pub mod sharding;
+pub mod signal;
+pub mod streaming;
+pub mod targets;
+pub mod telemetry;
+pub mod textfile;
+pub mod tokens;
+pub mod tracker;
+pub mod version;
+
+pub use collectors::Collector;
+pub use config::Config;
+pub use mastodon::MastodonClient;
@@ -1,197 +1,403 @@
-#[macro_use]
-extern crate lazy_mut;
-#[macro_use]
-extern crate lazy_static;
-extern crate serde_derive;
-extern crate serde_yaml;
-
-use std::time::Instant;
-use std::{fs, path::Path};
-
-use prometheus::{Encoder, IntGaugeVec, Opts, Registry, TextEncoder};
-use warp::Filter;
-
-mod collectors;
-mod config;
-mod mastodon;
-
-lazy_static! {
-    static ref REGISTRY: Registry = Registry::new();
-
-     // Ratelimit
-     static ref MASTODON_RATELIMIT_REMAINING: IntGaugeVec = IntGaugeVec::new(
-        Opts::new(
-            "mastodon_ratelimit_remaining",
-            "Current remaining ratelimit of instance.",
-        ),
-        &["instance"],
-    )
-    .unwrap();
-
-    // Ratelimit reset
-    static ref MASTODON_RATELIMIT_RESET: IntGaugeVec = IntGaugeVec::new(
-        Opts::new(
-            "mastodon_ratelimit_reset",
-            "Number of seconds since 1970 of ratelimit reset for instance.",
-        ),
-        &["instance"],
-    ).unwrap();
-
-    // Info
-    static ref MASTODON_INFO: IntGaugeVec = IntGaugeVec::new(
-        Opts::new(
-            "mastodon_info",
-            "General instance information.",
-        ),
-        &["instance", "domain", "title", "version"],
-    )
-    .unwrap();
-
-    // Registration enabled
-    static ref MASTODON_REGISTRATIONS_ENABLED: IntGaugeVec = IntGaugeVec::new(
-        Opts::new(
-            "mastodon_registrations_enabled",
-            "Whether or not registrations are enabled on instance.",
-        ),
-        &["instance"],
-    )
-    .unwrap();
-
-    // Registration approval required
-    static ref MASTODON_REGISTRATIONS_APPROVAL_REQUIRED: IntGaugeVec = IntGaugeVec::new(
-        Opts::new(
-            "mastodon_registrations_approval_required",
-            "Whether or not approval is required on instance.",
-        ),
-        &["instance"],
-    )
-    .unwrap();
-
-    // Account followers count
-    static ref MASTODON_ACCOUNT_FOLLOWERS_COUNT: IntGaugeVec = IntGaugeVec::new(
-        Opts::new(
-            "mastodon_account_followers_count",
-            "Number of followers for account.",
-        ),
-        &["instance", "account_id", "username"],
-    ).unwrap();
-
-    // Account following count
-    static ref MASTODON_ACCOUNT_FOLLOWING_COUNT: IntGaugeVec = IntGaugeVec::new(
-        Opts::new(
-            "mastodon_account_following_count",
-            "Number of accounts followed by account.",
-        ),
-        &["instance", "account_id", "username"],
-    ).unwrap();
-
-    // Account statuses count
-    static ref MASTODON_ACCOUNT_STATUSES_COUNT: IntGaugeVec = IntGaugeVec::new(
-        Opts::new(
-            "mastodon_account_statuses_count",
-            "Number of statuses for account.",
-        ),
-        &["instance", "account_id", "username"],
-    ).unwrap();
-
-    // Account last status at
-    static ref MASTODON_ACCOUNT_LAST_STATUS_AT: IntGaugeVec = IntGaugeVec::new(
-        Opts::new(
-            "mastodon_account_last_status_at",
-            "Number of seconds since 1970 of last status for account.",
-        ),
-        &["instance", "account_id", "username"],
-    ).unwrap();
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+
+use clap::{CommandFactory, Parser, Subcommand};
+use mastodon_exporter::{
+    config, dashboard, discovery, edit, export, init, log,
+    mastodon::{self, replay},
+    metrics::scope::Scope,
+    push, reload, rules, server, sharding, signal, targets, telemetry, textfile, Collector, Config,
+};
+
+/// A Mastodon exporter for Prometheus.
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Path to the configuration file, created with defaults if it doesn't exist.
+    #[arg(long, default_value = "mastodon_exporter.yml")]
+    config: PathBuf,
+
+    /// Record upstream responses to this directory and replay them on subsequent runs.
+    #[arg(long, value_name = "DIR")]
+    replay: Option<PathBuf>,
+
+    /// Write the metrics to this file after each collection cycle, e.g. for the textfile collector of node_exporter.
+    #[arg(long = "output.textfile-path", value_name = "PATH")]
+    textfile_path: Option<PathBuf>,
+
+    /// Seconds between the collection cycles written to the textfile.
+    #[arg(
+        long = "output.textfile-interval",
+        value_name = "SECONDS",
+        default_value_t = 60,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    textfile_interval: u64,
+
+    /// Only write the metrics to the textfile instead of serving them over HTTP.
+    #[arg(long = "output.textfile-only", requires = "textfile_path")]
+    textfile_only: bool,
+
+    /// Run one collection cycle, push the metrics to the configured sinks and exit.
+    ///
+    /// Exits with 1 if pushing fails, and with 2 if pushed but any target failed to be scraped.
+    #[arg(long)]
+    push_and_exit: bool,
+
+    /// Refuse to start if the configuration file contains access tokens and is world-readable,
+    /// instead of only warning.
+    #[arg(long)]
+    strict: bool,
+
+    /// Run as a Windows service, started by the service control manager.
+    #[cfg(windows)]
+    #[arg(long)]
+    service: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-lazy_mut! {
-    static mut INSTANCES: Vec<String> = Vec::new();
-    static mut ACCOUNTS: Vec<(String, String)> = Vec::new();
+#[derive(Subcommand)]
+enum Command {
+    /// Export the history as CSV if enabled, otherwise collect and export the current values.
+    Export {
+        /// The full name of a metric to export, e.g. `mastodon_account_followers_count`.
+        #[arg(long)]
+        metric: Option<String>,
+
+        /// The range of the history to export, e.g. `30m`, `12h` or `7d`.
+        #[arg(long, default_value = "1d")]
+        range: String,
+
+        /// Path to write the CSV file to.
+        #[arg(long, default_value = "mastodon_exporter.csv")]
+        output: PathBuf,
+    },
+
+    /// Generate a Grafana dashboard for the configured metrics and targets.
+    GenDashboard {
+        /// Path to write the dashboard JSON file to.
+        #[arg(long, default_value = "mastodon_exporter_dashboard.json")]
+        output: PathBuf,
+    },
+
+    /// Generate Prometheus alerting rules for the configured metrics and targets.
+    GenRules {
+        /// Path to write the rules file to.
+        #[arg(long, default_value = "rules.yml")]
+        output: PathBuf,
+    },
+
+    /// Print the completions of the command line for a shell, e.g. to
+    /// `/usr/share/bash-completion/completions/mastodon_exporter`.
+    Completions {
+        /// The shell to complete in.
+        shell: clap_complete::Shell,
+    },
+
+    /// Generate man pages for the command line and its subcommands.
+    GenMan {
+        /// Directory to write the man pages to.
+        #[arg(long, default_value = ".")]
+        output: PathBuf,
+    },
+
+    /// Interactively create the configuration file for the instances and accounts to monitor.
+    Init,
+
+    /// Print the id of an account and the instance hosting it, to add it to the accounts.
+    Resolve {
+        /// The address of the account, e.g. `Gargron@mastodon.social`.
+        acct: String,
+    },
+
+    /// Add or remove targets in the configuration file, keeping its comments.
+    Target {
+        #[command(subcommand)]
+        command: TargetCommand,
+    },
+
+    /// Rewrite the configuration file in the current format, keeping the original as a backup.
+    MigrateConfig,
 }
 
-async fn metrics() -> Result<impl warp::Reply, warp::Rejection> {
-    let start = Instant::now();
+#[derive(Subcommand)]
+enum TargetCommand {
+    /// Add an instance, e.g. `mas.to`, after checking that its API is reachable.
+    AddInstance {
+        instance: String,
+
+        /// Add the instance even if its API is unreachable.
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Add an account, e.g. `Gargron@mastodon.social`, by its id looked up on its instance.
+    AddAccount {
+        acct: String,
 
-    println!("Collecting metrics...");
+        /// Add the account by its username if it can't be looked up.
+        #[arg(long)]
+        force: bool,
+    },
 
-    collectors::instance::collect_instances(unsafe { INSTANCES.to_vec() })
-        .await
-        .ok();
-    collectors::account::collect_accounts(unsafe { ACCOUNTS.to_vec() })
-        .await
-        .ok();
+    /// Remove an instance.
+    RemoveInstance { instance: String },
 
-    println!("Collecting all metrics done in {:?}", start.elapsed());
-    println!();
+    /// Remove an account, whether it's configured by its username or its id.
+    RemoveAccount { acct: String },
+}
+
+fn main() {
+    let args = Args::parse();
+
+    // The service control manager starts the exporter in a thread of the service
+    #[cfg(windows)]
+    if args.service {
+        if let Err(err) = mastodon_exporter::service::run(|| run(Args::parse())) {
+            log!("Error: Unable to run as a service: {}", err);
+            process::exit(1);
+        }
+        return;
+    }
 
-    let mut buffer = vec![];
-    let encoder = TextEncoder::new();
-    encoder.encode(&REGISTRY.gather(), &mut buffer).unwrap();
-    Ok(String::from_utf8(buffer).unwrap())
+    run(args);
 }
 
 #[tokio::main]
-async fn main() {
-    REGISTRY
-        .register(Box::new(MASTODON_RATELIMIT_REMAINING.clone()))
-        .unwrap();
-    REGISTRY
-        .register(Box::new(MASTODON_RATELIMIT_RESET.clone()))
-        .unwrap();
-    REGISTRY.register(Box::new(MASTODON_INFO.clone())).unwrap();
-    REGISTRY
-        .register(Box::new(MASTODON_REGISTRATIONS_ENABLED.clone()))
-        .unwrap();
-    REGISTRY
-        .register(Box::new(MASTODON_REGISTRATIONS_APPROVAL_REQUIRED.clone()))
-        .unwrap();
-    REGISTRY
-        .register(Box::new(MASTODON_ACCOUNT_FOLLOWERS_COUNT.clone()))
-        .unwrap();
-    REGISTRY
-        .register(Box::new(MASTODON_ACCOUNT_FOLLOWING_COUNT.clone()))
-        .unwrap();
-    REGISTRY
-        .register(Box::new(MASTODON_ACCOUNT_STATUSES_COUNT.clone()))
-        .unwrap();
-    REGISTRY
-        .register(Box::new(MASTODON_ACCOUNT_LAST_STATUS_AT.clone()))
-        .unwrap();
-
-    let config_file_name = "mastodon_exporter.yml";
+async fn run(args: Args) {
+    if let Some(Command::Completions { shell }) = &args.command {
+        let mut command = Args::command();
+        let name = command.get_name().to_string();
+        clap_complete::generate(*shell, &mut command, name, &mut std::io::stdout());
+        return;
+    }
+
+    if let Some(Command::GenMan { output }) = &args.command {
+        clap_mangen::generate_to(Args::command(), output).unwrap();
+        log!("Generated man pages in {}", output.display());
+        return;
+    }
+
+    let config_file_name = args.config.as_path();
+
+    if let Some(Command::Init) = &args.command {
+        if let Err(err) = init::run(config_file_name).await {
+            log!("Error: Unable to create the configuration: {}", err);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(Command::Resolve { acct }) = &args.command {
+        match init::resolve_account(acct).await {
+            Ok(account) => {
+                log!("{} is {} on {}", acct, account.id, account.instance);
+                log!("  - instance: {}", account.instance);
+                log!("    id: \"{}\" # {}", account.id, account.username);
+            }
+            Err(err) => {
+                log!("Error: {}", err);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some(Command::Target { command }) = &args.command {
+        let result = match command {
+            TargetCommand::AddInstance { instance, force } => {
+                edit::add_instance(config_file_name, instance, *force).await
+            }
+            TargetCommand::AddAccount { acct, force } => {
+                edit::add_account(config_file_name, acct, *force).await
+            }
+            TargetCommand::RemoveInstance { instance } => {
+                edit::remove_instance(config_file_name, instance)
+            }
+            TargetCommand::RemoveAccount { acct } => {
+                edit::remove_account(config_file_name, acct).await
+            }
+        };
+        if let Err(err) = result {
+            log!("Error: {}", err);
+            process::exit(1);
+        }
+        return;
+    }
 
     // Create default config if it doesn't exist
-    if !Path::new(config_file_name).exists() {
-        let default_config_yaml = serde_yaml::to_string(&config::Config::default()).unwrap();
+    if !config_file_name.exists() {
+        let default_config_yaml = serde_yaml::to_string(&Config::default()).unwrap();
         fs::write(config_file_name, default_config_yaml).unwrap();
     }
 
+    if let Some(Command::MigrateConfig) = &args.command {
+        let migrated = Config::migrate(config_file_name).unwrap_or_else(|err| {
+            log!("Error: {}", err);
+            process::exit(1);
+        });
+        let mut backup = config_file_name.as_os_str().to_owned();
+        backup.push(".bak");
+        let written =
+            fs::copy(config_file_name, &backup).and_then(|_| fs::write(config_file_name, migrated));
+        if let Err(err) = written {
+            log!("Error: {}", err);
+            process::exit(1);
+        }
+        log!(
+            "Migrated {} to version {}, the original was kept as {}",
+            config_file_name.display(),
+            config::CONFIG_VERSION,
+            PathBuf::from(backup).display()
+        );
+        return;
+    }
+
     // Read yaml config file
-    let config_file = std::fs::File::open(config_file_name).unwrap();
-    let config: config::Config = serde_yaml::from_reader(config_file).unwrap();
+    let mut config = Config::load(config_file_name).unwrap_or_else(|err| {
+        log!("Error: {}", err);
+        process::exit(1);
+    });
 
-    // Read port from config
-    let port: u16 = config.server.http_listen_port;
+    if config.has_inline_access_tokens() && config::is_world_readable(config_file_name) {
+        if args.strict {
+            log!(
+                "Error: {} contains access tokens and is readable by all users, restrict it with `chmod o-r`",
+                config_file_name.display()
+            );
+            process::exit(1);
+        }
+        log!(
+            "Warning: {} contains access tokens and is readable by all users",
+            config_file_name.display()
+        );
+    }
 
-    // Read instances from config
-    let instances: Vec<String> = config.instance_info;
+    if let Some(Command::GenDashboard { output }) = &args.command {
+        let dashboard = dashboard::generate(&config);
+        fs::write(output, serde_json::to_string_pretty(&dashboard).unwrap()).unwrap();
+        log!("Generated dashboard {}", output.display());
+        return;
+    }
 
-    // TODO @Shinigami92 2022-11-20: Get rid of unsafe
-    unsafe {
-        INSTANCES.init();
-        INSTANCES.extend(instances);
+    if let Some(Command::GenRules { output }) = &args.command {
+        let rules = rules::generate(&config);
+        fs::write(output, serde_yaml::to_string(&rules).unwrap()).unwrap();
+        log!("Generated rules {}", output.display());
+        return;
     }
 
-    // Read accounts from config
-    let accounts: Vec<(String, String)> = config.accounts;
+    // Export traces of collection cycles if configured
+    if let Err(err) = telemetry::init(config.tracing.as_ref()) {
+        log!("Error: Unable to initialize tracing {}", err);
+    }
 
-    // TODO @Shinigami92 2022-11-21: Get rid of unsafe
-    unsafe {
-        ACCOUNTS.init();
-        ACCOUNTS.extend(accounts);
+    // Enable record/replay mode if requested
+    if let Some(replay_dir) = args.replay {
+        log!("Replaying upstream responses from {}", replay_dir.display());
+        replay::enable(replay_dir);
     }
 
-    let routes = warp::get().and(warp::path("metrics").and_then(metrics));
+    // Tune the connections to instances and resolve their domains as configured
+    mastodon::configure(
+        config.client.clone().unwrap_or_default(),
+        config.dns.clone(),
+        config.instance_timeouts(),
+    );
+
+    // Add targets from discovery rules
+    discovery::discover(&mut config).await;
+
+    // Only collect the targets of this replica if sharded
+    sharding::apply(&mut config);
+
+    let collector = Arc::new(Collector::new(config));
 
-    warp::serve(routes).run(([127, 0, 0, 1], port)).await;
+    if let Some(Command::Export {
+        metric,
+        range,
+        output,
+    }) = args.command
+    {
+        // Without the history, the current values are exported
+        if collector.history().is_none() {
+            collector.collect().await;
+        }
+        let csv = export::csv(&collector, &Scope::all(), metric.as_deref(), &range)
+            .await
+            .unwrap();
+        fs::write(&output, csv).unwrap();
+        log!("Exported to {}", output.display());
+        return;
+    }
+
+    if args.push_and_exit {
+        let Some(push_config) = collector.config().push.clone() else {
+            log!("Error: --push-and-exit needs push to be configured");
+            process::exit(1);
+        };
+
+        collector.collect().await;
+
+        if let Err(err) = push::push(&push_config).await {
+            log!("Error: Unable to push metrics {}", err);
+            process::exit(1);
+        }
+        let failed = targets::statuses()
+            .iter()
+            .filter(|target| matches!(target.health, targets::Health::Down))
+            .count();
+        if failed > 0 {
+            log!(
+                "Pushed metrics, but {} targets failed to be scraped",
+                failed
+            );
+            process::exit(2);
+        }
+        log!("Pushed metrics");
+        return;
+    }
+
+    // Start background tasks like the status engagement tracker if configured
+    collector.spawn_background_tasks();
+
+    // Reload on the reload signal and pick up changes of mounted target files and secrets
+    tokio::spawn(reload::watch(
+        collector.clone(),
+        config_file_name.to_path_buf(),
+    ));
+
+    // Write the metrics to a textfile after each collection cycle if requested
+    if let Some(textfile_path) = args.textfile_path {
+        let textfile = textfile::run(collector.clone(), textfile_path, args.textfile_interval);
+        if args.textfile_only {
+            until_shutdown(textfile).await;
+            return;
+        }
+        tokio::spawn(textfile);
+    }
+
+    until_shutdown(server::run(collector)).await;
+}
+
+/// Runs the future until it completes or the exporter is asked to shut down.
+async fn until_shutdown(future: impl std::future::Future<Output = ()>) {
+    let shutdown = async {
+        match signal::shutdown().await {
+            Ok(reason) => log!("Shutting down on {}", reason),
+            Err(err) => {
+                log!("Error: Unable to listen for the shutdown signal: {}", err);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    tokio::select! {
+        () = future => {}
+        () = shutdown => {}
+    }
 }
@@ -1,4 +1,14 @@
-use serde::Deserialize;
+use serde::{de::IgnoredAny, Deserialize};
+
+mod client;
+mod dns;
+pub mod domain;
+mod json;
+pub mod replay;
+
+pub use client::{
+    configure, last_rate_limit, set_timeouts, Error, MastodonClient, PageCount, RateLimit,
+};
 
 /// Information about registering for this website.
 ///
@@ -13,13 +23,189 @@ pub struct InstanceRegistrations {
     /// Whether registrations require moderator approval.
     ///
     /// [docs.joinmastodon.org/entities/Instance/#approval_required](https://docs.joinmastodon.org/entities/Instance/#approval_required)
+    #[serde(default)]
     pub approval_required: bool,
+
+    /// A custom message to be shown when registrations are closed.
+    ///
+    /// [docs.joinmastodon.org/entities/Instance/#registrations-message](https://docs.joinmastodon.org/entities/Instance/#registrations-message)
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+/// URLs of interest for clients apps.
+///
+/// [docs.joinmastodon.org/entities/Instance/#urls](https://docs.joinmastodon.org/entities/Instance/#urls)
+#[derive(Default, Deserialize)]
+pub struct InstanceUrls {
+    /// The Websockets URL for connecting to the streaming API.
+    ///
+    /// [docs.joinmastodon.org/entities/Instance/#streaming](https://docs.joinmastodon.org/entities/Instance/#streaming)
+    #[serde(default)]
+    pub streaming: Option<String>,
+}
+
+/// Hints related to translation.
+///
+/// [docs.joinmastodon.org/entities/Instance/#translation](https://docs.joinmastodon.org/entities/Instance/#translation)
+#[derive(Default, Deserialize)]
+pub struct InstanceTranslation {
+    /// Whether the Translations API is available on this instance.
+    ///
+    /// [docs.joinmastodon.org/entities/Instance/#translation-enabled](https://docs.joinmastodon.org/entities/Instance/#translation-enabled)
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+/// Configured values and limits for this website.
+///
+/// [docs.joinmastodon.org/entities/Instance/#configuration](https://docs.joinmastodon.org/entities/Instance/#configuration)
+#[derive(Default, Deserialize)]
+pub struct InstanceConfiguration {
+    /// URLs of interest for clients apps.
+    ///
+    /// [docs.joinmastodon.org/entities/Instance/#urls](https://docs.joinmastodon.org/entities/Instance/#urls)
+    #[serde(default)]
+    pub urls: InstanceUrls,
+
+    /// Hints related to translation.
+    ///
+    /// [docs.joinmastodon.org/entities/Instance/#translation](https://docs.joinmastodon.org/entities/Instance/#translation)
+    #[serde(default)]
+    pub translation: InstanceTranslation,
+}
+
+/// An image used to represent this instance.
+///
+/// [docs.joinmastodon.org/entities/Instance/#thumbnail](https://docs.joinmastodon.org/entities/Instance/#thumbnail)
+#[derive(Deserialize)]
+pub struct InstanceThumbnail {
+    /// The URL for the thumbnail image.
+    ///
+    /// [docs.joinmastodon.org/entities/Instance/#thumbnail-url](https://docs.joinmastodon.org/entities/Instance/#thumbnail-url)
+    pub url: String,
+}
+
+/// Registrations as returned by `/api/v2/instance`, or only whether they are enabled as returned
+/// by `/api/v1/instance`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawInstanceRegistrations {
+    Object(InstanceRegistrations),
+    Enabled(bool),
+}
+
+/// The URLs of `/api/v1/instance`, which are part of the configuration in `/api/v2/instance`.
+#[derive(Deserialize)]
+struct RawInstanceUrls {
+    #[serde(default)]
+    streaming_api: Option<String>,
+}
+
+/// A thumbnail as returned by `/api/v2/instance`, or only its URL as returned by
+/// `/api/v1/instance`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawInstanceThumbnail {
+    Object(InstanceThumbnail),
+    Url(String),
+}
+
+/// The instance as returned by `/api/v2/instance`, or by `/api/v1/instance` of older and compatible
+/// servers, with every field optional.
+#[derive(Deserialize)]
+struct RawInstanceResponse {
+    #[serde(default)]
+    domain: Option<String>,
+
+    /// The domain in `/api/v1/instance`, which is a URL on Pleroma and Akkoma.
+    #[serde(default)]
+    uri: Option<String>,
+
+    #[serde(default)]
+    title: String,
+
+    #[serde(default)]
+    version: String,
+
+    #[serde(default)]
+    source_url: String,
+
+    #[serde(default)]
+    description: String,
+
+    #[serde(default)]
+    registrations: Option<RawInstanceRegistrations>,
+
+    /// Whether registrations require approval in `/api/v1/instance`.
+    #[serde(default)]
+    approval_required: bool,
+
+    #[serde(default)]
+    thumbnail: Option<RawInstanceThumbnail>,
+
+    #[serde(default)]
+    configuration: InstanceConfiguration,
+
+    /// The URLs in `/api/v1/instance`.
+    #[serde(default)]
+    urls: Option<RawInstanceUrls>,
+}
+
+impl From<RawInstanceResponse> for InstanceResponse {
+    fn from(raw: RawInstanceResponse) -> Self {
+        let domain = raw
+            .domain
+            .or_else(|| {
+                raw.uri.map(|uri| {
+                    let uri = uri.split_once("://").map_or(uri.as_str(), |(_, uri)| uri);
+                    uri.trim_end_matches('/').to_string()
+                })
+            })
+            .unwrap_or_default();
+        let registrations = match raw.registrations {
+            Some(RawInstanceRegistrations::Object(registrations)) => registrations,
+            Some(RawInstanceRegistrations::Enabled(enabled)) => InstanceRegistrations {
+                enabled,
+                approval_required: raw.approval_required,
+                message: None,
+            },
+            None => InstanceRegistrations {
+                enabled: false,
+                approval_required: raw.approval_required,
+                message: None,
+            },
+        };
+        let mut configuration = raw.configuration;
+        if configuration.urls.streaming.is_none() {
+            configuration.urls.streaming = raw.urls.and_then(|urls| urls.streaming_api);
+        }
+        let thumbnail = raw.thumbnail.map(|thumbnail| match thumbnail {
+            RawInstanceThumbnail::Object(thumbnail) => thumbnail,
+            RawInstanceThumbnail::Url(url) => InstanceThumbnail { url },
+        });
+
+        Self {
+            domain,
+            title: raw.title,
+            version: raw.version,
+            source_url: raw.source_url,
+            description: raw.description,
+            registrations,
+            thumbnail,
+            configuration,
+        }
+    }
 }
 
 /// Represents the software instance of Mastodon running on this domain.
 ///
+/// Compatible servers like GoToSocial, Pleroma and Akkoma differ in some fields, so missing fields
+/// are left empty and the fields of `/api/v1/instance` are accepted as well.
+///
 /// [docs.joinmastodon.org/entities/Instance](https://docs.joinmastodon.org/entities/Instance)
 #[derive(Deserialize)]
+#[serde(from = "RawInstanceResponse")]
 pub struct InstanceResponse {
     /// The domain name of the instance.
     ///
@@ -36,35 +222,98 @@ pub struct InstanceResponse {
     /// [docs.joinmastodon.org/entities/Instance/#version](https://docs.joinmastodon.org/entities/Instance/#version)
     pub version: String,
 
+    /// The URL for the source code of the software running on this instance.
+    ///
+    /// [docs.joinmastodon.org/entities/Instance/#source_url](https://docs.joinmastodon.org/entities/Instance/#source_url)
+    pub source_url: String,
+
+    /// A short, plain-text description defined by the admin.
+    ///
+    /// [docs.joinmastodon.org/entities/Instance/#description](https://docs.joinmastodon.org/entities/Instance/#description)
+    pub description: String,
+
     /// Information about registering for this website.
     ///
     /// [docs.joinmastodon.org/entities/Instance/#registrations](https://docs.joinmastodon.org/entities/Instance/#registrations)
     pub registrations: InstanceRegistrations,
+
+    /// An image used to represent this instance.
+    ///
+    /// [docs.joinmastodon.org/entities/Instance/#thumbnail](https://docs.joinmastodon.org/entities/Instance/#thumbnail)
+    pub thumbnail: Option<InstanceThumbnail>,
+
+    /// Configured values and limits for this website, like whether translation is available.
+    ///
+    /// [docs.joinmastodon.org/entities/Instance/#configuration](https://docs.joinmastodon.org/entities/Instance/#configuration)
+    pub configuration: InstanceConfiguration,
+}
+
+/// Represents a profile field as a name-value pair with optional verification.
+///
+/// [docs.joinmastodon.org/entities/Account/#Field](https://docs.joinmastodon.org/entities/Account/#Field)
+#[derive(Deserialize)]
+pub struct AccountField {
+    /// Timestamp of when the server verified a URL value for a rel="me" link.
+    ///
+    /// String (ISO 8601 Datetime) if value is a verified URL. Otherwise, null.
+    ///
+    /// [docs.joinmastodon.org/entities/Account/#verified_at](https://docs.joinmastodon.org/entities/Account/#verified_at)
+    #[serde(default)]
+    pub verified_at: Option<String>,
 }
 
 /// Represents a user of Mastodon and their associated profile.
 ///
+/// Compatible servers may leave out some fields, which are then left empty.
+///
 /// [docs.joinmastodon.org/entities/Account](https://docs.joinmastodon.org/entities/Account)
 #[derive(Deserialize)]
 pub struct AccountResponse {
+    /// The account id.
+    ///
+    /// [docs.joinmastodon.org/entities/Account/#id](https://docs.joinmastodon.org/entities/Account/#id)
+    pub id: String,
+
     /// The username of the account, not including domain.
     ///
     /// [docs.joinmastodon.org/entities/Account/#username](https://docs.joinmastodon.org/entities/Account/#username)
     pub username: String,
 
+    /// The webfinger account URI, the username for local accounts and `username@domain` for
+    /// remote ones.
+    ///
+    /// [docs.joinmastodon.org/entities/Account/#acct](https://docs.joinmastodon.org/entities/Account/#acct)
+    #[serde(default)]
+    pub acct: String,
+
+    /// The profile's display name.
+    ///
+    /// [docs.joinmastodon.org/entities/Account/#display_name](https://docs.joinmastodon.org/entities/Account/#display_name)
+    #[serde(default)]
+    pub display_name: String,
+
+    /// An image icon that is shown next to statuses and in the profile.
+    ///
+    /// [docs.joinmastodon.org/entities/Account/#avatar](https://docs.joinmastodon.org/entities/Account/#avatar)
+    #[serde(default)]
+    pub avatar: String,
+
     /// The reported followers of this profile.
     ///
     /// [docs.joinmastodon.org/entities/Account/#followers_count](https://docs.joinmastodon.org/entities/Account/#followers_count)
+    #[serde(default, deserialize_with = "deserialize_number_or_string")]
     pub followers_count: i64,
 
     /// The reported follows of this profile.
     ///
     /// [docs.joinmastodon.org/entities/Account/#following_count](https://docs.joinmastodon.org/entities/Account/#following_count)
+    #[serde(default, deserialize_with = "deserialize_number_or_string")]
     pub following_count: i64,
 
     /// How many statuses are attached to this account.
     ///
     /// [docs.joinmastodon.org/entities/Account/#statuses_count](https://docs.joinmastodon.org/entities/Account/#statuses_count)
+    #[serde(default, deserialize_with = "deserialize_number_or_string")]
     pub statuses_count: i64,
 
     /// When the most recent status was posted.
@@ -72,5 +321,364 @@ pub struct AccountResponse {
     /// String (ISO 8601 Date), or null if no statuses.
     ///
     /// [docs.joinmastodon.org/entities/Account/#last_status_at](https://docs.joinmastodon.org/entities/Account/#last_status_at)
+    #[serde(default)]
     pub last_status_at: Option<String>,
+
+    /// Additional metadata attached to a profile as name-value pairs.
+    ///
+    /// [docs.joinmastodon.org/entities/Account/#fields](https://docs.joinmastodon.org/entities/Account/#fields)
+    #[serde(default)]
+    pub fields: Vec<AccountField>,
+
+    /// Indicates that the profile is currently inactive and that its user has moved to a new account.
+    ///
+    /// [docs.joinmastodon.org/entities/Account/#moved](https://docs.joinmastodon.org/entities/Account/#moved)
+    #[serde(default)]
+    pub moved: Option<serde_json::Value>,
+
+    /// An extra attribute returned only when an account is suspended.
+    ///
+    /// [docs.joinmastodon.org/entities/Account/#suspended](https://docs.joinmastodon.org/entities/Account/#suspended)
+    #[serde(default)]
+    pub suspended: bool,
+
+    /// An extra attribute returned only when an account is silenced.
+    ///
+    /// [docs.joinmastodon.org/entities/Account/#limited](https://docs.joinmastodon.org/entities/Account/#limited)
+    #[serde(default)]
+    pub limited: bool,
+}
+
+/// The default settings for posting and the pending follow requests of the account of the access
+/// token.
+///
+/// [docs.joinmastodon.org/entities/Account/#source](https://docs.joinmastodon.org/entities/Account/#source)
+#[derive(Deserialize)]
+pub struct AccountSource {
+    /// The default post privacy to be used for new statuses.
+    ///
+    /// [docs.joinmastodon.org/entities/Account/#source-privacy](https://docs.joinmastodon.org/entities/Account/#source-privacy)
+    #[serde(default)]
+    pub privacy: String,
+
+    /// Whether new statuses should be marked sensitive by default.
+    ///
+    /// [docs.joinmastodon.org/entities/Account/#source-sensitive](https://docs.joinmastodon.org/entities/Account/#source-sensitive)
+    #[serde(default)]
+    pub sensitive: bool,
+
+    /// The number of pending follow requests.
+    ///
+    /// [docs.joinmastodon.org/entities/Account/#follow_requests_count](https://docs.joinmastodon.org/entities/Account/#follow_requests_count)
+    #[serde(default)]
+    pub follow_requests_count: i64,
+}
+
+/// Represents the account of the access token, with its source.
+///
+/// [docs.joinmastodon.org/entities/Account/#CredentialAccount](https://docs.joinmastodon.org/entities/Account/#CredentialAccount)
+#[derive(Deserialize)]
+pub struct CredentialAccountResponse {
+    /// The account id.
+    ///
+    /// [docs.joinmastodon.org/entities/Account/#id](https://docs.joinmastodon.org/entities/Account/#id)
+    pub id: String,
+
+    /// An extra attribute that contains source values to be used with API methods that verify
+    /// credentials and update credentials.
+    ///
+    /// [docs.joinmastodon.org/entities/Account/#source](https://docs.joinmastodon.org/entities/Account/#source)
+    pub source: AccountSource,
+}
+
+/// Represents a conversation with direct statuses.
+///
+/// Only whether it's unread is of interest, so the accounts and the last status are not
+/// deserialized.
+///
+/// [docs.joinmastodon.org/entities/Conversation](https://docs.joinmastodon.org/entities/Conversation)
+#[derive(Deserialize)]
+pub struct ConversationResponse {
+    /// Is the conversation currently marked as unread?
+    ///
+    /// [docs.joinmastodon.org/entities/Conversation/#unread](https://docs.joinmastodon.org/entities/Conversation/#unread)
+    #[serde(default)]
+    pub unread: bool,
+}
+
+/// Represents the relationship between the account of the access token and another account.
+///
+/// [docs.joinmastodon.org/entities/Relationship](https://docs.joinmastodon.org/entities/Relationship)
+#[derive(Deserialize)]
+pub struct RelationshipResponse {
+    /// The account ID.
+    ///
+    /// [docs.joinmastodon.org/entities/Relationship/#id](https://docs.joinmastodon.org/entities/Relationship/#id)
+    pub id: String,
+
+    /// Whether the account of the access token is following this account.
+    ///
+    /// [docs.joinmastodon.org/entities/Relationship/#following](https://docs.joinmastodon.org/entities/Relationship/#following)
+    pub following: bool,
+}
+
+/// Represents a possible answer to a poll.
+///
+/// [docs.joinmastodon.org/entities/Poll/#Option](https://docs.joinmastodon.org/entities/Poll/#Option)
+#[derive(Deserialize)]
+pub struct PollOption {
+    /// The text value of the poll option.
+    ///
+    /// [docs.joinmastodon.org/entities/Poll/#option-title](https://docs.joinmastodon.org/entities/Poll/#option-title)
+    pub title: String,
+
+    /// The total number of received votes for this option.
+    ///
+    /// Integer, or null if results are not published yet.
+    ///
+    /// [docs.joinmastodon.org/entities/Poll/#option-votes_count](https://docs.joinmastodon.org/entities/Poll/#option-votes_count)
+    pub votes_count: Option<i64>,
+}
+
+/// Represents a poll attached to a status.
+///
+/// [docs.joinmastodon.org/entities/Poll](https://docs.joinmastodon.org/entities/Poll)
+#[derive(Deserialize)]
+pub struct PollResponse {
+    /// The ID of the poll in the database.
+    ///
+    /// [docs.joinmastodon.org/entities/Poll/#id](https://docs.joinmastodon.org/entities/Poll/#id)
+    pub id: String,
+
+    /// When the poll ends.
+    ///
+    /// String (ISO 8601 Datetime), or null if the poll does not end.
+    ///
+    /// [docs.joinmastodon.org/entities/Poll/#expires_at](https://docs.joinmastodon.org/entities/Poll/#expires_at)
+    pub expires_at: Option<String>,
+
+    /// Possible answers for the poll.
+    ///
+    /// [docs.joinmastodon.org/entities/Poll/#options](https://docs.joinmastodon.org/entities/Poll/#options)
+    pub options: Vec<PollOption>,
+}
+
+/// Represents a status posted by an account.
+///
+/// [docs.joinmastodon.org/entities/Status](https://docs.joinmastodon.org/entities/Status)
+#[derive(Deserialize)]
+pub struct StatusResponse {
+    /// ID of the status in the database.
+    ///
+    /// [docs.joinmastodon.org/entities/Status/#id](https://docs.joinmastodon.org/entities/Status/#id)
+    pub id: String,
+
+    /// URI of the status used for federation.
+    ///
+    /// [docs.joinmastodon.org/entities/Status/#uri](https://docs.joinmastodon.org/entities/Status/#uri)
+    pub uri: String,
+
+    /// The date when this status was created.
+    ///
+    /// String (ISO 8601 Datetime).
+    ///
+    /// [docs.joinmastodon.org/entities/Status/#created_at](https://docs.joinmastodon.org/entities/Status/#created_at)
+    pub created_at: String,
+
+    /// How many boosts this status has received.
+    ///
+    /// [docs.joinmastodon.org/entities/Status/#reblogs_count](https://docs.joinmastodon.org/entities/Status/#reblogs_count)
+    #[serde(default)]
+    pub reblogs_count: i64,
+
+    /// How many favourites this status has received.
+    ///
+    /// [docs.joinmastodon.org/entities/Status/#favourites_count](https://docs.joinmastodon.org/entities/Status/#favourites_count)
+    #[serde(default)]
+    pub favourites_count: i64,
+
+    /// How many replies this status has received.
+    ///
+    /// [docs.joinmastodon.org/entities/Status/#replies_count](https://docs.joinmastodon.org/entities/Status/#replies_count)
+    #[serde(default)]
+    pub replies_count: i64,
+
+    /// The poll attached to the status.
+    ///
+    /// [docs.joinmastodon.org/entities/Status/#poll](https://docs.joinmastodon.org/entities/Status/#poll)
+    #[serde(default)]
+    pub poll: Option<PollResponse>,
+}
+
+/// Represents the results of a search.
+///
+/// Only the number of results is of interest, so the entries are not deserialized.
+///
+/// [docs.joinmastodon.org/entities/Search](https://docs.joinmastodon.org/entities/Search)
+#[derive(Deserialize)]
+pub struct SearchResponse {
+    /// Accounts which match the given query.
+    ///
+    /// [docs.joinmastodon.org/entities/Search/#accounts](https://docs.joinmastodon.org/entities/Search/#accounts)
+    pub accounts: Vec<IgnoredAny>,
+
+    /// Statuses which match the given query.
+    ///
+    /// [docs.joinmastodon.org/entities/Search/#statuses](https://docs.joinmastodon.org/entities/Search/#statuses)
+    pub statuses: Vec<IgnoredAny>,
+
+    /// Hashtags which match the given query.
+    ///
+    /// [docs.joinmastodon.org/entities/Search/#hashtags](https://docs.joinmastodon.org/entities/Search/#hashtags)
+    pub hashtags: Vec<IgnoredAny>,
+}
+
+/// Deserializes a number which older versions of Mastodon return as a string.
+fn deserialize_number_or_string<'de, D>(deserializer: D) -> Result<i64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumberOrString {
+        Number(i64),
+        String(String),
+    }
+
+    match NumberOrString::deserialize(deserializer)? {
+        NumberOrString::Number(number) => Ok(number),
+        NumberOrString::String(string) => string.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+/// Represents a hashtag that is featured on a profile.
+///
+/// [docs.joinmastodon.org/entities/FeaturedTag](https://docs.joinmastodon.org/entities/FeaturedTag)
+#[derive(Deserialize)]
+pub struct FeaturedTagResponse {
+    /// The name of the hashtag being featured.
+    ///
+    /// [docs.joinmastodon.org/entities/FeaturedTag/#name](https://docs.joinmastodon.org/entities/FeaturedTag/#name)
+    pub name: String,
+
+    /// The number of authored statuses containing this hashtag.
+    ///
+    /// Mastodon before 4.0 returned the number as a string.
+    ///
+    /// [docs.joinmastodon.org/entities/FeaturedTag/#statuses_count](https://docs.joinmastodon.org/entities/FeaturedTag/#statuses_count)
+    #[serde(deserialize_with = "deserialize_number_or_string")]
+    pub statuses_count: i64,
+}
+
+/// Represents the links to the nodeinfo documents of a server.
+///
+/// [nodeinfo.diaspora.software/protocol](https://nodeinfo.diaspora.software/protocol)
+#[derive(Deserialize)]
+pub struct NodeInfoLinks {
+    pub links: Vec<NodeInfoLink>,
+}
+
+/// Represents a link to a nodeinfo document of a schema version.
+#[derive(Deserialize)]
+pub struct NodeInfoLink {
+    /// The schema of the document, like `http://nodeinfo.diaspora.software/ns/schema/2.0`.
+    pub rel: String,
+
+    /// The URL of the document.
+    pub href: String,
+}
+
+/// Represents the application an access token was issued to.
+///
+/// [docs.joinmastodon.org/entities/Application](https://docs.joinmastodon.org/entities/Application/)
+#[derive(Deserialize)]
+pub struct ApplicationResponse {
+    /// The name of the application.
+    pub name: String,
+
+    /// The scopes granted to the application, which Mastodon before 4.3 leaves out.
+    ///
+    /// [docs.joinmastodon.org/entities/Application/#scopes](https://docs.joinmastodon.org/entities/Application/#scopes)
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+/// Represents the WebFinger document of an account.
+///
+/// [docs.joinmastodon.org/spec/webfinger](https://docs.joinmastodon.org/spec/webfinger/)
+#[derive(Deserialize)]
+pub struct WebFingerResponse {
+    /// The canonical address of the account, like `acct:Gargron@mastodon.social`.
+    pub subject: String,
+
+    #[serde(default)]
+    pub links: Vec<WebFingerLink>,
+}
+
+/// Represents a link of a WebFinger document.
+#[derive(Deserialize)]
+pub struct WebFingerLink {
+    /// The relation of the link, like `self` for the ActivityPub actor of the account.
+    pub rel: String,
+
+    #[serde(default)]
+    pub href: Option<String>,
+}
+
+/// Represents a nodeinfo document, of which only the software is used.
+///
+/// [nodeinfo.diaspora.software/schema](https://nodeinfo.diaspora.software/schema.html)
+#[derive(Deserialize)]
+pub struct NodeInfoResponse {
+    pub software: NodeInfoSoftware,
+}
+
+/// Represents the software of a server in its nodeinfo document.
+#[derive(Deserialize)]
+pub struct NodeInfoSoftware {
+    /// The canonical name of the software, like `mastodon` or `gotosocial`.
+    pub name: String,
+
+    /// The version of the software.
+    #[serde(default)]
+    pub version: String,
+}
+
+/// Represents a hashtag used within the content of a status.
+///
+/// [docs.joinmastodon.org/entities/Tag](https://docs.joinmastodon.org/entities/Tag)
+#[derive(Deserialize)]
+pub struct TagResponse {
+    /// The ID of the hashtag in the database, which older versions of Mastodon leave out.
+    ///
+    /// [docs.joinmastodon.org/entities/Tag/#id](https://docs.joinmastodon.org/entities/Tag/#id)
+    #[serde(default)]
+    pub id: String,
+
+    /// The value of the hashtag after the # sign.
+    ///
+    /// [docs.joinmastodon.org/entities/Tag/#name](https://docs.joinmastodon.org/entities/Tag/#name)
+    pub name: String,
+
+    /// A link to the hashtag on the instance.
+    ///
+    /// [docs.joinmastodon.org/entities/Tag/#url](https://docs.joinmastodon.org/entities/Tag/#url)
+    pub url: String,
+}
+
+/// Represents quantitative data of an instance over a period, only visible to admins.
+///
+/// [docs.joinmastodon.org/entities/Admin_Measure](https://docs.joinmastodon.org/entities/Admin_Measure/)
+#[derive(Deserialize)]
+pub struct MeasureResponse {
+    /// The unique keystring for the requested measure.
+    ///
+    /// [docs.joinmastodon.org/entities/Admin_Measure/#key](https://docs.joinmastodon.org/entities/Admin_Measure/#key)
+    pub key: String,
+
+    /// The numerical total of the measure over the period.
+    ///
+    /// [docs.joinmastodon.org/entities/Admin_Measure/#total](https://docs.joinmastodon.org/entities/Admin_Measure/#total)
+    #[serde(deserialize_with = "deserialize_number_or_string")]
+    pub total: i64,
 }
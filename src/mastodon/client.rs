@@ -0,0 +1,1573 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use reqwest::{header::HeaderMap, RequestBuilder, Response, StatusCode};
+use serde::de::{DeserializeOwned, IgnoredAny};
+use tracing::Instrument;
+
+use super::{
+    dns::Resolver, domain, json::StringArrayParser, replay, AccountResponse, ApplicationResponse,
+    ConversationResponse, CredentialAccountResponse, FeaturedTagResponse, InstanceResponse,
+    MeasureResponse, NodeInfoLinks, NodeInfoResponse, PollResponse, RelationshipResponse,
+    SearchResponse, StatusResponse, TagResponse, WebFingerResponse,
+};
+use crate::config::{ClientConfig, DnsConfig};
+use crate::metrics::{
+    self, WithLimitedLabelValues, MASTODON_INSTANCE_REDIRECTED,
+    MASTODON_INSTANCE_TLS_CERTIFICATE_EXPIRY_TIMESTAMP_SECONDS, MASTODON_RATELIMIT_HEADERS_PRESENT,
+    MASTODON_RATELIMIT_LIMIT, MASTODON_RATELIMIT_REMAINING, MASTODON_RATELIMIT_RESET,
+    MASTODON_RESPONSE_TOO_LARGE_TOTAL, MASTODON_SCRAPE_ERRORS_TOTAL,
+    MASTODON_SCRAPE_PARSE_ERRORS_TOTAL, MASTODON_SCRAPE_TIMEOUT_TOTAL,
+    MASTODON_TARGET_NEGATIVE_CACHED, MASTODON_TARGET_SLOW,
+};
+use crate::redact::redact;
+
+/// The maximum number of pages fetched of paginated lists.
+const MAX_PAGES: usize = 50;
+
+/// The maximum number of peers received of an instance, far more than the largest instances have.
+const MAX_PEERS: usize = 1_000_000;
+
+/// The prefix of the schemas of nodeinfo documents.
+const NODEINFO_SCHEMA: &str = "http://nodeinfo.diaspora.software/ns/schema/";
+
+/// The maximum size of response bodies in bytes if `client.max_response_bytes` is not set.
+const DEFAULT_MAX_RESPONSE_BYTES: u64 = 16 * 1024 * 1024;
+
+/// The number of consecutive refused connections after which requests to an instance are skipped.
+const REFUSED_CONNECTIONS: u32 = 3;
+
+/// The share of its timeout a request may take before it counts as slow.
+const SLOW_RATIO: f64 = 0.8;
+
+/// The number of consecutive slow requests after which an instance is marked as slow.
+const SLOW_REQUESTS: u32 = 3;
+
+/// The configuration of the connections and the resolver of the shared HTTP client.
+static HTTP_CONFIG: OnceLock<(ClientConfig, Option<DnsConfig>)> = OnceLock::new();
+
+lazy_static! {
+    /// The request timeouts of instances overriding the timeout of the client, keyed by instance.
+    static ref TIMEOUTS: RwLock<HashMap<String, Duration>> = RwLock::new(HashMap::new());
+
+    /// The HTTP client shared by all Mastodon clients, so connections are reused.
+    static ref HTTP_CLIENT: reqwest::Client = http_client();
+
+    /// The location instances were last redirected to, keyed by instance.
+    static ref REDIRECTS: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+
+    /// The last rate limit reported by instances, keyed by instance.
+    static ref RATE_LIMITS: Mutex<HashMap<String, RateLimit>> = Mutex::new(HashMap::new());
+
+    /// The number of consecutive slow requests to instances, keyed by instance.
+    static ref SLOW_REQUEST_COUNTS: Mutex<HashMap<String, u32>> = Mutex::new(HashMap::new());
+
+    /// The failing instances requests are skipped for, keyed by instance.
+    static ref NEGATIVE_CACHE: Mutex<HashMap<String, NegativeCacheEntry>> = Mutex::new(HashMap::new());
+
+    /// The instances without `/api/v2/instance`, which are asked for `/api/v1/instance` instead.
+    static ref V1_INSTANCES: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+}
+
+/// Returns the last rate limit reported by the instance, if it reported one.
+pub fn last_rate_limit(instance: &str) -> Option<RateLimit> {
+    RATE_LIMITS.lock().unwrap().get(instance).copied()
+}
+
+/// Configures the connections of the HTTP client shared by all Mastodon clients and how it
+/// resolves domains.
+///
+/// This must be called before the first request, as the client is created with it.
+///
+/// Requests to the instances with a timeout time out after it instead of the timeout of the client.
+pub fn configure(client: ClientConfig, dns: Option<DnsConfig>, timeouts: HashMap<String, u64>) {
+    HTTP_CONFIG.set((client, dns)).ok();
+    set_timeouts(timeouts);
+}
+
+/// Replaces the request timeouts of instances, e.g. after reloading the configuration.
+pub fn set_timeouts(timeouts: HashMap<String, u64>) {
+    *TIMEOUTS.write().unwrap() = timeouts
+        .into_iter()
+        .map(|(instance, seconds)| (instance, Duration::from_secs(seconds)))
+        .collect();
+}
+
+fn http_client() -> reqwest::Client {
+    // The peer certificate is kept to export when it expires
+    let mut builder = reqwest::Client::builder().tls_info(true);
+    let Some((client, dns)) = HTTP_CONFIG.get() else {
+        return builder.build().unwrap();
+    };
+
+    if client.http2_prior_knowledge {
+        builder = builder.http2_prior_knowledge();
+    }
+    if let Some(pool_idle_timeout_seconds) = client.pool_idle_timeout_seconds {
+        builder = builder.pool_idle_timeout(Duration::from_secs(pool_idle_timeout_seconds));
+    }
+    if let Some(pool_max_idle_per_host) = client.pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(pool_max_idle_per_host);
+    }
+    if let Some(tcp_keepalive_seconds) = client.tcp_keepalive_seconds {
+        builder = builder.tcp_keepalive(Duration::from_secs(tcp_keepalive_seconds));
+    }
+    if let Some(dns) = dns {
+        builder = builder.dns_resolver(Arc::new(Resolver::new(dns.clone())));
+    }
+
+    builder.build().unwrap()
+}
+
+/// The consecutive failures of requests to an instance.
+struct NegativeCacheEntry {
+    /// The reason of the last failure, e.g. `dns` or `connect`.
+    reason: &'static str,
+    failures: u32,
+    /// Until when requests are skipped, if the failures are cached.
+    until: Option<Instant>,
+}
+
+/// An error which occurred while calling the API of an instance.
+#[derive(Debug)]
+pub enum Error {
+    /// The request could not be sent or the response could not be received.
+    Request(reqwest::Error),
+
+    /// The instance responded with an error status.
+    Status(StatusCode),
+
+    /// The response body could not be received.
+    Decode(reqwest::Error),
+
+    /// The response body is not valid JSON of the expected shape.
+    Json(serde_json::Error),
+
+    /// The response body is larger than the maximum size in bytes.
+    TooLarge(u64),
+
+    /// The fixture of the request could not be read or written in record/replay mode.
+    Replay(std::io::Error),
+
+    /// The request was skipped, as the last requests to the instance failed with the given reason.
+    Cached(&'static str),
+}
+
+impl Error {
+    /// Returns the status code if the instance responded with an error status.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Error::Status(status) => Some(*status),
+            _ => None,
+        }
+    }
+
+    /// Classifies the error for the `reason` label of the scrape errors metric.
+    ///
+    /// One of `dns`, `connect`, `tls`, `timeout`, `http_4xx`, `http_5xx`, `ratelimited`, `decode`,
+    /// `too_large`, `replay` or `other`.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Error::Request(err) if err.is_timeout() => "timeout",
+            Error::Request(err) => {
+                // reqwest doesn't expose the cause, so the messages of the error chain are checked
+                let mut messages = String::new();
+                let mut source: Option<&dyn std::error::Error> = Some(err);
+                while let Some(err) = source {
+                    messages.push_str(&err.to_string().to_lowercase());
+                    messages.push('\n');
+                    source = err.source();
+                }
+
+                if messages.contains("dns error") || messages.contains("failed to lookup address") {
+                    "dns"
+                } else if messages.contains("certificate")
+                    || messages.contains("tls")
+                    || messages.contains("ssl")
+                    || messages.contains("handshake")
+                {
+                    "tls"
+                } else if err.is_connect() {
+                    "connect"
+                } else {
+                    "other"
+                }
+            }
+            Error::Status(StatusCode::TOO_MANY_REQUESTS) => "ratelimited",
+            Error::Status(status) if status.is_server_error() => "http_5xx",
+            Error::Status(_) => "http_4xx",
+            Error::Decode(_) | Error::Json(_) => "decode",
+            Error::TooLarge(_) => "too_large",
+            Error::Cached(reason) => reason,
+            Error::Replay(_) => "replay",
+        }
+    }
+}
+
+// Besides the log, errors are served by `/targets` and in events, so their URLs are redacted here
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Request(err) => write!(f, "request failed: {}", redact(&err.to_string())),
+            Error::Status(status) => write!(f, "instance responded with {}", status),
+            Error::Decode(err) => {
+                write!(f, "unable to decode response: {}", redact(&err.to_string()))
+            }
+            Error::Json(err) => write!(f, "unable to decode response: {}", err),
+            Error::TooLarge(limit) => write!(f, "response exceeds {} bytes", limit),
+            Error::Cached(reason) => write!(f, "skipped after requests failed with {}", reason),
+            Error::Replay(err) => write!(f, "unable to access fixture: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Request(err) | Error::Decode(err) => Some(err),
+            Error::Json(err) => Some(err),
+            Error::Status(_) | Error::TooLarge(_) | Error::Cached(_) => None,
+            Error::Replay(err) => Some(err),
+        }
+    }
+}
+
+/// The number of items of a paginated list.
+#[derive(Clone, Copy, Debug)]
+pub struct PageCount {
+    pub count: usize,
+
+    /// Whether the list has more than `MAX_PAGES` pages, so only the items of the first pages
+    /// are counted.
+    pub truncated: bool,
+}
+
+/// The rate limit state of an instance as reported by the response headers.
+///
+/// [docs.joinmastodon.org/api/rate-limits](https://docs.joinmastodon.org/api/rate-limits/)
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RateLimit {
+    /// The number of requests allowed in a period, from `x-ratelimit-limit`.
+    pub limit: Option<i64>,
+
+    /// The number of requests remaining in the current period, from `x-ratelimit-remaining`.
+    pub remaining: Option<i64>,
+
+    /// When the rate limit resets, from `x-ratelimit-reset`.
+    pub reset: Option<DateTime<Utc>>,
+
+    /// Whether any rate limit header was present.
+    pub present: bool,
+}
+
+/// Parses an optional header, returning `Err` with the header name if its value is malformed.
+///
+/// The standardized name without the `x-` prefix is used if the header is missing.
+fn parse_header<T>(
+    headers: &HeaderMap,
+    name: &'static str,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Result<Option<T>, &'static str> {
+    let Some((name, value)) = [name, name.trim_start_matches("x-")]
+        .into_iter()
+        .find_map(|name| Some((name, headers.get(name)?)))
+    else {
+        return Ok(None);
+    };
+
+    value
+        .to_str()
+        .ok()
+        .and_then(|value| parse(value.trim()))
+        .map(Some)
+        .ok_or(name)
+}
+
+/// Parses when the rate limit resets.
+///
+/// Mastodon returns an ISO 8601 timestamp, some proxies the number of seconds since 1970, and the
+/// standardized `ratelimit-reset` header the number of seconds until the reset.
+fn parse_reset(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(reset) = value.parse::<DateTime<Utc>>() {
+        return Some(reset);
+    }
+
+    let seconds: i64 = value.parse().ok()?;
+    // Larger numbers of seconds than a year are from 1970
+    if seconds > 365 * 24 * 60 * 60 {
+        DateTime::from_timestamp(seconds, 0)
+    } else {
+        Some(Utc::now() + chrono::Duration::seconds(seconds))
+    }
+}
+
+impl RateLimit {
+    /// Reads the rate limit from response headers, ignoring missing or malformed values.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        Self::parse(headers).0
+    }
+
+    /// Reads the rate limit from response headers.
+    ///
+    /// Missing headers are treated as unknown, the names of malformed headers are returned.
+    pub fn parse(headers: &HeaderMap) -> (Self, Vec<&'static str>) {
+        let mut malformed = Vec::new();
+
+        let limit = parse_header(headers, "x-ratelimit-limit", |value| value.parse().ok())
+            .unwrap_or_else(|name| {
+                malformed.push(name);
+                None
+            });
+        let remaining = parse_header(headers, "x-ratelimit-remaining", |value| value.parse().ok())
+            .unwrap_or_else(|name| {
+                malformed.push(name);
+                None
+            });
+        let reset =
+            parse_header(headers, "x-ratelimit-reset", parse_reset).unwrap_or_else(|name| {
+                malformed.push(name);
+                None
+            });
+        let present = headers.keys().any(|name| {
+            name.as_str().starts_with("x-ratelimit-") || name.as_str().starts_with("ratelimit-")
+        });
+
+        (
+            Self {
+                limit,
+                remaining,
+                reset,
+                present,
+            },
+            malformed,
+        )
+    }
+}
+
+/// Returns the path of a request with ids replaced by `:id`, e.g. `/api/v1/accounts/:id`, to label
+/// the rate limits of endpoints.
+fn endpoint(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            let id = segment.chars().all(|c| c.is_ascii_alphanumeric())
+                && segment.chars().any(|c| c.is_ascii_digit())
+                && !(segment.starts_with('v') && segment[1..].chars().all(|c| c.is_ascii_digit()));
+            if id {
+                ":id"
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Resolves the link to the nodeinfo document against the URL of the instance, `None` if it's on
+/// another host or port.
+fn nodeinfo_url(instance: &str, href: &str) -> Option<reqwest::Url> {
+    let instance = reqwest::Url::parse(instance).ok()?;
+    let url = instance.join(href).ok()?;
+
+    let same_host = matches!(url.scheme(), "http" | "https")
+        && url.host_str() == instance.host_str()
+        && url.port() == instance.port();
+    same_host.then_some(url)
+}
+
+/// Returns the time a DER encoded certificate expires in seconds since 1970.
+fn certificate_expiry(certificate: &[u8]) -> Option<i64> {
+    let (_, certificate) = x509_parser::parse_x509_certificate(certificate).ok()?;
+
+    Some(certificate.validity().not_after.timestamp())
+}
+
+/// Returns the URL of the next page from the `Link` header of a paginated response.
+///
+/// Relative URLs are resolved against the URL of the response.
+fn next_link(headers: &HeaderMap, base: &reqwest::Url) -> Option<reqwest::Url> {
+    let link = headers.get(reqwest::header::LINK)?.to_str().ok()?;
+
+    link.split(',').find_map(|link| {
+        let (url, params) = link.split_once(';')?;
+        let url = url.trim().strip_prefix('<')?.strip_suffix('>')?;
+        let next = params.split(';').any(|param| {
+            let Some((name, value)) = param.split_once('=') else {
+                return false;
+            };
+            // The relation types are a space separated list, e.g. `rel="next last"`
+            name.trim().eq_ignore_ascii_case("rel")
+                && value
+                    .trim()
+                    .trim_matches('"')
+                    .split_ascii_whitespace()
+                    .any(|rel| rel.eq_ignore_ascii_case("next"))
+        });
+
+        next.then(|| base.join(url).ok()).flatten()
+    })
+}
+
+/// Returns the path of a hashtag, whose name may be written with a leading `#` and may contain
+/// characters which aren't allowed in a path segment.
+fn tag_path(name: &str) -> String {
+    format!(
+        "/api/v1/tags/{}",
+        urlencoding::encode(name.strip_prefix('#').unwrap_or(name))
+    )
+}
+
+/// A typed client for the API of a Mastodon instance.
+///
+/// Every response of the instance updates its rate limit metrics.
+#[derive(Clone)]
+pub struct MastodonClient {
+    domain: String,
+    access_token: Option<String>,
+    http: reqwest::Client,
+}
+
+impl MastodonClient {
+    /// Creates a client for the instance with the given domain.
+    pub fn new(domain: impl Into<String>) -> Self {
+        Self {
+            domain: domain.into(),
+            access_token: None,
+            http: HTTP_CLIENT.clone(),
+        }
+    }
+
+    /// Authenticates all requests of the client with the given access token.
+    pub fn with_access_token(mut self, access_token: impl Into<String>) -> Self {
+        self.access_token = Some(access_token.into());
+        self
+    }
+
+    /// Returns the domain of the instance.
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// Returns the URL of the given path on the instance.
+    ///
+    /// Instances are reached via HTTPS, unless the domain explicitly starts with a scheme like
+    /// `http://localhost:3000`.
+    pub fn url(&self, path: &str) -> String {
+        if self.domain.contains("://") {
+            format!("{}{}", self.domain, path)
+        } else {
+            format!("https://{}{}", domain::to_ascii(&self.domain), path)
+        }
+    }
+
+    /// Starts a `GET` request to the given path on the instance.
+    pub fn get(&self, path: &str) -> RequestBuilder {
+        self.authenticate(self.http.get(self.url(path)))
+    }
+
+    /// Starts an unauthenticated `GET` request to a URL outside of the API, e.g. of a media file on
+    /// another host, which must never receive the access token.
+    pub fn get_url(&self, url: &str) -> RequestBuilder {
+        self.http.get(url)
+    }
+
+    /// Starts a `POST` request to the given path on the instance.
+    pub fn post(&self, path: &str) -> RequestBuilder {
+        self.authenticate(self.http.post(self.url(path)))
+    }
+
+    /// Starts a `DELETE` request to the given path on the instance.
+    pub fn delete(&self, path: &str) -> RequestBuilder {
+        self.authenticate(self.http.delete(self.url(path)))
+    }
+
+    fn authenticate(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.access_token {
+            Some(access_token) => request.bearer_auth(access_token),
+            None => request,
+        }
+    }
+
+    /// Sends a request, records the rate limit and fails on error statuses.
+    ///
+    /// Only requests to the instance itself are skipped while it's negatively cached and count
+    /// towards its failures and slowness, unlike requests to other hosts like a CDN.
+    ///
+    /// In record/replay mode the response may be answered from a fixture instead.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, Error> {
+        let request = match request.build() {
+            Ok(request) => request,
+            Err(err) => {
+                let err = Error::Request(err);
+                self.record_error(&err);
+                return Err(err);
+            }
+        };
+        let to_instance = self.is_instance_url(request.url());
+
+        let negative_cache = HTTP_CONFIG
+            .get()
+            .and_then(|(client, _)| client.negative_cache_seconds)
+            .filter(|_| to_instance)
+            .map(Duration::from_secs);
+        if let Some(reason) = negative_cache.and_then(|_| self.negative_cached()) {
+            let err = Error::Cached(reason);
+            self.record_error(&err);
+            return Err(err);
+        }
+
+        let start = Instant::now();
+        let result = self.execute(request, to_instance).await;
+
+        if let Err(err) = &result {
+            self.record_error(err);
+        }
+        if let Some(timeout) = self.timeout().filter(|_| to_instance) {
+            self.record_slowness(start.elapsed(), timeout, &result);
+        }
+        if let Some(duration) = negative_cache {
+            self.record_failure(duration, &result);
+        }
+
+        result
+    }
+
+    /// Returns whether the URL is on the instance, unlike e.g. media files on a CDN.
+    fn is_instance_url(&self, url: &reqwest::Url) -> bool {
+        let origin = url.origin().ascii_serialization();
+        reqwest::Url::parse(&self.url("/"))
+            .is_ok_and(|instance| instance.origin().ascii_serialization() == origin)
+    }
+
+    /// Returns the reason of the last failure if requests to the instance are skipped.
+    fn negative_cached(&self) -> Option<&'static str> {
+        let cache = NEGATIVE_CACHE.lock().unwrap();
+        let entry = cache.get(&self.domain)?;
+        entry
+            .until
+            .is_some_and(|until| until > Instant::now())
+            .then_some(entry.reason)
+    }
+
+    /// Skips requests to the instance for the duration if its domain can't be resolved or it
+    /// refused connections repeatedly.
+    fn record_failure(&self, duration: Duration, result: &Result<Response, Error>) {
+        let mut cache = NEGATIVE_CACHE.lock().unwrap();
+        let reason = match result {
+            Err(err) if matches!(err.reason(), "dns" | "connect") => err.reason(),
+            // Any response, even an error status, shows the instance is alive
+            _ => {
+                if cache.remove(&self.domain).is_some() {
+                    if let Some(gauge) =
+                        MASTODON_TARGET_NEGATIVE_CACHED.with_limited_label_values(&[&self.domain])
+                    {
+                        gauge.set(0);
+                    }
+                }
+                return;
+            }
+        };
+
+        let entry = cache
+            .entry(self.domain.clone())
+            .or_insert(NegativeCacheEntry {
+                reason,
+                failures: 0,
+                until: None,
+            });
+        entry.reason = reason;
+        entry.failures += 1;
+
+        let cached = reason == "dns" || entry.failures >= REFUSED_CONNECTIONS;
+        if cached {
+            log!(
+                "{}: Skipping requests for {:?} after failing with {}",
+                self.domain,
+                duration,
+                reason
+            );
+            entry.until = Some(Instant::now() + duration);
+        }
+        if let Some(gauge) =
+            MASTODON_TARGET_NEGATIVE_CACHED.with_limited_label_values(&[&self.domain])
+        {
+            gauge.set(i64::from(cached));
+        }
+    }
+
+    /// Returns the timeout of requests to the instance, if any.
+    fn timeout(&self) -> Option<Duration> {
+        TIMEOUTS
+            .read()
+            .unwrap()
+            .get(&self.domain)
+            .copied()
+            .or_else(|| {
+                let (client, _) = HTTP_CONFIG.get()?;
+                client.timeout_seconds.map(Duration::from_secs)
+            })
+    }
+
+    /// Executes a request, `to_instance` if it's sent to the instance itself.
+    async fn execute(
+        &self,
+        mut request: reqwest::Request,
+        to_instance: bool,
+    ) -> Result<Response, Error> {
+        if let Some(timeout) = self.timeout() {
+            *request.timeout_mut() = Some(timeout);
+        }
+        let start = Instant::now();
+        let origin = request.url().origin().ascii_serialization();
+        let endpoint = endpoint(request.url().path());
+        let span = tracing::info_span!(
+            "request",
+            method = %request.method(),
+            url = %request.url(),
+            status = tracing::field::Empty,
+        );
+        let response = match replay::dir() {
+            Some(dir) => {
+                replay::execute(dir, &self.http, request)
+                    .instrument(span.clone())
+                    .await?
+            }
+            None => {
+                let response = self
+                    .http
+                    .execute(request)
+                    .instrument(span.clone())
+                    .await
+                    .map_err(Error::Request)?;
+                // Requests to other hosts, like media files on a CDN, don't tell about the instance
+                if to_instance {
+                    self.record_redirect(&origin, response.url());
+                    self.record_certificate(&response);
+                }
+                response
+            }
+        };
+        span.record("status", response.status().as_u16());
+
+        if to_instance {
+            metrics::observe_request_duration(&self.domain, start.elapsed().as_secs_f64(), &span);
+
+            let (ratelimit, malformed) = RateLimit::parse(response.headers());
+            for field in malformed {
+                log!("{}: Unable to parse header {}", self.domain, field);
+                if let Some(counter) = MASTODON_SCRAPE_PARSE_ERRORS_TOTAL
+                    .with_limited_label_values(&[&self.domain, field])
+                {
+                    counter.inc();
+                }
+            }
+            self.record_ratelimit(ratelimit, &endpoint);
+        }
+
+        let status = response.status();
+        if status.is_client_error() || status.is_server_error() {
+            return Err(Error::Status(status));
+        }
+
+        Ok(response)
+    }
+
+    async fn send_json<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, Error> {
+        let response = self.send(request).await?;
+        self.decode(response).await
+    }
+
+    /// Decodes the JSON body of a response.
+    async fn decode<T: DeserializeOwned>(&self, response: Response) -> Result<T, Error> {
+        let result = match self.read_body(response).await {
+            Ok(body) => serde_json::from_slice(&body).map_err(Error::Json),
+            Err(err) => Err(err),
+        };
+
+        if let Err(err) = &result {
+            self.record_error(err);
+        }
+
+        result
+    }
+
+    /// Reads the body of a response, which is aborted as soon as it exceeds the maximum size.
+    pub async fn read_body(&self, mut response: Response) -> Result<Vec<u8>, Error> {
+        let limit = HTTP_CONFIG
+            .get()
+            .and_then(|(client, _)| client.max_response_bytes)
+            .unwrap_or(DEFAULT_MAX_RESPONSE_BYTES);
+        let too_large = |response: &Response| {
+            log!(
+                "Error: {} response of {} exceeds {} bytes",
+                self.domain,
+                response.url().path(),
+                limit
+            );
+            if let Some(counter) = MASTODON_RESPONSE_TOO_LARGE_TOTAL
+                .with_limited_label_values(&[&self.domain, &endpoint(response.url().path())])
+            {
+                counter.inc();
+            }
+            Error::TooLarge(limit)
+        };
+
+        // The announced length is checked first, but can't be relied on, e.g. for chunked responses
+        if response
+            .content_length()
+            .is_some_and(|length| length > limit)
+        {
+            return Err(too_large(&response));
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(Error::Decode)? {
+            if (body.len() + chunk.len()) as u64 > limit {
+                return Err(too_large(&response));
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        Ok(body)
+    }
+
+    /// Fetches all items of a paginated list, following the `next` links up to `MAX_PAGES` pages.
+    ///
+    /// Links to other origins aren't followed, so the access token isn't sent to other hosts.
+    ///
+    /// [docs.joinmastodon.org/api/guidelines/#pagination](https://docs.joinmastodon.org/api/guidelines/#pagination)
+    async fn send_json_pages<T: DeserializeOwned>(&self, path: &str) -> Result<Vec<T>, Error> {
+        Ok(self.send_json_pages_capped(path).await?.0)
+    }
+
+    /// Fetches the items of a paginated list like [`Self::send_json_pages`], and returns whether
+    /// the list continues after the last page fetched, as it has more than `MAX_PAGES` pages.
+    async fn send_json_pages_capped<T: DeserializeOwned>(
+        &self,
+        path: &str,
+    ) -> Result<(Vec<T>, bool), Error> {
+        let mut items = Vec::new();
+        let mut request = self.get(path);
+
+        let origin = reqwest::Url::parse(&self.url("/")).map(|url| url.origin());
+
+        for _ in 0..MAX_PAGES {
+            let response = self.send(request).await?;
+            let next = next_link(response.headers(), response.url());
+            let page: Vec<T> = self.decode(response).await?;
+            items.extend(page);
+
+            match next {
+                Some(next) if origin.as_ref().is_ok_and(|origin| *origin == next.origin()) => {
+                    request = self.authenticate(self.http.get(next))
+                }
+                Some(next) => {
+                    log!(
+                        "Error: {} {} links to the next page on another origin {}, not following it",
+                        self.domain,
+                        path, next
+                    );
+                    return Ok((items, false));
+                }
+                None => return Ok((items, false)),
+            }
+        }
+
+        Ok((items, true))
+    }
+
+    /// Counts the items of a paginated list, up to `MAX_PAGES` pages.
+    async fn count_pages(&self, path: &str) -> Result<PageCount, Error> {
+        let (items, truncated): (Vec<IgnoredAny>, bool) = self.send_json_pages_capped(path).await?;
+
+        Ok(PageCount {
+            count: items.len(),
+            truncated,
+        })
+    }
+
+    fn record_error(&self, err: &Error) {
+        if let Some(counter) =
+            MASTODON_SCRAPE_ERRORS_TOTAL.with_limited_label_values(&[&self.domain, err.reason()])
+        {
+            counter.inc();
+        }
+    }
+
+    /// Records timeouts and marks the instance as slow if several consecutive requests took more
+    /// than `SLOW_RATIO` of the timeout.
+    fn record_slowness(
+        &self,
+        elapsed: Duration,
+        timeout: Duration,
+        result: &Result<Response, Error>,
+    ) {
+        let instance = self.domain.as_str();
+
+        let timed_out = matches!(result, Err(Error::Request(err)) if err.is_timeout());
+        if let Some(counter) = MASTODON_SCRAPE_TIMEOUT_TOTAL.with_limited_label_values(&[instance])
+        {
+            if timed_out {
+                counter.inc();
+            }
+        }
+
+        let mut counts = SLOW_REQUEST_COUNTS.lock().unwrap();
+        let count = counts.entry(instance.to_string()).or_default();
+        if timed_out || elapsed.as_secs_f64() > timeout.as_secs_f64() * SLOW_RATIO {
+            *count += 1;
+        } else {
+            *count = 0;
+        }
+
+        let slow = *count >= SLOW_REQUESTS;
+        if slow && *count == SLOW_REQUESTS {
+            log!(
+                "Warning: {} used more than {}% of its timeout in {} consecutive requests",
+                instance,
+                SLOW_RATIO * 100.0,
+                SLOW_REQUESTS
+            );
+        }
+        if let Some(gauge) = MASTODON_TARGET_SLOW.with_limited_label_values(&[instance]) {
+            gauge.set(i64::from(slow));
+        }
+    }
+
+    /// Records when the TLS certificate the instance presented expires.
+    fn record_certificate(&self, response: &Response) {
+        let Some(certificate) = response
+            .extensions()
+            .get::<reqwest::tls::TlsInfo>()
+            .and_then(|tls_info| tls_info.peer_certificate())
+        else {
+            return;
+        };
+
+        match certificate_expiry(certificate) {
+            Some(expiry) => {
+                if let Some(gauge) = MASTODON_INSTANCE_TLS_CERTIFICATE_EXPIRY_TIMESTAMP_SECONDS
+                    .with_limited_label_values(&[&self.domain])
+                {
+                    gauge.set(expiry);
+                }
+            }
+            None => log!("{}: Unable to parse TLS certificate", self.domain),
+        }
+    }
+
+    /// Records whether the request was redirected to another domain, e.g. after a migration.
+    ///
+    /// Redirects are followed, so the metrics are still exported for the configured instance.
+    fn record_redirect(&self, origin: &str, url: &reqwest::Url) {
+        let instance = self.domain.as_str();
+        let location = url.origin().ascii_serialization();
+        let mut redirects = REDIRECTS.lock().unwrap();
+
+        if location == origin {
+            if let Some(previous) = redirects.remove(instance) {
+                MASTODON_INSTANCE_REDIRECTED
+                    .remove_label_values(&[instance, &previous])
+                    .ok();
+            }
+            return;
+        }
+
+        if redirects.get(instance) != Some(&location) {
+            log!(
+                "{}: Redirected to {}, consider updating the configuration",
+                instance,
+                location
+            );
+            if let Some(previous) = redirects.insert(instance.to_string(), location.clone()) {
+                MASTODON_INSTANCE_REDIRECTED
+                    .remove_label_values(&[instance, &previous])
+                    .ok();
+            }
+        }
+        if let Some(gauge) =
+            MASTODON_INSTANCE_REDIRECTED.with_limited_label_values(&[instance, &location])
+        {
+            gauge.set(1);
+        }
+    }
+
+    fn record_ratelimit(&self, ratelimit: RateLimit, endpoint: &str) {
+        let instance = self.domain.as_str();
+
+        if let Some(gauge) =
+            MASTODON_RATELIMIT_HEADERS_PRESENT.with_limited_label_values(&[instance])
+        {
+            gauge.set(i64::from(ratelimit.present));
+        }
+        if ratelimit.present {
+            RATE_LIMITS
+                .lock()
+                .unwrap()
+                .insert(instance.to_string(), ratelimit);
+        }
+
+        if let Some(limit) = ratelimit.limit {
+            if let Some(gauge) =
+                MASTODON_RATELIMIT_LIMIT.with_limited_label_values(&[instance, endpoint])
+            {
+                gauge.set(limit);
+            }
+        }
+
+        if let Some(remaining) = ratelimit.remaining {
+            log!("{}: Ratelimit remaining: {}", instance, remaining);
+            if let Some(gauge) = MASTODON_RATELIMIT_REMAINING.with_limited_label_values(&[instance])
+            {
+                gauge.set(remaining);
+            }
+        }
+
+        if let Some(reset) = ratelimit.reset {
+            let reset = reset.timestamp();
+            log!("{}: Ratelimit reset: {}", instance, reset);
+            if let Some(gauge) = MASTODON_RATELIMIT_RESET.with_limited_label_values(&[instance]) {
+                gauge.set(reset);
+            }
+        }
+    }
+
+    /// Fetches information about the instance.
+    ///
+    /// Servers without `/api/v2/instance`, like Mastodon before 4.0 and older Pleroma, are asked
+    /// for `/api/v1/instance` instead, which is remembered for subsequent requests.
+    ///
+    /// [docs.joinmastodon.org/methods/instance/#v2](https://docs.joinmastodon.org/methods/instance/#v2)
+    pub async fn instance(&self) -> Result<InstanceResponse, Error> {
+        if !V1_INSTANCES.lock().unwrap().contains(&self.domain) {
+            match self.send_json(self.get("/api/v2/instance")).await {
+                Err(err) if err.status() == Some(StatusCode::NOT_FOUND) => {
+                    log!("{}: Falling back to /api/v1/instance", self.domain);
+                    V1_INSTANCES.lock().unwrap().insert(self.domain.clone());
+                }
+                result => return result,
+            }
+        }
+
+        self.send_json(self.get("/api/v1/instance")).await
+    }
+
+    /// Fetches the nodeinfo document of the instance, `None` if it links to none.
+    ///
+    /// Links to other hosts or ports aren't followed, so instances can't make the exporter request
+    /// internal services.
+    ///
+    /// [nodeinfo.diaspora.software/protocol](https://nodeinfo.diaspora.software/protocol)
+    pub async fn nodeinfo(&self) -> Result<Option<NodeInfoResponse>, Error> {
+        let links: NodeInfoLinks = self.send_json(self.get("/.well-known/nodeinfo")).await?;
+        // The latest schema version is listed last
+        let Some(link) = links
+            .links
+            .iter()
+            .rev()
+            .find(|link| link.rel.starts_with(NODEINFO_SCHEMA))
+        else {
+            return Ok(None);
+        };
+
+        let Some(url) = nodeinfo_url(&self.url("/"), &link.href) else {
+            log!(
+                "Error: {} nodeinfo links to another host {}, not following it",
+                self.domain,
+                link.href
+            );
+            return Ok(None);
+        };
+
+        // The document is public, so the access token is not sent along
+        self.send_json(self.http.get(url)).await.map(Some)
+    }
+
+    /// Calls the function with the domain of every instance the instance is aware of, up to
+    /// `MAX_PEERS` peers, and returns whether the peers were truncated at that limit.
+    ///
+    /// The response is parsed while it's received, so the peers of large instances are never held
+    /// in memory at once, which is why it's not limited to the maximum size of responses. To only
+    /// count the peers, pass a function incrementing a counter.
+    ///
+    /// [docs.joinmastodon.org/methods/instance/#peers](https://docs.joinmastodon.org/methods/instance/#peers)
+    pub async fn for_each_peer(&self, mut f: impl FnMut(String)) -> Result<bool, Error> {
+        let mut response = self.send(self.get("/api/v1/instance/peers")).await?;
+        let mut parser = StringArrayParser::default();
+        let mut count = 0;
+
+        let result = loop {
+            match response.chunk().await {
+                Ok(Some(chunk)) => {
+                    let mut limited = |peer| {
+                        if count < MAX_PEERS {
+                            count += 1;
+                            f(peer);
+                        }
+                    };
+                    if let Err(err) = parser.feed(&chunk, &mut limited) {
+                        break Err(Error::Json(serde::de::Error::custom(err)));
+                    }
+                    // The rest of the response is not received, as it could be endless
+                    if count >= MAX_PEERS {
+                        log!(
+                            "Warning: {} has more than {} peers, ignoring the rest",
+                            self.domain,
+                            MAX_PEERS
+                        );
+                        break Ok(true);
+                    }
+                }
+                Ok(None) => {
+                    break parser
+                        .finish()
+                        .map(|()| false)
+                        .map_err(|err| Error::Json(serde::de::Error::custom(err)))
+                }
+                Err(err) => break Err(Error::Decode(err)),
+            }
+        };
+
+        if let Err(err) = &result {
+            self.record_error(err);
+        }
+        result
+    }
+
+    /// Fetches an account by its id.
+    ///
+    /// [docs.joinmastodon.org/methods/accounts/#get](https://docs.joinmastodon.org/methods/accounts/#get)
+    pub async fn account(&self, account_id: &str) -> Result<AccountResponse, Error> {
+        self.send_json(self.get(&format!("/api/v1/accounts/{}", account_id)))
+            .await
+    }
+
+    /// Fetches the latest statuses posted by an account, excluding boosts.
+    ///
+    /// [docs.joinmastodon.org/methods/accounts/#statuses](https://docs.joinmastodon.org/methods/accounts/#statuses)
+    pub async fn account_statuses(
+        &self,
+        account_id: &str,
+        limit: usize,
+    ) -> Result<Vec<StatusResponse>, Error> {
+        self.send_json(self.get(&format!(
+            "/api/v1/accounts/{}/statuses?exclude_reblogs=true&limit={}",
+            account_id, limit
+        )))
+        .await
+    }
+
+    /// Fetches the hashtags featured on the profile of an account.
+    ///
+    /// [docs.joinmastodon.org/methods/accounts/#featured_tags](https://docs.joinmastodon.org/methods/accounts/#featured_tags)
+    pub async fn featured_tags(&self, account_id: &str) -> Result<Vec<FeaturedTagResponse>, Error> {
+        self.send_json(self.get(&format!("/api/v1/accounts/{}/featured_tags", account_id)))
+            .await
+    }
+
+    /// Fetches the relationships of the account of the access token to the given accounts.
+    ///
+    /// [docs.joinmastodon.org/methods/accounts/#relationships](https://docs.joinmastodon.org/methods/accounts/#relationships)
+    pub async fn relationships(
+        &self,
+        account_ids: &[&str],
+    ) -> Result<Vec<RelationshipResponse>, Error> {
+        let query: Vec<(&str, &str)> = account_ids.iter().map(|id| ("id[]", *id)).collect();
+        self.send_json(self.get("/api/v1/accounts/relationships").query(&query))
+            .await
+    }
+
+    /// Looks up an account by its username or webfinger address.
+    ///
+    /// [docs.joinmastodon.org/methods/accounts/#lookup](https://docs.joinmastodon.org/methods/accounts/#lookup)
+    pub async fn lookup_account(&self, acct: &str) -> Result<AccountResponse, Error> {
+        self.send_json(self.get("/api/v1/accounts/lookup").query(&[("acct", acct)]))
+            .await
+    }
+
+    /// Fetches the account of the access token with its default settings for posting.
+    ///
+    /// [docs.joinmastodon.org/methods/accounts/#verify_credentials](https://docs.joinmastodon.org/methods/accounts/#verify_credentials)
+    pub async fn verify_credentials(&self) -> Result<CredentialAccountResponse, Error> {
+        self.send_json(self.get("/api/v1/accounts/verify_credentials"))
+            .await
+    }
+
+    /// Verifies the access token of the client and fetches the application it was issued to.
+    ///
+    /// [docs.joinmastodon.org/methods/apps/#verify_credentials](https://docs.joinmastodon.org/methods/apps/#verify_credentials)
+    pub async fn verify_app_credentials(&self) -> Result<ApplicationResponse, Error> {
+        self.send_json(self.get("/api/v1/apps/verify_credentials"))
+            .await
+    }
+
+    /// Fetches the WebFinger document of an account by its address, like `Gargron@mastodon.social`.
+    ///
+    /// [docs.joinmastodon.org/spec/webfinger](https://docs.joinmastodon.org/spec/webfinger/)
+    pub async fn webfinger(&self, acct: &str) -> Result<WebFingerResponse, Error> {
+        let resource = format!("acct:{}", acct);
+        self.send_json(
+            self.get("/.well-known/webfinger")
+                .query(&[("resource", resource.as_str())]),
+        )
+        .await
+    }
+
+    /// Fetches a page of accounts of the profile directory, ordered by recent activity.
+    ///
+    /// [docs.joinmastodon.org/methods/directory/#get](https://docs.joinmastodon.org/methods/directory/#get)
+    pub async fn directory(
+        &self,
+        local: bool,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<AccountResponse>, Error> {
+        self.send_json(self.get(&format!(
+            "/api/v1/directory?local={}&offset={}&limit={}",
+            local, offset, limit
+        )))
+        .await
+    }
+
+    /// Fetches a poll by its id.
+    ///
+    /// [docs.joinmastodon.org/methods/polls/#get](https://docs.joinmastodon.org/methods/polls/#get)
+    pub async fn poll(&self, poll_id: &str) -> Result<PollResponse, Error> {
+        self.send_json(self.get(&format!("/api/v1/polls/{}", poll_id)))
+            .await
+    }
+
+    /// Fetches a status by its id.
+    ///
+    /// [docs.joinmastodon.org/methods/statuses/#get](https://docs.joinmastodon.org/methods/statuses/#get)
+    pub async fn status(&self, status_id: &str) -> Result<StatusResponse, Error> {
+        self.send_json(self.get(&format!("/api/v1/statuses/{}", status_id)))
+            .await
+    }
+
+    /// Fetches the latest statuses of the public timeline.
+    ///
+    /// [docs.joinmastodon.org/methods/timelines/#public](https://docs.joinmastodon.org/methods/timelines/#public)
+    pub async fn public_timeline(&self, local: bool) -> Result<Vec<StatusResponse>, Error> {
+        self.send_json(self.get(&format!(
+            "/api/v1/timelines/public?local={}&limit=40",
+            local
+        )))
+        .await
+    }
+
+    /// Fetches the latest statuses of the home timeline, requires an access token.
+    ///
+    /// [docs.joinmastodon.org/methods/timelines/#home](https://docs.joinmastodon.org/methods/timelines/#home)
+    pub async fn home_timeline(&self) -> Result<Vec<StatusResponse>, Error> {
+        self.send_json(self.get("/api/v1/timelines/home?limit=40"))
+            .await
+    }
+
+    /// Fetches the hashtags followed by the account of the access token.
+    ///
+    /// [docs.joinmastodon.org/methods/followed_tags/#get](https://docs.joinmastodon.org/methods/followed_tags/#get)
+    pub async fn followed_tags(&self) -> Result<Vec<TagResponse>, Error> {
+        self.send_json_pages("/api/v1/followed_tags?limit=200")
+            .await
+    }
+
+    /// Counts the accounts muted by the account of the access token.
+    ///
+    /// [docs.joinmastodon.org/methods/mutes/#get](https://docs.joinmastodon.org/methods/mutes/#get)
+    pub async fn mutes_count(&self) -> Result<PageCount, Error> {
+        self.count_pages("/api/v1/mutes?limit=80").await
+    }
+
+    /// Counts the accounts blocked by the account of the access token.
+    ///
+    /// [docs.joinmastodon.org/methods/blocks/#get](https://docs.joinmastodon.org/methods/blocks/#get)
+    pub async fn blocks_count(&self) -> Result<PageCount, Error> {
+        self.count_pages("/api/v1/blocks?limit=80").await
+    }
+
+    /// Counts the statuses bookmarked by the account of the access token.
+    ///
+    /// [docs.joinmastodon.org/methods/bookmarks/#get](https://docs.joinmastodon.org/methods/bookmarks/#get)
+    pub async fn bookmarks_count(&self) -> Result<PageCount, Error> {
+        self.count_pages("/api/v1/bookmarks?limit=40").await
+    }
+
+    /// Counts the statuses favourited by the account of the access token.
+    ///
+    /// [docs.joinmastodon.org/methods/favourites/#get](https://docs.joinmastodon.org/methods/favourites/#get)
+    pub async fn favourites_count(&self) -> Result<PageCount, Error> {
+        self.count_pages("/api/v1/favourites?limit=40").await
+    }
+
+    /// Fetches the conversations with direct statuses of the account of the access token.
+    ///
+    /// [docs.joinmastodon.org/methods/conversations/#get](https://docs.joinmastodon.org/methods/conversations/#get)
+    pub async fn conversations(&self) -> Result<Vec<ConversationResponse>, Error> {
+        self.send_json_pages("/api/v1/conversations?limit=40").await
+    }
+
+    /// Fetches the currently trending hashtags.
+    ///
+    /// [docs.joinmastodon.org/methods/trends/#tags](https://docs.joinmastodon.org/methods/trends/#tags)
+    pub async fn trends(&self) -> Result<Vec<TagResponse>, Error> {
+        self.send_json(self.get("/api/v1/trends/tags")).await
+    }
+
+    /// Fetches a hashtag by its name.
+    ///
+    /// [docs.joinmastodon.org/methods/tags/#get](https://docs.joinmastodon.org/methods/tags/#get)
+    pub async fn tag(&self, name: &str) -> Result<TagResponse, Error> {
+        self.send_json(self.get(&tag_path(name))).await
+    }
+
+    /// Fetches the measures with the given keys of a hashtag over the days from `start_at` to
+    /// `end_at`, like `2024-01-31`, requires an access token of an admin.
+    ///
+    /// [docs.joinmastodon.org/methods/admin/measures](https://docs.joinmastodon.org/methods/admin/measures/)
+    pub async fn tag_measures(
+        &self,
+        keys: &[&str],
+        tag_id: &str,
+        start_at: &str,
+        end_at: &str,
+    ) -> Result<Vec<MeasureResponse>, Error> {
+        let mut form = vec![
+            ("start_at".to_string(), start_at),
+            ("end_at".to_string(), end_at),
+        ];
+        for key in keys {
+            form.push(("keys[]".to_string(), key));
+            form.push((format!("{}[id]", key), tag_id));
+        }
+
+        self.send_json(self.post("/api/v1/admin/measures").form(&form))
+            .await
+    }
+
+    /// Searches for accounts, statuses and hashtags, requires an access token.
+    ///
+    /// [docs.joinmastodon.org/methods/search/#v2](https://docs.joinmastodon.org/methods/search/#v2)
+    pub async fn search(&self, query: &str) -> Result<SearchResponse, Error> {
+        self.send_json(self.get("/api/v2/search").query(&[("q", query)]))
+            .await
+    }
+
+    /// Resolves a remote account by its webfinger address, requires an access token.
+    ///
+    /// [docs.joinmastodon.org/methods/search/#v2](https://docs.joinmastodon.org/methods/search/#v2)
+    pub async fn resolve_account(&self, acct: &str) -> Result<SearchResponse, Error> {
+        self.send_json(self.get("/api/v2/search").query(&[
+            ("q", acct),
+            ("type", "accounts"),
+            ("resolve", "true"),
+        ]))
+        .await
+    }
+
+    /// Posts a status with the given text and visibility, requires an access token.
+    ///
+    /// [docs.joinmastodon.org/methods/statuses/#create](https://docs.joinmastodon.org/methods/statuses/#create)
+    pub async fn post_status(&self, text: &str, visibility: &str) -> Result<StatusResponse, Error> {
+        self.send_json(
+            self.post("/api/v1/statuses")
+                .form(&[("status", text), ("visibility", visibility)]),
+        )
+        .await
+    }
+
+    /// Deletes a status by its id, requires an access token.
+    ///
+    /// [docs.joinmastodon.org/methods/statuses/#delete](https://docs.joinmastodon.org/methods/statuses/#delete)
+    pub async fn delete_status(&self, status_id: &str) -> Result<(), Error> {
+        self.send(self.delete(&format!("/api/v1/statuses/{}", status_id)))
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(headers: &[(&'static str, &str)]) -> HeaderMap {
+        headers
+            .iter()
+            .map(|(name, value)| {
+                (
+                    reqwest::header::HeaderName::from_static(name),
+                    value.parse().unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    async fn request_error(url: &str, timeout: Duration) -> Error {
+        let client = reqwest::Client::builder().timeout(timeout).build().unwrap();
+        Error::Request(client.get(url).send().await.unwrap_err())
+    }
+
+    #[test]
+    fn classifies_responses() {
+        assert_eq!(Error::Status(StatusCode::NOT_FOUND).reason(), "http_4xx");
+        assert_eq!(
+            Error::Status(StatusCode::TOO_MANY_REQUESTS).reason(),
+            "ratelimited"
+        );
+        assert_eq!(Error::Status(StatusCode::BAD_GATEWAY).reason(), "http_5xx");
+        let json = serde_json::from_str::<u32>("{").unwrap_err();
+        assert_eq!(Error::Json(json).reason(), "decode");
+        assert_eq!(Error::TooLarge(1024).reason(), "too_large");
+        assert_eq!(Error::Cached("dns").reason(), "dns");
+        assert_eq!(
+            Error::Replay(std::io::ErrorKind::NotFound.into()).reason(),
+            "replay"
+        );
+    }
+
+    #[tokio::test]
+    async fn classifies_request_failures() {
+        // A port of a closed listener refuses connections
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let err = request_error(&format!("http://{}/", addr), Duration::from_secs(5)).await;
+        assert_eq!(err.reason(), "connect");
+
+        // A listener which never accepts lets the request time out
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let err = request_error(&format!("http://{}/", addr), Duration::from_millis(100)).await;
+        assert_eq!(err.reason(), "timeout");
+
+        let err = request_error("http://mastodon.invalid/", Duration::from_secs(5)).await;
+        assert_eq!(err.reason(), "dns");
+    }
+
+    #[test]
+    fn parses_mastodon_ratelimit_headers() {
+        let (ratelimit, malformed) = RateLimit::parse(&headers(&[
+            ("x-ratelimit-limit", "300"),
+            ("x-ratelimit-remaining", "299"),
+            ("x-ratelimit-reset", "2026-10-16T12:05:00.000Z"),
+        ]));
+
+        assert!(malformed.is_empty());
+        assert!(ratelimit.present);
+        assert_eq!(ratelimit.limit, Some(300));
+        assert_eq!(ratelimit.remaining, Some(299));
+        assert_eq!(
+            ratelimit.reset,
+            Some("2026-10-16T12:05:00Z".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn parses_alternate_ratelimit_headers() {
+        let (ratelimit, malformed) =
+            RateLimit::parse(&headers(&[("x-ratelimit-reset", "1791000000")]));
+        assert!(malformed.is_empty());
+        assert_eq!(ratelimit.reset, DateTime::from_timestamp(1791000000, 0));
+
+        let before = Utc::now();
+        let (ratelimit, malformed) = RateLimit::parse(&headers(&[
+            ("ratelimit-limit", "100"),
+            ("ratelimit-remaining", "5"),
+            ("ratelimit-reset", "60"),
+        ]));
+        assert!(malformed.is_empty());
+        assert!(ratelimit.present);
+        assert_eq!(ratelimit.limit, Some(100));
+        assert_eq!(ratelimit.remaining, Some(5));
+        let reset = ratelimit.reset.unwrap();
+        assert!(reset >= before + chrono::Duration::seconds(60));
+        assert!(reset <= Utc::now() + chrono::Duration::seconds(60));
+    }
+
+    #[test]
+    fn reports_missing_and_malformed_ratelimit_headers() {
+        let (ratelimit, malformed) = RateLimit::parse(&HeaderMap::new());
+        assert!(malformed.is_empty());
+        assert!(!ratelimit.present);
+        assert_eq!(ratelimit.limit, None);
+
+        let (ratelimit, malformed) = RateLimit::parse(&headers(&[
+            ("x-ratelimit-limit", "many"),
+            ("x-ratelimit-remaining", "-"),
+            ("ratelimit-reset", "soon"),
+        ]));
+        assert!(ratelimit.present);
+        assert_eq!(
+            malformed,
+            [
+                "x-ratelimit-limit",
+                "x-ratelimit-remaining",
+                "ratelimit-reset"
+            ]
+        );
+    }
+
+    #[test]
+    fn labels_endpoints_without_ids() {
+        assert_eq!(
+            endpoint("/api/v1/accounts/109348932/statuses"),
+            "/api/v1/accounts/:id/statuses"
+        );
+        assert_eq!(endpoint("/api/v2/instance"), "/api/v2/instance");
+        assert_eq!(endpoint("/api/v1/polls/AbC12"), "/api/v1/polls/:id");
+    }
+
+    #[test]
+    fn parses_next_links() {
+        let base = reqwest::Url::parse("https://mastodon.social/api/v1/blocks").unwrap();
+        let next =
+            |link: &str| next_link(&headers(&[("link", link)]), &base).map(|url| url.to_string());
+
+        assert_eq!(
+            next("<https://mastodon.social/api/v1/blocks?max_id=2>; rel=\"next\", <https://mastodon.social/api/v1/blocks?min_id=9>; rel=\"prev\""),
+            Some("https://mastodon.social/api/v1/blocks?max_id=2".to_string())
+        );
+        assert_eq!(
+            next("<https://mastodon.social/api/v1/blocks?min_id=9>; rel=\"prev\",<https://mastodon.social/api/v1/blocks?max_id=2>;REL=next"),
+            Some("https://mastodon.social/api/v1/blocks?max_id=2".to_string())
+        );
+        assert_eq!(
+            next("</api/v1/blocks?max_id=2>; title=\"more\"; rel=\"last next\""),
+            Some("https://mastodon.social/api/v1/blocks?max_id=2".to_string())
+        );
+        assert_eq!(
+            next("<https://mastodon.social/api/v1/blocks?min_id=9>; rel=\"prev\""),
+            None
+        );
+        assert_eq!(next("https://mastodon.social/; rel=\"next\""), None);
+        assert_eq!(next_link(&HeaderMap::new(), &base), None);
+    }
+
+    /// Serves pages with one item each, linking to the next page returned by the function for
+    /// the number of the request, and returns the port and the number of requests.
+    async fn serve_pages(
+        next: impl Fn(usize, u16) -> String + Send + 'static,
+    ) -> (u16, Arc<std::sync::atomic::AtomicUsize>) {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let served = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buffer[..read]);
+                }
+
+                let link = next(served.fetch_add(1, Ordering::SeqCst), port);
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\nlink: {}\r\ncontent-length: 4\r\nconnection: close\r\n\r\n[{{}}]",
+                    link
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (port, requests)
+    }
+
+    #[tokio::test]
+    async fn follows_next_links_only_to_the_instance() {
+        // The second page links to the same listener, but by another origin
+        let (port, requests) = serve_pages(|request, port| match request {
+            0 => "</api/v1/blocks?page=2>; rel=\"next\"".to_string(),
+            _ => format!(
+                "<http://localhost:{}/api/v1/blocks?page=3>; rel=\"next\"",
+                port
+            ),
+        })
+        .await;
+
+        let client =
+            MastodonClient::new(format!("http://127.0.0.1:{}", port)).with_access_token("token");
+        let blocks = client.blocks_count().await.unwrap();
+        assert_eq!((blocks.count, blocks.truncated), (2, false));
+        assert_eq!(requests.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn counts_are_truncated_at_the_page_limit() {
+        let (port, requests) = serve_pages(|request, _| {
+            format!("</api/v1/bookmarks?page={}>; rel=\"next\"", request + 2)
+        })
+        .await;
+
+        let client =
+            MastodonClient::new(format!("http://127.0.0.1:{}", port)).with_access_token("token");
+        let bookmarks = client.bookmarks_count().await.unwrap();
+        assert_eq!((bookmarks.count, bookmarks.truncated), (MAX_PAGES, true));
+        assert_eq!(
+            requests.load(std::sync::atomic::Ordering::SeqCst),
+            MAX_PAGES
+        );
+    }
+
+    #[test]
+    fn tag_names_are_a_single_path_segment() {
+        assert_eq!(tag_path("#rust"), "/api/v1/tags/rust");
+        assert_eq!(tag_path("rust"), "/api/v1/tags/rust");
+        assert_eq!(tag_path("c#/../x?y"), "/api/v1/tags/c%23%2F..%2Fx%3Fy");
+        assert_eq!(tag_path("日本"), "/api/v1/tags/%E6%97%A5%E6%9C%AC");
+    }
+
+    #[test]
+    fn only_follows_nodeinfo_links_on_the_instance() {
+        let url = |href| nodeinfo_url("https://mastodon.social/", href).map(|url| url.to_string());
+
+        assert_eq!(
+            url("https://mastodon.social/nodeinfo/2.0"),
+            Some("https://mastodon.social/nodeinfo/2.0".to_string())
+        );
+        assert_eq!(
+            url("/nodeinfo/2.1"),
+            Some("https://mastodon.social/nodeinfo/2.1".to_string())
+        );
+        assert_eq!(
+            url("http://MASTODON.social/nodeinfo/2.0"),
+            Some("http://mastodon.social/nodeinfo/2.0".to_string())
+        );
+        assert_eq!(url("https://169.254.169.254/latest/meta-data"), None);
+        assert_eq!(
+            url("https://mastodon.social.evil.example/nodeinfo/2.0"),
+            None
+        );
+        assert_eq!(url("https://mastodon.social:6379/"), None);
+        assert_eq!(url("file:///etc/passwd"), None);
+    }
+
+    #[test]
+    fn only_counts_requests_to_the_instance() {
+        let client = MastodonClient::new("mastodon.social");
+        let url = |url| reqwest::Url::parse(url).unwrap();
+
+        assert!(client.is_instance_url(&url("https://mastodon.social/api/v1/instance")));
+        assert!(!client.is_instance_url(&url("https://files.mastodon.social/thumbnail.png")));
+        assert!(!client.is_instance_url(&url("http://mastodon.social/api/v1/instance")));
+    }
+
+    #[test]
+    fn parses_certificate_expiry() {
+        let certificate = include_bytes!("../../tests/fixtures/certificate.der");
+
+        // 2027-01-01T00:00:00Z
+        assert_eq!(certificate_expiry(certificate), Some(1_798_761_600));
+        assert_eq!(certificate_expiry(b"not a certificate"), None);
+    }
+}
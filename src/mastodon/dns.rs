@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use trust_dns_resolver::config::{
+    NameServerConfigGroup, ResolverConfig, ResolverOpts, ServerOrderingStrategy,
+};
+use trust_dns_resolver::proto::op::{Message, MessageType, OpCode, Query, ResponseCode};
+use trust_dns_resolver::proto::rr::{RData, RecordType};
+use trust_dns_resolver::proto::serialize::binary::{BinDecodable, BinEncodable};
+use trust_dns_resolver::TokioAsyncResolver;
+
+use crate::config::DnsConfig;
+
+/// The error of domains which don't exist.
+const NXDOMAIN: &str = "no such domain";
+
+/// The result of a lookup, cached until it expires.
+struct CacheEntry {
+    expires: Instant,
+    result: Result<Vec<IpAddr>, String>,
+}
+
+/// A resolver which caches lookups and queries the configured nameservers, a DNS-over-HTTPS
+/// server or the nameservers of the system.
+#[derive(Clone)]
+pub struct Resolver {
+    config: Arc<DnsConfig>,
+    cache: Arc<Mutex<HashMap<String, CacheEntry>>>,
+    resolver: TokioAsyncResolver,
+    http: reqwest::Client,
+}
+
+impl Resolver {
+    pub fn new(config: DnsConfig) -> Self {
+        Self {
+            resolver: resolver(&config),
+            config: Arc::new(config),
+            cache: Arc::new(Mutex::new(HashMap::new())),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Resolves the addresses of the domain, answering from the cache if possible.
+    async fn lookup(&self, domain: &str) -> Result<Vec<IpAddr>, String> {
+        if let Some(entry) = self.cache.lock().unwrap().get(domain) {
+            if entry.expires > Instant::now() {
+                return entry.result.clone();
+            }
+        }
+
+        let result = match &self.config.doh_url {
+            Some(doh_url) => self.lookup_doh(doh_url, domain).await,
+            None => self.lookup_nameservers(domain).await,
+        };
+
+        let cache_seconds = match result {
+            Ok(_) => self.config.cache_seconds,
+            Err(_) => self.config.negative_cache_seconds,
+        };
+        if cache_seconds > 0 {
+            self.cache.lock().unwrap().insert(
+                domain.to_string(),
+                CacheEntry {
+                    expires: Instant::now() + Duration::from_secs(cache_seconds),
+                    result: result.clone(),
+                },
+            );
+        }
+
+        result
+    }
+
+    /// Queries the configured nameservers in order, or the nameservers of the system.
+    async fn lookup_nameservers(&self, domain: &str) -> Result<Vec<IpAddr>, String> {
+        let lookup = self
+            .resolver
+            .lookup_ip(domain)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        found(lookup.iter().collect())
+    }
+
+    /// Queries the DNS-over-HTTPS server with the wire format of RFC 8484.
+    async fn lookup_doh(&self, doh_url: &str, domain: &str) -> Result<Vec<IpAddr>, String> {
+        let mut addresses = Vec::new();
+
+        for record_type in [RecordType::A, RecordType::AAAA] {
+            let response = self
+                .http
+                .post(doh_url)
+                .header("content-type", "application/dns-message")
+                .header("accept", "application/dns-message")
+                .timeout(Duration::from_secs(self.config.timeout_seconds))
+                .body(query(domain, record_type)?)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|err| err.to_string())?;
+            let body = response.bytes().await.map_err(|err| err.to_string())?;
+
+            addresses.extend(parse_response(&body)?);
+        }
+
+        found(addresses)
+    }
+}
+
+impl Resolve for Resolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+
+        Box::pin(async move {
+            let addresses = resolver.lookup(name.as_str()).await?;
+            let addrs: Addrs = Box::new(
+                addresses
+                    .into_iter()
+                    .map(|address| SocketAddr::new(address, 0)),
+            );
+            Ok(addrs)
+        })
+    }
+}
+
+/// Returns a resolver for the configured nameservers, or the nameservers of the system.
+fn resolver(config: &DnsConfig) -> TokioAsyncResolver {
+    let (resolver_config, mut options) = if config.nameservers.is_empty() {
+        trust_dns_resolver::system_conf::read_system_conf().unwrap_or_else(|err| {
+            log!(
+                "Error: unable to read the DNS configuration of the system, using the default nameservers: {}",
+                err
+            );
+            (ResolverConfig::default(), ResolverOpts::default())
+        })
+    } else {
+        let mut nameservers = NameServerConfigGroup::new();
+        for nameserver in &config.nameservers {
+            // The nameservers were validated when loading the configuration
+            let address = match nameserver.parse::<IpAddr>() {
+                Ok(ip) => SocketAddr::new(ip, 53),
+                Err(_) => match nameserver.parse() {
+                    Ok(address) => address,
+                    Err(_) => continue,
+                },
+            };
+            nameservers.merge(NameServerConfigGroup::from_ips_clear(
+                &[address.ip()],
+                address.port(),
+                true,
+            ));
+        }
+
+        let mut options = ResolverOpts::default();
+        options.server_ordering_strategy = ServerOrderingStrategy::UserProvidedOrder;
+        (
+            ResolverConfig::from_parts(None, vec![], nameservers),
+            options,
+        )
+    };
+
+    options.timeout = Duration::from_secs(config.timeout_seconds);
+    // Lookups are cached by `Resolver` for the configured durations, also those over HTTPS
+    options.positive_max_ttl = Some(Duration::ZERO);
+    options.negative_max_ttl = Some(Duration::ZERO);
+
+    TokioAsyncResolver::tokio(resolver_config, options)
+}
+
+fn found(addresses: Vec<IpAddr>) -> Result<Vec<IpAddr>, String> {
+    if addresses.is_empty() {
+        Err("no addresses found".to_string())
+    } else {
+        Ok(addresses)
+    }
+}
+
+/// Encodes a recursive query for records of the given type of the domain.
+fn query(domain: &str, record_type: RecordType) -> Result<Vec<u8>, String> {
+    let name = trust_dns_resolver::Name::from_ascii(domain)
+        .map_err(|_| format!("invalid domain {}", domain))?;
+
+    let mut message = Message::new();
+    // The ID should be 0 to make responses cacheable by HTTP caches
+    message
+        .set_id(0)
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(true)
+        .add_query(Query::query(name, record_type));
+
+    message.to_bytes().map_err(|err| err.to_string())
+}
+
+/// Decodes the addresses of the answer records of a response.
+fn parse_response(packet: &[u8]) -> Result<Vec<IpAddr>, String> {
+    let message = Message::from_bytes(packet).map_err(|_| "malformed response".to_string())?;
+    match message.response_code() {
+        ResponseCode::NoError => {}
+        ResponseCode::NXDomain => return Err(NXDOMAIN.to_string()),
+        code => return Err(format!("response code {}", code)),
+    }
+
+    // Other records like CNAMEs precede the addresses of their target
+    Ok(message
+        .answers()
+        .iter()
+        .filter_map(|record| match record.data()? {
+            RData::A(address) => Some(IpAddr::V4(address.0)),
+            RData::AAAA(address) => Some(IpAddr::V6(address.0)),
+            _ => None,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use trust_dns_resolver::proto::rr::rdata::{A, AAAA, CNAME};
+    use trust_dns_resolver::proto::rr::Record;
+    use trust_dns_resolver::Name as DomainName;
+
+    use super::*;
+
+    fn response(code: ResponseCode, answers: Vec<Record>) -> Vec<u8> {
+        let mut message =
+            Message::from_bytes(&query("mastodon.social", RecordType::A).unwrap()).unwrap();
+        message
+            .set_message_type(MessageType::Response)
+            .set_response_code(code)
+            .add_answers(answers);
+        message.to_bytes().unwrap()
+    }
+
+    #[test]
+    fn parses_addresses_of_responses() {
+        let name = DomainName::from_ascii("mastodon.social.").unwrap();
+        let target = DomainName::from_ascii("cdn.mastodon.social.").unwrap();
+        let answers = vec![
+            Record::from_rdata(name, 60, RData::CNAME(CNAME(target.clone()))),
+            Record::from_rdata(target.clone(), 60, RData::A(A(Ipv4Addr::new(192, 0, 2, 1)))),
+            Record::from_rdata(target, 60, RData::AAAA(AAAA(Ipv6Addr::LOCALHOST))),
+        ];
+
+        assert_eq!(
+            parse_response(&response(ResponseCode::NoError, answers)),
+            Ok(vec![
+                IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+                IpAddr::V6(Ipv6Addr::LOCALHOST)
+            ])
+        );
+    }
+
+    #[test]
+    fn rejects_failed_and_malformed_responses() {
+        assert_eq!(
+            parse_response(&response(ResponseCode::NXDomain, vec![])),
+            Err(NXDOMAIN.to_string())
+        );
+        assert_eq!(
+            parse_response(&response(ResponseCode::ServFail, vec![])),
+            Err("response code Server Failure".to_string())
+        );
+        assert_eq!(
+            parse_response(&[0, 1, 2]),
+            Err("malformed response".to_string())
+        );
+        assert!(query("not a..domain", RecordType::A).is_err());
+    }
+}
@@ -0,0 +1,40 @@
+/// Converts the domain of an instance with the given function, keeping a port as it is.
+///
+/// Instances with a scheme like `http://localhost:3000` are returned unchanged.
+fn convert(instance: &str, f: impl Fn(&str) -> Option<String>) -> String {
+    if instance.contains("://") {
+        return instance.to_string();
+    }
+
+    let (domain, port) = match instance.split_once(':') {
+        Some((domain, port)) => (domain, Some(port)),
+        None => (instance, None),
+    };
+    let Some(domain) = f(domain) else {
+        return instance.to_string();
+    };
+
+    match port {
+        Some(port) => format!("{}:{}", domain, port),
+        None => domain,
+    }
+}
+
+/// Returns the Unicode form of the domain of an instance, e.g. `社交.example` for
+/// `xn--tlq815h.example`, which is used for labels.
+///
+/// Invalid domains are returned unchanged.
+pub fn to_unicode(instance: &str) -> String {
+    convert(instance, |domain| {
+        let (unicode, result) = idna::domain_to_unicode(domain);
+        result.ok().map(|_| unicode)
+    })
+}
+
+/// Returns the ASCII form of the domain of an instance, e.g. `xn--tlq815h.example` for
+/// `社交.example`, which is used for requests.
+///
+/// Invalid domains are returned unchanged.
+pub fn to_ascii(instance: &str) -> String {
+    convert(instance, |domain| idna::domain_to_ascii(domain).ok())
+}
@@ -0,0 +1,234 @@
+/// Where the parser is in the array.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum State {
+    /// Before the opening bracket.
+    #[default]
+    Start,
+
+    /// After the opening bracket, where the array may end.
+    First,
+
+    /// After a comma, where a string has to follow.
+    Value,
+
+    /// In a string.
+    String,
+
+    /// After a backslash in a string.
+    Escape,
+
+    /// In the hex digits of a `\u` escape.
+    Unicode,
+
+    /// After a string, where a comma or the closing bracket has to follow.
+    Separator,
+
+    /// After the closing bracket.
+    End,
+}
+
+/// An incremental parser of a JSON array of strings, fed with the chunks of a response.
+///
+/// Only the string being parsed is held in memory, so arrays larger than the available memory can
+/// be processed. Anything but an array of strings is rejected.
+#[derive(Default)]
+pub struct StringArrayParser {
+    state: State,
+
+    /// The bytes of the string being parsed.
+    current: Vec<u8>,
+
+    /// The hex digits of a `\u` escape being parsed.
+    digits: String,
+
+    /// The high surrogate of a UTF-16 surrogate pair, waiting for the low surrogate.
+    high_surrogate: Option<u16>,
+}
+
+impl StringArrayParser {
+    /// Parses the next chunk and calls the function with every completed string.
+    pub fn feed(&mut self, chunk: &[u8], f: &mut impl FnMut(String)) -> Result<(), String> {
+        for &byte in chunk {
+            self.state = match (self.state, byte) {
+                (State::String, b'"') => {
+                    self.end_surrogate();
+                    f(String::from_utf8_lossy(&self.current).into_owned());
+                    self.current.clear();
+                    State::Separator
+                }
+                (State::String, b'\\') => State::Escape,
+                (State::String, byte) if byte < 0x20 => {
+                    return Err("control character in string".to_string())
+                }
+                (State::String, byte) => {
+                    self.end_surrogate();
+                    self.current.push(byte);
+                    State::String
+                }
+                (State::Escape, b'u') => State::Unicode,
+                (State::Escape, byte) => {
+                    self.end_surrogate();
+                    let char = match byte {
+                        b'"' | b'\\' | b'/' => byte,
+                        b'b' => b'\x08',
+                        b'f' => b'\x0c',
+                        b'n' => b'\n',
+                        b'r' => b'\r',
+                        b't' => b'\t',
+                        _ => return Err(format!("invalid escape \\{}", byte as char)),
+                    };
+                    self.current.push(char);
+                    State::String
+                }
+                (State::Unicode, byte) => {
+                    if !byte.is_ascii_hexdigit() {
+                        return Err("invalid unicode escape".to_string());
+                    }
+                    self.digits.push(byte as char);
+                    if self.digits.len() < 4 {
+                        State::Unicode
+                    } else {
+                        let code = u16::from_str_radix(&self.digits, 16).unwrap();
+                        self.digits.clear();
+                        self.push_code_unit(code);
+                        State::String
+                    }
+                }
+                (_, b' ' | b'\t' | b'\n' | b'\r') => self.state,
+                (State::Start, b'[') => State::First,
+                (State::First | State::Value, b'"') => State::String,
+                (State::First, b']') | (State::Separator, b']') => State::End,
+                (State::Separator, b',') => State::Value,
+                (state, byte) => {
+                    return Err(format!(
+                        "unexpected {:?} {}",
+                        byte as char,
+                        match state {
+                            State::Start => "instead of an array",
+                            State::End => "after the array",
+                            _ => "instead of a string",
+                        }
+                    ))
+                }
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Returns an error if the array is incomplete.
+    pub fn finish(&self) -> Result<(), String> {
+        match self.state {
+            State::End => Ok(()),
+            _ => Err("unexpected end of the array".to_string()),
+        }
+    }
+
+    /// Adds a UTF-16 code unit of a `\u` escape, combining surrogate pairs.
+    fn push_code_unit(&mut self, code: u16) {
+        let char = match (self.high_surrogate.take(), code) {
+            (Some(high), 0xdc00..=0xdfff) => {
+                let code =
+                    0x10000 + ((u32::from(high) - 0xd800) << 10) + (u32::from(code) - 0xdc00);
+                char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+            }
+            (high, 0xd800..=0xdbff) => {
+                self.push_char(high.map(|_| char::REPLACEMENT_CHARACTER));
+                self.high_surrogate = Some(code);
+                return;
+            }
+            (high, code) => {
+                self.push_char(high.map(|_| char::REPLACEMENT_CHARACTER));
+                char::from_u32(u32::from(code)).unwrap_or(char::REPLACEMENT_CHARACTER)
+            }
+        };
+        self.push_char(Some(char));
+    }
+
+    /// Replaces a high surrogate without a low surrogate following it.
+    fn end_surrogate(&mut self) {
+        if self.high_surrogate.take().is_some() {
+            self.push_char(Some(char::REPLACEMENT_CHARACTER));
+        }
+    }
+
+    fn push_char(&mut self, char: Option<char>) {
+        if let Some(char) = char {
+            self.current
+                .extend_from_slice(char.encode_utf8(&mut [0; 4]).as_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses the chunks, returning the strings and the result.
+    fn parse(chunks: &[&[u8]]) -> (Vec<String>, Result<(), String>) {
+        let mut parser = StringArrayParser::default();
+        let mut strings = Vec::new();
+        let mut f = |string| strings.push(string);
+
+        let result = chunks
+            .iter()
+            .try_for_each(|chunk| parser.feed(chunk, &mut f))
+            .and_then(|_| parser.finish());
+        (strings, result)
+    }
+
+    #[test]
+    fn parses_strings_across_chunks() {
+        let (strings, result) = parse(&[
+            b" [\"mastodon.social\", \"a\\\"b\\\\c\\/",
+            b"d\\n\"",
+            b",\"\\u00e9\\u",
+            b"20AC\"\n]\n",
+        ]);
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(strings, ["mastodon.social", "a\"b\\c/d\n", "é€"]);
+        assert_eq!(parse(&[b"[ ]"]), (vec![], Ok(())));
+    }
+
+    #[test]
+    fn decodes_surrogate_pairs() {
+        let (strings, result) = parse(&[b"[\"\\ud83d", b"\\ude00\", \"\\ud83d!\", \"\\ude00\"]"]);
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(strings, ["😀", "\u{fffd}!", "\u{fffd}"]);
+    }
+
+    #[test]
+    fn rejects_anything_but_an_array_of_strings() {
+        for json in [
+            &b"{\"error\": \"Not found\"}"[..],
+            b"\"mastodon.social\"",
+            b"[\"mastodon.social\", 1]",
+            b"[\"mastodon.social\",]",
+            b"[\"a\" \"b\"]",
+            b"[\"a\"] []",
+            b"[\"\\x\"]",
+            b"[\"\\u12g4\"]",
+            b"[\"a\tb\"]",
+        ] {
+            assert!(
+                parse(&[json]).1.is_err(),
+                "{}",
+                String::from_utf8_lossy(json)
+            );
+        }
+
+        assert_eq!(
+            parse(&[b"[\"a\", \"b"]),
+            (
+                vec!["a".to_string()],
+                Err("unexpected end of the array".to_string())
+            )
+        );
+        assert_eq!(
+            parse(&[b"<html>"]).1,
+            Err("unexpected '<' instead of an array".to_string())
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+static REPLAY_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// A recorded response of an instance.
+#[derive(Serialize, Deserialize)]
+struct Fixture {
+    status: u16,
+    headers: BTreeMap<String, String>,
+    body: String,
+}
+
+impl Fixture {
+    async fn record(response: reqwest::Response) -> Result<Self, reqwest::Error> {
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let body = response.text().await?;
+
+        Ok(Self {
+            status,
+            headers,
+            body,
+        })
+    }
+
+    /// Returns the recorded response, or an error if the fixture was edited to be invalid, e.g.
+    /// with an unknown status or a malformed header.
+    fn into_response(self) -> Result<reqwest::Response, super::Error> {
+        let mut builder = http::Response::builder().status(self.status);
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+
+        builder
+            .body(self.body)
+            .map(reqwest::Response::from)
+            .map_err(|err| super::Error::Replay(io::Error::other(err)))
+    }
+}
+
+/// Enables record/replay mode with fixtures stored in the given directory.
+///
+/// Responses of requests without a fixture are fetched and recorded, all other requests are
+/// answered from their fixture without hitting the instance.
+pub fn enable(dir: impl Into<PathBuf>) {
+    REPLAY_DIR.set(dir.into()).ok();
+}
+
+/// Returns the fixture directory if record/replay mode is enabled.
+pub(super) fn dir() -> Option<&'static Path> {
+    REPLAY_DIR.get().map(PathBuf::as_path)
+}
+
+/// Returns the fixture file of a request, derived from its method and URL.
+fn fixture_path(dir: &Path, request: &reqwest::Request) -> PathBuf {
+    let url = request.url();
+    let key = format!(
+        "{} {}{}?{}",
+        request.method(),
+        url.host_str().unwrap_or_default(),
+        url.path(),
+        url.query().unwrap_or_default()
+    );
+    let file_name: String = key
+        .trim_end_matches('?')
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    dir.join(format!("{}.json", file_name))
+}
+
+/// Answers a request from its fixture, or executes and records it if there is none.
+pub(super) async fn execute(
+    dir: &Path,
+    http: &reqwest::Client,
+    request: reqwest::Request,
+) -> Result<reqwest::Response, super::Error> {
+    let path = fixture_path(dir, &request);
+
+    if path.exists() {
+        let fixture = tokio::fs::read(&path).await.map_err(super::Error::Replay)?;
+        let fixture: Fixture = serde_json::from_slice(&fixture)
+            .map_err(|err| super::Error::Replay(io::Error::other(err)))?;
+
+        return fixture.into_response();
+    }
+
+    let response = http.execute(request).await.map_err(super::Error::Request)?;
+    let fixture = Fixture::record(response)
+        .await
+        .map_err(super::Error::Request)?;
+
+    log!("Recording fixture {}", path.display());
+    tokio::fs::create_dir_all(dir)
+        .await
+        .map_err(super::Error::Replay)?;
+    tokio::fs::write(&path, serde_json::to_vec_pretty(&fixture).unwrap())
+        .await
+        .map_err(super::Error::Replay)?;
+
+    fixture.into_response()
+}
@@ -0,0 +1,1393 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, Once, RwLock};
+
+use prometheus::{
+    core::{Collector, MetricVec, MetricVecBuilder},
+    proto::{LabelPair, MetricFamily, MetricType},
+    Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts,
+    ProtobufEncoder, Registry, TextEncoder,
+};
+
+mod exemplars;
+mod native;
+mod openmetrics;
+pub mod scope;
+
+lazy_static! {
+    /// The registry all metrics are registered in.
+    pub static ref REGISTRY: Registry = Registry::new();
+
+    // Series rejected
+    pub static ref MASTODON_SERIES_REJECTED: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_series_rejected",
+            "Number of series of metric which were dropped for exceeding the series limit.",
+        ),
+        &["metric"],
+    )
+    .unwrap();
+
+    // Ratelimit limit
+    pub static ref MASTODON_RATELIMIT_LIMIT: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_ratelimit_limit",
+            "Number of requests allowed per ratelimit period of instance by endpoint.",
+        ),
+        &["instance", "endpoint"],
+    )
+    .unwrap();
+
+    // Ratelimit remaining
+    pub static ref MASTODON_RATELIMIT_REMAINING: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_ratelimit_remaining",
+            "Current remaining ratelimit of instance.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Ratelimit reset
+    pub static ref MASTODON_RATELIMIT_RESET: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_ratelimit_reset",
+            "Number of seconds since 1970 of ratelimit reset for instance.",
+        ),
+        &["instance"],
+    ).unwrap();
+
+    // Ratelimit headers present
+    pub static ref MASTODON_RATELIMIT_HEADERS_PRESENT: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_ratelimit_headers_present",
+            "Whether or not the last response of instance contained ratelimit headers.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Scrape errors
+    pub static ref MASTODON_SCRAPE_ERRORS_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "mastodon_scrape_errors_total",
+            "Number of failed requests to instance by reason.",
+        ),
+        &["instance", "reason"],
+    )
+    .unwrap();
+
+    // Request duration
+    pub static ref MASTODON_REQUEST_DURATION_SECONDS: HistogramVec = HistogramVec::new(
+        HistogramOpts::new(
+            "mastodon_request_duration_seconds",
+            "Duration of requests to instance in seconds.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Responses too large
+    pub static ref MASTODON_RESPONSE_TOO_LARGE_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "mastodon_response_too_large_total",
+            "Number of responses of endpoint of instance which exceeded the maximum size and were discarded.",
+        ),
+        &["instance", "endpoint"],
+    )
+    .unwrap();
+
+    // Scrape timeouts
+    pub static ref MASTODON_SCRAPE_TIMEOUT_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "mastodon_scrape_timeout_total",
+            "Number of requests to instance which timed out.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Token valid
+    pub static ref MASTODON_TOKEN_VALID: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_token_valid",
+            "Whether or not all access tokens of instance are valid and grant the scopes they are used with.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Collector panics total
+    pub static ref MASTODON_COLLECTOR_PANICS_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "mastodon_collector_panics_total",
+            "Number of collection tasks of collector for instance which panicked.",
+        ),
+        &["collector", "instance"],
+    )
+    .unwrap();
+
+    // Target negative cached
+    pub static ref MASTODON_TARGET_NEGATIVE_CACHED: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_target_negative_cached",
+            "Whether or not requests to instance are skipped, as its domain can't be resolved or it refused connections repeatedly.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Target slow
+    pub static ref MASTODON_TARGET_SLOW: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_target_slow",
+            "Whether or not consecutive requests to instance took more than 80% of its timeout.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Scrape parse errors
+    pub static ref MASTODON_SCRAPE_PARSE_ERRORS_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "mastodon_scrape_parse_errors_total",
+            "Number of malformed values in responses of instance by field.",
+        ),
+        &["instance", "field"],
+    )
+    .unwrap();
+
+    // Target disabled
+    pub static ref MASTODON_TARGET_DISABLED: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_target_disabled",
+            "Whether or not target is disabled in the configuration, account_id is empty for instances.",
+        ),
+        &["instance", "account_id"],
+    )
+    .unwrap();
+
+    // Instance version info
+    pub static ref MASTODON_INSTANCE_VERSION_INFO: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_instance_version_info",
+            "Structured version information of instance.",
+        ),
+        &["instance", "version", "major", "minor", "patch", "flavour"],
+    )
+    .unwrap();
+
+    // Instance software info
+    pub static ref MASTODON_INSTANCE_SOFTWARE_INFO: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_instance_software_info",
+            "Server software of instance, detected from its version and nodeinfo.",
+        ),
+        &["instance", "software", "flavour"],
+    )
+    .unwrap();
+
+    // Instance version changes
+    pub static ref MASTODON_INSTANCE_VERSION_CHANGES_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "mastodon_instance_version_changes_total",
+            "Number of times the version of instance changed since the exporter started.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Instance branding changes
+    pub static ref MASTODON_INSTANCE_BRANDING_CHANGES_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "mastodon_instance_branding_changes_total",
+            "Number of times the title, description or thumbnail of instance changed since the exporter started.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Instance version change info
+    pub static ref MASTODON_INSTANCE_VERSION_CHANGE_INFO: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_instance_version_change_info",
+            "The old and new version of the last version change of instance.",
+        ),
+        &["instance", "old_version", "new_version"],
+    )
+    .unwrap();
+
+    // Instance seconds since version change
+    pub static ref MASTODON_INSTANCE_SECONDS_SINCE_VERSION_CHANGE: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_instance_seconds_since_version_change",
+            "Seconds since the version of instance changed, or since it was first seen by the exporter.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Instance version major
+    pub static ref MASTODON_INSTANCE_VERSION_MAJOR: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_instance_version_major",
+            "Major version of instance.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Instance version minor
+    pub static ref MASTODON_INSTANCE_VERSION_MINOR: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_instance_version_minor",
+            "Minor version of instance.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Instance version outdated
+    pub static ref MASTODON_INSTANCE_VERSION_OUTDATED: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_instance_version_outdated",
+            "Whether or not a newer upstream Mastodon release than the version of instance exists.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Instance version behind patches
+    pub static ref MASTODON_INSTANCE_VERSION_BEHIND_PATCHES: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_instance_version_behind_patches",
+            "Number of upstream patch releases of the same minor series newer than the version of instance.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Instance redirected
+    pub static ref MASTODON_INSTANCE_REDIRECTED: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_instance_redirected",
+            "Whether or not requests to instance are redirected to another domain at location.",
+        ),
+        &["instance", "location"],
+    )
+    .unwrap();
+
+    // Instance TLS certificate expiry
+    pub static ref MASTODON_INSTANCE_TLS_CERTIFICATE_EXPIRY_TIMESTAMP_SECONDS: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_instance_tls_certificate_expiry_timestamp_seconds",
+            "Time the TLS certificate of instance expires in seconds since 1970.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Local posting rate
+    pub static ref MASTODON_INSTANCE_LOCAL_POSTING_RATE: GaugeVec = GaugeVec::new(
+        Opts::new(
+            "mastodon_instance_local_posting_rate",
+            "Number of posts per minute on the local public timeline of instance.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Federated posting rate
+    pub static ref MASTODON_INSTANCE_FEDERATED_POSTING_RATE: GaugeVec = GaugeVec::new(
+        Opts::new(
+            "mastodon_instance_federated_posting_rate",
+            "Number of posts per minute on the federated public timeline of instance.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Search duration
+    pub static ref MASTODON_SEARCH_DURATION_SECONDS: GaugeVec = GaugeVec::new(
+        Opts::new(
+            "mastodon_search_duration_seconds",
+            "Duration of the search probe against instance in seconds.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Search success
+    pub static ref MASTODON_SEARCH_SUCCESS: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_search_success",
+            "Whether or not the search probe against instance succeeded.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Search results
+    pub static ref MASTODON_SEARCH_RESULTS: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_search_results",
+            "Number of results of the search probe against instance by type.",
+        ),
+        &["instance", "type"],
+    )
+    .unwrap();
+
+    // Media fetch duration
+    pub static ref MASTODON_MEDIA_FETCH_DURATION_SECONDS: GaugeVec = GaugeVec::new(
+        Opts::new(
+            "mastodon_media_fetch_duration_seconds",
+            "Duration of fetching the probed media file of instance in seconds.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Media fetch success
+    pub static ref MASTODON_MEDIA_FETCH_SUCCESS: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_media_fetch_success",
+            "Whether or not fetching the probed media file of instance succeeded.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Instance health up
+    pub static ref MASTODON_INSTANCE_HEALTH_UP: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_instance_health_up",
+            "Whether or not the health endpoint of instance responded successfully.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Instance health duration
+    pub static ref MASTODON_INSTANCE_HEALTH_DURATION_SECONDS: GaugeVec = GaugeVec::new(
+        Opts::new(
+            "mastodon_instance_health_duration_seconds",
+            "Duration of the last request to the health endpoint of instance.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Well-known endpoint success
+    pub static ref MASTODON_WELL_KNOWN_SUCCESS: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_well_known_success",
+            "Whether or not the well-known endpoint of instance responded successfully.",
+        ),
+        &["instance", "endpoint"],
+    )
+    .unwrap();
+
+    // Registration enabled
+    pub static ref MASTODON_REGISTRATIONS_ENABLED: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_registrations_enabled",
+            "Whether or not registrations are enabled on instance.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Registration message
+    pub static ref MASTODON_REGISTRATIONS_MESSAGE_SET: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_registrations_message_set",
+            "Whether or not a message for closed registrations is set on instance.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Translation enabled
+    pub static ref MASTODON_INSTANCE_TRANSLATION_ENABLED: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_instance_translation_enabled",
+            "Whether or not the translation API is available on instance.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Streaming URL
+    pub static ref MASTODON_INSTANCE_STREAMING_AVAILABLE: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_instance_streaming_available",
+            "Whether or not instance announces the URL of its streaming API.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Registration approval required
+    pub static ref MASTODON_REGISTRATIONS_APPROVAL_REQUIRED: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_registrations_approval_required",
+            "Whether or not approval is required on instance.",
+        ),
+        &["instance"],
+    )
+    .unwrap();
+
+    // Account followers count
+    pub static ref MASTODON_ACCOUNT_FOLLOWERS_COUNT: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_account_followers_count",
+            "Number of followers for account.",
+        ),
+        &["instance", "account_id", "username"],
+    ).unwrap();
+
+    // Account following count
+    pub static ref MASTODON_ACCOUNT_FOLLOWING_COUNT: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_account_following_count",
+            "Number of accounts followed by account.",
+        ),
+        &["instance", "account_id", "username"],
+    ).unwrap();
+
+    // Account statuses count
+    pub static ref MASTODON_ACCOUNT_STATUSES_COUNT: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_account_statuses_count",
+            "Number of statuses for account.",
+        ),
+        &["instance", "account_id", "username"],
+    ).unwrap();
+
+    // Account last status at
+    pub static ref MASTODON_ACCOUNT_LAST_STATUS_AT: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_account_last_status_at",
+            "Number of seconds since 1970 of last status for account.",
+        ),
+        &["instance", "account_id", "username"],
+    ).unwrap();
+
+    // Account verified fields
+    pub static ref MASTODON_ACCOUNT_VERIFIED_FIELDS: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_account_verified_fields",
+            "Number of verified profile fields for account.",
+        ),
+        &["instance", "account_id", "username"],
+    ).unwrap();
+
+    // Account info
+    pub static ref MASTODON_ACCOUNT_INFO: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_account_info",
+            "Profile of account, labeled with display name and avatar if enabled.",
+        ),
+        &["instance", "account_id", "display_name", "avatar"],
+    )
+    .unwrap();
+
+    // Account followed tags
+    pub static ref MASTODON_ACCOUNT_FOLLOWED_TAGS: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_account_followed_tags",
+            "Number of hashtags followed by account, requires an access token of the account.",
+        ),
+        &["instance", "account_id", "username"],
+    )
+    .unwrap();
+
+    // Account mutes count
+    pub static ref MASTODON_ACCOUNT_MUTES_COUNT: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_account_mutes_count",
+            "Number of accounts muted by account, requires an access token of the account.",
+        ),
+        &["instance", "account_id", "username"],
+    )
+    .unwrap();
+
+    // Account bookmarks count
+    pub static ref MASTODON_ACCOUNT_BOOKMARKS_COUNT: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_account_bookmarks_count",
+            "Number of statuses bookmarked by account, requires an access token of the account and account_bookmarks_favourites.",
+        ),
+        &["instance", "account_id", "username"],
+    )
+    .unwrap();
+
+    // Account favourites count
+    pub static ref MASTODON_ACCOUNT_FAVOURITES_COUNT: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_account_favourites_count",
+            "Number of statuses favourited by account, requires an access token of the account and account_bookmarks_favourites.",
+        ),
+        &["instance", "account_id", "username"],
+    )
+    .unwrap();
+
+    // Account count truncated
+    pub static ref MASTODON_ACCOUNT_COUNT_TRUNCATED: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_account_count_truncated",
+            "Whether or not count of account stopped at the page limit and is a lower bound, count is mutes, blocks, bookmarks or favourites.",
+        ),
+        &["instance", "account_id", "username", "count"],
+    )
+    .unwrap();
+
+    // Account unread conversations
+    pub static ref MASTODON_ACCOUNT_UNREAD_CONVERSATIONS: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_account_unread_conversations",
+            "Number of unread conversations with direct statuses of account, requires an access token of the account.",
+        ),
+        &["instance", "account_id", "username"],
+    )
+    .unwrap();
+
+    // Account default visibility
+    pub static ref MASTODON_ACCOUNT_DEFAULT_VISIBILITY: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_account_default_visibility",
+            "Whether or not visibility (public, unlisted, private or direct) is the default for new statuses of account, requires an access token of the account.",
+        ),
+        &["instance", "account_id", "username", "visibility"],
+    )
+    .unwrap();
+
+    // Account default sensitive
+    pub static ref MASTODON_ACCOUNT_DEFAULT_SENSITIVE: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_account_default_sensitive",
+            "Whether or not new statuses of account are marked sensitive by default, requires an access token of the account.",
+        ),
+        &["instance", "account_id", "username"],
+    )
+    .unwrap();
+
+    // Account follow requests count
+    pub static ref MASTODON_ACCOUNT_FOLLOW_REQUESTS_COUNT: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_account_follow_requests_count",
+            "Number of pending follow requests of account, requires an access token of the account.",
+        ),
+        &["instance", "account_id", "username"],
+    )
+    .unwrap();
+
+    // Account blocks count
+    pub static ref MASTODON_ACCOUNT_BLOCKS_COUNT: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_account_blocks_count",
+            "Number of accounts blocked by account, requires an access token of the account.",
+        ),
+        &["instance", "account_id", "username"],
+    )
+    .unwrap();
+
+    // Account featured tags count
+    pub static ref MASTODON_ACCOUNT_FEATURED_TAGS_COUNT: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_account_featured_tags_count",
+            "Number of hashtags featured on profile of account.",
+        ),
+        &["instance", "account_id", "username"],
+    )
+    .unwrap();
+
+    // Account featured tag statuses count
+    pub static ref MASTODON_ACCOUNT_FEATURED_TAG_STATUSES_COUNT: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_account_featured_tag_statuses_count",
+            "Number of statuses of account with hashtag featured on its profile.",
+        ),
+        &["instance", "account_id", "tag"],
+    )
+    .unwrap();
+
+    // Federation resolvable
+    pub static ref MASTODON_FEDERATION_RESOLVABLE: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_federation_resolvable",
+            "Whether or not remote account can be resolved from instance.",
+        ),
+        &["instance", "acct"],
+    )
+    .unwrap();
+
+    // Account follows
+    pub static ref MASTODON_ACCOUNT_FOLLOWS: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_account_follows",
+            "Whether or not source account follows target account, requires an access token of the source account.",
+        ),
+        &["instance", "source", "target"],
+    )
+    .unwrap();
+
+    // Account recent average favourites
+    pub static ref MASTODON_ACCOUNT_RECENT_AVG_FAVOURITES: GaugeVec = GaugeVec::new(
+        Opts::new(
+            "mastodon_account_recent_avg_favourites",
+            "Average number of favourites of recent statuses of account.",
+        ),
+        &["instance", "account_id", "username"],
+    )
+    .unwrap();
+
+    // Account recent average reblogs
+    pub static ref MASTODON_ACCOUNT_RECENT_AVG_REBLOGS: GaugeVec = GaugeVec::new(
+        Opts::new(
+            "mastodon_account_recent_avg_reblogs",
+            "Average number of boosts of recent statuses of account.",
+        ),
+        &["instance", "account_id", "username"],
+    )
+    .unwrap();
+
+    // Account recent average replies
+    pub static ref MASTODON_ACCOUNT_RECENT_AVG_REPLIES: GaugeVec = GaugeVec::new(
+        Opts::new(
+            "mastodon_account_recent_avg_replies",
+            "Average number of replies to recent statuses of account.",
+        ),
+        &["instance", "account_id", "username"],
+    )
+    .unwrap();
+
+    // Account posting rate per day
+    pub static ref MASTODON_ACCOUNT_POSTING_RATE_PER_DAY: GaugeVec = GaugeVec::new(
+        Opts::new(
+            "mastodon_account_posting_rate_per_day",
+            "Number of statuses per day posted by account over the last week.",
+        ),
+        &["instance", "account_id", "username"],
+    )
+    .unwrap();
+
+    // Account state
+    pub static ref MASTODON_ACCOUNT_STATE: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_account_state",
+            "Whether or not account is in state (active, moved, suspended or limited).",
+        ),
+        &["instance", "account_id", "state"],
+    ).unwrap();
+
+    // Account found
+    pub static ref MASTODON_ACCOUNT_FOUND: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_account_found",
+            "Whether or not account exists on instance, 0 if it is not found or gone.",
+        ),
+        &["instance", "account_id"],
+    )
+    .unwrap();
+
+    // Poll votes count
+    pub static ref MASTODON_POLL_VOTES_COUNT: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_poll_votes_count",
+            "Number of votes for poll option.",
+        ),
+        &["instance", "poll_id", "option"],
+    ).unwrap();
+
+    // Poll expires at
+    pub static ref MASTODON_POLL_EXPIRES_AT_SECONDS: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_poll_expires_at_seconds",
+            "Number of seconds since 1970 of poll expiration.",
+        ),
+        &["instance", "poll_id"],
+    ).unwrap();
+
+    // Status reblogs per hour
+    pub static ref MASTODON_STATUS_REBLOGS_PER_HOUR: GaugeVec = GaugeVec::new(
+        Opts::new(
+            "mastodon_status_reblogs_per_hour",
+            "Rate of reblogs per hour for status over the tracker retention window.",
+        ),
+        &["instance", "status_id"],
+    ).unwrap();
+
+    // Status favourites per hour
+    pub static ref MASTODON_STATUS_FAVOURITES_PER_HOUR: GaugeVec = GaugeVec::new(
+        Opts::new(
+            "mastodon_status_favourites_per_hour",
+            "Rate of favourites per hour for status over the tracker retention window.",
+        ),
+        &["instance", "status_id"],
+    ).unwrap();
+
+    // Status replies per hour
+    pub static ref MASTODON_STATUS_REPLIES_PER_HOUR: GaugeVec = GaugeVec::new(
+        Opts::new(
+            "mastodon_status_replies_per_hour",
+            "Rate of replies per hour for status over the tracker retention window.",
+        ),
+        &["instance", "status_id"],
+    ).unwrap();
+
+    // Canary post duration
+    pub static ref MASTODON_CANARY_POST_DURATION_SECONDS: GaugeVec = GaugeVec::new(
+        Opts::new(
+            "mastodon_canary_post_duration_seconds",
+            "Duration of posting the last canary status on instance in seconds.",
+        ),
+        &["instance"],
+    ).unwrap();
+
+    // Canary post success
+    pub static ref MASTODON_CANARY_POST_SUCCESS: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_canary_post_success",
+            "Whether or not posting the last canary status on instance succeeded.",
+        ),
+        &["instance"],
+    ).unwrap();
+
+    // Federation delivery duration
+    pub static ref MASTODON_FEDERATION_DELIVERY_SECONDS: GaugeVec = GaugeVec::new(
+        Opts::new(
+            "mastodon_federation_delivery_seconds",
+            "Duration until the last canary status posted on instance from was delivered to instance to in seconds.",
+        ),
+        &["from", "to"],
+    ).unwrap();
+
+    // Federation delivery success
+    pub static ref MASTODON_FEDERATION_DELIVERY_SUCCESS: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_federation_delivery_success",
+            "Whether or not the last canary status posted on instance from was delivered to instance to in time.",
+        ),
+        &["from", "to"],
+    ).unwrap();
+
+    // Instance peers count
+    pub static ref MASTODON_INSTANCE_PEERS_COUNT: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_instance_peers_count",
+            "Number of instances instance is aware of.",
+        ),
+        &["instance"],
+    ).unwrap();
+
+    // Federation shared peers
+    pub static ref MASTODON_FEDERATION_SHARED_PEERS: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_federation_shared_peers",
+            "Number of instances both instance_a and instance_b are aware of.",
+        ),
+        &["instance_a", "instance_b"],
+    ).unwrap();
+
+    // Admin tag accounts
+    pub static ref MASTODON_ADMIN_TAG_ACCOUNTS: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_admin_tag_accounts",
+            "Number of accounts which used hashtag tag on instance in the configured days.",
+        ),
+        &["instance", "tag"],
+    ).unwrap();
+
+    // Admin tag uses
+    pub static ref MASTODON_ADMIN_TAG_USES: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_admin_tag_uses",
+            "Number of statuses which used hashtag tag on instance in the configured days.",
+        ),
+        &["instance", "tag"],
+    ).unwrap();
+
+    // Admin tag servers
+    pub static ref MASTODON_ADMIN_TAG_SERVERS: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_admin_tag_servers",
+            "Number of remote servers whose statuses used hashtag tag on instance in the configured days.",
+        ),
+        &["instance", "tag"],
+    ).unwrap();
+
+    // Streaming events total
+    pub static ref MASTODON_STREAMING_EVENTS_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "mastodon_streaming_events_total",
+            "Number of events received from the streaming API of instance.",
+        ),
+        &["instance", "event"],
+    ).unwrap();
+
+    // Streaming connected
+    pub static ref MASTODON_STREAMING_CONNECTED: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "mastodon_streaming_connected",
+            "Whether or not the streaming API connection to instance is established.",
+        ),
+        &["instance"],
+    ).unwrap();
+}
+
+/// Registers all metrics in the registry.
+///
+/// Calling this more than once has no effect.
+pub fn register() {
+    static REGISTER: Once = Once::new();
+
+    REGISTER.call_once(|| {
+        REGISTRY
+            .register(Box::new(MASTODON_SERIES_REJECTED.clone()))
+            .unwrap();
+        register_limited(&MASTODON_RATELIMIT_LIMIT);
+        register_limited(&MASTODON_RATELIMIT_REMAINING);
+        register_limited(&MASTODON_RATELIMIT_RESET);
+        register_limited(&MASTODON_RATELIMIT_HEADERS_PRESENT);
+        register_limited(&MASTODON_SCRAPE_ERRORS_TOTAL);
+        register_limited(&MASTODON_REQUEST_DURATION_SECONDS);
+        register_limited(&MASTODON_SCRAPE_PARSE_ERRORS_TOTAL);
+        register_limited(&MASTODON_SCRAPE_TIMEOUT_TOTAL);
+        register_limited(&MASTODON_RESPONSE_TOO_LARGE_TOTAL);
+        register_limited(&MASTODON_TARGET_SLOW);
+        register_limited(&MASTODON_TARGET_NEGATIVE_CACHED);
+        register_limited(&MASTODON_COLLECTOR_PANICS_TOTAL);
+        register_limited(&MASTODON_TOKEN_VALID);
+        register_limited(&MASTODON_TARGET_DISABLED);
+        register_limited(&MASTODON_INSTANCE_VERSION_INFO);
+        register_limited(&MASTODON_INSTANCE_SOFTWARE_INFO);
+        register_limited(&MASTODON_INSTANCE_VERSION_CHANGES_TOTAL);
+        register_limited(&MASTODON_INSTANCE_VERSION_CHANGE_INFO);
+        register_limited(&MASTODON_INSTANCE_BRANDING_CHANGES_TOTAL);
+        register_limited(&MASTODON_INSTANCE_SECONDS_SINCE_VERSION_CHANGE);
+        register_limited(&MASTODON_INSTANCE_VERSION_MAJOR);
+        register_limited(&MASTODON_INSTANCE_VERSION_MINOR);
+        register_limited(&MASTODON_INSTANCE_VERSION_OUTDATED);
+        register_limited(&MASTODON_INSTANCE_VERSION_BEHIND_PATCHES);
+        register_limited(&MASTODON_INSTANCE_REDIRECTED);
+        register_limited(&MASTODON_INSTANCE_TLS_CERTIFICATE_EXPIRY_TIMESTAMP_SECONDS);
+        register_limited(&MASTODON_INSTANCE_LOCAL_POSTING_RATE);
+        register_limited(&MASTODON_INSTANCE_FEDERATED_POSTING_RATE);
+        register_limited(&MASTODON_SEARCH_DURATION_SECONDS);
+        register_limited(&MASTODON_SEARCH_SUCCESS);
+        register_limited(&MASTODON_SEARCH_RESULTS);
+        register_limited(&MASTODON_MEDIA_FETCH_DURATION_SECONDS);
+        register_limited(&MASTODON_MEDIA_FETCH_SUCCESS);
+        register_limited(&MASTODON_INSTANCE_HEALTH_UP);
+        register_limited(&MASTODON_INSTANCE_HEALTH_DURATION_SECONDS);
+        register_limited(&MASTODON_WELL_KNOWN_SUCCESS);
+        register_limited(&MASTODON_REGISTRATIONS_ENABLED);
+        register_limited(&MASTODON_REGISTRATIONS_APPROVAL_REQUIRED);
+        register_limited(&MASTODON_REGISTRATIONS_MESSAGE_SET);
+        register_limited(&MASTODON_INSTANCE_TRANSLATION_ENABLED);
+        register_limited(&MASTODON_INSTANCE_STREAMING_AVAILABLE);
+        register_limited(&MASTODON_ACCOUNT_FOLLOWERS_COUNT);
+        register_limited(&MASTODON_ACCOUNT_FOLLOWING_COUNT);
+        register_limited(&MASTODON_ACCOUNT_STATUSES_COUNT);
+        register_limited(&MASTODON_ACCOUNT_LAST_STATUS_AT);
+        register_limited(&MASTODON_ACCOUNT_VERIFIED_FIELDS);
+        register_limited(&MASTODON_ACCOUNT_FOLLOWED_TAGS);
+        register_limited(&MASTODON_ACCOUNT_MUTES_COUNT);
+        register_limited(&MASTODON_ACCOUNT_BLOCKS_COUNT);
+        register_limited(&MASTODON_ACCOUNT_BOOKMARKS_COUNT);
+        register_limited(&MASTODON_ACCOUNT_FAVOURITES_COUNT);
+        register_limited(&MASTODON_ACCOUNT_COUNT_TRUNCATED);
+        register_limited(&MASTODON_ACCOUNT_UNREAD_CONVERSATIONS);
+        register_limited(&MASTODON_ACCOUNT_DEFAULT_VISIBILITY);
+        register_limited(&MASTODON_ACCOUNT_DEFAULT_SENSITIVE);
+        register_limited(&MASTODON_ACCOUNT_FOLLOW_REQUESTS_COUNT);
+        register_limited(&MASTODON_ACCOUNT_FEATURED_TAGS_COUNT);
+        register_limited(&MASTODON_ACCOUNT_FEATURED_TAG_STATUSES_COUNT);
+        register_limited(&MASTODON_ACCOUNT_FOLLOWS);
+        register_limited(&MASTODON_FEDERATION_RESOLVABLE);
+        register_limited(&MASTODON_ACCOUNT_RECENT_AVG_FAVOURITES);
+        register_limited(&MASTODON_ACCOUNT_RECENT_AVG_REBLOGS);
+        register_limited(&MASTODON_ACCOUNT_RECENT_AVG_REPLIES);
+        register_limited(&MASTODON_ACCOUNT_POSTING_RATE_PER_DAY);
+        register_limited(&MASTODON_ACCOUNT_INFO);
+        register_limited(&MASTODON_ACCOUNT_STATE);
+        register_limited(&MASTODON_ACCOUNT_FOUND);
+        register_limited(&MASTODON_POLL_VOTES_COUNT);
+        register_limited(&MASTODON_POLL_EXPIRES_AT_SECONDS);
+        register_limited(&MASTODON_STATUS_REBLOGS_PER_HOUR);
+        register_limited(&MASTODON_STATUS_FAVOURITES_PER_HOUR);
+        register_limited(&MASTODON_STATUS_REPLIES_PER_HOUR);
+        register_limited(&MASTODON_CANARY_POST_DURATION_SECONDS);
+        register_limited(&MASTODON_CANARY_POST_SUCCESS);
+        register_limited(&MASTODON_FEDERATION_DELIVERY_SECONDS);
+        register_limited(&MASTODON_FEDERATION_DELIVERY_SUCCESS);
+        register_limited(&MASTODON_INSTANCE_PEERS_COUNT);
+        register_limited(&MASTODON_FEDERATION_SHARED_PEERS);
+        register_limited(&MASTODON_ADMIN_TAG_ACCOUNTS);
+        register_limited(&MASTODON_ADMIN_TAG_USES);
+        register_limited(&MASTODON_ADMIN_TAG_SERVERS);
+        register_limited(&MASTODON_STREAMING_EVENTS_TOTAL);
+        register_limited(&MASTODON_STREAMING_CONNECTED);
+    });
+}
+
+/// The maximum number of series per metric, unlimited if not set.
+static SERIES_LIMIT: RwLock<Option<usize>> = RwLock::new(None);
+
+/// Limits the number of series per metric, or lifts the limit if not set.
+pub fn set_series_limit(limit: Option<usize>) {
+    *SERIES_LIMIT.write().unwrap() = limit;
+}
+
+/// A metric whose number of series can be limited.
+trait LimitSeries: Send {
+    /// Returns the name of the metric.
+    fn name(&self) -> &str;
+
+    /// Returns whether the series with the label values exists or may be created within the
+    /// limit, and counts it as rejected otherwise.
+    fn admit_series(&mut self, label_values: &[&str], limit: usize) -> bool;
+
+    /// Removes new series beyond the limit and returns the number of rejected series since the
+    /// previous enforcement, including the series refused by [`admit_series`].
+    ///
+    /// [`admit_series`]: LimitSeries::admit_series
+    fn limit_series(&mut self, limit: usize) -> (String, i64);
+
+    /// Removes the series whose labels match.
+    fn remove_series(&mut self, matches: &dyn Fn(&[LabelPair]) -> bool);
+
+    /// Returns the name of the metric and its description, see [`describe`].
+    fn describe(&self) -> (String, String);
+}
+
+/// A metric vector, the series admitted since the previous enforcement of the limit and the
+/// number of series refused since.
+struct SeriesLimit<P: MetricVecBuilder> {
+    name: String,
+    metric: MetricVec<P>,
+    series: HashSet<Vec<String>>,
+    refused: i64,
+}
+
+impl<P: MetricVecBuilder> LimitSeries for SeriesLimit<P> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn admit_series(&mut self, label_values: &[&str], limit: usize) -> bool {
+        let values: Vec<String> = label_values.iter().map(|value| value.to_string()).collect();
+        if self.series.contains(&values) {
+            return true;
+        }
+        if self.series.len() < limit {
+            self.series.insert(values);
+            return true;
+        }
+
+        self.refused += 1;
+        false
+    }
+
+    fn limit_series(&mut self, limit: usize) -> (String, i64) {
+        let refused = std::mem::take(&mut self.refused);
+        let families = self.metric.collect();
+        let Some(family) = families.first() else {
+            return (self.name.clone(), refused);
+        };
+
+        let label_values = |metric: &prometheus::proto::Metric| -> Vec<String> {
+            metric
+                .get_label()
+                .iter()
+                .map(|label| label.get_value().to_string())
+                .collect()
+        };
+
+        // Previous series first, so they aren't replaced by new series
+        let mut series: HashSet<Vec<String>> = family
+            .get_metric()
+            .iter()
+            .map(label_values)
+            .filter(|values| self.series.contains(values))
+            .collect();
+        let mut rejected = refused;
+        for metric in family.get_metric() {
+            let values = label_values(metric);
+            if series.contains(&values) {
+                continue;
+            }
+            if series.len() < limit {
+                series.insert(values);
+                continue;
+            }
+
+            let labels: HashMap<&str, &str> = metric
+                .get_label()
+                .iter()
+                .map(|label| (label.get_name(), label.get_value()))
+                .collect();
+            self.metric.remove(&labels).ok();
+            rejected += 1;
+        }
+        self.series = series;
+
+        (family.get_name().to_string(), rejected)
+    }
+
+    fn remove_series(&mut self, matches: &dyn Fn(&[LabelPair]) -> bool) {
+        for family in self.metric.collect() {
+            for metric in family.get_metric() {
+                if !matches(metric.get_label()) {
+                    continue;
+                }
+
+                let labels: HashMap<&str, &str> = metric
+                    .get_label()
+                    .iter()
+                    .map(|label| (label.get_name(), label.get_value()))
+                    .collect();
+                self.metric.remove(&labels).ok();
+                self.series.remove(
+                    &metric
+                        .get_label()
+                        .iter()
+                        .map(|label| label.get_value().to_string())
+                        .collect::<Vec<_>>(),
+                );
+            }
+        }
+    }
+
+    fn describe(&self) -> (String, String) {
+        describe_collector(&self.metric)
+    }
+}
+
+/// Returns the name of the metric of the collector and its help, type and label names.
+fn describe_collector(collector: &impl Collector) -> (String, String) {
+    // Metric vectors return their family even without series
+    let families = collector.collect();
+    let family = &families[0];
+    let kind = match family.get_field_type() {
+        MetricType::COUNTER => "counter",
+        MetricType::GAUGE => "gauge",
+        MetricType::HISTOGRAM => "histogram",
+        MetricType::SUMMARY => "summary",
+        MetricType::UNTYPED => "untyped",
+    };
+    let label_names = collector
+        .desc()
+        .iter()
+        .flat_map(|desc| desc.variable_labels.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let name = family.get_name();
+    let description = format!(
+        "# HELP {0} {1}\n# TYPE {0} {2}\n{0}{{{3}}}\n",
+        name,
+        family.get_help(),
+        kind,
+        label_names
+    );
+
+    (name.to_string(), description)
+}
+
+/// Describes every registered metric with its help, type and label names, sorted by name.
+///
+/// Unlike the exposition, metrics without any series are described as well, so the names, help
+/// and labels of all metrics can be compared between releases.
+pub fn describe() -> String {
+    let mut descriptions: Vec<(String, String)> = LIMITED_METRICS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|metric| metric.describe())
+        .collect();
+    descriptions.push(describe_collector(&*MASTODON_SERIES_REJECTED));
+    descriptions.sort();
+
+    descriptions
+        .into_iter()
+        .map(|(_, description)| description)
+        .collect()
+}
+
+lazy_static! {
+    /// The metrics whose number of series is limited.
+    static ref LIMITED_METRICS: Mutex<Vec<Box<dyn LimitSeries>>> = Mutex::new(Vec::new());
+}
+
+/// Registers a metric vector whose number of series is limited to the configured limit.
+pub(crate) fn register_limited<P: MetricVecBuilder + 'static>(metric: &MetricVec<P>) {
+    REGISTRY.register(Box::new(metric.clone())).unwrap();
+    LIMITED_METRICS.lock().unwrap().push(Box::new(SeriesLimit {
+        name: metric.desc()[0].fq_name.clone(),
+        metric: metric.clone(),
+        series: HashSet::new(),
+        refused: 0,
+    }));
+}
+
+/// Creates the series of a metric vector within the series limit.
+pub trait WithLimitedLabelValues<M> {
+    /// Returns the series with the label values, like `with_label_values`, or `None` without
+    /// creating it if the metric already has as many series as the limit allows.
+    ///
+    /// Refused series are counted by the series rejected metric at the next enforcement of the
+    /// limit.
+    fn with_limited_label_values(&self, label_values: &[&str]) -> Option<M>;
+}
+
+impl<P: MetricVecBuilder> WithLimitedLabelValues<P::M> for MetricVec<P> {
+    fn with_limited_label_values(&self, label_values: &[&str]) -> Option<P::M> {
+        if let Some(limit) = *SERIES_LIMIT.read().unwrap() {
+            let name = &self.desc()[0].fq_name;
+            let mut limited = LIMITED_METRICS.lock().unwrap();
+            let admitted = limited
+                .iter_mut()
+                .find(|metric| metric.name() == name)
+                .is_none_or(|metric| metric.admit_series(label_values, limit));
+            if !admitted {
+                return None;
+            }
+        }
+
+        Some(self.with_label_values(label_values))
+    }
+}
+
+/// Unregisters a metric vector registered with [`register_limited`], e.g. to replace it with
+/// other label names.
+fn unregister_limited<P: MetricVecBuilder + 'static>(metric: &MetricVec<P>) {
+    REGISTRY.unregister(Box::new(metric.clone())).ok();
+    let name = &metric.desc()[0].fq_name;
+    LIMITED_METRICS
+        .lock()
+        .unwrap()
+        .retain(|limited| limited.name() != name);
+}
+
+/// Returns whether the labels of a series contain the label with the value.
+pub fn has_label(labels: &[LabelPair], name: &str, value: &str) -> bool {
+    labels
+        .iter()
+        .any(|label| label.get_name() == name && label.get_value() == value)
+}
+
+/// Removes the series of all metrics whose labels match, e.g. of targets which were removed from
+/// the configuration.
+pub fn remove_series(matches: impl Fn(&[LabelPair]) -> bool) {
+    for metric in LIMITED_METRICS.lock().unwrap().iter_mut() {
+        metric.remove_series(&matches);
+    }
+
+    native::prune();
+    exemplars::prune();
+}
+
+/// Removes the new series of every metric beyond the configured limit.
+///
+/// Series which already existed at the previous enforcement are kept, the number of removed and
+/// refused series is exported by the series rejected metric while series are rejected. The native buckets
+/// and exemplars of removed histogram series are dropped as well.
+pub fn enforce_series_limit() {
+    if let Some(limit) = *SERIES_LIMIT.read().unwrap() {
+        limit_series(limit);
+    }
+
+    native::prune();
+    exemplars::prune();
+}
+
+/// Returns the name and label pairs of every series of the metric families.
+fn series_keys(families: &[MetricFamily]) -> HashSet<(String, Vec<(String, String)>)> {
+    families
+        .iter()
+        .flat_map(|family| {
+            family.get_metric().iter().map(|metric| {
+                let labels = metric
+                    .get_label()
+                    .iter()
+                    .map(|label| (label.get_name().to_string(), label.get_value().to_string()))
+                    .collect();
+                (family.get_name().to_string(), labels)
+            })
+        })
+        .collect()
+}
+
+fn limit_series(limit: usize) {
+    for metric in LIMITED_METRICS.lock().unwrap().iter_mut() {
+        let (name, rejected) = metric.limit_series(limit);
+        if rejected == 0 {
+            MASTODON_SERIES_REJECTED.remove_label_values(&[&name]).ok();
+            continue;
+        }
+
+        let rejected_gauge = MASTODON_SERIES_REJECTED.with_label_values(&[&name]);
+        if rejected_gauge.get() == 0 {
+            log!(
+                "Warning: Dropping new series of {} exceeding the limit of {} series",
+                name,
+                limit
+            );
+        }
+        rejected_gauge.set(rejected);
+    }
+}
+
+/// Registers a metric with label names from the configuration in the slot, or unregisters it
+/// without label names.
+///
+/// A metric registered with other label names, e.g. before a reload, is replaced along with its
+/// series, while one with the same label names is kept.
+fn register_configured(
+    slot: &RwLock<Option<IntGaugeVec>>,
+    opts: Opts,
+    label_names: Option<&[&str]>,
+) {
+    let mut slot = slot.write().unwrap();
+    if let Some(metric) = slot.as_ref() {
+        if label_names.is_some_and(|label_names| metric.desc()[0].variable_labels == label_names) {
+            return;
+        }
+        unregister_limited(metric);
+        *slot = None;
+    }
+
+    if let Some(label_names) = label_names {
+        let metric = IntGaugeVec::new(opts, label_names).unwrap();
+        register_limited(&metric);
+        *slot = Some(metric);
+    }
+}
+
+/// General instance information.
+///
+/// The label names depend on the configuration, so the metric is created at runtime.
+static MASTODON_INFO: RwLock<Option<IntGaugeVec>> = RwLock::new(None);
+
+/// Registers the instance info metric with the given label names besides `instance`.
+pub fn register_info(label_names: &[&str]) {
+    let label_names: Vec<&str> = ["instance"]
+        .into_iter()
+        .chain(label_names.iter().copied())
+        .collect();
+
+    register_configured(
+        &MASTODON_INFO,
+        Opts::new("mastodon_info", "General instance information."),
+        Some(&label_names),
+    );
+}
+
+/// Returns the instance info metric if it has been registered.
+pub fn info() -> Option<IntGaugeVec> {
+    MASTODON_INFO.read().unwrap().clone()
+}
+
+/// The additional labels of the configured accounts.
+///
+/// The label names depend on the configuration, so the metric is created at runtime.
+static MASTODON_ACCOUNT_LABELS: RwLock<Option<IntGaugeVec>> = RwLock::new(None);
+
+/// Registers the account labels metric with the given label names besides `instance` and
+/// `account_id`, or unregisters it without any label names.
+pub fn register_account_labels(label_names: &[&str]) {
+    let label_names: Vec<&str> = ["instance", "account_id"]
+        .into_iter()
+        .chain(label_names.iter().copied())
+        .collect();
+
+    register_configured(
+        &MASTODON_ACCOUNT_LABELS,
+        Opts::new(
+            "mastodon_account_labels",
+            "Additional labels of account from the configuration.",
+        ),
+        (label_names.len() > 2).then_some(&label_names[..]),
+    );
+}
+
+/// Returns the account labels metric if it has been registered.
+pub fn account_labels() -> Option<IntGaugeVec> {
+    MASTODON_ACCOUNT_LABELS.read().unwrap().clone()
+}
+
+/// Records the duration of a request to an instance in the request duration histogram, including
+/// its native buckets, and the trace of the span of the request as the exemplar of its bucket.
+pub fn observe_request_duration(instance: &str, seconds: f64, span: &tracing::Span) {
+    let Some(histogram) = MASTODON_REQUEST_DURATION_SECONDS.with_limited_label_values(&[instance])
+    else {
+        return;
+    };
+    histogram.observe(seconds);
+    native::observe(
+        "mastodon_request_duration_seconds",
+        &[("instance", instance)],
+        seconds,
+    );
+    // The histogram has the default buckets
+    exemplars::observe(
+        "mastodon_request_duration_seconds",
+        &[("instance", instance)],
+        prometheus::DEFAULT_BUCKETS,
+        seconds,
+        span,
+    );
+}
+
+/// An exposition format of the metrics.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// The Prometheus text format.
+    Text,
+
+    /// The OpenMetrics text format, which adds exemplars to the buckets of histograms.
+    OpenMetrics,
+
+    /// The Prometheus protobuf format, which adds native buckets and exemplars to the histograms
+    /// with them.
+    Protobuf,
+}
+
+impl Format {
+    /// Returns the content type of the format.
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Text => prometheus::TEXT_FORMAT,
+            Format::OpenMetrics => openmetrics::FORMAT,
+            Format::Protobuf => prometheus::PROTOBUF_FORMAT,
+        }
+    }
+}
+
+/// Encodes all registered metrics in the Prometheus text format.
+pub fn encode() -> String {
+    String::from_utf8(encode_families(&REGISTRY.gather(), Format::Text)).unwrap()
+}
+
+/// Encodes the metrics of the registry in the given format.
+pub fn encode_as(registry: &Registry, format: Format) -> Vec<u8> {
+    encode_families(&registry.gather(), format)
+}
+
+fn encode_families(families: &[MetricFamily], format: Format) -> Vec<u8> {
+    let mut buffer = vec![];
+    match format {
+        Format::Text => TextEncoder::new().encode(families, &mut buffer).unwrap(),
+        Format::OpenMetrics => buffer = openmetrics::encode(families).into_bytes(),
+        Format::Protobuf => {
+            let mut families = families.to_vec();
+            native::add_buckets(&mut families);
+            exemplars::add_to_buckets(&mut families);
+            ProtobufEncoder::new()
+                .encode(&families, &mut buffer)
+                .unwrap()
+        }
+    }
+    buffer
+}
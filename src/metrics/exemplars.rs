@@ -0,0 +1,191 @@
+//! Exemplars, which link the buckets of histograms to the traces of observations in them.
+//!
+//! The `prometheus` crate doesn't support exemplars, so the last exemplar of each bucket is
+//! tracked here and added when the histograms are encoded in the OpenMetrics or protobuf format.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use opentelemetry::trace::TraceContextExt;
+use prometheus::proto::MetricFamily;
+use prost::Message;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// The name and label pairs of a series.
+type SeriesKey = (String, Vec<(String, String)>);
+
+lazy_static! {
+    /// The last exemplar of each bucket by series, keyed by the upper bound of the bucket.
+    static ref EXEMPLARS: Mutex<HashMap<SeriesKey, HashMap<u64, Exemplar>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// An observation in a bucket with the trace it was observed in.
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct Exemplar {
+    pub trace_id: String,
+    pub value: f64,
+    /// The time of the observation in seconds since 1970.
+    pub timestamp: f64,
+}
+
+/// The exemplar field of an `io.prometheus.client.Bucket`.
+#[derive(Message)]
+struct BucketFields {
+    #[prost(message, optional, tag = "3")]
+    exemplar: Option<ExemplarFields>,
+}
+
+/// An `io.prometheus.client.Exemplar`.
+#[derive(Message)]
+struct ExemplarFields {
+    #[prost(message, repeated, tag = "1")]
+    label: Vec<LabelPair>,
+
+    #[prost(double, tag = "2")]
+    value: f64,
+
+    #[prost(message, optional, tag = "3")]
+    timestamp: Option<Timestamp>,
+}
+
+#[derive(Message)]
+struct LabelPair {
+    #[prost(string, tag = "1")]
+    name: String,
+
+    #[prost(string, tag = "2")]
+    value: String,
+}
+
+/// A `google.protobuf.Timestamp`.
+#[derive(Message)]
+struct Timestamp {
+    #[prost(int64, tag = "1")]
+    seconds: i64,
+
+    #[prost(int32, tag = "2")]
+    nanos: i32,
+}
+
+/// Returns the ID of the sampled trace of the span, if it's exported.
+fn trace_id(span: &tracing::Span) -> Option<String> {
+    let context = span.context();
+    let span_context = context.span().span_context().clone();
+
+    (span_context.is_valid() && span_context.is_sampled())
+        .then(|| span_context.trace_id().to_string())
+}
+
+fn series_key(name: &str, labels: &[(&str, &str)]) -> SeriesKey {
+    let mut labels: Vec<(String, String)> = labels
+        .iter()
+        .map(|&(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    labels.sort();
+
+    (name.to_string(), labels)
+}
+
+/// Records the observation as the exemplar of its bucket if it was observed in a sampled trace.
+pub(super) fn observe(
+    name: &str,
+    labels: &[(&str, &str)],
+    buckets: &[f64],
+    value: f64,
+    span: &tracing::Span,
+) {
+    let Some(trace_id) = trace_id(span) else {
+        return;
+    };
+    let upper_bound = buckets
+        .iter()
+        .copied()
+        .find(|upper_bound| value <= *upper_bound)
+        .unwrap_or(f64::INFINITY);
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+
+    EXEMPLARS
+        .lock()
+        .unwrap()
+        .entry(series_key(name, labels))
+        .or_default()
+        .insert(
+            upper_bound.to_bits(),
+            Exemplar {
+                trace_id,
+                value,
+                timestamp,
+            },
+        );
+}
+
+/// Returns the exemplar of the bucket with the upper bound of the series, if there is one.
+pub(super) fn get(name: &str, labels: &[(String, String)], upper_bound: f64) -> Option<Exemplar> {
+    EXEMPLARS
+        .lock()
+        .unwrap()
+        .get(&(name.to_string(), labels.to_vec()))?
+        .get(&upper_bound.to_bits())
+        .cloned()
+}
+
+/// Drops the exemplars of the series which no longer exist, like the native buckets.
+pub(super) fn prune() {
+    let mut exemplars = EXEMPLARS.lock().unwrap();
+    if exemplars.is_empty() {
+        return;
+    }
+
+    // Gathered while locked, so series observed meanwhile aren't dropped
+    let series: HashSet<SeriesKey> = super::series_keys(&super::REGISTRY.gather());
+    exemplars.retain(|key, _| series.contains(key));
+}
+
+/// Adds the exemplars to the buckets of the histograms of the metric families.
+pub(super) fn add_to_buckets(families: &mut [MetricFamily]) {
+    let exemplars = EXEMPLARS.lock().unwrap();
+    if exemplars.is_empty() {
+        return;
+    }
+
+    for family in families {
+        let name = family.get_name().to_string();
+        for metric in family.mut_metric().iter_mut() {
+            let labels: Vec<(String, String)> = metric
+                .get_label()
+                .iter()
+                .map(|label| (label.get_name().to_string(), label.get_value().to_string()))
+                .collect();
+            let Some(series) = exemplars.get(&(name.clone(), labels)) else {
+                continue;
+            };
+
+            for bucket in metric.mut_histogram().mut_bucket().iter_mut() {
+                let Some(exemplar) = series.get(&bucket.get_upper_bound().to_bits()) else {
+                    continue;
+                };
+                let fields = BucketFields {
+                    exemplar: Some(ExemplarFields {
+                        label: vec![LabelPair {
+                            name: "trace_id".to_string(),
+                            value: exemplar.trace_id.clone(),
+                        }],
+                        value: exemplar.value,
+                        timestamp: Some(Timestamp {
+                            seconds: exemplar.timestamp.trunc() as i64,
+                            nanos: (exemplar.timestamp.fract() * 1e9) as i32,
+                        }),
+                    }),
+                };
+
+                // The field is unknown to the generated message, but encoded like any other field
+                protobuf::Message::merge_from_bytes(bucket, &fields.encode_to_vec()).ok();
+            }
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! Native histograms, which are only exposed in the protobuf format.
+//!
+//! The `prometheus` crate only supports classic histograms with fixed buckets, so the sparse
+//! buckets of the observations are tracked here and added to the classic histograms when they're
+//! encoded in the protobuf format.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+
+use prometheus::proto::MetricFamily;
+use prost::Message;
+
+/// The initial resolution of the buckets, each bucket is 2^(2^-3), about 1.09, times as wide as
+/// the previous one.
+const SCHEMA: i32 = 3;
+
+/// The lowest resolution, each bucket is 2^(2^4) times as wide as the previous one.
+const MIN_SCHEMA: i32 = -4;
+
+/// The maximum number of buckets of a series, like the default of the Go client.
+///
+/// The resolution is halved until the buckets fit, so outliers can't grow the buckets unbounded.
+const MAX_BUCKETS: usize = 160;
+
+/// The largest absolute value counted in the zero bucket, 2^-128.
+const ZERO_THRESHOLD: f64 = 2.938_735_877_055_719e-39;
+
+/// The name and label pairs of a series.
+type SeriesKey = (String, Vec<(String, String)>);
+
+lazy_static! {
+    /// The sparse buckets of native histograms by series.
+    static ref HISTOGRAMS: Mutex<HashMap<SeriesKey, Buckets>> = Mutex::new(HashMap::new());
+}
+
+/// The sparse buckets of a native histogram.
+struct Buckets {
+    schema: i32,
+    zero_count: u64,
+    /// The counts of the buckets of positive observations by index.
+    positive: BTreeMap<i32, u64>,
+    /// The counts of the buckets of negative observations by index.
+    negative: BTreeMap<i32, u64>,
+}
+
+/// The fields of a native histogram in `io.prometheus.client.Histogram`.
+#[derive(Message)]
+struct NativeFields {
+    #[prost(sint32, tag = "5")]
+    schema: i32,
+
+    #[prost(double, tag = "6")]
+    zero_threshold: f64,
+
+    #[prost(uint64, tag = "7")]
+    zero_count: u64,
+
+    #[prost(message, repeated, tag = "9")]
+    negative_span: Vec<BucketSpan>,
+
+    /// The count of each bucket as the difference to the previous bucket.
+    #[prost(sint64, repeated, tag = "10")]
+    negative_delta: Vec<i64>,
+
+    #[prost(message, repeated, tag = "12")]
+    positive_span: Vec<BucketSpan>,
+
+    /// The count of each bucket as the difference to the previous bucket.
+    #[prost(sint64, repeated, tag = "13")]
+    positive_delta: Vec<i64>,
+}
+
+/// A run of consecutive buckets.
+#[derive(Message)]
+struct BucketSpan {
+    /// The gap to the previous span, or the index of the first bucket of the first span.
+    #[prost(sint32, tag = "1")]
+    offset: i32,
+
+    #[prost(uint32, tag = "2")]
+    length: u32,
+}
+
+impl Default for Buckets {
+    fn default() -> Self {
+        Self {
+            schema: SCHEMA,
+            zero_count: 0,
+            positive: BTreeMap::new(),
+            negative: BTreeMap::new(),
+        }
+    }
+}
+
+impl Buckets {
+    fn observe(&mut self, value: f64) {
+        if value.abs() <= ZERO_THRESHOLD {
+            self.zero_count += 1;
+            return;
+        }
+
+        // Bucket i contains the values in (2^((i - 1) / 2^schema), 2^(i / 2^schema)]
+        let index = (value.abs().log2() * 2f64.powi(self.schema)).ceil() as i32;
+        let buckets = if value > 0.0 {
+            &mut self.positive
+        } else {
+            &mut self.negative
+        };
+        *buckets.entry(index).or_default() += 1;
+
+        while self.positive.len() + self.negative.len() > MAX_BUCKETS && self.schema > MIN_SCHEMA {
+            self.halve_resolution();
+        }
+    }
+
+    /// Merges every two adjacent buckets into one, by decreasing the schema.
+    fn halve_resolution(&mut self) {
+        // Bucket i of the lower schema contains the buckets 2i - 1 and 2i
+        let merge = |buckets: &BTreeMap<i32, u64>| {
+            let mut merged = BTreeMap::new();
+            for (&index, &count) in buckets {
+                *merged.entry((index + 1).div_euclid(2)).or_default() += count;
+            }
+            merged
+        };
+
+        self.positive = merge(&self.positive);
+        self.negative = merge(&self.negative);
+        self.schema -= 1;
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let (positive_span, positive_delta) = spans(&self.positive);
+        let (negative_span, negative_delta) = spans(&self.negative);
+
+        NativeFields {
+            schema: self.schema,
+            zero_threshold: ZERO_THRESHOLD,
+            zero_count: self.zero_count,
+            negative_span,
+            negative_delta,
+            positive_span,
+            positive_delta,
+        }
+        .encode_to_vec()
+    }
+}
+
+/// Returns the spans of the buckets and their counts as deltas.
+fn spans(buckets: &BTreeMap<i32, u64>) -> (Vec<BucketSpan>, Vec<i64>) {
+    let mut spans: Vec<BucketSpan> = Vec::new();
+    let mut deltas = Vec::new();
+    let mut previous: Option<(i32, u64)> = None;
+
+    for (&index, &count) in buckets {
+        match previous {
+            Some((previous_index, _)) if index == previous_index + 1 => {
+                if let Some(span) = spans.last_mut() {
+                    span.length += 1;
+                }
+            }
+            Some((previous_index, _)) => spans.push(BucketSpan {
+                offset: index - previous_index - 1,
+                length: 1,
+            }),
+            None => spans.push(BucketSpan {
+                offset: index,
+                length: 1,
+            }),
+        }
+
+        let previous_count = previous.map_or(0, |(_, count)| count);
+        deltas.push(count as i64 - previous_count as i64);
+        previous = Some((index, count));
+    }
+
+    (spans, deltas)
+}
+
+/// Records an observation in the native buckets of the series of a histogram.
+pub(super) fn observe(name: &str, labels: &[(&str, &str)], value: f64) {
+    let mut labels: Vec<(String, String)> = labels
+        .iter()
+        .map(|&(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    labels.sort();
+
+    HISTOGRAMS
+        .lock()
+        .unwrap()
+        .entry((name.to_string(), labels))
+        .or_default()
+        .observe(value);
+}
+
+/// Adds the native buckets to the histograms of the metric families.
+pub(super) fn add_buckets(families: &mut [MetricFamily]) {
+    let histograms = HISTOGRAMS.lock().unwrap();
+    if histograms.is_empty() {
+        return;
+    }
+
+    for family in families {
+        let name = family.get_name().to_string();
+        for metric in family.mut_metric().iter_mut() {
+            let labels: Vec<(String, String)> = metric
+                .get_label()
+                .iter()
+                .map(|label| (label.get_name().to_string(), label.get_value().to_string()))
+                .collect();
+            let Some(buckets) = histograms.get(&(name.clone(), labels)) else {
+                continue;
+            };
+
+            // The fields are unknown to the generated message, but encoded like any other field
+            protobuf::Message::merge_from_bytes(metric.mut_histogram(), &buckets.encode()).ok();
+        }
+    }
+}
+
+/// Drops the buckets of the series which no longer exist, e.g. for exceeding the series limit or
+/// of removed targets, so they restart together with their classic series.
+pub(super) fn prune() {
+    let mut histograms = HISTOGRAMS.lock().unwrap();
+    if histograms.is_empty() {
+        return;
+    }
+
+    // Gathered while locked, so series observed meanwhile aren't dropped
+    let series: HashSet<SeriesKey> = super::series_keys(&super::REGISTRY.gather());
+    histograms.retain(|key, _| series.contains(key));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_observations_in_exponential_buckets() {
+        let mut buckets = Buckets::default();
+        for value in [1.0, 1.05, 2.0, -0.5, 0.0] {
+            buckets.observe(value);
+        }
+
+        assert_eq!(buckets.schema, SCHEMA);
+        assert_eq!(buckets.zero_count, 1);
+        assert_eq!(buckets.positive, BTreeMap::from([(0, 1), (1, 1), (8, 1)]));
+        assert_eq!(buckets.negative, BTreeMap::from([(-8, 1)]));
+    }
+
+    #[test]
+    fn halves_the_resolution_beyond_the_maximum_number_of_buckets() {
+        let mut buckets = Buckets::default();
+        // Every observation falls into its own bucket, the bucket with the index of the loop
+        for index in 0..=MAX_BUCKETS as i32 {
+            buckets.observe(2f64.powf((index as f64 - 0.5) / 8.0));
+        }
+
+        assert_eq!(buckets.schema, SCHEMA - 1);
+        assert!(buckets.positive.len() <= MAX_BUCKETS);
+        assert_eq!(
+            buckets.positive.values().sum::<u64>(),
+            MAX_BUCKETS as u64 + 1
+        );
+        // The buckets 1 and 2 were merged into bucket 1
+        assert_eq!(buckets.positive.get(&0), Some(&1));
+        assert_eq!(buckets.positive.get(&1), Some(&2));
+    }
+}
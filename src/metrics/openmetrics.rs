@@ -0,0 +1,294 @@
+//! The OpenMetrics text format, which adds exemplars to the buckets of histograms.
+//!
+//! [OpenMetrics specification](https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md)
+
+use std::fmt::Write;
+
+use prometheus::proto::{LabelPair, Metric, MetricFamily, MetricType};
+
+use super::exemplars::{self, Exemplar};
+
+/// The content type of the OpenMetrics text format.
+pub const FORMAT: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Formats a number, with the spelling of infinity and NaN of the specification.
+fn number(value: f64) -> String {
+    if value == f64::INFINITY {
+        "+Inf".to_string()
+    } else if value == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else if value.is_nan() {
+        "NaN".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Escapes backslashes, double quotes and line feeds of label values and help texts.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Writes a sample with the labels of the metric and an additional label, e.g. `le`.
+fn write_sample(
+    output: &mut String,
+    name: &str,
+    labels: &[LabelPair],
+    extra: Option<(&str, String)>,
+    value: f64,
+    exemplar: Option<Exemplar>,
+) {
+    output.push_str(name);
+
+    let labels = labels
+        .iter()
+        .map(|label| (label.get_name(), escape(label.get_value())))
+        .chain(extra)
+        .map(|(name, value)| format!("{}=\"{}\"", name, value))
+        .collect::<Vec<_>>();
+    if !labels.is_empty() {
+        write!(output, "{{{}}}", labels.join(",")).unwrap();
+    }
+
+    write!(output, " {}", number(value)).unwrap();
+    if let Some(exemplar) = exemplar {
+        write!(
+            output,
+            " # {{trace_id=\"{}\"}} {} {}",
+            exemplar.trace_id,
+            number(exemplar.value),
+            exemplar.timestamp
+        )
+        .unwrap();
+    }
+    output.push('\n');
+}
+
+fn write_histogram(output: &mut String, name: &str, metric: &Metric) {
+    let histogram = metric.get_histogram();
+    let labels = metric.get_label();
+    let series: Vec<(String, String)> = labels
+        .iter()
+        .map(|label| (label.get_name().to_string(), label.get_value().to_string()))
+        .collect();
+
+    let mut upper_bounds: Vec<(f64, u64)> = histogram
+        .get_bucket()
+        .iter()
+        .map(|bucket| (bucket.get_upper_bound(), bucket.get_cumulative_count()))
+        .collect();
+    // The classic buckets of the `prometheus` crate leave out the required `+Inf` bucket
+    if upper_bounds
+        .last()
+        .is_none_or(|(upper_bound, _)| *upper_bound != f64::INFINITY)
+    {
+        upper_bounds.push((f64::INFINITY, histogram.get_sample_count()));
+    }
+
+    for (upper_bound, count) in upper_bounds {
+        write_sample(
+            output,
+            &format!("{}_bucket", name),
+            labels,
+            Some(("le", number(upper_bound))),
+            count as f64,
+            exemplars::get(name, &series, upper_bound),
+        );
+    }
+    write_sample(
+        output,
+        &format!("{}_count", name),
+        labels,
+        None,
+        histogram.get_sample_count() as f64,
+        None,
+    );
+    write_sample(
+        output,
+        &format!("{}_sum", name),
+        labels,
+        None,
+        histogram.get_sample_sum(),
+        None,
+    );
+}
+
+fn write_summary(output: &mut String, name: &str, metric: &Metric) {
+    let summary = metric.get_summary();
+    let labels = metric.get_label();
+
+    for quantile in summary.get_quantile() {
+        write_sample(
+            output,
+            name,
+            labels,
+            Some(("quantile", number(quantile.get_quantile()))),
+            quantile.get_value(),
+            None,
+        );
+    }
+    write_sample(
+        output,
+        &format!("{}_count", name),
+        labels,
+        None,
+        summary.get_sample_count() as f64,
+        None,
+    );
+    write_sample(
+        output,
+        &format!("{}_sum", name),
+        labels,
+        None,
+        summary.get_sample_sum(),
+        None,
+    );
+}
+
+/// Encodes the metric families in the OpenMetrics text format.
+///
+/// Counters without the `_total` suffix the format requires are exposed as `unknown`.
+pub fn encode(families: &[MetricFamily]) -> String {
+    let mut output = String::new();
+
+    for family in families {
+        let name = family.get_name();
+        let (metric_name, metric_type) = match family.get_field_type() {
+            MetricType::COUNTER => match name.strip_suffix("_total") {
+                Some(metric_name) => (metric_name, "counter"),
+                None => (name, "unknown"),
+            },
+            MetricType::GAUGE => (name, "gauge"),
+            MetricType::HISTOGRAM => (name, "histogram"),
+            MetricType::SUMMARY => (name, "summary"),
+            MetricType::UNTYPED => (name, "unknown"),
+        };
+
+        writeln!(output, "# TYPE {} {}", metric_name, metric_type).unwrap();
+        writeln!(
+            output,
+            "# HELP {} {}",
+            metric_name,
+            escape(family.get_help())
+        )
+        .unwrap();
+
+        for metric in family.get_metric() {
+            match family.get_field_type() {
+                MetricType::COUNTER => write_sample(
+                    &mut output,
+                    name,
+                    metric.get_label(),
+                    None,
+                    metric.get_counter().get_value(),
+                    None,
+                ),
+                MetricType::GAUGE => write_sample(
+                    &mut output,
+                    name,
+                    metric.get_label(),
+                    None,
+                    metric.get_gauge().get_value(),
+                    None,
+                ),
+                MetricType::HISTOGRAM => write_histogram(&mut output, name, metric),
+                MetricType::SUMMARY => write_summary(&mut output, name, metric),
+                MetricType::UNTYPED => write_sample(
+                    &mut output,
+                    name,
+                    metric.get_label(),
+                    None,
+                    metric.get_untyped().get_value(),
+                    None,
+                ),
+            }
+        }
+    }
+
+    output.push_str("# EOF\n");
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::trace::{TraceContextExt, TracerProvider as _};
+    use prometheus::{Counter, Gauge, Histogram, HistogramOpts, Opts, Registry};
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    #[test]
+    fn encodes_counters_and_gauges() {
+        let registry = Registry::new();
+        let counter = Counter::new("requests_total", "Number of requests.").unwrap();
+        counter.inc_by(3.0);
+        registry.register(Box::new(counter)).unwrap();
+        let gauge = Gauge::with_opts(
+            Opts::new("temperature", "Temperature in \"degrees\".").const_label("room", "a\\b"),
+        )
+        .unwrap();
+        gauge.set(f64::NEG_INFINITY);
+        registry.register(Box::new(gauge)).unwrap();
+
+        assert_eq!(
+            encode(&registry.gather()),
+            "# TYPE requests counter\n\
+             # HELP requests Number of requests.\n\
+             requests_total 3\n\
+             # TYPE temperature gauge\n\
+             # HELP temperature Temperature in \\\"degrees\\\".\n\
+             temperature{room=\"a\\\\b\"} -Inf\n\
+             # EOF\n"
+        );
+    }
+
+    #[test]
+    fn encodes_histograms_with_exemplars() {
+        let registry = Registry::new();
+        let histogram = Histogram::with_opts(
+            HistogramOpts::new("openmetrics_test_seconds", "Duration.").buckets(vec![0.1, 1.0]),
+        )
+        .unwrap();
+        registry.register(Box::new(histogram.clone())).unwrap();
+
+        // Spans are only sampled while the provider of their tracer exists
+        let provider = opentelemetry_sdk::trace::TracerProvider::builder().build();
+        let tracer = provider.tracer("test");
+        let subscriber =
+            tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+        let trace_id = tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("request");
+            histogram.observe(0.5);
+            exemplars::observe("openmetrics_test_seconds", &[], &[0.1, 1.0], 0.5, &span);
+
+            let context = span.context();
+            let trace_id = context.span().span_context().trace_id();
+            trace_id.to_string()
+        });
+        histogram.observe(2.0);
+        // Observations outside of a trace have no exemplar
+        exemplars::observe(
+            "openmetrics_test_seconds",
+            &[],
+            &[0.1, 1.0],
+            2.0,
+            &tracing::Span::none(),
+        );
+
+        let output = encode(&registry.gather());
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[2], "openmetrics_test_seconds_bucket{le=\"0.1\"} 0");
+        assert!(lines[3].starts_with(&format!(
+            "openmetrics_test_seconds_bucket{{le=\"1\"}} 1 # {{trace_id=\"{}\"}} 0.5 ",
+            trace_id
+        )));
+        assert_eq!(lines[4], "openmetrics_test_seconds_bucket{le=\"+Inf\"} 2");
+        assert_eq!(lines[5], "openmetrics_test_seconds_count 2");
+        assert_eq!(lines[6], "openmetrics_test_seconds_sum 2.5");
+        assert_eq!(lines[7], "# EOF");
+    }
+}
@@ -0,0 +1,143 @@
+//! The series served to a request, which keep the series of the instances of metric groups apart
+//! from each other and from the endpoints without a group.
+
+use std::collections::HashSet;
+
+use prometheus::{
+    core::{Collector, Desc},
+    proto::MetricFamily,
+    Registry,
+};
+
+use super::REGISTRY;
+use crate::config::{Config, MetricGroupConfig};
+
+/// The labels whose values are instances, e.g. of the federation probes between two instances.
+const INSTANCE_LABELS: [&str; 5] = ["instance", "from", "to", "instance_a", "instance_b"];
+
+/// The instances whose series are served.
+#[derive(Clone)]
+pub enum Scope {
+    /// The instances of a metric group.
+    Group(HashSet<String>),
+
+    /// All instances except the given instances of metric groups, with the series of the exporter
+    /// itself.
+    Ungrouped(HashSet<String>),
+}
+
+impl Scope {
+    /// Returns the scope of the endpoints without a metric group.
+    pub fn ungrouped(config: &Config) -> Self {
+        Scope::Ungrouped(
+            config
+                .metric_groups
+                .iter()
+                .flat_map(|group| group.instances.iter().cloned())
+                .collect(),
+        )
+    }
+
+    pub fn group(group: &MetricGroupConfig) -> Self {
+        Scope::Group(group.instances.iter().cloned().collect())
+    }
+
+    /// Returns the scope of all series, for local use like the `export` command.
+    pub fn all() -> Self {
+        Scope::Ungrouped(HashSet::new())
+    }
+
+    /// Whether the series with the labels is in the scope.
+    ///
+    /// Series of a group only refer to its instances, while the series of other endpoints don't
+    /// refer to any instance of a group.
+    pub fn includes<'a>(&self, labels: impl IntoIterator<Item = (&'a str, &'a str)>) -> bool {
+        let mut instances = labels
+            .into_iter()
+            .filter(|(name, _)| INSTANCE_LABELS.contains(name))
+            .map(|(_, value)| value)
+            .peekable();
+
+        match self {
+            Scope::Group(group) => {
+                instances.peek().is_some() && instances.all(|instance| group.contains(instance))
+            }
+            Scope::Ungrouped(grouped) => instances.all(|instance| !grouped.contains(instance)),
+        }
+    }
+
+    /// Returns a registry of the series in the scope, gathered from the registry of all metrics.
+    pub fn registry(&self) -> Registry {
+        let registry = Registry::new();
+        registry
+            .register(Box::new(ScopedCollector(self.clone())))
+            .unwrap();
+        registry
+    }
+}
+
+/// Collects the series in a scope from the registry of all metrics.
+struct ScopedCollector(Scope);
+
+impl Collector for ScopedCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        Vec::new()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let mut families = REGISTRY.gather();
+        for family in &mut families {
+            let metrics = family.take_metric().into_iter().filter(|metric| {
+                self.0.includes(
+                    metric
+                        .get_label()
+                        .iter()
+                        .map(|label| (label.get_name(), label.get_value())),
+                )
+            });
+            family.set_metric(metrics.collect());
+        }
+        families.retain(|family| !family.get_metric().is_empty());
+
+        families
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scopes() -> (Scope, Scope) {
+        let group = HashSet::from(["a.example".to_string(), "b.example".to_string()]);
+        let grouped = HashSet::from([
+            "a.example".to_string(),
+            "b.example".to_string(),
+            "c.example".to_string(),
+        ]);
+
+        (Scope::Group(group), Scope::Ungrouped(grouped))
+    }
+
+    #[test]
+    fn groups_only_include_series_of_their_instances() {
+        let (group, _) = scopes();
+
+        assert!(group.includes([("instance", "a.example"), ("account_id", "1")]));
+        assert!(group.includes([("from", "a.example"), ("to", "b.example")]));
+        assert!(!group.includes([("from", "a.example"), ("to", "c.example")]));
+        assert!(!group.includes([("instance", "d.example")]));
+        // The series of the exporter itself
+        assert!(!group.includes([("metric", "mastodon_up")]));
+    }
+
+    #[test]
+    fn ungrouped_scopes_exclude_series_of_groups() {
+        let (_, ungrouped) = scopes();
+
+        assert!(ungrouped.includes([("instance", "d.example")]));
+        assert!(ungrouped.includes([("metric", "mastodon_up")]));
+        assert!(!ungrouped.includes([("instance", "a.example")]));
+        assert!(!ungrouped.includes([("instance_a", "d.example"), ("instance_b", "c.example")]));
+        assert!(Scope::all().includes([("instance", "a.example")]));
+    }
+}
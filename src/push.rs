@@ -0,0 +1,225 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use base64::Engine;
+use prometheus::proto::{MetricFamily, MetricType};
+use prost::Message;
+use reqwest::header;
+
+use crate::{config::PushConfig, metrics};
+
+/// The number of seconds after which pushing the metrics is given up.
+const TIMEOUT_SECONDS: u64 = 30;
+
+lazy_static! {
+    /// The HTTP client used to push the metrics.
+    static ref HTTP_CLIENT: reqwest::Client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(TIMEOUT_SECONDS))
+        .build()
+        .unwrap();
+}
+
+/// The request body of Prometheus remote write.
+#[derive(Message)]
+struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    timeseries: Vec<TimeSeries>,
+}
+
+#[derive(Message)]
+struct TimeSeries {
+    /// The labels including `__name__`, sorted by name.
+    #[prost(message, repeated, tag = "1")]
+    labels: Vec<Label>,
+
+    #[prost(message, repeated, tag = "2")]
+    samples: Vec<Sample>,
+}
+
+#[derive(Message)]
+struct Label {
+    #[prost(string, tag = "1")]
+    name: String,
+
+    #[prost(string, tag = "2")]
+    value: String,
+}
+
+#[derive(Message)]
+struct Sample {
+    #[prost(double, tag = "1")]
+    value: f64,
+
+    /// The number of milliseconds since 1970.
+    #[prost(int64, tag = "2")]
+    timestamp: i64,
+}
+
+/// Collects the series of the metric families with the given timestamp and job, expanding
+/// histograms and summaries into their `_bucket`, `_sum` and `_count` series like the text format.
+fn time_series(families: &[MetricFamily], timestamp: i64, job: &str) -> Vec<TimeSeries> {
+    let mut series = Vec::new();
+
+    for family in families {
+        let name = family.get_name();
+        for metric in family.get_metric() {
+            let labels: BTreeMap<String, String> = metric
+                .get_label()
+                .iter()
+                .map(|label| (label.get_name().to_string(), label.get_value().to_string()))
+                .chain([("job".to_string(), job.to_string())])
+                .collect();
+            let mut push = |suffix: &str, extra: Option<(&str, String)>, value: f64| {
+                let mut labels = labels.clone();
+                labels.insert("__name__".to_string(), format!("{}{}", name, suffix));
+                if let Some((name, value)) = extra {
+                    labels.insert(name.to_string(), value);
+                }
+                series.push(TimeSeries {
+                    labels: labels
+                        .into_iter()
+                        .map(|(name, value)| Label { name, value })
+                        .collect(),
+                    samples: vec![Sample { value, timestamp }],
+                });
+            };
+
+            match family.get_field_type() {
+                MetricType::COUNTER => push("", None, metric.get_counter().get_value()),
+                MetricType::GAUGE => push("", None, metric.get_gauge().get_value()),
+                MetricType::UNTYPED => push("", None, metric.get_untyped().get_value()),
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    for bucket in histogram.get_bucket() {
+                        let le = Some(("le", bucket.get_upper_bound().to_string()));
+                        push("_bucket", le, bucket.get_cumulative_count() as f64);
+                    }
+                    let count = histogram.get_sample_count() as f64;
+                    push("_bucket", Some(("le", "+Inf".to_string())), count);
+                    push("_sum", None, histogram.get_sample_sum());
+                    push("_count", None, count);
+                }
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    for quantile in summary.get_quantile() {
+                        let label = Some(("quantile", quantile.get_quantile().to_string()));
+                        push("", label, quantile.get_value());
+                    }
+                    push("_sum", None, summary.get_sample_sum());
+                    push("_count", None, summary.get_sample_count() as f64);
+                }
+            }
+        }
+    }
+
+    series
+}
+
+/// Returns the path segments of the job in the URL of the Pushgateway.
+///
+/// Jobs with a slash are base64 encoded, as the Pushgateway doesn't decode `%2F`.
+fn job_path(job: &str) -> String {
+    if job.contains('/') {
+        format!(
+            "job@base64/{}",
+            base64::engine::general_purpose::URL_SAFE.encode(job)
+        )
+    } else {
+        format!("job/{}", urlencoding::encode(job))
+    }
+}
+
+/// Pushes the current metrics to the Pushgateway, replacing the metrics of the job.
+async fn push_to_pushgateway(url: &str, job: &str) -> Result<(), reqwest::Error> {
+    let url = format!("{}/metrics/{}", url.trim_end_matches('/'), job_path(job));
+    log!("Pushing metrics to {}", url);
+
+    HTTP_CLIENT
+        .put(&url)
+        .header(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)
+        .body(metrics::encode())
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(())
+}
+
+/// Pushes the current metrics to a Prometheus remote write endpoint, labeled with the job like
+/// scraped series.
+async fn push_to_remote_write(url: &str, job: &str) -> Result<(), reqwest::Error> {
+    log!("Pushing metrics to {}", url);
+
+    let request = WriteRequest {
+        timeseries: time_series(
+            &metrics::REGISTRY.gather(),
+            chrono::Utc::now().timestamp_millis(),
+            job,
+        ),
+    };
+    // Encoding into a vector and compressing it in memory can't fail
+    let body = snap::raw::Encoder::new()
+        .compress_vec(&request.encode_to_vec())
+        .unwrap();
+
+    HTTP_CLIENT
+        .post(url)
+        .header(header::CONTENT_TYPE, "application/x-protobuf")
+        .header(header::CONTENT_ENCODING, "snappy")
+        .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+        .body(body)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(())
+}
+
+/// Pushes the current metrics to all configured sinks.
+pub async fn push(config: &PushConfig) -> Result<(), reqwest::Error> {
+    if let Some(url) = &config.pushgateway_url {
+        push_to_pushgateway(url, &config.job).await?;
+    }
+    if let Some(url) = &config.remote_write_url {
+        push_to_remote_write(url, &config.job).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use prometheus::{IntGaugeVec, Opts, Registry};
+
+    use super::*;
+
+    #[test]
+    fn encodes_the_job_in_the_url() {
+        assert_eq!(job_path("mastodon_exporter"), "job/mastodon_exporter");
+        assert_eq!(job_path("mastodon exporter?"), "job/mastodon%20exporter%3F");
+        assert_eq!(job_path("team/a"), "job@base64/dGVhbS9h");
+    }
+
+    #[test]
+    fn labels_series_with_the_job() {
+        let registry = Registry::new();
+        let gauge = IntGaugeVec::new(Opts::new("mastodon_up", "Up."), &["instance"]).unwrap();
+        gauge.with_label_values(&["mastodon.social"]).set(1);
+        registry.register(Box::new(gauge)).unwrap();
+
+        let series = time_series(&registry.gather(), 1700000000000, "mastodon_exporter");
+        let labels: Vec<(&str, &str)> = series[0]
+            .labels
+            .iter()
+            .map(|label| (label.name.as_str(), label.value.as_str()))
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                ("__name__", "mastodon_up"),
+                ("instance", "mastodon.social"),
+                ("job", "mastodon_exporter")
+            ]
+        );
+    }
+}
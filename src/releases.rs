@@ -0,0 +1,99 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::{config::UpstreamReleasesConfig, version::Version};
+
+const RELEASES_URL: &str = "https://api.github.com/repos/mastodon/mastodon/releases?per_page=100";
+
+/// A release of Mastodon as returned by the GitHub API.
+///
+/// [docs.github.com/en/rest/releases/releases](https://docs.github.com/en/rest/releases/releases#list-releases)
+#[derive(Deserialize)]
+struct GitHubRelease {
+    /// The name of the tag, e.g. `v4.2.1`.
+    tag_name: String,
+
+    /// Whether the release is a draft.
+    draft: bool,
+
+    /// Whether the release is a pre-release.
+    prerelease: bool,
+}
+
+/// The number of seconds to wait for the GitHub API.
+const TIMEOUT_SECONDS: u64 = 30;
+
+/// The releases fetched last, and when they were last attempted to be fetched.
+struct ReleasesCache {
+    attempted_at: Instant,
+    releases: Option<Arc<Vec<Version>>>,
+}
+
+lazy_static! {
+    static ref RELEASES_CACHE: Mutex<Option<ReleasesCache>> = Mutex::new(None);
+}
+
+async fn fetch_releases() -> Result<Vec<Version>, reqwest::Error> {
+    let client = reqwest::Client::builder()
+        .user_agent(concat!("mastodon_exporter/", env!("CARGO_PKG_VERSION")))
+        .timeout(Duration::from_secs(TIMEOUT_SECONDS))
+        .build()?;
+
+    let releases = client
+        .get(RELEASES_URL)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Vec<GitHubRelease>>()
+        .await?;
+
+    Ok(releases
+        .into_iter()
+        .filter(|release| !release.draft && !release.prerelease)
+        .filter_map(|release| Version::parse(release.tag_name.trim_start_matches('v')))
+        .collect())
+}
+
+/// Returns the stable upstream releases, fetching them at most once per cache duration.
+///
+/// Failed fetches are also only retried after the cache duration, and keep serving the
+/// previously fetched releases, if any. Collections running meanwhile don't wait for the fetch
+/// and get the previous releases as well.
+pub async fn upstream_releases(config: &UpstreamReleasesConfig) -> Option<Arc<Vec<Version>>> {
+    let max_age = Duration::from_secs(config.cache_seconds);
+
+    let previous = {
+        let mut cache = RELEASES_CACHE.lock().unwrap();
+        let previous = cache.as_ref().and_then(|cache| cache.releases.clone());
+        if cache
+            .as_ref()
+            .is_some_and(|cache| cache.attempted_at.elapsed() < max_age)
+        {
+            return previous;
+        }
+
+        *cache = Some(ReleasesCache {
+            attempted_at: Instant::now(),
+            releases: previous.clone(),
+        });
+        previous
+    };
+
+    log!("Fetching upstream Mastodon releases");
+
+    match fetch_releases().await {
+        Ok(releases) => {
+            let releases = Arc::new(releases);
+            if let Some(cache) = RELEASES_CACHE.lock().unwrap().as_mut() {
+                cache.releases = Some(releases.clone());
+            }
+            Some(releases)
+        }
+        Err(err) => {
+            log!("Error: Fetching upstream releases failed: {}", err);
+            previous
+        }
+    }
+}
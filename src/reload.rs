@@ -0,0 +1,149 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{discovery, sharding, signal::ReloadSignal, Collector, Config};
+
+/// The number of seconds between checks of the watched files for changes.
+const CHECK_INTERVAL_SECONDS: u64 = 30;
+
+/// A request to reload the configuration, answered with whether it was reloaded.
+type Request = oneshot::Sender<Result<(), String>>;
+
+/// The requests to reload, e.g. from the reload endpoint, while the configuration is watched.
+static REQUESTS: OnceLock<mpsc::Sender<Request>> = OnceLock::new();
+
+/// Reloads the configuration like on the reload signal, and returns the error if the new one is
+/// invalid and the current configuration is kept.
+pub async fn request() -> Result<(), String> {
+    let requests = REQUESTS.get().ok_or("the configuration is not watched")?;
+    let (reply, outcome) = oneshot::channel();
+    requests
+        .send(reply)
+        .await
+        .map_err(|_| "the configuration is no longer watched")?;
+
+    outcome
+        .await
+        .map_err(|_| "the reload was aborted".to_string())?
+}
+
+/// Hashes the size and modification time of the file, following links.
+fn hash_file(path: &Path, hasher: &mut DefaultHasher) {
+    path.hash(hasher);
+    if let Ok(metadata) = fs::metadata(path) {
+        metadata.len().hash(hasher);
+        metadata.modified().ok().hash(hasher);
+    }
+}
+
+/// Returns a fingerprint of the configuration file and the files in the watched directories,
+/// which changes whenever a file is added, removed or modified.
+///
+/// Kubernetes updates mounted ConfigMaps and Secrets by swapping a link, so the files are
+/// followed instead of relying on change notifications.
+fn fingerprint(path: &Path, dirs: &[&str]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hash_file(path, &mut hasher);
+
+    for dir in dirs {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| Some(entry.ok()?.path()))
+                    .filter(|path| {
+                        !path
+                            .file_name()
+                            .unwrap_or_default()
+                            .to_string_lossy()
+                            .starts_with('.')
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        paths.sort();
+        for path in paths {
+            hash_file(&path, &mut hasher);
+        }
+    }
+
+    hasher.finish()
+}
+
+/// Loads the configuration like at startup, including discovered and sharded targets.
+///
+/// Reading the files and running the access token commands blocks, so the configuration is loaded
+/// on the blocking thread pool.
+async fn load(path: &Path) -> Result<Config, String> {
+    let path = path.to_path_buf();
+    let mut config = tokio::task::spawn_blocking(move || Config::load(path))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())?;
+    discovery::discover(&mut config).await;
+    sharding::apply(&mut config);
+
+    Ok(config)
+}
+
+/// Reloads the configuration on the reload signal or a reload request, and whenever the
+/// configuration file, the targets directory or the secrets directory change if any directory is
+/// watched. The current configuration is kept if the new one is invalid.
+///
+/// This runs until the process exits.
+pub async fn watch(collector: Arc<Collector>, path: PathBuf) {
+    let mut interval = tokio::time::interval(Duration::from_secs(CHECK_INTERVAL_SECONDS));
+    let mut last_fingerprint = fingerprint(&path, &collector.config().watched_dirs());
+    let mut reload_signal = ReloadSignal::new()
+        .map_err(|err| log!("Error: Unable to listen for the reload signal: {}", err))
+        .ok();
+    let (sender, mut requests) = mpsc::channel::<Request>(8);
+    REQUESTS.set(sender).ok();
+
+    loop {
+        let reply = tokio::select! {
+            _ = interval.tick() => {
+                let config = collector.config();
+                let dirs = config.watched_dirs();
+                if dirs.is_empty() || fingerprint(&path, &dirs) == last_fingerprint {
+                    continue;
+                }
+                None
+            }
+            _ = async { reload_signal.as_mut().unwrap().recv().await }, if reload_signal.is_some() => {
+                log!("Reloading {} on signal", path.display());
+                None
+            }
+            Some(reply) = requests.recv() => {
+                log!("Reloading {} on request", path.display());
+                Some(reply)
+            }
+        };
+        last_fingerprint = fingerprint(&path, &collector.config().watched_dirs());
+
+        let outcome = match load(&path).await {
+            Ok(config) => {
+                log!(
+                    "Reloaded {} with {} instances and {} accounts",
+                    path.display(),
+                    config.instance_info.len(),
+                    config.accounts.len()
+                );
+                collector.reload(config);
+                Ok(())
+            }
+            Err(err) => {
+                log!("Error: Unable to reload {}: {}", path.display(), err);
+                Err(err)
+            }
+        };
+        if let Some(reply) = reply {
+            reply.send(outcome).ok();
+        }
+    }
+}
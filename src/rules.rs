@@ -0,0 +1,250 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::config::Config;
+
+/// A Prometheus rule file.
+#[derive(Serialize)]
+pub struct RuleFile {
+    pub groups: Vec<RuleGroup>,
+}
+
+/// A group of alerting rules evaluated together.
+#[derive(Serialize)]
+pub struct RuleGroup {
+    pub name: String,
+    pub rules: Vec<Rule>,
+}
+
+/// A Prometheus alerting rule.
+#[derive(Serialize)]
+pub struct Rule {
+    pub alert: String,
+    pub expr: String,
+    #[serde(rename = "for")]
+    pub for_duration: String,
+    pub labels: BTreeMap<String, String>,
+    pub annotations: BTreeMap<String, String>,
+}
+
+impl Rule {
+    fn new(alert: &str, expr: String, for_duration: &str, severity: &str, summary: &str) -> Self {
+        Self {
+            alert: alert.to_string(),
+            expr,
+            for_duration: for_duration.to_string(),
+            labels: BTreeMap::from([("severity".to_string(), severity.to_string())]),
+            annotations: BTreeMap::from([("summary".to_string(), summary.to_string())]),
+        }
+    }
+}
+
+/// Returns a label matcher for the given instances, or for all instances if discovered.
+fn instance_matcher(instances: &[String], discovered: bool) -> String {
+    if discovered || instances.is_empty() {
+        return String::new();
+    }
+
+    let instances: Vec<String> = instances
+        .iter()
+        .map(|instance| instance.replace('.', "\\\\."))
+        .collect();
+    format!("instance=~\"{}\"", instances.join("|"))
+}
+
+/// Joins label matchers into a selector.
+fn selector(matchers: &[&str]) -> String {
+    let matchers: Vec<&str> = matchers
+        .iter()
+        .copied()
+        .filter(|matcher| !matcher.is_empty())
+        .collect();
+    format!("{{{}}}", matchers.join(", "))
+}
+
+/// Generates alerting rules for the metrics exported with the configuration.
+///
+/// The rules only match the configured instances, or all instances if instances are discovered.
+/// Certificates are alerted two weeks before they expire.
+pub fn generate(config: &Config) -> RuleFile {
+    let mut instances = config.enabled_instances();
+    for account in config.enabled_accounts() {
+        if !instances.contains(&account.instance) {
+            instances.push(account.instance);
+        }
+    }
+    let instance = instance_matcher(&instances, !config.discover_instances.is_empty());
+
+    let mut rules = vec![
+        Rule::new(
+            "MastodonInstanceDown",
+            format!(
+                "increase(mastodon_scrape_errors_total{}[10m]) > 0",
+                selector(&[&instance, "reason=~\"dns|connect|timeout|http_5xx\""])
+            ),
+            "10m",
+            "critical",
+            "Mastodon instance {{ $labels.instance }} is unreachable ({{ $labels.reason }}).",
+        ),
+        Rule::new(
+            "MastodonInstanceTlsErrors",
+            format!(
+                "increase(mastodon_scrape_errors_total{}[10m]) > 0",
+                selector(&[&instance, "reason=\"tls\""])
+            ),
+            "10m",
+            "critical",
+            "TLS connections to Mastodon instance {{ $labels.instance }} fail, the certificate may have expired.",
+        ),
+        Rule::new(
+            "MastodonInstanceCertificateExpiring",
+            format!(
+                "mastodon_instance_tls_certificate_expiry_timestamp_seconds{} - time() < 14 * 24 * 3600",
+                selector(&[&instance])
+            ),
+            "1h",
+            "warning",
+            "The TLS certificate of Mastodon instance {{ $labels.instance }} expires in less than two weeks.",
+        ),
+        Rule::new(
+            "MastodonRatelimitExhausted",
+            format!(
+                "mastodon_ratelimit_remaining{} == 0",
+                selector(&[&instance])
+            ),
+            "5m",
+            "warning",
+            "The ratelimit of Mastodon instance {{ $labels.instance }} is exhausted.",
+        ),
+    ];
+
+    if !config.instance_info.is_empty() || !config.discover_instances.is_empty() {
+        rules.push(Rule::new(
+            "MastodonRegistrationsEnabled",
+            format!(
+                "mastodon_registrations_enabled{} == 1 and changes(mastodon_registrations_enabled{}[1h]) > 0",
+                selector(&[&instance]),
+                selector(&[&instance])
+            ),
+            "0m",
+            "warning",
+            "Registrations of Mastodon instance {{ $labels.instance }} have been enabled.",
+        ));
+        rules.push(Rule::new(
+            "MastodonBrandingChanged",
+            format!(
+                "increase(mastodon_instance_branding_changes_total{}[1h]) > 0",
+                selector(&[&instance])
+            ),
+            "0m",
+            "warning",
+            "The title, description or thumbnail of Mastodon instance {{ $labels.instance }} changed.",
+        ));
+    }
+    if config.upstream_releases.is_some() {
+        rules.push(Rule::new(
+            "MastodonInstanceOutdated",
+            format!(
+                "mastodon_instance_version_outdated{} == 1",
+                selector(&[&instance])
+            ),
+            "1d",
+            "info",
+            "Mastodon instance {{ $labels.instance }} runs an outdated version.",
+        ));
+    }
+    let timeouts = config
+        .client
+        .as_ref()
+        .is_some_and(|client| client.timeout_seconds.is_some())
+        || !config.instance_timeouts().is_empty();
+    if timeouts {
+        rules.push(Rule::new(
+            "MastodonInstanceSlow",
+            format!("mastodon_target_slow{} == 1", selector(&[&instance])),
+            "15m",
+            "warning",
+            "Requests to Mastodon instance {{ $labels.instance }} are close to timing out.",
+        ));
+    }
+    if config.health_checks {
+        rules.push(Rule::new(
+            "MastodonInstanceUnhealthy",
+            format!("mastodon_instance_health_up{} == 0", selector(&[&instance])),
+            "5m",
+            "critical",
+            "The health endpoint of Mastodon instance {{ $labels.instance }} fails.",
+        ));
+    }
+    if config.well_known_checks {
+        rules.push(Rule::new(
+            "MastodonWellKnownFailing",
+            format!("mastodon_well_known_success{} == 0", selector(&[&instance])),
+            "15m",
+            "warning",
+            "The {{ $labels.endpoint }} endpoint of Mastodon instance {{ $labels.instance }} fails.",
+        ));
+    }
+    if !config.search_probes.is_empty() {
+        rules.push(Rule::new(
+            "MastodonSearchFailing",
+            "mastodon_search_success == 0".to_string(),
+            "15m",
+            "warning",
+            "Search of Mastodon instance {{ $labels.instance }} fails.",
+        ));
+    }
+    if !config.media_probes.is_empty() {
+        rules.push(Rule::new(
+            "MastodonMediaFetchFailing",
+            "mastodon_media_fetch_success == 0".to_string(),
+            "15m",
+            "warning",
+            "Media of Mastodon instance {{ $labels.instance }} can't be fetched.",
+        ));
+    }
+    if !config.resolve_probes.is_empty() {
+        rules.push(Rule::new(
+            "MastodonRemoteAccountUnresolvable",
+            "mastodon_federation_resolvable == 0".to_string(),
+            "30m",
+            "warning",
+            "{{ $labels.acct }} can't be resolved from Mastodon instance {{ $labels.instance }}.",
+        ));
+    }
+    if !config.canaries.is_empty() {
+        rules.push(Rule::new(
+            "MastodonCanaryFailing",
+            "mastodon_canary_post_success == 0".to_string(),
+            "15m",
+            "critical",
+            "Posting to Mastodon instance {{ $labels.instance }} fails.",
+        ));
+    }
+    if !config.federation_probes.is_empty() {
+        rules.push(Rule::new(
+            "MastodonFederationFailing",
+            "mastodon_federation_delivery_success == 0".to_string(),
+            "30m",
+            "warning",
+            "Statuses of {{ $labels.from }} aren't delivered to {{ $labels.to }}.",
+        ));
+    }
+    if !config.streaming.is_empty() {
+        rules.push(Rule::new(
+            "MastodonStreamingDisconnected",
+            "mastodon_streaming_connected == 0".to_string(),
+            "10m",
+            "warning",
+            "The streaming API of Mastodon instance {{ $labels.instance }} is disconnected.",
+        ));
+    }
+
+    RuleFile {
+        groups: vec![RuleGroup {
+            name: "mastodon_exporter".to_string(),
+            rules,
+        }],
+    }
+}
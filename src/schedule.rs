@@ -0,0 +1,215 @@
+//! Cron expressions to run collectors only at specific times.
+
+use chrono::{DateTime, Datelike, Duration, Timelike, Utc};
+
+/// A cron expression of the minute, hour, day of month, month and day of week, in UTC.
+///
+/// Each field is `*`, a value, a range like `1-5`, a step like `*/15` or `0-30/10`, or a comma
+/// separated list of them. Days of week are `0` to `7`, where both `0` and `7` are Sunday.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether or not the day of month is restricted, i.e. doesn't start with `*`.
+    days_restricted: bool,
+    /// Whether or not the day of week is restricted, i.e. doesn't start with `*`.
+    weekdays_restricted: bool,
+}
+
+impl Schedule {
+    /// Parses a cron expression with five fields, like `*/5 * * * *`.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = input.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!("cron expression {:?} must have 5 fields", input));
+        };
+        let field = |field: &str, min: u32, max: u32| {
+            parse_field(field, min, max)
+                .map_err(|reason| format!("invalid cron expression {:?}: {}", input, reason))
+        };
+
+        let mut weekday_bits = field(weekdays, 0, 7)?;
+        if weekday_bits & (1 << 7) != 0 {
+            weekday_bits |= 1;
+        }
+
+        Ok(Self {
+            minutes: field(minutes, 0, 59)?,
+            hours: field(hours, 0, 23)?,
+            days: field(days, 1, 31)?,
+            months: field(months, 1, 12)?,
+            weekdays: weekday_bits,
+            days_restricted: !days.starts_with('*'),
+            weekdays_restricted: !weekdays.starts_with('*'),
+        })
+    }
+
+    /// Returns whether the schedule matches the day of the time.
+    fn matches_day(&self, time: DateTime<Utc>) -> bool {
+        let day = has(self.days, time.day());
+        let weekday = has(self.weekdays, time.weekday().num_days_from_sunday());
+
+        // Like cron, a day matches either field if both the day of month and of week are restricted
+        has(self.months, time.month())
+            && if self.days_restricted && self.weekdays_restricted {
+                day || weekday
+            } else {
+                day && weekday
+            }
+    }
+
+    /// Returns whether the schedule matches any minute after `since` up to and including `until`.
+    pub fn is_due(&self, since: DateTime<Utc>, until: DateTime<Utc>) -> bool {
+        let Some(start) = since
+            .with_second(0)
+            .and_then(|time| time.with_nanosecond(0))
+        else {
+            return false;
+        };
+
+        // Days and hours which don't match are skipped as a whole
+        let mut time = start + Duration::minutes(1);
+        while time <= until {
+            if !self.matches_day(time) {
+                time = time.with_hour(0).unwrap().with_minute(0).unwrap() + Duration::days(1);
+            } else if !has(self.hours, time.hour()) {
+                time = time.with_minute(0).unwrap() + Duration::hours(1);
+            } else if has(self.minutes, time.minute()) {
+                return true;
+            } else {
+                time += Duration::minutes(1);
+            }
+        }
+
+        false
+    }
+}
+
+/// Returns whether the bit of the value is set.
+fn has(bits: u64, value: u32) -> bool {
+    bits & (1 << value) != 0
+}
+
+/// Parses a field of a cron expression into a bit set of the matching values.
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let value = |value: &str| {
+        value
+            .parse::<u32>()
+            .ok()
+            .filter(|value| (min..=max).contains(value))
+            .ok_or_else(|| format!("{:?} is not a value from {} to {}", value, min, max))
+    };
+
+    let mut bits = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => {
+                let step = step
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|&step| step > 0)
+                    .ok_or_else(|| format!("invalid step {:?}", step))?;
+                (range, Some(step))
+            }
+            None => (item, None),
+        };
+
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (value(start)?, value(end)?),
+            // A single value with a step repeats up to the maximum
+            None if step.is_some() => (value(range)?, max),
+            None => (value(range)?, value(range)?),
+        };
+        if start > end {
+            return Err(format!("range {:?} is empty", range));
+        }
+
+        for value in (start..=end).step_by(step.unwrap_or(1)) {
+            bits |= 1 << value;
+        }
+    }
+
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_fields() {
+        assert_eq!(parse_field("*", 0, 3), Ok(0b1111));
+        assert_eq!(parse_field("2", 0, 59), Ok(1 << 2));
+        assert_eq!(parse_field("1-3", 0, 59), Ok(0b1110));
+        assert_eq!(
+            parse_field("*/15", 0, 59),
+            Ok(1 | 1 << 15 | 1 << 30 | 1 << 45)
+        );
+        assert_eq!(
+            parse_field("0-30/10", 0, 59),
+            Ok(1 | 1 << 10 | 1 << 20 | 1 << 30)
+        );
+        assert_eq!(parse_field("50/5", 0, 59), Ok(1 << 50 | 1 << 55));
+        assert_eq!(parse_field("1,5-6", 1, 12), Ok(1 << 1 | 1 << 5 | 1 << 6));
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        assert!(Schedule::parse("* * * *").is_err());
+        assert!(Schedule::parse("* * * * * *").is_err());
+        assert!(Schedule::parse("60 * * * *").is_err());
+        assert!(Schedule::parse("* 24 * * *").is_err());
+        assert!(Schedule::parse("* * 0 * *").is_err());
+        assert!(Schedule::parse("* * * 13 *").is_err());
+        assert!(Schedule::parse("* * * * 8").is_err());
+        assert!(Schedule::parse("*/0 * * * *").is_err());
+        assert!(Schedule::parse("30-10 * * * *").is_err());
+        assert!(Schedule::parse("a * * * *").is_err());
+    }
+
+    #[test]
+    fn is_due_within_the_window() {
+        let schedule = Schedule::parse("*/15 * * * *").unwrap();
+
+        assert!(schedule.is_due(time("2026-10-16T12:14:59Z"), time("2026-10-16T12:15:00Z")));
+        assert!(!schedule.is_due(time("2026-10-16T12:15:00Z"), time("2026-10-16T12:29:59Z")));
+        assert!(schedule.is_due(time("2026-10-16T12:16:00Z"), time("2026-10-16T12:30:00Z")));
+    }
+
+    #[test]
+    fn skips_days_and_hours() {
+        let schedule = Schedule::parse("30 3 1 * *").unwrap();
+
+        assert!(schedule.is_due(time("2026-10-16T00:00:00Z"), time("2026-11-01T03:30:00Z")));
+        assert!(!schedule.is_due(time("2026-10-16T00:00:00Z"), time("2026-11-01T03:29:00Z")));
+    }
+
+    #[test]
+    fn matches_either_restricted_day() {
+        // The 16th of October 2026 is a Friday
+        let schedule = Schedule::parse("0 12 1 * 5").unwrap();
+        assert!(schedule.matches_day(time("2026-10-16T12:00:00Z")));
+        assert!(schedule.matches_day(time("2026-10-01T12:00:00Z")));
+        assert!(!schedule.matches_day(time("2026-10-15T12:00:00Z")));
+
+        let schedule = Schedule::parse("0 12 * * 5").unwrap();
+        assert!(schedule.matches_day(time("2026-10-16T12:00:00Z")));
+        assert!(!schedule.matches_day(time("2026-10-01T12:00:00Z")));
+    }
+
+    #[test]
+    fn treats_seven_as_sunday() {
+        // The 18th of October 2026 is a Sunday
+        let schedule = Schedule::parse("0 0 * * 7").unwrap();
+        assert!(schedule.matches_day(time("2026-10-18T00:00:00Z")));
+        assert_eq!(schedule, Schedule::parse("0 0 * * 0,7").unwrap());
+    }
+}
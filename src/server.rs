@@ -0,0 +1,889 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use flate2::{write::GzEncoder, Compression};
+use futures::future::{BoxFuture, FutureExt, Shared};
+use serde::Deserialize;
+use subtle::ConstantTimeEq;
+use tokio::sync::broadcast::error::RecvError;
+use warp::http::{header, Response, StatusCode};
+use warp::{Filter, Reply};
+
+use crate::{
+    config::{Config, ServerConfig, WarmUp},
+    export, history,
+    metrics::{self, scope::Scope, Format},
+    reload, targets, Collector,
+};
+
+mod access_log;
+mod rate_limit;
+mod runtime;
+
+use access_log::AccessLog;
+use rate_limit::{RateLimited, RateLimiter};
+use runtime::RuntimeStats;
+
+/// The dashboard page, which renders the history with the history endpoint.
+const UI: &str = include_str!("server/ui.html");
+
+/// The targets page, which renders the status of the targets with the targets endpoint.
+const TARGETS: &str = include_str!("server/targets.html");
+
+/// The time reserved to encode and send the metrics within the scrape timeout of Prometheus.
+const SCRAPE_TIMEOUT_MARGIN: Duration = Duration::from_millis(500);
+
+/// Returns the time available to collect the metrics within the scrape timeout Prometheus sends
+/// in the `X-Prometheus-Scrape-Timeout-Seconds` header.
+fn collection_timeout(scrape_timeout: Option<&str>) -> Option<Duration> {
+    let seconds = scrape_timeout?
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite() && *seconds > 0.0)?;
+    Some(Duration::from_secs_f64(seconds).saturating_sub(SCRAPE_TIMEOUT_MARGIN))
+}
+
+/// A collection cycle running in its own task, which concurrent scrapes wait for together.
+type Cycle = Shared<BoxFuture<'static, ()>>;
+
+lazy_static! {
+    /// The collection cycles in progress, keyed by metric group, `None` for all targets.
+    static ref CYCLES: Mutex<HashMap<Option<String>, Cycle>> = Mutex::new(HashMap::new());
+}
+
+/// Starts a collection cycle over all targets or those of the metric group, or joins the cycle in
+/// progress, and waits for it, but at most for the timeout if set.
+///
+/// The cycle runs in its own task, so it still completes after the timeout and its metrics are
+/// served by the next scrape, without the next scrape starting another cycle meanwhile.
+async fn collect_within(
+    collector: Arc<Collector>,
+    group: Option<String>,
+    timeout: Option<Duration>,
+) {
+    let cycle = CYCLES
+        .lock()
+        .unwrap()
+        .entry(group.clone())
+        .or_insert_with(|| {
+            let key = group.clone();
+            let collection = tokio::spawn(async move {
+                match &group {
+                    Some(name) => {
+                        let config = collector.config();
+                        if let Some(group) = config
+                            .metric_groups
+                            .iter()
+                            .find(|group| &group.name == name)
+                        {
+                            collector.collect_group(group).await;
+                        }
+                    }
+                    None => collector.collect().await,
+                }
+            });
+            // Removed even if the cycle panics, so the next scrape starts a new one
+            let task = tokio::spawn(async move {
+                collection.await.ok();
+                CYCLES.lock().unwrap().remove(&key);
+            });
+            task.map(|_| ()).boxed().shared()
+        })
+        .clone();
+
+    let Some(timeout) = timeout else {
+        cycle.await;
+        return;
+    };
+
+    if tokio::time::timeout(timeout, cycle).await.is_err() {
+        log!(
+            "Scrape timeout reached after {:?}, serving the metrics collected so far",
+            timeout
+        );
+    }
+}
+
+/// Returns the quality of the parameters of a header element, `1` if it isn't set.
+fn quality(params: &[String]) -> f64 {
+    params
+        .iter()
+        .find_map(|param| param.strip_prefix("q="))
+        .and_then(|quality| quality.parse::<f64>().ok())
+        .unwrap_or(1.0)
+}
+
+/// Returns whether the client accepts gzip encoded responses.
+///
+/// An explicit `gzip` takes precedence over `*`, and a quality of zero, e.g. `gzip;q=0.0`, marks
+/// the encoding as not acceptable.
+fn accepts_gzip(accept_encoding: Option<&str>) -> bool {
+    let Some(accept_encoding) = accept_encoding else {
+        return false;
+    };
+
+    let mut gzip_quality = None;
+    let mut wildcard_quality = None;
+    for encoding in accept_encoding.split(',') {
+        let mut parts = encoding.split(';').map(str::trim);
+        let name = parts.next().unwrap_or_default();
+        let params: Vec<String> = parts.map(|param| param.replace(' ', "")).collect();
+
+        if name.eq_ignore_ascii_case("gzip") {
+            gzip_quality = Some(quality(&params));
+        } else if name == "*" {
+            wildcard_quality = Some(quality(&params));
+        }
+    }
+
+    gzip_quality
+        .or(wildcard_quality)
+        .is_some_and(|quality| quality > 0.0)
+}
+
+/// Returns the exposition format preferred by the client, the text format unless it accepts the
+/// protobuf or OpenMetrics format with a higher or equal quality.
+///
+/// On equal qualities, the protobuf format is preferred over the OpenMetrics format.
+fn negotiate_format(accept: Option<&str>) -> Format {
+    let Some(accept) = accept else {
+        return Format::Text;
+    };
+
+    let mut protobuf_quality = 0.0;
+    let mut openmetrics_quality = 0.0_f64;
+    let mut text_quality = 0.0_f64;
+    for media_range in accept.split(',') {
+        let mut parts = media_range.split(';').map(str::trim);
+        let media_type = parts.next().unwrap_or_default();
+        let params: Vec<String> = parts.map(|param| param.replace(' ', "")).collect();
+        let quality = quality(&params);
+
+        match media_type {
+            // Only the length-delimited metric families are supported
+            "application/vnd.google.protobuf" => {
+                let delimited = params.iter().any(|param| param == "encoding=delimited");
+                let families = params
+                    .iter()
+                    .any(|param| param == "proto=io.prometheus.client.MetricFamily");
+                if delimited && families {
+                    protobuf_quality = quality;
+                }
+            }
+            // Only version 1.0.0 of OpenMetrics is supported
+            "application/openmetrics-text" => {
+                let version = params
+                    .iter()
+                    .find_map(|param| param.strip_prefix("version="));
+                if version.is_none_or(|version| version == "1.0.0") {
+                    openmetrics_quality = openmetrics_quality.max(quality);
+                }
+            }
+            "text/plain" | "text/*" | "*/*" => {
+                text_quality = text_quality.max(quality);
+            }
+            _ => {}
+        }
+    }
+
+    if protobuf_quality > 0.0
+        && protobuf_quality >= openmetrics_quality
+        && protobuf_quality >= text_quality
+    {
+        Format::Protobuf
+    } else if openmetrics_quality > 0.0 && openmetrics_quality >= text_quality {
+        Format::OpenMetrics
+    } else {
+        Format::Text
+    }
+}
+
+fn gzip(body: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(body).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Returns a `503 Service Unavailable` response if the first collection cycle is still warming
+/// up in the background.
+fn warming_up(collector: &Collector) -> Option<Response<Vec<u8>>> {
+    if collector.config().server.warm_up != WarmUp::Unavailable || collector.has_collected() {
+        return None;
+    }
+
+    Some(
+        Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .body(b"Warming up".to_vec())
+            .unwrap(),
+    )
+}
+
+/// The query parameter of endpoints which serve the series of a metric group if given.
+#[derive(Deserialize)]
+struct GroupQuery {
+    group: Option<String>,
+}
+
+/// Whether a request may access the series of its scope.
+enum Access {
+    Granted(Scope),
+    Unauthorized,
+}
+
+/// Returns the access of a request to the series of the metric group, authenticated with the
+/// token of the group if it has one, or to the series of the instances of no metric group.
+fn access(
+    config: &Config,
+    group: Option<&str>,
+    authorization: Option<&str>,
+) -> Result<Access, warp::Rejection> {
+    let Some(name) = group else {
+        return Ok(Access::Granted(Scope::ungrouped(config)));
+    };
+    let Some(group) = config.metric_groups.iter().find(|group| group.name == name) else {
+        return Err(warp::reject::not_found());
+    };
+
+    if let Some(access_token) = group.access_token.get() {
+        // Compared in constant time, so the token can't be guessed by timing responses
+        let expected = format!("Bearer {}", access_token);
+        let authorized: bool = authorization
+            .unwrap_or_default()
+            .as_bytes()
+            .ct_eq(expected.as_bytes())
+            .into();
+        if !authorized {
+            return Ok(Access::Unauthorized);
+        }
+    }
+
+    Ok(Access::Granted(Scope::group(group)))
+}
+
+fn unauthorized<T: From<&'static str>>() -> Response<T> {
+    Response::builder()
+        .status(StatusCode::UNAUTHORIZED)
+        .header(header::WWW_AUTHENTICATE, "Bearer")
+        .body(T::from("Unauthorized"))
+        .unwrap()
+}
+
+/// Serves the series of all instances except those of metric groups.
+async fn metrics(
+    collector: Arc<Collector>,
+    scrape_timeout: Option<String>,
+    accept: Option<String>,
+    accept_encoding: Option<String>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if let Some(response) = warming_up(&collector) {
+        return Ok(response);
+    }
+
+    let start = Instant::now();
+
+    log!("Collecting metrics...");
+
+    collect_within(
+        collector.clone(),
+        None,
+        collection_timeout(scrape_timeout.as_deref()),
+    )
+    .await;
+
+    log!("Collecting all metrics done in {:?}", start.elapsed());
+    log!();
+
+    let format = negotiate_format(accept.as_deref());
+    Ok(exposition(
+        metrics::encode_as(&Scope::ungrouped(&collector.config()).registry(), format),
+        format,
+        collector.config().server.compression,
+        accept_encoding.as_deref(),
+    ))
+}
+
+/// Returns a response with the exposition, compressed if enabled and accepted by the client.
+fn exposition(
+    body: Vec<u8>,
+    format: Format,
+    compression: bool,
+    accept_encoding: Option<&str>,
+) -> Response<Vec<u8>> {
+    let response = Response::builder().header(header::CONTENT_TYPE, format.content_type());
+
+    if compression && accepts_gzip(accept_encoding) {
+        return response
+            .header(header::CONTENT_ENCODING, "gzip")
+            .body(gzip(&body))
+            .unwrap();
+    }
+
+    response.body(body).unwrap()
+}
+
+/// Serves the metrics of the instances of a metric group, authenticated with its token.
+async fn group_metrics(
+    name: String,
+    collector: Arc<Collector>,
+    authorization: Option<String>,
+    scrape_timeout: Option<String>,
+    accept: Option<String>,
+    accept_encoding: Option<String>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let config = collector.config();
+    let Access::Granted(scope) = access(&config, Some(&name), authorization.as_deref())? else {
+        return Ok(unauthorized());
+    };
+    let Some(group) = config.metric_groups.iter().find(|group| group.name == name) else {
+        return Err(warp::reject::not_found());
+    };
+
+    if let Some(response) = warming_up(&collector) {
+        return Ok(response);
+    }
+
+    let start = Instant::now();
+    log!("Collecting metrics of group {}...", group.name);
+
+    collect_within(
+        collector.clone(),
+        Some(group.name.clone()),
+        collection_timeout(scrape_timeout.as_deref()),
+    )
+    .await;
+
+    log!(
+        "Collecting metrics of group {} done in {:?}",
+        group.name,
+        start.elapsed()
+    );
+    log!();
+
+    let format = negotiate_format(accept.as_deref());
+    Ok(exposition(
+        metrics::encode_as(&scope.registry(), format),
+        format,
+        collector.config().server.compression,
+        accept_encoding.as_deref(),
+    ))
+}
+
+/// The query parameters of the history endpoint.
+#[derive(Deserialize)]
+struct HistoryQuery {
+    /// The full name of the metric, e.g. `mastodon_account_followers_count`.
+    metric: String,
+
+    /// The range to return values of, e.g. `30m`, `12h` or `7d`.
+    #[serde(default = "HistoryQuery::default_range")]
+    range: String,
+
+    /// The metric group to return values of.
+    group: Option<String>,
+}
+
+impl HistoryQuery {
+    fn default_range() -> String {
+        "1d".to_string()
+    }
+}
+
+async fn history(
+    collector: Arc<Collector>,
+    query: HistoryQuery,
+    authorization: Option<String>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let Some(history) = collector.history() else {
+        return Err(warp::reject::not_found());
+    };
+    let config = collector.config();
+    let Access::Granted(scope) = access(&config, query.group.as_deref(), authorization.as_deref())?
+    else {
+        return Ok(unauthorized());
+    };
+    let Some(range_seconds) = history::parse_range(&query.range) else {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(format!("Invalid range {}", query.range))
+            .unwrap());
+    };
+
+    match history.query(&query.metric, range_seconds).await {
+        Ok(mut series) => {
+            series.retain(|series| {
+                scope.includes(
+                    series
+                        .labels
+                        .iter()
+                        .map(|(name, value)| (name.as_str(), value.as_str())),
+                )
+            });
+            Ok(Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(serde_json::to_string(&series).unwrap())
+                .unwrap())
+        }
+        Err(err) => Ok(Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(format!("Unable to query history: {}", err))
+            .unwrap()),
+    }
+}
+
+/// Streams the values of every completed collection cycle as server-sent events.
+async fn events(
+    collector: Arc<Collector>,
+    query: GroupQuery,
+    authorization: Option<String>,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let config = collector.config();
+    let Access::Granted(scope) = access(&config, query.group.as_deref(), authorization.as_deref())?
+    else {
+        return Ok(unauthorized::<String>().into_response());
+    };
+
+    let receiver = collector.subscribe();
+    let stream = futures::stream::unfold((receiver, scope), |(mut receiver, scope)| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    let samples: Vec<_> = event
+                        .samples
+                        .iter()
+                        .filter(|sample| {
+                            scope.includes(
+                                sample
+                                    .labels
+                                    .iter()
+                                    .map(|(name, value)| (name.as_str(), value.as_str())),
+                            )
+                        })
+                        .collect();
+                    let event = warp::sse::Event::default()
+                        .event("collection")
+                        .json_data(serde_json::json!({
+                            "timestamp": event.timestamp,
+                            "samples": samples,
+                        }))
+                        .unwrap();
+                    return Some((Ok::<_, Infallible>(event), (receiver, scope)));
+                }
+                // Slow clients skip the events they missed
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(stream)).into_response())
+}
+
+/// The query parameters of the CSV export endpoint.
+#[derive(Deserialize)]
+struct ExportQuery {
+    /// The full name of a metric to export, all metrics are exported if not set.
+    metric: Option<String>,
+
+    /// The range of the history to export, e.g. `30m`, `12h` or `7d`.
+    #[serde(default = "HistoryQuery::default_range")]
+    range: String,
+
+    /// The metric group to export values of.
+    group: Option<String>,
+}
+
+async fn export_csv(
+    collector: Arc<Collector>,
+    query: ExportQuery,
+    authorization: Option<String>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let config = collector.config();
+    let Access::Granted(scope) = access(&config, query.group.as_deref(), authorization.as_deref())?
+    else {
+        return Ok(unauthorized());
+    };
+
+    match export::csv(&collector, &scope, query.metric.as_deref(), &query.range).await {
+        Ok(csv) => Ok(Response::builder()
+            .header(header::CONTENT_TYPE, "text/csv")
+            .body(csv)
+            .unwrap()),
+        Err(err) => Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(err)
+            .unwrap()),
+    }
+}
+
+/// Turns rejections of rate limited requests into `429 Too Many Requests` responses.
+async fn recover(rejection: warp::Rejection) -> Result<impl warp::Reply, warp::Rejection> {
+    if let Some(rate_limited) = rejection.find::<RateLimited>() {
+        return Ok(Response::builder()
+            .status(StatusCode::TOO_MANY_REQUESTS)
+            .header(header::RETRY_AFTER, rate_limited.retry_after_seconds)
+            .body("Too many requests")
+            .unwrap());
+    }
+
+    Err(rejection)
+}
+
+/// Rejects all requests as not found unless the route is enabled.
+fn enabled(enabled: bool) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::any()
+        .and_then(move || async move {
+            if enabled {
+                Ok(())
+            } else {
+                Err(warp::reject::not_found())
+            }
+        })
+        .untuple_one()
+}
+
+/// The reason of failing probes if the background collection cycles are stale.
+const STALE: &str = "Stale, no collection cycle completed in time";
+
+/// Returns `200 OK`, or `503 Service Unavailable` with the reason if failing.
+fn probe(failure: Option<&'static str>) -> Response<&'static str> {
+    match failure {
+        None => Response::new("OK"),
+        Some(reason) => Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .body(reason)
+            .unwrap(),
+    }
+}
+
+/// Returns why the exporter isn't alive, if the background collection cycles are stale.
+fn not_alive(collector: &Collector) -> Option<&'static str> {
+    let config = collector.config();
+    collector
+        .is_stale(config.server.stale_after_cycles)
+        .then_some(STALE)
+}
+
+/// Returns why the exporter isn't ready to be scraped, if it isn't.
+fn not_ready(collector: &Collector) -> Option<&'static str> {
+    not_alive(collector).or_else(|| {
+        (collector.config().server.warm_up == WarmUp::Unavailable && !collector.has_collected())
+            .then_some("Warming up")
+    })
+}
+
+/// Reloads the configuration, responding with the error if the new one is invalid.
+async fn reload() -> Result<Response<String>, Infallible> {
+    let response = match reload::request().await {
+        Ok(()) => Response::new("Reloaded".to_string()),
+        Err(err) => Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(format!("Unable to reload: {}", err))
+            .unwrap(),
+    };
+
+    Ok(response)
+}
+
+/// Serves the status of the targets of the metric group, or of the instances of no metric group.
+async fn target_statuses(
+    collector: Arc<Collector>,
+    query: GroupQuery,
+    authorization: Option<String>,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let config = collector.config();
+    let Access::Granted(scope) = access(&config, query.group.as_deref(), authorization.as_deref())?
+    else {
+        return Ok(unauthorized::<String>().into_response());
+    };
+
+    let mut statuses = targets::statuses();
+    statuses.retain(|status| scope.includes([("instance", status.instance.as_str())]));
+    Ok(warp::reply::json(&statuses).into_response())
+}
+
+/// Routes of operational endpoints, served on the admin port if configured.
+///
+/// The targets and the configuration reveal the errors of every target and the configuration
+/// without its secrets, and `/-/reload` reloads the configuration, so they're only served on the
+/// admin port if there is one.
+///
+/// `/livez` fails once the background collection cycles are stale, so Kubernetes restarts a
+/// wedged exporter, and `/readyz` also fails while warming up.
+fn admin_routes(
+    collector: Arc<Collector>,
+    server_config: &ServerConfig,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let with_collector = warp::any().map(move || collector.clone());
+
+    let healthz = warp::path("healthz").and(warp::path::end()).map(|| "OK");
+    let livez = warp::path("livez")
+        .and(warp::path::end())
+        .and(with_collector.clone())
+        .map(|collector: Arc<Collector>| probe(not_alive(&collector)));
+    let readyz = warp::path("readyz")
+        .and(warp::path::end())
+        .and(with_collector.clone())
+        .map(|collector: Arc<Collector>| probe(not_ready(&collector)));
+    let runtime = warp::path!("debug" / "runtime")
+        .and(enabled(server_config.runtime_diagnostics))
+        .map(|| warp::reply::json(&RuntimeStats::current()));
+    let targets = warp::path!("api" / "v1" / "targets")
+        .and(with_collector.clone())
+        .and(warp::query::<GroupQuery>())
+        .and(warp::header::optional::<String>("authorization"))
+        .and_then(target_statuses)
+        .or(warp::path("targets")
+            .and(warp::path::end())
+            .map(|| warp::reply::html(TARGETS)));
+    let config = warp::path("config")
+        .and(warp::path::end())
+        .and(with_collector)
+        .map(|collector: Arc<Collector>| {
+            Response::builder()
+                .header(header::CONTENT_TYPE, "text/yaml; charset=utf-8")
+                .body(collector.config().to_redacted_yaml())
+                .unwrap()
+        });
+    // Like the reload endpoint of Prometheus, which only accepts `POST` and `PUT`
+    let reload = warp::path!("-" / "reload")
+        .and(warp::post().or(warp::put()).unify())
+        .and_then(reload);
+
+    warp::get()
+        .and(
+            healthz
+                .or(livez)
+                .or(readyz)
+                .or(runtime)
+                .or(targets)
+                .or(config),
+        )
+        .or(reload)
+}
+
+/// Serves the metrics of the collector until the process exits.
+///
+/// Operational endpoints are served on the admin port if configured, otherwise on the same port.
+pub async fn run(collector: Arc<Collector>) {
+    let config = collector.config();
+    let server_config = &config.server;
+
+    // Complete the first collection cycle as configured, so no scrape returns empty metrics
+    match server_config.warm_up {
+        WarmUp::Off => {}
+        WarmUp::Collect => {
+            log!("Warming up...");
+            collector.collect().await;
+        }
+        WarmUp::Unavailable => {
+            let collector = collector.clone();
+            tokio::spawn(async move {
+                log!("Warming up in the background...");
+                collector.collect().await;
+            });
+        }
+    }
+
+    let port = server_config.http_listen_port;
+    let admin_port = server_config.admin_listen_port;
+    let rate_limiter = server_config
+        .rate_limit
+        .as_ref()
+        .map(|rate_limit| Arc::new(RateLimiter::new(rate_limit)));
+    // An unwritable access log file shouldn't keep the metrics from being served
+    let access_log = if server_config.access_log {
+        match AccessLog::open(server_config.access_log_file.as_deref()) {
+            Ok(access_log) => Some(access_log),
+            Err(err) => {
+                log!(
+                    "Error: unable to open access log, not logging requests: {}",
+                    err
+                );
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let log = warp::log::custom(move |info| {
+        if let Some(access_log) = &access_log {
+            access_log.log(info);
+        }
+    });
+
+    let admin_routes = admin_routes(collector.clone(), server_config);
+    let ui_enabled = server_config.ui;
+
+    let with_collector = warp::any().map(move || collector.clone());
+
+    let metrics_route = warp::get().and(
+        warp::path("metrics")
+            .and(warp::path::end())
+            .and(rate_limit::filter(rate_limiter.clone()))
+            .and(with_collector.clone())
+            .and(warp::header::optional::<String>(
+                "x-prometheus-scrape-timeout-seconds",
+            ))
+            .and(warp::header::optional::<String>("accept"))
+            .and(warp::header::optional::<String>("accept-encoding"))
+            .and_then(metrics),
+    );
+    let group_metrics_route = warp::get().and(
+        warp::path!("metrics" / String)
+            .and(rate_limit::filter(rate_limiter))
+            .and(with_collector.clone())
+            .and(warp::header::optional::<String>("authorization"))
+            .and(warp::header::optional::<String>(
+                "x-prometheus-scrape-timeout-seconds",
+            ))
+            .and(warp::header::optional::<String>("accept"))
+            .and(warp::header::optional::<String>("accept-encoding"))
+            .and_then(group_metrics),
+    );
+    let history_route = warp::get().and(
+        warp::path!("api" / "v1" / "history")
+            .and(with_collector.clone())
+            .and(warp::query::<HistoryQuery>())
+            .and(warp::header::optional::<String>("authorization"))
+            .and_then(history),
+    );
+    let export_route = warp::get().and(
+        warp::path!("export.csv")
+            .and(with_collector.clone())
+            .and(warp::query::<ExportQuery>())
+            .and(warp::header::optional::<String>("authorization"))
+            .and_then(export_csv),
+    );
+    let events_route = warp::get().and(
+        warp::path("events")
+            .and(warp::path::end())
+            .and(with_collector.clone())
+            .and(warp::query::<GroupQuery>())
+            .and(warp::header::optional::<String>("authorization"))
+            .and_then(events),
+    );
+    let ui_route = warp::get().and(
+        warp::path("ui")
+            .and(warp::path::end())
+            .and(enabled(ui_enabled))
+            .map(|| warp::reply::html(UI)),
+    );
+    let routes = metrics_route
+        .or(group_metrics_route)
+        .or(history_route)
+        .or(export_route)
+        .or(events_route)
+        .or(ui_route);
+
+    match admin_port {
+        Some(admin_port) => {
+            let routes = routes.recover(recover).with(log.clone());
+            let admin_routes = admin_routes.with(log);
+
+            tokio::join!(
+                warp::serve(routes).run(([127, 0, 0, 1], port)),
+                warp::serve(admin_routes).run(([127, 0, 0, 1], admin_port)),
+            );
+        }
+        None => {
+            let routes = routes.or(admin_routes).recover(recover).with(log);
+
+            warp::serve(routes).run(([127, 0, 0, 1], port)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_gzip_if_listed() {
+        assert!(accepts_gzip(Some("gzip")));
+        assert!(accepts_gzip(Some("deflate, gzip;q=0.5")));
+        assert!(accepts_gzip(Some("br, *")));
+        assert!(!accepts_gzip(None));
+        assert!(!accepts_gzip(Some("")));
+        assert!(!accepts_gzip(Some("deflate, br")));
+    }
+
+    #[test]
+    fn rejects_gzip_with_zero_quality() {
+        assert!(!accepts_gzip(Some("gzip;q=0")));
+        assert!(!accepts_gzip(Some("gzip; q=0.0")));
+        assert!(!accepts_gzip(Some("gzip;q=0.000, deflate")));
+        assert!(!accepts_gzip(Some("*;q=0")));
+        assert!(!accepts_gzip(Some("gzip;q=0, *")));
+        assert!(accepts_gzip(Some("gzip;q=0.1, *;q=0")));
+    }
+
+    #[test]
+    fn negotiates_the_protobuf_format() {
+        let protobuf = "application/vnd.google.protobuf;proto=io.prometheus.client.MetricFamily;encoding=delimited";
+
+        assert!(matches!(negotiate_format(Some(protobuf)), Format::Protobuf));
+        assert!(matches!(
+            negotiate_format(Some(&format!(
+                "{};q=0.7,text/plain;version=0.0.4;q=0.3",
+                protobuf
+            ))),
+            Format::Protobuf
+        ));
+    }
+
+    #[test]
+    fn negotiates_the_openmetrics_format() {
+        // The default of Prometheus
+        assert!(matches!(
+            negotiate_format(Some("application/openmetrics-text;version=1.0.0,application/openmetrics-text;version=0.0.1;q=0.75,text/plain;version=0.0.4;q=0.5,*/*;q=0.1")),
+            Format::OpenMetrics
+        ));
+        assert!(matches!(
+            negotiate_format(Some("application/openmetrics-text")),
+            Format::OpenMetrics
+        ));
+        assert!(matches!(
+            negotiate_format(Some(
+                "application/openmetrics-text;version=0.0.1,text/plain;q=0.5"
+            )),
+            Format::Text
+        ));
+        assert!(matches!(
+            negotiate_format(Some("application/openmetrics-text;q=0.5,text/plain")),
+            Format::Text
+        ));
+    }
+
+    #[test]
+    fn negotiates_the_text_format() {
+        assert!(matches!(negotiate_format(None), Format::Text));
+        assert!(matches!(negotiate_format(Some("*/*")), Format::Text));
+        assert!(matches!(
+            negotiate_format(Some("application/vnd.google.protobuf;encoding=text")),
+            Format::Text
+        ));
+        assert!(matches!(
+            negotiate_format(Some(
+                "application/vnd.google.protobuf;proto=io.prometheus.client.MetricFamily;encoding=delimited;q=0.5,text/plain;q=0.9"
+            )),
+            Format::Text
+        ));
+    }
+
+    #[test]
+    fn parses_the_scrape_timeout() {
+        assert_eq!(
+            collection_timeout(Some("10")),
+            Some(Duration::from_millis(9500))
+        );
+        assert_eq!(collection_timeout(Some("0.2")), Some(Duration::ZERO));
+        assert_eq!(collection_timeout(Some("-1")), None);
+        assert_eq!(collection_timeout(Some("NaN")), None);
+        assert_eq!(collection_timeout(None), None);
+    }
+}
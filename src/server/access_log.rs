@@ -0,0 +1,48 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use warp::log::Info;
+
+/// Where the access log is written to.
+#[derive(Clone)]
+pub enum AccessLog {
+    Stdout,
+    File(Arc<Mutex<File>>),
+}
+
+impl AccessLog {
+    /// Opens the access log file for appending, or logs to stdout if no file is given.
+    pub fn open(file: Option<&str>) -> io::Result<Self> {
+        match file {
+            Some(path) => {
+                let file = OpenOptions::new().create(true).append(true).open(path)?;
+                Ok(AccessLog::File(Arc::new(Mutex::new(file))))
+            }
+            None => Ok(AccessLog::Stdout),
+        }
+    }
+
+    /// Logs a request with its remote address, method, path, status and duration.
+    pub fn log(&self, info: Info<'_>) {
+        let line = format!(
+            "{} {} \"{} {}\" {} {:?}",
+            chrono::Utc::now().to_rfc3339(),
+            info.remote_addr()
+                .map_or_else(|| "-".to_string(), |addr| addr.to_string()),
+            info.method(),
+            info.path(),
+            info.status().as_u16(),
+            info.elapsed()
+        );
+
+        match self {
+            AccessLog::Stdout => log!("{}", line),
+            AccessLog::File(file) => {
+                if let Err(err) = writeln!(file.lock().unwrap(), "{}", line) {
+                    log!("Error: Writing access log failed: {}", err);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use warp::Filter;
+
+use crate::config::RateLimitConfig;
+
+/// The number of tracked clients after which idle clients are forgotten.
+const MAX_TRACKED_CLIENTS: usize = 1024;
+
+/// Rejection of a request which exceeded the rate limit of its client.
+#[derive(Debug)]
+pub struct RateLimited {
+    /// The number of seconds until the client may send the next request.
+    pub retry_after_seconds: u64,
+}
+
+impl warp::reject::Reject for RateLimited {}
+
+/// The token bucket of a client.
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// Limits the request rate per client IP address using token buckets.
+pub struct RateLimiter {
+    capacity: f64,
+    refill_per_second: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        let capacity = f64::from(config.requests.max(1));
+
+        Self {
+            capacity,
+            refill_per_second: capacity / config.period_seconds.max(1) as f64,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token of the client, or returns the duration until a token is available.
+    fn acquire(&self, ip: IpAddr) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let now = Instant::now();
+
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            let full_after = Duration::from_secs_f64(self.capacity / self.refill_per_second);
+            buckets.retain(|_, bucket| now - bucket.refilled_at < full_after);
+        }
+
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.capacity,
+            refilled_at: now,
+        });
+
+        let elapsed = (now - bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_per_second).min(self.capacity);
+        bucket.refilled_at = now;
+
+        if bucket.tokens < 1.0 {
+            let missing = 1.0 - bucket.tokens;
+            return Err(Duration::from_secs_f64(missing / self.refill_per_second));
+        }
+
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+/// Rejects requests of clients which exceeded the rate limit, if one is configured.
+pub fn filter(
+    limiter: Option<Arc<RateLimiter>>,
+) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::addr::remote()
+        .and_then(move |addr: Option<SocketAddr>| {
+            let limiter = limiter.clone();
+            async move {
+                match (limiter, addr) {
+                    (Some(limiter), Some(addr)) => {
+                        limiter.acquire(addr.ip()).map_err(|retry_after| {
+                            log!("Rate limited request from {}", addr.ip());
+                            warp::reject::custom(RateLimited {
+                                retry_after_seconds: retry_after.as_secs() + 1,
+                            })
+                        })
+                    }
+                    _ => Ok(()),
+                }
+            }
+        })
+        .untuple_one()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(requests: u32, period_seconds: u64) -> RateLimiter {
+        RateLimiter::new(&RateLimitConfig {
+            requests,
+            period_seconds,
+        })
+    }
+
+    #[test]
+    fn limits_each_client_separately() {
+        let limiter = limiter(2, 60);
+        let client: IpAddr = "192.0.2.1".parse().unwrap();
+        let other: IpAddr = "192.0.2.2".parse().unwrap();
+
+        assert!(limiter.acquire(client).is_ok());
+        assert!(limiter.acquire(client).is_ok());
+        assert!(limiter.acquire(client).is_err());
+        assert!(limiter.acquire(other).is_ok());
+    }
+
+    #[test]
+    fn returns_the_time_until_the_next_token() {
+        let limiter = limiter(1, 60);
+        let client: IpAddr = "2001:db8::1".parse().unwrap();
+
+        assert!(limiter.acquire(client).is_ok());
+        let retry_after = limiter.acquire(client).unwrap_err();
+        assert!(retry_after > Duration::from_secs(59));
+        assert!(retry_after <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn forgets_idle_clients() {
+        let limiter = limiter(1, 1);
+        for i in 0..MAX_TRACKED_CLIENTS as u32 {
+            limiter.acquire(IpAddr::from(i.to_be_bytes())).unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(1100));
+
+        limiter.acquire("192.0.2.1".parse().unwrap()).unwrap();
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 1);
+    }
+}
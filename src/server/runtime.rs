@@ -0,0 +1,57 @@
+use serde::Serialize;
+use tokio::runtime::Handle;
+
+/// Scheduler statistics of a single worker thread.
+#[derive(Serialize)]
+struct WorkerStats {
+    /// The number of seconds the worker has been busy polling tasks.
+    busy_seconds: f64,
+
+    /// The number of times the worker has parked, because it ran out of tasks.
+    park_count: u64,
+
+    /// Whether the worker is currently active instead of parked.
+    ///
+    /// A worker which stays active with an unchanged park count across several requests is likely
+    /// blocked.
+    active: bool,
+}
+
+/// A snapshot of the statistics of the Tokio runtime.
+#[derive(Serialize)]
+pub struct RuntimeStats {
+    /// The number of worker threads.
+    workers: usize,
+
+    /// The number of tasks which are currently alive.
+    alive_tasks: usize,
+
+    /// The number of tasks waiting in the global queue to be picked up by a worker.
+    global_queue_depth: usize,
+
+    /// The statistics of each worker thread.
+    worker_stats: Vec<WorkerStats>,
+}
+
+impl RuntimeStats {
+    /// Takes a snapshot of the statistics of the current runtime.
+    pub fn current() -> Self {
+        let metrics = Handle::current().metrics();
+
+        let worker_stats = (0..metrics.num_workers())
+            .map(|worker| WorkerStats {
+                busy_seconds: metrics.worker_total_busy_duration(worker).as_secs_f64(),
+                park_count: metrics.worker_park_count(worker),
+                // The park/unpark count is even while the worker is unparked
+                active: metrics.worker_park_unpark_count(worker).is_multiple_of(2),
+            })
+            .collect();
+
+        Self {
+            workers: metrics.num_workers(),
+            alive_tasks: metrics.num_alive_tasks(),
+            global_queue_depth: metrics.global_queue_depth(),
+            worker_stats,
+        }
+    }
+}
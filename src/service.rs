@@ -0,0 +1,94 @@
+//! Running as a Windows service, started and stopped by the service control manager.
+//!
+//! The service is registered with `--service` in its command line, e.g. with
+//! `sc.exe create mastodon_exporter binPath= "C:\mastodon_exporter\mastodon_exporter.exe --service --config C:\mastodon_exporter\mastodon_exporter.yml"`.
+
+use std::ffi::OsString;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use windows_service::{
+    define_windows_service,
+    service::{
+        ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus,
+        ServiceType,
+    },
+    service_control_handler::{self, ServiceControlHandlerResult},
+    service_dispatcher,
+};
+
+use crate::signal::{SERVICE_RELOAD, SERVICE_STOP};
+
+/// The name of the service, which is ignored for services running in their own process.
+const NAME: &str = "mastodon_exporter";
+
+/// The function running the exporter until it shuts down.
+static EXPORTER: OnceLock<fn()> = OnceLock::new();
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// Runs the exporter as a service, which returns once the service stopped.
+///
+/// This fails if the process wasn't started by the service control manager.
+pub fn run(exporter: fn()) -> windows_service::Result<()> {
+    EXPORTER.set(exporter).ok();
+    service_dispatcher::start(NAME, ffi_service_main)
+}
+
+fn status(state: ServiceState, controls_accepted: ServiceControlAccept) -> ServiceStatus {
+    ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state: state,
+        controls_accepted,
+        exit_code: ServiceExitCode::Win32(0),
+        checkpoint: 0,
+        wait_hint: Duration::default(),
+        process_id: None,
+    }
+}
+
+/// Runs the exporter in the thread the service control manager started the service in.
+fn service_main(_arguments: Vec<OsString>) {
+    // Stopping and reloading are handled like the shutdown and reload signals
+    let handler = |control: ServiceControl| match control {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            SERVICE_STOP.notify_one();
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::ParamChange => {
+            SERVICE_RELOAD.notify_one();
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    };
+    let status_handle = match service_control_handler::register(NAME, handler) {
+        Ok(status_handle) => status_handle,
+        Err(err) => {
+            log!(
+                "Error: Unable to register the service control handler: {}",
+                err
+            );
+            return;
+        }
+    };
+
+    let running = status(
+        ServiceState::Running,
+        ServiceControlAccept::STOP
+            | ServiceControlAccept::SHUTDOWN
+            | ServiceControlAccept::PARAM_CHANGE,
+    );
+    if let Err(err) = status_handle.set_service_status(running) {
+        log!("Error: Unable to report the service as running: {}", err);
+    }
+
+    if let Some(exporter) = EXPORTER.get() {
+        exporter();
+    }
+
+    let stopped = status(ServiceState::Stopped, ServiceControlAccept::empty());
+    if let Err(err) = status_handle.set_service_status(stopped) {
+        log!("Error: Unable to report the service as stopped: {}", err);
+    }
+}
@@ -0,0 +1,69 @@
+use crate::{config::Config, discovery::hash};
+
+/// Returns whether the target with the given key belongs to the shard.
+fn owns(key: &str, total: usize, index: usize) -> bool {
+    hash(key) % total as u64 == index as u64
+}
+
+/// Removes the targets which belong to other shards from the configuration.
+///
+/// Accounts are distributed individually, so the accounts of a large instance are spread across
+/// all replicas. All other targets are distributed by instance. Targets are hashed by their
+/// name, so every replica agrees on the shards without coordination.
+pub fn apply(config: &mut Config) {
+    let Some(sharding) = config.sharding.clone() else {
+        return;
+    };
+    let (total, index) = (sharding.total, sharding.index.unwrap_or_default());
+    let owns_instance = |instance: &str| owns(instance, total, index);
+
+    let instances = config.instance_info.len();
+    let accounts = config.accounts.len();
+
+    config
+        .instance_info
+        .retain(|instance| owns_instance(&instance.instance));
+    config.accounts.retain(|account| {
+        owns(
+            &format!("{}/{}", account.instance, account.key()),
+            total,
+            index,
+        )
+    });
+    config.polls.retain(|poll| owns_instance(&poll.instance));
+    config
+        .posting_rate
+        .retain(|instance| owns_instance(instance));
+    config.peers.retain(|instance| owns_instance(instance));
+    config
+        .streaming
+        .retain(|streaming| owns_instance(&streaming.instance));
+    config
+        .search_probes
+        .retain(|probe| owns_instance(&probe.instance));
+    config
+        .resolve_probes
+        .retain(|probe| owns_instance(&probe.instance));
+    config
+        .tag_measures
+        .retain(|measures| owns_instance(&measures.instance));
+    config
+        .media_probes
+        .retain(|probe| owns_instance(&probe.instance));
+    config
+        .canaries
+        .retain(|canary| owns_instance(&canary.instance));
+    config
+        .federation_probes
+        .retain(|probe| owns_instance(&probe.from.instance));
+
+    log!(
+        "Shard {} of {}: Collecting {} of {} instances and {} of {} accounts",
+        index,
+        total,
+        config.instance_info.len(),
+        instances,
+        config.accounts.len(),
+        accounts
+    );
+}
@@ -0,0 +1,83 @@
+use std::io;
+
+#[cfg(unix)]
+use tokio::signal::unix::{signal, Signal, SignalKind};
+#[cfg(windows)]
+use tokio::signal::windows::{ctrl_break, ctrl_close, ctrl_shutdown, CtrlBreak};
+#[cfg(windows)]
+use tokio::sync::Notify;
+
+#[cfg(windows)]
+lazy_static! {
+    /// Notified when the service control manager asks the service to stop.
+    pub(crate) static ref SERVICE_STOP: Notify = Notify::new();
+
+    /// Notified when the service control manager tells the service its parameters changed.
+    pub(crate) static ref SERVICE_RELOAD: Notify = Notify::new();
+}
+
+/// Listens for the signal to reload the configuration, `SIGHUP` on Unix and Ctrl+Break on
+/// Windows, which has no hangup signal, or the parameter change control of a Windows service.
+pub struct ReloadSignal {
+    #[cfg(unix)]
+    hangup: Signal,
+    #[cfg(windows)]
+    ctrl_break: CtrlBreak,
+}
+
+impl ReloadSignal {
+    /// Starts listening, which replaces the default handling of the signal, like exiting.
+    ///
+    /// This must be called from within a Tokio runtime.
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            #[cfg(unix)]
+            hangup: signal(SignalKind::hangup())?,
+            #[cfg(windows)]
+            ctrl_break: ctrl_break()?,
+        })
+    }
+
+    /// Waits for the next signal, which never arrives on other platforms.
+    pub async fn recv(&mut self) {
+        #[cfg(unix)]
+        self.hangup.recv().await;
+        #[cfg(windows)]
+        tokio::select! {
+            _ = self.ctrl_break.recv() => {}
+            () = SERVICE_RELOAD.notified() => {}
+        }
+        #[cfg(not(any(unix, windows)))]
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Waits for the signal to shut down, returning its name.
+///
+/// This is Ctrl+C everywhere, `SIGTERM` on Unix, like sent by Docker, Kubernetes or systemd, and
+/// closing the console, shutting down or stopping the service on Windows.
+pub async fn shutdown() -> io::Result<&'static str> {
+    #[cfg(unix)]
+    {
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result.map(|()| "Ctrl+C"),
+            _ = terminate.recv() => Ok("SIGTERM"),
+        }
+    }
+    #[cfg(windows)]
+    {
+        let mut close = ctrl_close()?;
+        let mut shutdown = ctrl_shutdown()?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result.map(|()| "Ctrl+C"),
+            _ = close.recv() => Ok("closing the console"),
+            _ = shutdown.recv() => Ok("system shutdown"),
+            () = SERVICE_STOP.notified() => Ok("service stop"),
+        }
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        tokio::signal::ctrl_c().await.map(|()| "Ctrl+C")
+    }
+}
@@ -0,0 +1,98 @@
+use std::time::Duration;
+
+use futures::StreamExt;
+use serde::Deserialize;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::{
+    config::StreamingConfig,
+    mastodon::domain,
+    metrics::{
+        WithLimitedLabelValues, MASTODON_STREAMING_CONNECTED, MASTODON_STREAMING_EVENTS_TOTAL,
+    },
+};
+
+/// The number of seconds to wait before reconnecting after the connection was lost.
+const RECONNECT_DELAY_SECONDS: u64 = 30;
+
+/// An event received from the streaming API.
+///
+/// [docs.joinmastodon.org/methods/streaming/#events](https://docs.joinmastodon.org/methods/streaming/#events)
+#[derive(Deserialize)]
+struct StreamingEvent {
+    /// The type of the event, e.g. `update`, `delete` or `status.update`.
+    event: String,
+}
+
+async fn stream(config: &StreamingConfig) -> Result<(), tokio_tungstenite::tungstenite::Error> {
+    let mut url = format!(
+        "wss://{}/api/v1/streaming?stream={}",
+        domain::to_ascii(&config.instance),
+        config.stream
+    );
+    if let Some(access_token) = config.access_token.get() {
+        url.push_str(&format!("&access_token={}", access_token));
+    }
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await?;
+
+    log!(
+        "{}: Connected to streaming {}",
+        config.instance,
+        config.stream
+    );
+    if let Some(gauge) = MASTODON_STREAMING_CONNECTED.with_limited_label_values(&[&config.instance])
+    {
+        gauge.set(1);
+    }
+
+    while let Some(message) = socket.next().await {
+        let text = match message? {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+
+        match serde_json::from_str::<StreamingEvent>(&text) {
+            Ok(event) => {
+                if let Some(counter) = MASTODON_STREAMING_EVENTS_TOTAL
+                    .with_limited_label_values(&[&config.instance, &event.event])
+                {
+                    counter.inc();
+                }
+            }
+            Err(err) => log!(
+                "{}: Unable to parse streaming event: {}",
+                config.instance,
+                err
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+/// Keeps a connection to the streaming API of an instance and counts the received events.
+///
+/// The connection is re-established whenever it is lost. This runs until the process exits or a
+/// reload changes it.
+pub async fn run(config: StreamingConfig) {
+    loop {
+        if let Err(err) = stream(&config).await {
+            log!("Error: {} streaming {}", config.instance, err);
+        }
+
+        log!(
+            "{}: Streaming disconnected, reconnecting in {} seconds",
+            config.instance,
+            RECONNECT_DELAY_SECONDS
+        );
+        if let Some(gauge) =
+            MASTODON_STREAMING_CONNECTED.with_limited_label_values(&[&config.instance])
+        {
+            gauge.set(0);
+        }
+
+        tokio::time::sleep(Duration::from_secs(RECONNECT_DELAY_SECONDS)).await;
+    }
+}
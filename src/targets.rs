@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use serde::Serialize;
+
+use crate::mastodon;
+
+lazy_static! {
+    /// The status of every target, keyed by instance and account, which is empty for instances.
+    static ref TARGETS: Mutex<BTreeMap<(String, String), TargetStatus>> =
+        Mutex::new(BTreeMap::new());
+}
+
+/// The health of a target after its last scrape.
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Health {
+    /// The target has not been scraped yet.
+    Unknown,
+    Up,
+    Down,
+}
+
+/// The outcome of the last scrape of an instance or account.
+#[derive(Clone, Serialize)]
+pub struct TargetStatus {
+    pub instance: String,
+
+    /// The id or acct of the account, `None` for instances.
+    pub account: Option<String>,
+
+    pub health: Health,
+
+    /// The number of seconds since 1970 the target was last scraped at.
+    pub last_scrape: Option<i64>,
+
+    /// The number of seconds the last scrape took.
+    pub last_scrape_duration_seconds: Option<f64>,
+
+    /// The error of the last scrape if it failed.
+    pub last_error: Option<String>,
+
+    /// The number of requests remaining until the rate limit of the instance resets.
+    pub ratelimit_remaining: Option<i64>,
+
+    /// The number of seconds since 1970 the rate limit of the instance resets at.
+    pub ratelimit_reset: Option<i64>,
+}
+
+/// Adds a target which has not been scraped yet, so it's listed before its first scrape.
+pub fn add(instance: &str, account: Option<&str>) {
+    let key = (
+        instance.to_string(),
+        account.unwrap_or_default().to_string(),
+    );
+    TARGETS
+        .lock()
+        .unwrap()
+        .entry(key)
+        .or_insert_with(|| TargetStatus {
+            instance: instance.to_string(),
+            account: account.map(str::to_string),
+            health: Health::Unknown,
+            last_scrape: None,
+            last_scrape_duration_seconds: None,
+            last_error: None,
+            ratelimit_remaining: None,
+            ratelimit_reset: None,
+        });
+}
+
+/// Records the outcome of a scrape of the target which started at the given instant.
+pub fn record(instance: &str, account: Option<&str>, start: Instant, result: Result<(), String>) {
+    add(instance, account);
+
+    let key = (
+        instance.to_string(),
+        account.unwrap_or_default().to_string(),
+    );
+    let mut targets = TARGETS.lock().unwrap();
+    let Some(status) = targets.get_mut(&key) else {
+        return;
+    };
+
+    status.last_scrape = Some(chrono::Utc::now().timestamp());
+    status.last_scrape_duration_seconds = Some(start.elapsed().as_secs_f64());
+    match result {
+        Ok(()) => {
+            status.health = Health::Up;
+            status.last_error = None;
+        }
+        Err(err) => {
+            status.health = Health::Down;
+            status.last_error = Some(err);
+        }
+    }
+}
+
+/// Removes a target which is no longer collected, e.g. after it was removed from the configuration.
+pub fn remove(instance: &str, account: Option<&str>) {
+    let key = (
+        instance.to_string(),
+        account.unwrap_or_default().to_string(),
+    );
+    TARGETS.lock().unwrap().remove(&key);
+}
+
+/// Returns the status of all targets with the current rate limit of their instance.
+pub fn statuses() -> Vec<TargetStatus> {
+    let mut statuses: Vec<TargetStatus> = TARGETS.lock().unwrap().values().cloned().collect();
+
+    for status in &mut statuses {
+        if let Some(rate_limit) = mastodon::last_rate_limit(&status.instance) {
+            status.ratelimit_remaining = rate_limit.remaining;
+            status.ratelimit_reset = rate_limit.reset.map(|reset| reset.timestamp());
+        }
+    }
+
+    statuses
+}
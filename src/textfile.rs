@@ -0,0 +1,41 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{metrics, Collector};
+
+/// Writes the exposition to the given path, replacing the file atomically.
+///
+/// The exposition is written to a temporary file next to it first, which the textfile collector of
+/// node_exporter ignores as it doesn't end with `.prom`.
+pub fn write(path: &Path, exposition: &str) -> io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+
+    fs::write(&temporary, exposition)?;
+    fs::rename(&temporary, path)
+}
+
+/// Runs a collection cycle every interval and writes the exposition to the given path after each.
+pub async fn run(collector: Arc<Collector>, path: PathBuf, interval_seconds: u64) {
+    let mut interval = tokio::time::interval(Duration::from_secs(interval_seconds));
+    collector.schedule_cycles(interval.period());
+
+    loop {
+        interval.tick().await;
+
+        collector.collect().await;
+
+        match write(&path, &metrics::encode()) {
+            Ok(()) => log!("Wrote metrics to {}", path.display()),
+            Err(err) => log!(
+                "Error: Unable to write metrics to {}: {}",
+                path.display(),
+                err
+            ),
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::{
+    config::Config,
+    mastodon::MastodonClient,
+    metrics::{WithLimitedLabelValues, MASTODON_TOKEN_VALID},
+};
+
+/// The number of seconds between checks of the access tokens.
+const CHECK_INTERVAL_SECONDS: u64 = 3600;
+
+/// An access token to check, with the scope its use needs.
+#[derive(Serialize)]
+pub struct TokenCheck {
+    pub instance: String,
+    pub access_token: String,
+    pub scope: &'static str,
+}
+
+/// Returns the checks of all configured access tokens, each token once per scope.
+pub fn checks(config: &Config) -> Vec<TokenCheck> {
+    let mut checks: Vec<TokenCheck> = Vec::new();
+    for (instance, access_token, scope) in config.access_tokens() {
+        let access_token = access_token.get().unwrap_or_default();
+        if checks.iter().any(|check| {
+            check.instance == instance && check.access_token == access_token && check.scope == scope
+        }) {
+            continue;
+        }
+        checks.push(TokenCheck {
+            instance: instance.to_string(),
+            access_token: access_token.to_string(),
+            scope,
+        });
+    }
+
+    checks
+}
+
+/// Returns whether the scopes grant the scope, e.g. `read` grants `read:statuses`.
+fn grants(scopes: &[String], scope: &str) -> bool {
+    let parent = scope.split(':').next().unwrap_or_default();
+
+    scopes
+        .iter()
+        .any(|granted| granted == scope || granted == parent)
+}
+
+/// Returns whether the access token is valid and grants the scope of its use.
+///
+/// Instances which don't return the scopes of tokens only have the token verified.
+async fn check(check: &TokenCheck) -> bool {
+    let application = match MastodonClient::new(check.instance.as_str())
+        .with_access_token(check.access_token.as_str())
+        .verify_app_credentials()
+        .await
+    {
+        Ok(application) => application,
+        Err(err) => {
+            log!("Error: {} access token {}", check.instance, err);
+            return false;
+        }
+    };
+
+    if !application.scopes.is_empty() && !grants(&application.scopes, check.scope) {
+        log!(
+            "Error: {} access token of {} lacks the {} scope, it has {}",
+            check.instance,
+            application.name,
+            check.scope,
+            application.scopes.join(" ")
+        );
+        return false;
+    }
+
+    true
+}
+
+/// Periodically verifies the access tokens and exports whether all tokens of each instance are
+/// valid, so expired or revoked tokens are noticed before collecting with them fails.
+///
+/// This runs until the process exits or a reload changes it, independent of `/metrics` scrapes.
+pub async fn run(checks: Vec<TokenCheck>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(CHECK_INTERVAL_SECONDS));
+
+    loop {
+        interval.tick().await;
+
+        let mut valid: BTreeMap<&str, bool> = BTreeMap::new();
+        for token_check in &checks {
+            let token_valid = check(token_check).await;
+            *valid.entry(token_check.instance.as_str()).or_insert(true) &= token_valid;
+        }
+
+        for (instance, valid) in valid {
+            log!("{}: Access tokens valid: {}", instance, valid);
+            if let Some(gauge) = MASTODON_TOKEN_VALID.with_limited_label_values(&[instance]) {
+                gauge.set(i64::from(valid));
+            }
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    config::StatusTrackerConfig,
+    mastodon::{self, MastodonClient},
+    metrics::{
+        WithLimitedLabelValues, MASTODON_STATUS_FAVOURITES_PER_HOUR,
+        MASTODON_STATUS_REBLOGS_PER_HOUR, MASTODON_STATUS_REPLIES_PER_HOUR,
+    },
+};
+
+/// A single observation of the engagement counters of a status.
+struct Sample {
+    at: DateTime<Utc>,
+    reblogs_count: i64,
+    favourites_count: i64,
+    replies_count: i64,
+}
+
+/// Samples of a status, ordered from oldest to newest.
+type Samples = VecDeque<Sample>;
+
+async fn sample_status(instance: &str, status_id: &str) -> Result<Option<Sample>, mastodon::Error> {
+    let body = match MastodonClient::new(instance).status(status_id).await {
+        Ok(body) => body,
+        Err(err) if err.status() == Some(reqwest::StatusCode::NOT_FOUND) => {
+            log!("{}: Status {} not found", instance, status_id);
+            return Ok(None);
+        }
+        Err(err) => return Err(err),
+    };
+
+    Ok(Some(Sample {
+        at: Utc::now(),
+        reblogs_count: body.reblogs_count,
+        favourites_count: body.favourites_count,
+        replies_count: body.replies_count,
+    }))
+}
+
+/// Computes the per hour rate of a counter between the oldest and newest sample.
+fn per_hour(samples: &Samples, counter: fn(&Sample) -> i64) -> Option<f64> {
+    let (first, last) = (samples.front()?, samples.back()?);
+    let hours = (last.at - first.at).num_milliseconds() as f64 / 3_600_000.0;
+
+    if hours <= 0.0 {
+        return None;
+    }
+
+    Some((counter(last) - counter(first)) as f64 / hours)
+}
+
+/// Periodically samples the monitored statuses and exports engagement rates.
+///
+/// This runs until the process exits or a reload changes it, independent of `/metrics` scrapes.
+pub async fn run(config: StatusTrackerConfig) {
+    let retention = chrono::Duration::seconds(config.retention_seconds as i64);
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval_seconds));
+    let mut samples: HashMap<(String, String), Samples> = HashMap::new();
+
+    loop {
+        interval.tick().await;
+
+        for (instance, status_id) in &config.statuses {
+            let sample = match sample_status(instance, status_id).await {
+                Ok(Some(sample)) => sample,
+                Ok(None) => continue,
+                Err(err) => {
+                    log!("Error: {} {} {}", instance, status_id, err);
+                    continue;
+                }
+            };
+
+            let status_samples = samples
+                .entry((instance.clone(), status_id.clone()))
+                .or_default();
+            status_samples.push_back(sample);
+
+            // Drop samples that left the retention window
+            let now = Utc::now();
+            while status_samples
+                .front()
+                .is_some_and(|sample| now - sample.at > retention)
+            {
+                status_samples.pop_front();
+            }
+
+            let labels = [instance.as_str(), status_id.as_str()];
+
+            if let Some(rate) = per_hour(status_samples, |sample| sample.reblogs_count) {
+                log!(
+                    "{}: Status {} reblogs per hour: {}",
+                    instance,
+                    status_id,
+                    rate
+                );
+                if let Some(gauge) =
+                    MASTODON_STATUS_REBLOGS_PER_HOUR.with_limited_label_values(&labels)
+                {
+                    gauge.set(rate);
+                }
+            }
+
+            if let Some(rate) = per_hour(status_samples, |sample| sample.favourites_count) {
+                log!(
+                    "{}: Status {} favourites per hour: {}",
+                    instance,
+                    status_id,
+                    rate
+                );
+                if let Some(gauge) =
+                    MASTODON_STATUS_FAVOURITES_PER_HOUR.with_limited_label_values(&labels)
+                {
+                    gauge.set(rate);
+                }
+            }
+
+            if let Some(rate) = per_hour(status_samples, |sample| sample.replies_count) {
+                log!(
+                    "{}: Status {} replies per hour: {}",
+                    instance,
+                    status_id,
+                    rate
+                );
+                if let Some(gauge) =
+                    MASTODON_STATUS_REPLIES_PER_HOUR.with_limited_label_values(&labels)
+                {
+                    gauge.set(rate);
+                }
+            }
+        }
+    }
+}
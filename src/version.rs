@@ -0,0 +1,210 @@
+/// A Mastodon version string split into its components.
+///
+/// Versions look like `4.1.2`, `4.2.0-beta1`, `4.2.0+glitch` or `4.0.2+hometown-1.1.0`.
+/// Compatible servers may append a suffix like `2.7.2 (compatible; Pleroma 2.5.0)`.
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+
+    /// The name of the fork from the build metadata, or `vanilla` for upstream Mastodon.
+    pub flavour: String,
+}
+
+impl Version {
+    /// Parses a version string as reported by the instance API.
+    ///
+    /// Returns `None` if the string doesn't start with at least `major.minor`, a missing patch
+    /// number is parsed as `0`. Pre-release identifiers like `-beta1` are ignored.
+    pub fn parse(version: &str) -> Option<Self> {
+        let version = version.split_whitespace().next()?;
+        let (core, build) = match version.split_once('+') {
+            Some((core, build)) => (core, Some(build)),
+            None => (version, None),
+        };
+
+        // Strip pre-release identifiers like `-beta1` or `-rc.2`
+        let core = core.split('-').next()?;
+
+        let mut numbers = core.split('.').map(|number| number.parse::<u64>());
+        let major = numbers.next()?.ok()?;
+        let minor = numbers.next()?.ok()?;
+        let patch = numbers.next().unwrap_or(Ok(0)).ok()?;
+
+        // The fork name is the leading alphabetic part of the build metadata
+        let flavour = build
+            .map(|build| {
+                build
+                    .split(|c: char| !c.is_ascii_alphabetic() && c != '_')
+                    .next()
+                    .unwrap_or_default()
+                    .to_lowercase()
+            })
+            .filter(|flavour| !flavour.is_empty())
+            .unwrap_or_else(|| "vanilla".to_string());
+
+        Some(Self {
+            major,
+            minor,
+            patch,
+            flavour,
+        })
+    }
+
+    /// Returns the numeric part of the version, suitable for ordering.
+    pub fn triple(&self) -> (u64, u64, u64) {
+        (self.major, self.minor, self.patch)
+    }
+
+    /// Returns whether a newer release than this version exists.
+    pub fn is_outdated(&self, releases: &[Version]) -> bool {
+        releases
+            .iter()
+            .any(|release| release.triple() > self.triple())
+    }
+
+    /// Returns how many patch versions this version is behind the newest release of the same
+    /// minor series, e.g. `2` for `4.1.2` if `4.1.4` is released, even if `4.1.3` was skipped.
+    pub fn behind_patches(&self, releases: &[Version]) -> u64 {
+        releases
+            .iter()
+            .filter(|release| (release.major, release.minor) == (self.major, self.minor))
+            .map(|release| release.patch.saturating_sub(self.patch))
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// The server software of an instance, detected from its version string and nodeinfo.
+#[derive(Clone, PartialEq)]
+pub struct Software {
+    /// The implementation, like `mastodon`, `glitch-soc`, `hometown`, `gotosocial`, `pleroma` or
+    /// `akkoma`.
+    pub name: String,
+
+    /// The name of the fork from the build metadata of its own version, or `vanilla`.
+    pub flavour: String,
+}
+
+impl Software {
+    /// Detects the software from the version string reported by the instance API and the software
+    /// name reported by nodeinfo, if available.
+    ///
+    /// Compatible servers are recognized by a suffix like `(compatible; Pleroma 2.5.0)`, others
+    /// like GoToSocial only by their nodeinfo. Forks of Mastodon report `mastodon` in nodeinfo and
+    /// are recognized by their build metadata like `4.2.0+glitch`.
+    pub fn detect(version: &str, nodeinfo: Option<&str>) -> Self {
+        let (name, own_version) = match version.split_once("(compatible;") {
+            Some((_, compatible)) => {
+                let compatible = compatible.trim().trim_end_matches(')');
+                match compatible.split_once(' ') {
+                    Some((name, own_version)) => (name.to_lowercase(), own_version),
+                    None => (compatible.to_lowercase(), ""),
+                }
+            }
+            None => ("mastodon".to_string(), version),
+        };
+        let name = match nodeinfo {
+            Some(nodeinfo) if name == "mastodon" && !nodeinfo.is_empty() => nodeinfo.to_lowercase(),
+            _ => name,
+        };
+
+        let flavour = Version::parse(own_version)
+            .map(|version| version.flavour)
+            .unwrap_or_else(|| "vanilla".to_string());
+        let name = match (name.as_str(), flavour.as_str()) {
+            ("mastodon", "glitch") => "glitch-soc".to_string(),
+            ("mastodon", "hometown") => "hometown".to_string(),
+            _ => name,
+        };
+
+        Self { name, flavour }
+    }
+
+    /// Returns whether the software is Mastodon or one of its forks, which follow its releases.
+    pub fn is_mastodon(&self) -> bool {
+        matches!(self.name.as_str(), "mastodon" | "glitch-soc" | "hometown")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(version: &str) -> (u64, u64, u64, String) {
+        let version = Version::parse(version).unwrap();
+        (version.major, version.minor, version.patch, version.flavour)
+    }
+
+    #[test]
+    fn parses_upstream_versions() {
+        assert_eq!(parse("4.1.2"), (4, 1, 2, "vanilla".to_string()));
+        assert_eq!(parse("4.2.0-beta1"), (4, 2, 0, "vanilla".to_string()));
+        assert_eq!(parse("4.3.0-rc.2"), (4, 3, 0, "vanilla".to_string()));
+    }
+
+    #[test]
+    fn parses_the_flavour_of_forks() {
+        assert_eq!(parse("4.2.0+glitch"), (4, 2, 0, "glitch".to_string()));
+        assert_eq!(
+            parse("4.0.2+hometown-1.1.0"),
+            (4, 0, 2, "hometown".to_string())
+        );
+        assert_eq!(
+            parse("4.1.2+Glitch_Soc"),
+            (4, 1, 2, "glitch_soc".to_string())
+        );
+        assert_eq!(parse("4.1.2+1.0"), (4, 1, 2, "vanilla".to_string()));
+    }
+
+    #[test]
+    fn parses_versions_of_compatible_servers() {
+        assert_eq!(
+            parse("2.7.2 (compatible; Pleroma 2.5.0)"),
+            (2, 7, 2, "vanilla".to_string())
+        );
+        assert_eq!(parse("3.5"), (3, 5, 0, "vanilla".to_string()));
+    }
+
+    #[test]
+    fn rejects_invalid_versions() {
+        assert!(Version::parse("").is_none());
+        assert!(Version::parse("4").is_none());
+        assert!(Version::parse("v4.1.2").is_none());
+        assert!(Version::parse("4.x.2").is_none());
+        assert!(Version::parse("4.1.x").is_none());
+    }
+
+    #[test]
+    fn compares_against_releases() {
+        let releases: Vec<Version> = ["4.1.4", "4.2.1", "4.0.9"]
+            .iter()
+            .map(|release| Version::parse(release).unwrap())
+            .collect();
+
+        let version = Version::parse("4.1.2").unwrap();
+        assert!(version.is_outdated(&releases));
+        assert_eq!(version.behind_patches(&releases), 2);
+
+        let version = Version::parse("4.2.1+glitch").unwrap();
+        assert!(!version.is_outdated(&releases));
+        assert_eq!(version.behind_patches(&releases), 0);
+    }
+
+    #[test]
+    fn detects_the_software() {
+        let software = Software::detect("4.2.0+glitch", Some("mastodon"));
+        assert_eq!(software.name, "glitch-soc");
+        assert!(software.is_mastodon());
+
+        let software = Software::detect("2.7.2 (compatible; Akkoma 3.9.3)", None);
+        assert_eq!(
+            (software.name.as_str(), software.flavour.as_str()),
+            ("akkoma", "vanilla")
+        );
+        assert!(!software.is_mastodon());
+
+        let software = Software::detect("4.0.0", Some("gotosocial"));
+        assert_eq!(software.name, "gotosocial");
+    }
+}
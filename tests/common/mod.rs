@@ -0,0 +1,66 @@
+// Not every test uses every helper
+#![allow(dead_code)]
+
+use std::fs;
+
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// Responds with a fixture and the rate limit headers Mastodon sends.
+pub fn fixture_response(name: &str) -> ResponseTemplate {
+    let body = fs::read_to_string(format!("tests/fixtures/{}", name)).unwrap();
+
+    ResponseTemplate::new(200)
+        .insert_header("content-type", "application/json")
+        .insert_header("x-ratelimit-limit", "300")
+        .insert_header("x-ratelimit-remaining", "299")
+        .insert_header("x-ratelimit-reset", "2022-11-21T12:00:00.000Z")
+        .set_body_string(body)
+}
+
+/// Mounts a response with the fixture on the mock server for `GET` requests to the path.
+pub async fn mount_fixture(server: &MockServer, request_path: &str, name: &str) {
+    Mock::given(method("GET"))
+        .and(path(request_path))
+        .respond_with(fixture_response(name))
+        .mount(server)
+        .await;
+}
+
+/// Mounts the nodeinfo links and the nodeinfo document with the fixture on the mock server.
+pub async fn mount_nodeinfo(server: &MockServer, name: &str) {
+    Mock::given(method("GET"))
+        .and(path("/.well-known/nodeinfo"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "links": [{
+                "rel": "http://nodeinfo.diaspora.software/ns/schema/2.0",
+                "href": format!("{}/nodeinfo/2.0", server.uri()),
+            }],
+        })))
+        .mount(server)
+        .await;
+    mount_fixture(server, "/nodeinfo/2.0", name).await;
+}
+
+/// Compares the output with the golden file of the given name in `tests/golden`.
+///
+/// Run with `UPDATE_GOLDEN=1` to accept intended changes of the output.
+pub fn assert_golden(name: &str, output: &str) {
+    let path = format!("tests/golden/{}", name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, output).unwrap();
+    }
+
+    let golden = fs::read_to_string(&path).unwrap();
+
+    assert_eq!(output, golden);
+}
+
+/// Removes the series of request durations, which vary between runs, keeping their metadata.
+pub fn without_durations(exposition: &str) -> String {
+    exposition
+        .lines()
+        .filter(|line| !line.starts_with("mastodon_request_duration_seconds"))
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
@@ -0,0 +1,35 @@
+mod common;
+
+use common::{assert_golden, mount_fixture, mount_nodeinfo, without_durations};
+use mastodon_exporter::{metrics, Collector, Config};
+use wiremock::MockServer;
+
+/// Pleroma only serves `/api/v1/instance` and leaves out some fields of accounts.
+#[tokio::test]
+async fn pleroma_exposition_matches_golden_file() {
+    let server = MockServer::start().await;
+
+    mount_fixture(&server, "/api/v1/instance", "pleroma/instance.json").await;
+    mount_fixture(
+        &server,
+        "/api/v1/accounts/AQ3pWiVSBrHJ3tDJGi",
+        "pleroma/account.json",
+    )
+    .await;
+    mount_nodeinfo(&server, "pleroma/nodeinfo.json").await;
+
+    let instance = server.uri();
+    let config = Config {
+        instance_info: vec![instance.clone().into()],
+        accounts: vec![(instance.clone(), "AQ3pWiVSBrHJ3tDJGi".to_string()).into()],
+        ..Config::default()
+    };
+
+    let collector = Collector::new(config);
+    collector.collect().await;
+
+    // The mock server listens on a random port, so it is replaced by a placeholder
+    let exposition = metrics::encode().replace(&instance, "{mock}");
+
+    assert_golden("pleroma.prom", &without_durations(&exposition));
+}
@@ -0,0 +1,13 @@
+mod common;
+
+use common::assert_golden;
+use mastodon_exporter::{metrics, Collector, Config};
+
+/// Guards the names, help, types and labels of all metrics, including those without series.
+#[test]
+fn descriptions_match_golden_file() {
+    // The collector also registers the metrics whose labels depend on the configuration
+    Collector::new(Config::default());
+
+    assert_golden("descriptions.prom", &metrics::describe());
+}
@@ -0,0 +1,29 @@
+mod common;
+
+use common::{assert_golden, mount_fixture, mount_nodeinfo, without_durations};
+use mastodon_exporter::{metrics, Collector, Config};
+use wiremock::MockServer;
+
+#[tokio::test]
+async fn exposition_matches_golden_file() {
+    let server = MockServer::start().await;
+
+    mount_fixture(&server, "/api/v2/instance", "instance.json").await;
+    mount_fixture(&server, "/api/v1/accounts/1", "account.json").await;
+    mount_nodeinfo(&server, "nodeinfo.json").await;
+
+    let instance = server.uri();
+    let config = Config {
+        instance_info: vec![instance.clone().into()],
+        accounts: vec![(instance.clone(), "1".to_string()).into()],
+        ..Config::default()
+    };
+
+    let collector = Collector::new(config);
+    collector.collect().await;
+
+    // The mock server listens on a random port, so it is replaced by a placeholder
+    let exposition = metrics::encode().replace(&instance, "{mock}");
+
+    assert_golden("metrics.prom", &without_durations(&exposition));
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use common::{mount_fixture, mount_nodeinfo};
+use mastodon_exporter::{
+    config::{AccountConfig, StatusTrackerConfig},
+    metrics, targets, Collector, Config,
+};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[tokio::test]
+async fn reload_removes_targets_which_are_no_longer_collected() {
+    let server = MockServer::start().await;
+
+    mount_fixture(&server, "/api/v2/instance", "instance.json").await;
+    mount_fixture(&server, "/api/v1/accounts/1", "account.json").await;
+    mount_nodeinfo(&server, "nodeinfo.json").await;
+
+    let instance = server.uri();
+    let account: AccountConfig = (instance.clone(), "1".to_string()).into();
+    let collector = Collector::new(Config {
+        instance_info: vec![instance.clone().into()],
+        accounts: vec![account.clone()],
+        ..Config::default()
+    });
+    collector.collect().await;
+
+    let exposition = metrics::encode();
+    assert!(exposition.contains("mastodon_account_followers_count{"));
+    assert_eq!(targets::statuses().len(), 2);
+
+    // The account is parked, so it's no longer collected
+    collector.reload(Config {
+        instance_info: vec![instance.clone().into()],
+        accounts: vec![AccountConfig {
+            enabled: false,
+            ..account
+        }],
+        ..Config::default()
+    });
+
+    let exposition = metrics::encode();
+    assert!(!exposition.contains("mastodon_account_followers_count{"));
+    assert!(exposition.contains(&format!(
+        "mastodon_target_disabled{{account_id=\"1\",instance=\"{}\"}} 1",
+        instance
+    )));
+    assert!(exposition.contains("mastodon_info{"));
+    let statuses = targets::statuses();
+    assert_eq!(statuses.len(), 1);
+    assert_eq!(statuses[0].account, None);
+
+    collector.reload(Config {
+        instance_info: vec![],
+        ..Config::default()
+    });
+
+    let exposition = metrics::encode();
+    assert!(!exposition.contains(&instance));
+    assert!(targets::statuses().is_empty());
+}
+
+#[tokio::test]
+async fn reload_removes_polls_which_are_no_longer_collected() {
+    let server = MockServer::start().await;
+
+    let poll = serde_json::json!({
+        "id": "7",
+        "expires_at": null,
+        "options": [{ "title": "yes", "votes_count": 3 }],
+    });
+    Mock::given(method("GET"))
+        .and(path("/api/v1/statuses/5"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "id": "5",
+            "uri": "https://example.com/statuses/5",
+            "created_at": "2022-11-21T12:00:00.000Z",
+            "poll": poll,
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/polls/7"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(poll))
+        .mount(&server)
+        .await;
+
+    // The poll is discovered on the tracked status
+    let instance = server.uri();
+    let collector = Collector::new(Config {
+        instance_info: vec![],
+        status_tracker: Some(StatusTrackerConfig {
+            interval_seconds: 60,
+            retention_seconds: 3600,
+            statuses: vec![(instance.clone(), "5".to_string())],
+            polls: true,
+        }),
+        ..Config::default()
+    });
+    collector.collect().await;
+
+    let votes = format!(
+        "mastodon_poll_votes_count{{instance=\"{}\",option=\"yes\",poll_id=\"7\"}} 3",
+        instance
+    );
+    assert!(metrics::encode().contains(&votes));
+
+    collector.reload(Config {
+        instance_info: vec![],
+        ..Config::default()
+    });
+
+    assert!(!metrics::encode().contains("mastodon_poll_votes_count{"));
+}
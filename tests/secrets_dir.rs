@@ -0,0 +1,83 @@
+mod common;
+
+use std::{env, fs};
+
+use common::{fixture_response, mount_fixture};
+use mastodon_exporter::{metrics, Collector, Config};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// The token of an instance in the secrets directory may only belong to one of its accounts, so
+/// the metrics of the token's account must not be exported for the others.
+#[tokio::test]
+async fn shared_token_only_collects_its_own_account() {
+    let server = MockServer::start().await;
+
+    mount_fixture(&server, "/api/v1/accounts/1", "account.json").await;
+    let mut account: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string("tests/fixtures/account.json").unwrap()).unwrap();
+    account["id"] = "2".into();
+    Mock::given(method("GET"))
+        .and(path("/api/v1/accounts/2"))
+        .respond_with(fixture_response("account.json").set_body_json(account))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/accounts/verify_credentials"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "id": "1",
+            "source": { "privacy": "public" },
+        })))
+        .mount(&server)
+        .await;
+    for list in ["followed_tags", "mutes", "blocks", "conversations"] {
+        Mock::given(method("GET"))
+            .and(path(format!("/api/v1/{}", list)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([])))
+            .mount(&server)
+            .await;
+    }
+
+    let instance = server.uri();
+    let dir = env::temp_dir().join(format!("mastodon_exporter-shared-{}", std::process::id()));
+    let secrets_dir = dir.join("secrets");
+    fs::create_dir_all(&secrets_dir).unwrap();
+    fs::write(
+        secrets_dir.join(instance.trim_start_matches("http://")),
+        "secret\n",
+    )
+    .unwrap();
+    let path = dir.join("mastodon_exporter.yml");
+    let config = [
+        "server:".to_string(),
+        "  http_listen_port: 9498".to_string(),
+        "instance_info: []".to_string(),
+        "accounts:".to_string(),
+        format!("  - instance: {}", instance),
+        "    id: \"1\"".to_string(),
+        format!("  - instance: {}", instance),
+        "    id: \"2\"".to_string(),
+        format!("secrets_dir: {}", secrets_dir.to_string_lossy()),
+    ];
+    fs::write(&path, config.join("\n")).unwrap();
+    let config = Config::load(&path).unwrap();
+    fs::remove_dir_all(&dir).ok();
+    assert!(config
+        .accounts
+        .iter()
+        .all(|account| account.access_token.get() == Some("secret")));
+
+    let collector = Collector::new(config);
+    collector.collect().await;
+
+    let exposition = metrics::encode();
+    let followed_tags: Vec<&str> = exposition
+        .lines()
+        .filter(|line| line.starts_with("mastodon_account_followed_tags{"))
+        .collect();
+    assert_eq!(followed_tags.len(), 1);
+    assert!(followed_tags[0].contains("account_id=\"1\""));
+    assert!(!exposition.lines().any(|line| {
+        line.starts_with("mastodon_account_mutes_count{") && line.contains("account_id=\"2\"")
+    }));
+}
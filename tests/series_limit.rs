@@ -0,0 +1,24 @@
+use mastodon_exporter::metrics::{
+    self, WithLimitedLabelValues, MASTODON_SEARCH_RESULTS, MASTODON_SERIES_REJECTED,
+};
+
+#[test]
+fn new_series_beyond_the_limit_are_never_created() {
+    metrics::register();
+    metrics::set_series_limit(Some(1));
+
+    let gauge = MASTODON_SEARCH_RESULTS.with_limited_label_values(&["mas.to", "accounts"]);
+    gauge.unwrap().set(1);
+    assert!(MASTODON_SEARCH_RESULTS
+        .with_limited_label_values(&["mas.to", "statuses"])
+        .is_none());
+    assert!(!metrics::encode().contains("result_type=\"statuses\""));
+
+    // Existing series are still updated at the limit
+    let gauge = MASTODON_SEARCH_RESULTS.with_limited_label_values(&["mas.to", "accounts"]);
+    gauge.unwrap().set(2);
+
+    metrics::enforce_series_limit();
+    let rejected = MASTODON_SERIES_REJECTED.with_label_values(&["mastodon_search_results"]);
+    assert_eq!(rejected.get(), 1);
+}